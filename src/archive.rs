@@ -0,0 +1,378 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// Tar archives are made of 512-byte blocks: a header block for each entry, followed by its contents
+const BLOCK_SIZE: usize = 512;
+
+// A file or directory stored in an archive
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    // The path inside the archive, without a leading './' or a trailing '/'
+    pub path: String,
+    pub is_directory: bool,
+    pub contents: Vec<u8>,
+}
+
+// A tar archive read into memory, so that it can be browsed like a read-only directory
+pub struct Archive {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let entries = parse_tar(&fs::read(path)?)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Not a tar archive"))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    // Gets the path of the archive file itself
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Checks whether a path inside the archive is a directory
+    // Directories don't need their own entry, as long as something is stored inside them
+    pub fn is_directory(&self, path: &str) -> bool {
+        let prefix = format!("{}/", path);
+        path.is_empty()
+            || self.entries.iter().any(|entry| {
+                (entry.is_directory && entry.path == path) || entry.path.starts_with(&prefix)
+            })
+    }
+
+    // Gets the contents of a file inside the archive
+    pub fn read(&self, path: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|entry| !entry.is_directory && entry.path == path)
+            .map(|entry| entry.contents.as_slice())
+    }
+
+    // Lists the names directly inside a directory of the archive, split into directories and files and sorted
+    // Returns None if the path isn't a directory in the archive
+    pub fn list(&self, directory: &str) -> Option<(Vec<String>, Vec<String>)> {
+        if !self.is_directory(directory) {
+            return None;
+        }
+
+        let mut directories = Vec::new();
+        let mut files = Vec::new();
+
+        for entry in &self.entries {
+            let relative = match directory.is_empty() {
+                true => entry.path.as_str(),
+                false => match entry
+                    .path
+                    .strip_prefix(directory)
+                    .and_then(|p| p.strip_prefix('/'))
+                {
+                    Some(relative) => relative,
+                    None => continue,
+                },
+            };
+
+            match relative.split_once('/') {
+                // Something deeper down implies a directory, even without its own entry
+                Some((name, _)) => directories.push(name.to_string()),
+                None if relative.is_empty() => (),
+                None if entry.is_directory => directories.push(relative.to_string()),
+                None => files.push(relative.to_string()),
+            }
+        }
+
+        for names in [&mut directories, &mut files] {
+            names.sort();
+            names.dedup();
+        }
+
+        Some((directories, files))
+    }
+}
+
+// The directory being browsed inside an archive, after 'change-directory' into a tar file
+pub struct ArchiveView {
+    pub archive: Archive,
+    // The path of the directory inside the archive ('' for the top of the archive)
+    pub directory: String,
+}
+
+impl ArchiveView {
+    pub fn new(archive: Archive) -> Self {
+        Self {
+            archive,
+            directory: String::new(),
+        }
+    }
+
+    // Gets the path the view would have if the archive were a real directory
+    pub fn virtual_path(&self) -> PathBuf {
+        self.archive.path().join(&self.directory)
+    }
+
+    // Works out where a path leads from the directory being browsed
+    // Relative paths stay inside the archive unless they go above its top, in which case they
+    // continue from the real directory that holds the archive
+    pub fn locate(&self, path: &str) -> Location {
+        match join(&self.directory, path) {
+            Ok(inside) => Location::Inside(inside),
+            Err(Some(rest)) => {
+                let parent = self.archive.path().parent().unwrap_or(Path::new("/"));
+                Location::Outside(parent.join(rest).to_string_lossy().to_string())
+            }
+            Err(None) => Location::Outside(path.to_string()),
+        }
+    }
+}
+
+// Where a path leads while browsing an archive
+#[derive(Debug, PartialEq, Eq)]
+pub enum Location {
+    // A path inside the archive
+    Inside(String),
+    // A path on the real filesystem
+    Outside(String),
+}
+
+// Checks whether a file looks like an archive that can be browsed (by its extension)
+pub fn is_archive_path(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|extension| extension == "tar")
+}
+
+// Resolves a relative path against a directory inside an archive, handling '.' and '..'
+// A path that goes above the top of the archive gives the rest of the path after that point,
+// and absolute paths (which never lead into the archive) give None
+fn join(directory: &str, path: &str) -> Result<String, Option<String>> {
+    if path.starts_with('/') || path.starts_with('~') {
+        return Err(None);
+    }
+
+    let mut components: Vec<&str> = directory.split('/').filter(|c| !c.is_empty()).collect();
+    let mut rest = path.split('/');
+    while let Some(component) = rest.next() {
+        match component {
+            "" | "." => (),
+            ".." if components.is_empty() => return Err(Some(rest.collect::<Vec<_>>().join("/"))),
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    Ok(components.join("/"))
+}
+
+// Reads the entries of a tar archive (in the ustar or GNU format)
+// Returns None if the data isn't a tar archive or is cut short
+pub fn parse_tar(bytes: &[u8]) -> Option<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    // A GNU long name applies to the entry after it
+    let mut long_name = None;
+
+    while offset + BLOCK_SIZE <= bytes.len() {
+        let header = &bytes[offset..offset + BLOCK_SIZE];
+        // The archive ends with empty blocks
+        if header.iter().all(|byte| *byte == 0) {
+            return Some(entries);
+        }
+
+        if !has_valid_checksum(header) {
+            return None;
+        }
+
+        let size = parse_octal(&header[124..136])? as usize;
+        let contents_start = offset + BLOCK_SIZE;
+        let contents = bytes.get(contents_start..contents_start + size)?;
+        offset = contents_start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+        let name = match long_name.take() {
+            Some(name) => name,
+            None => header_name(header),
+        };
+        let path = name
+            .trim_start_matches("./")
+            .trim_end_matches('/')
+            .to_string();
+
+        match header[156] {
+            b'L' => long_name = Some(text(contents)),
+            b'0' | 0 if !path.is_empty() => entries.push(Entry {
+                path,
+                is_directory: false,
+                contents: contents.to_vec(),
+            }),
+            b'5' if !path.is_empty() => entries.push(Entry {
+                path,
+                is_directory: true,
+                contents: Vec::new(),
+            }),
+            // Links, devices and extended headers aren't shown
+            _ => (),
+        }
+    }
+
+    // Archives without the closing empty blocks are still readable
+    (offset == bytes.len()).then_some(entries)
+}
+
+// Gets the name in a header, including the prefix that ustar uses for long paths
+fn header_name(header: &[u8]) -> String {
+    let name = text(&header[0..100]);
+    let prefix = match &header[257..262] == b"ustar" {
+        true => text(&header[345..500]),
+        false => String::new(),
+    };
+
+    match prefix.is_empty() {
+        true => name,
+        false => format!("{}/{}", prefix, name),
+    }
+}
+
+// Reads a NUL-terminated string from a header field
+fn text(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+// Reads a number from a header field, which is written in octal and padded with spaces or NULs
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let digits = text(field);
+    let digits = digits.trim();
+    match digits.is_empty() {
+        true => Some(0),
+        false => u64::from_str_radix(digits, 8).ok(),
+    }
+}
+
+// The checksum is the sum of the header's bytes, counting the checksum field itself as spaces
+fn has_valid_checksum(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| match index {
+            148..156 => u64::from(b' '),
+            _ => u64::from(*byte),
+        })
+        .sum();
+
+    parse_octal(&header[148..156]) == Some(sum)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Builds a tar archive, where entries without contents are directories
+    pub fn fake_tar(entries: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+
+        for (path, contents) in entries {
+            let mut header = vec![0; BLOCK_SIZE];
+            header[..path.len()].copy_from_slice(path.as_bytes());
+            let size = contents.map_or(0, |contents| contents.len());
+            header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+            header[156] = match contents {
+                Some(_) => b'0',
+                None => b'5',
+            };
+            header[257..263].copy_from_slice(b"ustar\0");
+
+            header[148..156].copy_from_slice(b"        ");
+            let sum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+
+            archive.extend(header);
+            if let Some(contents) = contents {
+                archive.extend(*contents);
+                archive.resize(archive.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+            }
+        }
+
+        archive.extend([0; BLOCK_SIZE * 2]);
+        archive
+    }
+
+    fn archive() -> Archive {
+        let bytes = fake_tar(&[
+            ("./docs/", None),
+            ("./docs/guide.md", Some(b"# Guide\n")),
+            ("./README.md", Some(b"hello\n")),
+            ("./src/main.rs", Some(b"fn main() {}\n")),
+        ]);
+
+        Archive {
+            path: PathBuf::from("/tmp/project.tar"),
+            entries: parse_tar(&bytes).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_tar() {
+        let archive = archive();
+        assert_eq!(archive.read("README.md"), Some(&b"hello\n"[..]));
+        assert_eq!(archive.read("docs"), None);
+        assert_eq!(archive.read("missing"), None);
+
+        assert_eq!(parse_tar(b"not an archive"), None);
+        let mut corrupted = fake_tar(&[("a", Some(b"a"))]);
+        corrupted[0] = b'b';
+        assert_eq!(parse_tar(&corrupted), None);
+        // Contents that run past the end of the data are rejected
+        let truncated = fake_tar(&[("a", Some(&[1; 600]))]);
+        assert_eq!(parse_tar(&truncated[..BLOCK_SIZE + 100]), None);
+    }
+
+    #[test]
+    fn test_list_archive() {
+        let archive = archive();
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            archive.list(""),
+            Some((names(&["docs", "src"]), names(&["README.md"])))
+        );
+        // 'src' has no entry of its own, but is still a directory
+        assert_eq!(archive.list("src"), Some((names(&[]), names(&["main.rs"]))));
+        assert_eq!(
+            archive.list("docs"),
+            Some((names(&[]), names(&["guide.md"])))
+        );
+        assert_eq!(archive.list("README.md"), None);
+        assert_eq!(archive.list("missing"), None);
+    }
+
+    #[test]
+    fn test_locate() {
+        let view = ArchiveView {
+            archive: archive(),
+            directory: String::from("docs"),
+        };
+        let inside = |path: &str| Location::Inside(path.to_string());
+        let outside = |path: &str| Location::Outside(path.to_string());
+
+        assert_eq!(view.locate("guide.md"), inside("docs/guide.md"));
+        assert_eq!(view.locate("../src/./"), inside("src"));
+        assert_eq!(view.locate(".."), inside(""));
+        // Going above the top of the archive continues from the directory that holds it
+        assert_eq!(view.locate("../.."), outside("/tmp/"));
+        assert_eq!(view.locate("../../other/x"), outside("/tmp/other/x"));
+        assert_eq!(view.locate("/etc"), outside("/etc"));
+        assert_eq!(view.locate("~/x"), outside("~/x"));
+    }
+}
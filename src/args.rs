@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use crate::errors::ArgsError;
+
+// A flag that a command accepts, such as '-F' or '--classify'
+struct FlagSpec {
+    // The name the flag is looked up by once the arguments are parsed
+    name: &'static str,
+    // Every way of writing the flag, such as ["-F", "--classify"]
+    spellings: &'static [&'static str],
+    value: FlagValue,
+}
+
+// Whether a flag is followed by a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagValue {
+    None,
+    Required,
+    // Only given inline ('--backup=numbered'), so the flag can also be used on its own
+    Optional,
+}
+
+// Splits a command's arguments into flags and positional arguments
+// Flags may appear anywhere, short flags can be combined ('-aF'), values can be given as
+// '--flag value' or '--flag=value', and everything after '--' is positional
+#[derive(Default)]
+pub struct FlagParser {
+    flags: Vec<FlagSpec>,
+}
+
+// The result of parsing arguments with a FlagParser
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ParsedArgs<'a> {
+    // The values given to each flag, in order (empty for flags without values)
+    flags: HashMap<&'static str, Vec<&'a str>>,
+    pub positional: Vec<&'a str>,
+}
+
+impl FlagParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a flag that is either present or not ('list-directory -F')
+    pub fn flag(mut self, name: &'static str, spellings: &'static [&'static str]) -> Self {
+        self.flags.push(FlagSpec {
+            name,
+            spellings,
+            value: FlagValue::None,
+        });
+        self
+    }
+
+    // Adds a flag that is followed by a value ('read-file --lines 10:20')
+    pub fn option(mut self, name: &'static str, spellings: &'static [&'static str]) -> Self {
+        self.flags.push(FlagSpec {
+            name,
+            spellings,
+            value: FlagValue::Required,
+        });
+        self
+    }
+
+    // Adds a flag that may be given a value with '=' ('copy-file --backup' or '--backup=numbered')
+    pub fn optional(mut self, name: &'static str, spellings: &'static [&'static str]) -> Self {
+        self.flags.push(FlagSpec {
+            name,
+            spellings,
+            value: FlagValue::Optional,
+        });
+        self
+    }
+
+    fn find(&self, spelling: &str) -> Option<&FlagSpec> {
+        self.flags
+            .iter()
+            .find(|flag| flag.spellings.contains(&spelling))
+    }
+
+    pub fn parse<'a>(&self, args: &[&'a str]) -> Result<ParsedArgs<'a>, ArgsError> {
+        let mut parsed = ParsedArgs::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            // A lone '-' usually means stdin, so it's positional
+            if !arg.starts_with('-') || *arg == "-" {
+                parsed.positional.push(arg);
+                continue;
+            }
+
+            if *arg == "--" {
+                parsed.positional.extend(args.by_ref());
+                break;
+            }
+
+            if arg.starts_with("--") {
+                let (spelling, inline_value) = match arg.split_once('=') {
+                    Some((spelling, value)) => (spelling, Some(value)),
+                    None => (*arg, None),
+                };
+                let flag = self
+                    .find(spelling)
+                    .ok_or_else(|| ArgsError::UnknownFlag(spelling.to_string()))?;
+
+                let value = match (flag.value, inline_value) {
+                    (FlagValue::Required | FlagValue::Optional, Some(value)) => Some(value),
+                    (FlagValue::Required, None) => Some(
+                        *args
+                            .next()
+                            .ok_or_else(|| ArgsError::MissingValue(spelling.to_string()))?,
+                    ),
+                    (FlagValue::None, Some(_)) => {
+                        return Err(ArgsError::UnexpectedValue(spelling.to_string()))
+                    }
+                    (FlagValue::None | FlagValue::Optional, None) => None,
+                };
+                parsed.add(flag.name, value);
+                continue;
+            }
+
+            // Short flags can be combined, and one that takes a value uses the rest of the word or the next argument
+            for (index, c) in arg.char_indices().skip(1) {
+                let spelling = format!("-{}", c);
+                let flag = self
+                    .find(&spelling)
+                    .ok_or_else(|| ArgsError::UnknownFlag(spelling.clone()))?;
+
+                if flag.value != FlagValue::Required {
+                    parsed.add(flag.name, None);
+                    continue;
+                }
+
+                let rest = &arg[index + c.len_utf8()..];
+                let value = match rest.is_empty() {
+                    true => *args.next().ok_or(ArgsError::MissingValue(spelling))?,
+                    false => rest,
+                };
+                parsed.add(flag.name, Some(value));
+                break;
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl<'a> ParsedArgs<'a> {
+    // Checks whether a flag was given
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    // Gets the value given to a flag (the last one, if it was given more than once)
+    pub fn value(&self, name: &str) -> Option<&'a str> {
+        self.flags
+            .get(name)
+            .and_then(|values| values.last())
+            .copied()
+    }
+
+    // Gets every value given to a flag that can be repeated ('--ignore a --ignore b')
+    pub fn values(&self, name: &str) -> Vec<&'a str> {
+        self.flags.get(name).cloned().unwrap_or_default()
+    }
+
+    fn add(&mut self, name: &'static str, value: Option<&'a str>) {
+        self.flags.entry(name).or_default().extend(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> FlagParser {
+        FlagParser::new()
+            .flag("all", &["-a", "--all"])
+            .flag("classify", &["-F", "--classify"])
+            .option("lines", &["-n", "--lines"])
+            .optional("backup", &["-b", "--backup"])
+    }
+
+    #[test]
+    fn test_flags_and_positional() {
+        let parsed = parser().parse(&["one", "-a", "two", "--classify"]).unwrap();
+        assert!(parsed.has("all"));
+        assert!(parsed.has("classify"));
+        assert!(!parsed.has("lines"));
+        assert_eq!(parsed.positional, ["one", "two"]);
+
+        // Short flags can be combined
+        let parsed = parser().parse(&["-aF", "-"]).unwrap();
+        assert!(parsed.has("all") && parsed.has("classify"));
+        assert_eq!(parsed.positional, ["-"]);
+    }
+
+    #[test]
+    fn test_flag_values() {
+        for args in [
+            &["--lines", "1:5", "file"][..],
+            &["--lines=1:5", "file"],
+            &["-n", "1:5", "file"],
+            &["-n1:5", "file"],
+            &["-an", "1:5", "file"],
+        ] {
+            let parsed = parser().parse(args).unwrap();
+            assert_eq!(parsed.value("lines"), Some("1:5"), "{:?}", args);
+            assert_eq!(parsed.positional, ["file"], "{:?}", args);
+        }
+
+        assert_eq!(parser().parse(&[]).unwrap().value("lines"), None);
+    }
+
+    #[test]
+    fn test_repeated_values() {
+        let parsed = parser()
+            .parse(&["-n", "1:5", "file", "--lines=7:9", "-a"])
+            .unwrap();
+        assert_eq!(parsed.values("lines"), ["1:5", "7:9"]);
+        // The last value wins when only one is used
+        assert_eq!(parsed.value("lines"), Some("7:9"));
+        assert!(parsed.values("all").is_empty());
+        assert!(parsed.values("classify").is_empty());
+    }
+
+    #[test]
+    fn test_optional_values() {
+        // The value can only be given inline, so the next argument stays positional
+        let parsed = parser().parse(&["--backup", "file"]).unwrap();
+        assert!(parsed.has("backup"));
+        assert_eq!(parsed.value("backup"), None);
+        assert_eq!(parsed.positional, ["file"]);
+
+        let parsed = parser().parse(&["--backup=numbered", "file"]).unwrap();
+        assert_eq!(parsed.value("backup"), Some("numbered"));
+        let parsed = parser().parse(&["-ba", "file"]).unwrap();
+        assert!(parsed.has("backup") && parsed.has("all"));
+    }
+
+    #[test]
+    fn test_end_of_flags() {
+        let parsed = parser().parse(&["-a", "--", "-F", "--lines"]).unwrap();
+        assert!(parsed.has("all"));
+        assert!(!parsed.has("classify"));
+        assert_eq!(parsed.positional, ["-F", "--lines"]);
+    }
+
+    #[test]
+    fn test_invalid_flags() {
+        assert_eq!(
+            parser().parse(&["-x"]),
+            Err(ArgsError::UnknownFlag(String::from("-x")))
+        );
+        assert_eq!(
+            parser().parse(&["-ax"]),
+            Err(ArgsError::UnknownFlag(String::from("-x")))
+        );
+        assert_eq!(
+            parser().parse(&["--lines"]),
+            Err(ArgsError::MissingValue(String::from("--lines")))
+        );
+        assert_eq!(
+            parser().parse(&["--all=yes"]),
+            Err(ArgsError::UnexpectedValue(String::from("--all")))
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// Evaluates the integer expressions inside $((...)), with the usual precedence:
+//   expression = term (('+' | '-') term)*
+//   term       = factor (('*' | '/' | '%') factor)*
+//   factor     = ('+' | '-') factor | number | variable | '(' expression ')'
+
+use crate::errors::ArithmeticError;
+
+// How deeply parentheses and unary signs can nest, so that a long run of them can't overflow the stack
+const MAX_NESTING: usize = 256;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Token {
+    Number(i64),
+    Operator(char),
+}
+
+// Evaluates an integer expression, looking up variables (written as NAME, $NAME or ${NAME}) with a function
+// Unset and empty variables count as 0, and an empty expression is 0 as well
+pub fn evaluate(
+    expression: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<i64, ArithmeticError> {
+    let tokens = tokenize(expression, lookup)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.expression()?;
+
+    match parser.next() {
+        Some((_, text)) => Err(ArithmeticError::UnexpectedToken(text.to_string())),
+        None => Ok(value),
+    }
+}
+
+// Splits an expression into numbers and operators, along with the text each came from
+// Variables are replaced with their values here, so the parser only sees numbers
+fn tokenize<'a>(
+    expression: &'a str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<(Token, &'a str)>, ArithmeticError> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = match c {
+            '0'..='9' => rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len()),
+            '$' => match rest[1..].chars().next() {
+                Some('{') => rest.find('}').map_or(rest.len(), |end| end + 1),
+                Some('?' | '#' | '0'..='9') => 2,
+                _ => {
+                    1 + rest[1..]
+                        .find(|c: char| !is_name_char(c))
+                        .unwrap_or(rest.len() - 1)
+                }
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len())
+            }
+            _ => c.len_utf8(),
+        };
+        let (text, remaining) = rest.split_at(length);
+
+        let token = match c {
+            '0'..='9' => Token::Number(parse_number(text)?),
+            '+' | '-' | '*' | '/' | '%' | '(' | ')' => Token::Operator(c),
+            '$' | 'a'..='z' | 'A'..='Z' | '_' => {
+                let name = text.trim_start_matches('$');
+                let name = match name.strip_prefix('{') {
+                    Some(name) => name
+                        .strip_suffix('}')
+                        .ok_or(ArithmeticError::UnexpectedEnd)?,
+                    None => name,
+                };
+                if name.is_empty() {
+                    return Err(ArithmeticError::UnexpectedToken(text.to_string()));
+                }
+                Token::Number(variable_value(name, lookup)?)
+            }
+            _ => return Err(ArithmeticError::UnexpectedToken(text.to_string())),
+        };
+
+        tokens.push((token, text));
+        rest = remaining.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Result<i64, ArithmeticError> {
+    if !text.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ArithmeticError::UnexpectedToken(text.to_string()));
+    }
+
+    text.parse().map_err(|_| ArithmeticError::Overflow)
+}
+
+fn variable_value(
+    name: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<i64, ArithmeticError> {
+    let value = lookup(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let number = match parse_number(digits) {
+        Ok(number) => number,
+        Err(ArithmeticError::Overflow) => return Err(ArithmeticError::Overflow),
+        Err(_) => return Err(ArithmeticError::NotAnInteger(name.to_string())),
+    };
+
+    Ok(if negative { -number } else { number })
+}
+
+struct Parser<'t, 'a> {
+    tokens: &'t [(Token, &'a str)],
+    position: usize,
+    // How many factors are being parsed inside each other
+    depth: usize,
+}
+
+impl<'a> Parser<'_, 'a> {
+    fn next(&mut self) -> Option<(Token, &'a str)> {
+        let token = self.tokens.get(self.position).copied();
+        self.position += 1;
+        token
+    }
+
+    // Moves past the next token if it's one of the operators
+    fn next_operator(&mut self, operators: &str) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some((Token::Operator(c), _)) if operators.contains(*c) => {
+                self.position += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expression(&mut self) -> Result<i64, ArithmeticError> {
+        let mut value = self.term()?;
+        while let Some(operator) = self.next_operator("+-") {
+            let right = self.term()?;
+            value = match operator {
+                '+' => value.checked_add(right),
+                _ => value.checked_sub(right),
+            }
+            .ok_or(ArithmeticError::Overflow)?;
+        }
+
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<i64, ArithmeticError> {
+        let mut value = self.factor()?;
+        while let Some(operator) = self.next_operator("*/%") {
+            let right = self.factor()?;
+            if operator != '*' && right == 0 {
+                return Err(ArithmeticError::DivisionByZero);
+            }
+
+            value = match operator {
+                '*' => value.checked_mul(right),
+                '/' => value.checked_div(right),
+                _ => value.checked_rem(right),
+            }
+            .ok_or(ArithmeticError::Overflow)?;
+        }
+
+        Ok(value)
+    }
+
+    fn factor(&mut self) -> Result<i64, ArithmeticError> {
+        if self.depth == MAX_NESTING {
+            return Err(ArithmeticError::TooDeep);
+        }
+
+        self.depth += 1;
+        let value = self.unnested_factor();
+        self.depth -= 1;
+        value
+    }
+
+    fn unnested_factor(&mut self) -> Result<i64, ArithmeticError> {
+        match self.next() {
+            Some((Token::Number(number), _)) => Ok(number),
+            Some((Token::Operator('+'), _)) => self.factor(),
+            Some((Token::Operator('-'), _)) => self
+                .factor()?
+                .checked_neg()
+                .ok_or(ArithmeticError::Overflow),
+            Some((Token::Operator('('), _)) => {
+                let value = self.expression()?;
+                match self.next() {
+                    Some((Token::Operator(')'), _)) => Ok(value),
+                    Some((_, text)) => Err(ArithmeticError::UnexpectedToken(text.to_string())),
+                    None => Err(ArithmeticError::UnexpectedEnd),
+                }
+            }
+            Some((_, text)) => Err(ArithmeticError::UnexpectedToken(text.to_string())),
+            None => Err(ArithmeticError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str) -> Result<i64, ArithmeticError> {
+        let lookup = |name: &str| match name {
+            "x" => Some(String::from("6")),
+            "NEGATIVE" => Some(String::from("-4")),
+            "EMPTY" => Some(String::new()),
+            "WORD" => Some(String::from("abc")),
+            "1" => Some(String::from("10")),
+            _ => None,
+        };
+        super::evaluate(expression, &lookup)
+    }
+
+    #[test]
+    fn test_evaluate_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok(3));
+        assert_eq!(evaluate("2 * 3 + 4 * 5"), Ok(26));
+        assert_eq!(evaluate("20 / 3 % 4"), Ok(2));
+        assert_eq!(evaluate("7 - -2"), Ok(9));
+        assert_eq!(evaluate("-3 * -3"), Ok(9));
+        assert_eq!(evaluate("1+2*3-4/2"), Ok(5));
+    }
+
+    #[test]
+    fn test_evaluate_parentheses() {
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("2 * (3 + (4 - 1)) / 3"), Ok(4));
+        assert_eq!(evaluate("-(2 + 3)"), Ok(-5));
+        assert_eq!(evaluate("((7))"), Ok(7));
+        assert_eq!(evaluate("(1 + 2"), Err(ArithmeticError::UnexpectedEnd));
+        assert_eq!(
+            evaluate("1 + 2)"),
+            Err(ArithmeticError::UnexpectedToken(String::from(")")))
+        );
+        assert_eq!(
+            evaluate("()"),
+            Err(ArithmeticError::UnexpectedToken(String::from(")")))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_variables() {
+        assert_eq!(evaluate("x * 2"), Ok(12));
+        assert_eq!(evaluate("$x + ${x}"), Ok(12));
+        assert_eq!(evaluate("x + NEGATIVE"), Ok(2));
+        assert_eq!(evaluate("$1 / 2"), Ok(5));
+        // Unset and empty variables count as 0
+        assert_eq!(evaluate("UNSET + EMPTY + 1"), Ok(1));
+        assert_eq!(
+            evaluate("WORD + 1"),
+            Err(ArithmeticError::NotAnInteger(String::from("WORD")))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert_eq!(evaluate(""), Ok(0));
+        assert_eq!(evaluate("  42 "), Ok(42));
+        assert_eq!(evaluate("1 / 0"), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(
+            evaluate("5 % (x - 6)"),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(evaluate("1 +"), Err(ArithmeticError::UnexpectedEnd));
+        assert_eq!(
+            evaluate("2 3"),
+            Err(ArithmeticError::UnexpectedToken(String::from("3")))
+        );
+        assert_eq!(
+            evaluate("2 ^ 3"),
+            Err(ArithmeticError::UnexpectedToken(String::from("^")))
+        );
+        assert_eq!(
+            evaluate("12abc"),
+            Err(ArithmeticError::UnexpectedToken(String::from("12abc")))
+        );
+        assert_eq!(
+            evaluate("99999999999999999999"),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            evaluate("9223372036854775807 + 1"),
+            Err(ArithmeticError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_nesting() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate(&nested(100)), Ok(1));
+        assert_eq!(evaluate(&nested(10_000)), Err(ArithmeticError::TooDeep));
+        assert_eq!(evaluate(&"(".repeat(10_000)), Err(ArithmeticError::TooDeep));
+        assert_eq!(evaluate(&format!("{}1", "-".repeat(100))), Ok(1));
+        assert_eq!(
+            evaluate(&format!("{}1", "- ".repeat(10_000))),
+            Err(ArithmeticError::TooDeep)
+        );
+    }
+}
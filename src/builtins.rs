@@ -9,37 +9,243 @@ You may notice that builtin commands are referenced in commands::Runnable::Inter
 An 'External' will only have access to its arguments and environment variables, but not the shell's state, mostly for security reasons.
  */
 
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{self, stderr, stdin, stdout, BufRead, BufReader, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
 
 use colored::Colorize;
 
-use crate::commands::{Context, StatusCode};
+use crate::archive::{self, Archive, ArchiveView};
+use crate::args::FlagParser;
+use crate::commands::{
+    exec_external, run_external, run_external_with_env, search_commands, Command, Context,
+    StatusCode,
+};
+use crate::config;
+use crate::datetime::{self, DateTime};
+use crate::disk;
+use crate::editor;
+use crate::errors::ShellError;
+use crate::expansion;
+use crate::frecency;
+use crate::git;
+use crate::history::{self, ExportFormat};
+use crate::jobs::{self, JobState};
+use crate::parser;
 use crate::path;
+use crate::script;
+use crate::shell::LoopControl;
+use crate::trash;
 
+// Evaluates a conditional expression, succeeding if it is true
 pub fn test(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        println!("{}", "Test command!".yellow());
-        StatusCode::success()
-    } else {
-        eprintln!("Usage: test");
-        StatusCode::new(1)
+    condition_status(evaluate_condition(&args))
+}
+
+// Variant of test that requires the expression to be closed with a ']' ('[ -f file ]')
+pub fn bracket(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args.split_last() {
+        Some((&"]", expression)) => condition_status(evaluate_condition(expression)),
+        _ => {
+            eprintln!("Missing closing ']'");
+            StatusCode::new(2)
+        }
     }
 }
 
-pub fn exit(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        std::process::exit(0);
-    } else {
-        eprintln!("Usage: exit");
-        StatusCode::new(1)
+// Checks a test-style condition in a script, printing a message and failing if it is false
+// The last argument is taken as the message when the condition doesn't make sense with it ('assert -f config.txt "config missing"')
+pub fn assert(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        eprintln!("Usage: assert <condition...> [message]");
+        return StatusCode::new(1);
+    }
+
+    let (result, message) = match (evaluate_condition(&args), args.split_last()) {
+        (Err(_), Some((message, condition))) if evaluate_condition(condition).is_ok() => {
+            (evaluate_condition(condition), message.to_string())
+        }
+        (result, _) => (result, args.join(" ")),
+    };
+
+    match result {
+        Ok(true) => StatusCode::success(),
+        Ok(false) => {
+            eprintln!("Assertion failed: {}", message);
+            StatusCode::new(1)
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            StatusCode::new(2)
+        }
+    }
+}
+
+// Converts the result of a condition into a status code (0 if true, 1 if false, 2 if invalid)
+fn condition_status(result: Result<bool, String>) -> StatusCode {
+    match result {
+        Ok(true) => StatusCode::success(),
+        Ok(false) => StatusCode::new(1),
+        Err(message) => {
+            eprintln!("{}", message);
+            StatusCode::new(2)
+        }
+    }
+}
+
+// Evaluates a test-style conditional expression
+// Supports file checks (-e, -f, -d, -L, -s), string checks (-z, -n, =, !=),
+// integer comparisons (-eq, -ne, -lt, -le, -gt, -ge), and negation with a leading '!'
+pub fn evaluate_condition(args: &[&str]) -> Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        // As in POSIX test, three arguments around a binary operator are always a comparison, so '! = !' compares
+        [left, operator, right] if is_binary_operator(operator) => {
+            evaluate_binary(left, operator, right)
+        }
+        ["!", rest @ ..] => evaluate_condition(rest).map(|result| !result),
+        // A lone string is true as long as it isn't empty
+        [string] => Ok(!string.is_empty()),
+        [operator, operand] => evaluate_unary(operator, operand),
+        [left, operator, right] => evaluate_binary(left, operator, right),
+        _ => Err(String::from("Too many arguments")),
+    }
+}
+
+fn evaluate_unary(operator: &str, operand: &str) -> Result<bool, String> {
+    let metadata = || fs::metadata(operand);
+
+    match operator {
+        "-e" => Ok(metadata().is_ok()),
+        "-f" => Ok(metadata().is_ok_and(|m| m.is_file())),
+        "-d" => Ok(metadata().is_ok_and(|m| m.is_dir())),
+        "-s" => Ok(metadata().is_ok_and(|m| m.len() > 0)),
+        "-L" => Ok(fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink())),
+        "-z" => Ok(operand.is_empty()),
+        "-n" => Ok(!operand.is_empty()),
+        _ => Err(format!("Unknown unary operator: '{}'", operator)),
+    }
+}
+
+fn is_binary_operator(operator: &str) -> bool {
+    matches!(
+        operator,
+        "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"
+    )
+}
+
+fn evaluate_binary(left: &str, operator: &str, right: &str) -> Result<bool, String> {
+    let parse = |operand: &str| {
+        operand
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| format!("Integer expected: '{}'", operand))
+    };
+
+    match operator {
+        "=" | "==" => Ok(left == right),
+        "!=" => Ok(left != right),
+        "-eq" => Ok(parse(left)? == parse(right)?),
+        "-ne" => Ok(parse(left)? != parse(right)?),
+        "-lt" => Ok(parse(left)? < parse(right)?),
+        "-le" => Ok(parse(left)? <= parse(right)?),
+        "-gt" => Ok(parse(left)? > parse(right)?),
+        "-ge" => Ok(parse(left)? >= parse(right)?),
+        _ => Err(format!("Unknown binary operator: '{}'", operator)),
+    }
+}
+
+// Exits the shell, first warning about any running jobs (which a second 'exit' straight after, or -f, ignores)
+pub fn exit(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let force = match args[..] {
+        [] => false,
+        ["-f" | "--force"] => true,
+        _ => {
+            eprintln!("Usage: exit [-f]");
+            return StatusCode::new(1);
+        }
+    };
+
+    let running = context.shell.jobs.running_count();
+    let interactive = context.shell.is_interactive();
+    if let Some(warning) = exit_warning(running, interactive, context.shell.exit_pending, force) {
+        eprintln!("{}", warning);
+        context.shell.exit_pending = true;
+        return StatusCode::new(1);
+    }
+
+    context.shell.shutdown();
+    std::process::exit(0);
+}
+
+// Gets the warning 'exit' gives instead of exiting when jobs are still running
+// Returns None if the shell should exit: there are no running jobs, the warning was already given, or -f was used
+// Scripts always exit straight away, since there's nobody to answer the warning
+pub fn exit_warning(
+    running_jobs: usize,
+    interactive: bool,
+    pending: bool,
+    force: bool,
+) -> Option<String> {
+    match running_jobs {
+        _ if !interactive || pending || force => None,
+        0 => None,
+        1 => Some(String::from("There is 1 running job")),
+        count => Some(format!("There are {} running jobs", count)),
+    }
+}
+
+// Registers a command line to run when the shell exits ('trap <command> EXIT'), or lists the traps
+// 'trap - EXIT' removes them again; EXIT (or 0) is the only condition supported so far
+pub fn trap(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args[..] {
+        [] => {
+            for command in &context.shell.exit_traps {
+                println!("trap -- {} EXIT", parser::quote(command));
+            }
+            StatusCode::success()
+        }
+        ["-", "EXIT" | "0"] => {
+            context.shell.exit_traps.clear();
+            StatusCode::success()
+        }
+        [command, "EXIT" | "0"] => {
+            context.shell.exit_traps.push(command.to_string());
+            StatusCode::success()
+        }
+        [_, condition] => {
+            eprintln!("Unsupported trap condition: '{}'", condition);
+            StatusCode::new(2)
+        }
+        _ => {
+            eprintln!("Usage: trap [<command | -> EXIT]");
+            StatusCode::new(1)
+        }
     }
 }
 
+// Always succeeds, ignoring any arguments (useful as a placeholder in conditional chains)
+pub fn r#true(_context: &mut Context, _args: Vec<&str>) -> StatusCode {
+    StatusCode::success()
+}
+
+// Always fails, ignoring any arguments
+pub fn r#false(_context: &mut Context, _args: Vec<&str>) -> StatusCode {
+    StatusCode::new(1)
+}
+
 pub fn working_directory(context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        println!("{}", context.cwd());
+    if args.is_empty() {
+        match &context.env().archive {
+            Some(view) => println!("{}", view.virtual_path().display()),
+            None => println!("{}", context.cwd()),
+        }
         StatusCode::success()
     } else {
         eprintln!("Usage: working-directory");
@@ -48,63 +254,480 @@ pub fn working_directory(context: &mut Context, args: Vec<&str>) -> StatusCode {
 }
 
 pub fn change_directory(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    // -P resolves symlinks in the new path, while -L (the default) keeps them
+    let physical = args.first() == Some(&"-P");
+    let args = match args.first() {
+        Some(&("-L" | "-P")) => args[1..].to_vec(),
+        _ => args,
+    };
+
+    // 'cd --' lists the recent directories, 'cd -' goes back to the previous one and 'cd -N' jumps to one of them
+    if args == ["--"] {
+        return recent_directories(context, Vec::new());
+    }
+
+    if args == ["-"] {
+        return go_back(context, Vec::new());
+    }
+
+    if let Some(number) = args
+        .first()
+        .and_then(|arg| arg.strip_prefix('-'))
+        .and_then(|n| n.parse().ok())
+    {
+        if args.len() == 1 {
+            return jump_to_recent_directory(context, number);
+        }
+    }
+
+    // -p creates the directory (along with any missing parents) before changing into it
+    if let ["-p" | "--create", path] = args[..] {
+        if path::resolve(path, context.home()).is_none() {
+            let created = path::expand_home(path, context.home())
+                .map_err(|_| ())
+                .and_then(|expanded| fs::create_dir_all(expanded).map_err(|_| ()));
+            if created.is_err() {
+                eprintln!("Failed to create directory: '{}'", path);
+                return StatusCode::new(3);
+            }
+        }
+
+        return match physical {
+            true => change_directory(context, vec!["-P", path]),
+            false => change_directory(context, vec![path]),
+        };
+    }
+
     if args.len() == 1 {
-        match context.env_mut().set_path(args[0]) {
-            Ok(_) => {
-                // ! This might be better to have happen automatically
-                context.env_mut().update_process_env_vars();
-                StatusCode::success()
+        let mut path = args[0].to_string();
+
+        // '@name' (or '@name/sub/dir') starts from a bookmarked directory
+        if let Some(bookmark) = path.strip_prefix('@') {
+            match resolve_bookmark(&context.shell.bookmarks, bookmark) {
+                Some(bookmarked) => path = bookmarked.to_string_lossy().to_string(),
+                None => {
+                    eprintln!("Unknown bookmark: '{}'", args[0]);
+                    return StatusCode::new(2);
+                }
+            }
+        }
+
+        // Inside an archive, relative paths move around the archive until they go above its top
+        if let Some(view) = &mut context.env_mut().archive {
+            match view.locate(&path) {
+                archive::Location::Inside(directory) if view.archive.is_directory(&directory) => {
+                    view.directory = directory;
+                    return StatusCode::success();
+                }
+                archive::Location::Inside(_) => {
+                    eprintln!("Invalid path: '{}'", args[0]);
+                    return StatusCode::new(2);
+                }
+                archive::Location::Outside(outside) => path = outside,
+            }
+        }
+
+        // Names that aren't an existing path are looked up in $CDPATH (unless they are explicitly relative)
+        // Hidden names like '.config' are still looked up, so only '.' and '..' themselves count as relative
+        let explicit = ["/", "~", "./", "../"]
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+            || path == "."
+            || path == "..";
+        if !explicit && path::resolve(&path, context.home()).is_none() {
+            let cdpath = context.env().get_variable("CDPATH").unwrap_or_default();
+            if let Some(found) = path::search_cdpath(&path, &cdpath, context.home()) {
+                // Like other shells, print the directory that was found
+                println!("{}", found.display());
+                path = found.to_string_lossy().to_string();
+            }
+        }
+
+        // A tar archive can be browsed as a read-only directory, from the directory that holds it
+        if let Some(file) =
+            path::resolve(&path, context.home()).filter(|p| archive::is_archive_path(p))
+        {
+            let archive = match Archive::open(&file) {
+                Ok(archive) => archive,
+                Err(_) => {
+                    eprintln!("Failed to read archive: '{}'", args[0]);
+                    return StatusCode::new(3);
+                }
+            };
+
+            let parent = file
+                .parent()
+                .unwrap_or(Path::new("/"))
+                .to_string_lossy()
+                .to_string();
+            if context.shell.change_directory(&parent, physical).is_err() {
+                eprintln!("Invalid path: '{}'", args[0]);
+                return StatusCode::new(2);
             }
+
+            context.env_mut().archive = Some(ArchiveView::new(archive));
+            return StatusCode::success();
+        }
+
+        match context.shell.change_directory(&path, physical) {
+            Ok(_) => StatusCode::success(),
             Err(_) => {
                 eprintln!("Invalid path: '{}'", args[0]);
                 StatusCode::new(2)
             }
         }
     } else {
-        eprintln!("Usage: change-directory <path>");
+        eprintln!("Usage: change-directory [-L | -P] [-p] <path>");
         StatusCode::new(1)
     }
 }
 
+// Changes to the most frequently and recently visited directory whose path contains every word given
+// Directories that no longer exist are forgotten, and the working directory itself is skipped
+pub fn jump(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        eprintln!("Usage: jump <words...>");
+        return StatusCode::new(1);
+    }
+
+    let current = context.cwd().absolute().clone();
+    let ranked: Vec<PathBuf> = frecency::rank(
+        context.shell.directories.directories(),
+        &args,
+        frecency::now(),
+    )
+    .into_iter()
+    .filter(|path| *path != current)
+    .map(Path::to_path_buf)
+    .collect();
+
+    for directory in ranked {
+        if !directory.is_dir() {
+            context.shell.directories.remove(&directory);
+            continue;
+        }
+
+        if context
+            .shell
+            .change_directory(&directory.to_string_lossy(), false)
+            .is_ok()
+        {
+            return StatusCode::success();
+        }
+    }
+
+    eprintln!("No visited directory matches: '{}'", args.join(" "));
+    StatusCode::new(2)
+}
+
+// Lists the recently visited directories, numbered for use with 'cd -N'
+pub fn recent_directories(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: recent-directories");
+        return StatusCode::new(1);
+    }
+
+    let recent = context.env().recent_directories();
+    if recent.is_empty() {
+        eprintln!("No recent directories available");
+        return StatusCode::new(2);
+    }
+
+    for (index, directory) in recent.iter().enumerate() {
+        println!("{:>3}  {}", index + 1, directory.display());
+    }
+
+    StatusCode::success()
+}
+
+fn jump_to_recent_directory(context: &mut Context, number: usize) -> StatusCode {
+    let directory = match context.env().recent_directories().get(number) {
+        Some(directory) => directory.to_string_lossy().to_string(),
+        None => {
+            eprintln!("No recent directory with number {}", number);
+            return StatusCode::new(2);
+        }
+    };
+
+    match context.shell.change_directory(&directory, false) {
+        Ok(_) => StatusCode::success(),
+        // The directory may have been deleted since it was visited
+        Err(_) => {
+            eprintln!("Directory no longer exists: '{}'", directory);
+            StatusCode::new(3)
+        }
+    }
+}
+
 // TODO: Break up some of this code into different functions
 pub fn list_directory(context: &mut Context, args: Vec<&str>) -> StatusCode {
-    let files_and_directories = match args.len() {
+    let usage =
+        "Usage: list-directory [-1] [-F] [-L] [-R] [--git] [--summary] [-l [-h] [--relative-time]] [--ignore <pattern>]... <path>";
+    let parsed = match FlagParser::new()
+        .flag("one-per-line", &["-1"])
+        .flag("recursive", &["-R", "--recursive"])
+        .flag("dereference", &["-L", "--dereference"])
+        .flag("long", &["-l"])
+        .flag("relative-time", &["--relative-time"])
+        .flag("human", &["-h", "--human"])
+        .flag("summary", &["--summary"])
+        .flag("classify", &["-F", "--classify"])
+        .flag("git", &["--git"])
+        .option("ignore", &["-I", "--ignore"])
+        .parse(&args)
+    {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("{}\n{}", error, usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    // -F marks executables with '*' and symlinks with '@' (directories always end with '/')
+    let classify = parsed.has("classify");
+    // -L follows symlinks to directories, listing what's inside them rather than the links themselves
+    let dereference = parsed.has("dereference");
+    // --git marks files that aren't tracked, when the directory is inside a git repository
+    let git = parsed.has("git");
+    // -R lists every directory beneath the path as well, each under a header like 'ls -R'
+    let recursive = parsed.has("recursive");
+    // -l shows when each entry was modified, as a timestamp or (with --relative-time) like '3 days ago'
+    let times = match (parsed.has("long"), parsed.has("relative-time")) {
+        (true, false) => Some(TimeStyle::Absolute),
+        (true, true) => Some(TimeStyle::Relative(SystemTime::now())),
+        (false, false) => None,
+        (false, true) => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+    // -l also shows the size of each entry, in bytes or (with -h) like '1.5K'
+    let sizes = match (parsed.has("long"), parsed.has("human")) {
+        (true, false) => Some(SizeStyle::Bytes),
+        (true, true) => Some(SizeStyle::Human),
+        (false, false) => None,
+        (false, true) => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+    // Entries are laid out in columns in a terminal, unless -1 or -l asks for one per line
+    let is_terminal = stdout().is_terminal();
+    let one_per_line = parsed.has("one-per-line") || times.is_some() || !is_terminal;
+    // --summary ends the listing with the number of directories and files, but only in a terminal,
+    // so that piped output is just the names
+    let summary = parsed.has("summary") && is_terminal;
+    // --ignore leaves out names matching a glob pattern, and can be given more than once
+    let ignore: Vec<String> = parsed
+        .values("ignore")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let real_path;
+    let mut args = parsed.positional;
+
+    // Inside an archive, relative paths are listed from the archive rather than the real filesystem
+    if let (Some(view), 0 | 1) = (&context.env().archive, args.len()) {
+        let path = args.first().copied().unwrap_or(".");
+        match view.locate(path) {
+            archive::Location::Inside(_) if recursive => {
+                eprintln!("Recursive listings aren't supported inside archives");
+                return StatusCode::new(1);
+            }
+            archive::Location::Inside(directory) => {
+                return match archive_listing(view, &directory, &ignore) {
+                    Some((directories, files)) => {
+                        print_listing(directories, files, one_per_line, summary)
+                    }
+                    None => {
+                        eprintln!("Invalid path: '{}'", path);
+                        StatusCode::new(2)
+                    }
+                };
+            }
+            archive::Location::Outside(outside) => {
+                real_path = outside;
+                args = vec![real_path.as_str()];
+            }
+        }
+    }
+
+    // A symlink to a directory is shown as an entry of its own (unless it ends with '/')
+    if let (false, [link]) = (dereference, &args[..]) {
+        let expanded = path::expand_home(link, context.home()).unwrap_or_default();
+        if let Some(target) = symlink_target(&expanded).filter(|_| Path::new(&expanded).is_dir()) {
+            let entry = format!("{} -> {}", link, target.display());
+            return print_listing(Vec::new(), vec![entry], one_per_line, summary);
+        }
+    }
+
+    let absolute_path = match args.len() {
         // Use the working directory as the default path argument
         // This uses expect() because it needs to crash if the working directory is invalid,
         // though in the future the error should be handled properly
-        0 => fs::read_dir(env::current_dir().expect("Failed to get working directory"))
-            .expect("Failed to read directory"),
-        1 => {
-            // Path::from_str_path() will attempt to expand and canonicalize the path, and return None if the path does not exist
-            let absolute_path = match path::resolve(args[0], context.home()) {
-                Some(path) => path,
-                None => {
-                    eprintln!("Invalid path: '{}'", args[0]);
-                    return StatusCode::new(2);
-                }
-            };
-
-            match fs::read_dir(&absolute_path) {
-                Ok(files_and_directories) => files_and_directories,
-                Err(_) => {
-                    eprintln!(
-                        "Failed to read directory: '{}'",
-                        absolute_path.to_string_lossy().to_string()
-                    );
-                    return StatusCode::new(3);
-                }
+        0 => env::current_dir().expect("Failed to get working directory"),
+        // Path::from_str_path() will attempt to expand and canonicalize the path, and return None if the path does not exist
+        1 => match path::resolve(args[0], context.home()) {
+            Some(path) => path,
+            None => {
+                eprintln!("Invalid path: '{}'", args[0]);
+                return StatusCode::new(2);
             }
-        }
+        },
         _ => {
-            eprintln!("Usage: list-directory <path>");
+            eprintln!("{}", usage);
             return StatusCode::new(1);
         }
     };
 
+    let repository = match git {
+        true => git::Repository::discover(&absolute_path),
+        false => None,
+    };
+
+    let options = ListingOptions {
+        classify,
+        dereference,
+        sizes,
+        times,
+        ignore,
+    };
+
+    if recursive {
+        let label = args.first().copied().unwrap_or(".");
+        let mut status = StatusCode::success();
+        let mut first = true;
+
+        // Each directory is printed as soon as it's read, rather than after the whole tree
+        recursive_listing(
+            &absolute_path,
+            label,
+            &options,
+            repository.as_ref(),
+            &mut |header, entries| {
+                if !first {
+                    println!();
+                }
+                first = false;
+                println!("{}:", header);
+                match entries {
+                    Ok((directories, files)) => {
+                        print_listing(directories, files, one_per_line, summary);
+                    }
+                    Err(_) => {
+                        eprintln!("Failed to read directory: '{}'", header);
+                        status = StatusCode::new(3);
+                    }
+                }
+            },
+        );
+
+        return status;
+    }
+
+    match listing_entries(&absolute_path, &options, repository.as_ref()) {
+        Ok((directories, files)) => print_listing(directories, files, one_per_line, summary),
+        Err(_) => {
+            eprintln!(
+                "Failed to read directory: '{}'",
+                absolute_path.to_string_lossy()
+            );
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Prints the names for 'list-directory', directories first, with an optional summary footer
+fn print_listing(
+    mut directories: Vec<String>,
+    mut files: Vec<String>,
+    one_per_line: bool,
+    summary: bool,
+) -> StatusCode {
+    let footer = summary.then(|| listing_summary(directories.len(), files.len()));
+    directories.append(&mut files);
+
+    let lines = match one_per_line {
+        true => directories,
+        false => columnize(&directories, editor::terminal_width().unwrap_or(80)),
+    };
+
+    for line in lines {
+        println!("{}", line);
+    }
+
+    if let Some(footer) = footer {
+        println!("{}", footer.dimmed());
+    }
+
+    StatusCode::success()
+}
+
+// How 'list-directory -l' shows modification times
+#[derive(Debug, Clone, Copy)]
+enum TimeStyle {
+    Absolute,
+    // Relative to the time the listing started
+    Relative(SystemTime),
+}
+
+impl TimeStyle {
+    fn format(self, modified: SystemTime) -> String {
+        match self {
+            Self::Absolute => DateTime::from_system_time(modified).format("%Y-%m-%d %H:%M"),
+            Self::Relative(now) => datetime::relative_time(modified, now),
+        }
+    }
+}
+
+// How 'list-directory -l' shows sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SizeStyle {
+    Bytes,
+    // Like '1.5K' or '23M'
+    Human,
+}
+
+impl SizeStyle {
+    fn format(self, bytes: u64) -> String {
+        disk::format_bytes(bytes, self == Self::Human)
+    }
+}
+
+// How 'list-directory' shows the entries of a directory
+#[derive(Debug, Default)]
+struct ListingOptions {
+    // Add -F indicators to files
+    classify: bool,
+    // Show symlinks to directories as directories, and use the modification times of what links point to
+    dereference: bool,
+    // Precede each name with its size, right-aligned in a column
+    sizes: Option<SizeStyle>,
+    // Precede each name with when it was last modified
+    times: Option<TimeStyle>,
+    // Glob patterns for names to leave out
+    ignore: Vec<String>,
+}
+
+// The names in a directory, as directories and then files
+type Listing = (Vec<String>, Vec<String>);
+
+// Checks whether a name is left out of a listing, either because it's hidden or it matches an --ignore pattern
+fn is_listing_hidden(name: &str, ignore: &[String]) -> bool {
+    name.starts_with('.') || ignore.iter().any(|pattern| path::glob_match(pattern, name))
+}
+
+// Reads the names in a directory for 'list-directory', split into directories and files and sorted
+// Hidden names are skipped, and names are decorated with colors, -F indicators and git annotations
+fn listing_entries(
+    absolute_path: &Path,
+    options: &ListingOptions,
+    repository: Option<&git::Repository>,
+) -> io::Result<Listing> {
     let mut directories = Vec::new();
     let mut files = Vec::new();
 
-    for fd in files_and_directories {
+    for fd in fs::read_dir(absolute_path)? {
         let fd = fd.expect("Failed to read directory");
 
         let fd_name = fd
@@ -114,304 +737,8173 @@ pub fn list_directory(context: &mut Context, args: Vec<&str>) -> StatusCode {
             .to_string();
 
         // TODO: Add a flag to show hidden files
-        if fd_name.starts_with('.') {
+        if is_listing_hidden(&fd_name, &options.ignore) {
+            continue;
+        }
+
+        let annotation = match repository.and_then(|r| r.status(&fd.path())) {
+            Some(git::GitStatus::Untracked) => format!(" {}", "(untracked)".red()),
+            _ => String::new(),
+        };
+
+        // DirEntry::metadata() doesn't follow symlinks, while fs::metadata() does
+        let metadata = match options.dereference {
+            true => fs::metadata(fd.path()),
+            false => fd.metadata(),
+        };
+
+        let size = match (options.sizes, &metadata) {
+            (Some(style), Ok(metadata)) => style.format(metadata.len()),
+            (Some(_), Err(_)) => String::from("?"),
+            (None, _) => String::new(),
+        };
+        let modified = match options.times {
+            Some(style) => match metadata.as_ref().map(|metadata| metadata.modified()) {
+                Ok(Ok(modified)) => format!("{:<16}  ", style.format(modified)),
+                _ => format!("{:<16}  ", "?"),
+            },
+            None => String::new(),
+        };
+        let is_directory = metadata.is_ok_and(|metadata| metadata.is_dir());
+
+        // Entries are paired with their names, so that they sort by name rather than by time
+        // Sizes are kept apart until the column width is known
+        if is_directory {
+            // Append a '/' to directories
+            let decorated = format!("{}/", fd_name).bright_green().to_string();
+            let line = format!("{}{}{}", modified, decorated, annotation);
+            directories.push((fd_name, size, line))
+        } else if options.classify {
+            let indicator = file_indicator(&fd.path())
+                .map(String::from)
+                .unwrap_or_default();
+            let line = format!("{}{}{}{}", modified, fd_name, indicator, annotation);
+            files.push((fd_name, size, line))
+        } else {
+            let line = format!("{}{}{}", modified, fd_name, annotation);
+            files.push((fd_name, size, line))
+        };
+    }
+
+    directories.sort();
+    files.sort();
+    let width = directories
+        .iter()
+        .chain(&files)
+        .map(|(_, size, _)| size.len())
+        .max()
+        .unwrap_or(0);
+    let lines = |entries: Vec<(String, String, String)>| {
+        entries
+            .into_iter()
+            .map(|(_, size, line)| match options.sizes {
+                Some(_) => format!("{:>width$}  {}", size, line, width = width),
+                None => line,
+            })
+            .collect()
+    };
+    Ok((lines(directories), lines(files)))
+}
+
+// Reads a directory and every visible directory beneath it for 'list-directory -R', in the order 'ls -R'
+// shows them, passing each to a function with a header naming the directory (starting from the label given for the root)
+// Hidden and ignored directories aren't descended into, and symlinks to directories only with -L
+fn recursive_listing(
+    root: &Path,
+    label: &str,
+    options: &ListingOptions,
+    repository: Option<&git::Repository>,
+    section: &mut dyn FnMut(String, io::Result<Listing>),
+) {
+    let is_listed = |directory: &Path| {
+        let name = directory.file_name().unwrap_or_default().to_string_lossy();
+        let is_symlink = fs::symlink_metadata(directory).is_ok_and(|m| m.file_type().is_symlink());
+        directory.is_dir()
+            && (options.dereference || !is_symlink)
+            && !is_listing_hidden(&name, &options.ignore)
+    };
+
+    path::walk_each(root, options.dereference, &mut |directory| {
+        if directory != root && !is_listed(directory) {
+            return false;
+        }
+
+        let header = match directory.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => {
+                Path::new(label).join(relative).display().to_string()
+            }
+            _ => label.to_string(),
+        };
+        section(header, listing_entries(directory, options, repository));
+        true
+    });
+}
+
+// Reads the names in a directory of an archive for 'list-directory', decorated like listing_entries()
+// Returns None if the path isn't a directory in the archive
+fn archive_listing(view: &ArchiveView, directory: &str, ignore: &[String]) -> Option<Listing> {
+    let (directories, files) = view.archive.list(directory)?;
+    let visible = |name: &String| !is_listing_hidden(name, ignore);
+
+    Some((
+        directories
+            .iter()
+            .filter(|name| visible(name))
+            .map(|name| format!("{}/", name).bright_green().to_string())
+            .collect(),
+        files.into_iter().filter(visible).collect(),
+    ))
+}
+
+// Describes how many directories and files a listing has, like '3 directories, 1 file'
+pub fn listing_summary(directories: usize, files: usize) -> String {
+    let plural = |count: usize, singular: &str, plural: &str| match count {
+        1 => format!("1 {}", singular),
+        count => format!("{} {}", count, plural),
+    };
+
+    format!(
+        "{}, {}",
+        plural(directories, "directory", "directories"),
+        plural(files, "file", "files")
+    )
+}
+
+// The spaces left between columns
+const COLUMN_GAP: usize = 2;
+
+// Lays out names in as few rows as will fit in the width, filling each column top to bottom like 'ls'
+// Names that are too wide for any layout end up one per line
+pub fn columnize(names: &[String], width: usize) -> Vec<String> {
+    let widths: Vec<usize> = names
+        .iter()
+        .map(|name| editor::visible_width(name))
+        .collect();
+
+    for rows in 1..=names.len() {
+        let columns = names.len().div_ceil(rows);
+        let column_widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                let end = ((column + 1) * rows).min(names.len());
+                widths[column * rows..end]
+                    .iter()
+                    .copied()
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let total = column_widths.iter().sum::<usize>() + COLUMN_GAP * (columns - 1);
+        if total > width && rows < names.len() {
             continue;
         }
 
-        if fd.file_type().expect("Failed to read file type").is_dir() {
-            // Append a '/' to directories
-            let fd_name = format!("{}/", fd_name).bright_green().to_string();
-            directories.push(fd_name)
-        } else {
-            files.push(fd_name)
+        return (0..rows)
+            .map(|row| {
+                let mut line = String::new();
+                for (column, column_width) in column_widths.iter().enumerate() {
+                    let index = column * rows + row;
+                    if index >= names.len() {
+                        break;
+                    }
+
+                    // The last name in a row isn't padded, so lines don't end in spaces
+                    if index + rows < names.len() {
+                        let padding = column_width - widths[index] + COLUMN_GAP;
+                        line += &format!("{}{}", names[index], " ".repeat(padding));
+                    } else {
+                        line += &names[index];
+                    }
+                }
+                line
+            })
+            .collect();
+    }
+
+    Vec::new()
+}
+
+// Gets the character 'ls -F' would append to a path: '/' for directories, '@' for symlinks and '*' for executables
+// Returns None for other files, and for files whose metadata can't be read
+pub fn file_indicator(path: &Path) -> Option<char> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        Some('@')
+    } else if file_type.is_dir() {
+        Some('/')
+    } else if path::is_executable(path) {
+        Some('*')
+    } else {
+        None
+    }
+}
+
+// TODO: Find a better name for this
+pub fn go_back(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        go_back_to_previous(context, &mut stdout())
+    } else {
+        eprintln!("Usage: go-back");
+        StatusCode::new(1)
+    }
+}
+
+// Changes to the previous working directory, and like 'cd -' in other shells, writes where it ended up
+fn go_back_to_previous(context: &mut Context, output: &mut impl Write) -> StatusCode {
+    let prev_dir = match context.env().previous_working_directory() {
+        Some(dir) => dir,
+        None => {
+            eprintln!("No previous working directory available");
+            return StatusCode::new(2);
+        }
+    }
+    .to_string_lossy()
+    .to_string();
+
+    match context.shell.change_directory(&prev_dir, false) {
+        Ok(_) => {
+            let _ = writeln!(output, "{}", context.cwd().absolute().display());
+            StatusCode::success()
+        }
+        Err(_) => {
+            eprintln!("Invalid path: '{}'", prev_dir);
+            StatusCode::new(3)
+        }
+    }
+}
+
+pub fn clear_terminal(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        // * "Magic" ANSI escape sequence to clear the terminal
+        print!("\x1B[2J\x1B[1;1H");
+        StatusCode::success()
+    } else {
+        eprintln!("Usage: clear-terminal");
+        StatusCode::new(1)
+    }
+}
+
+// Restores the terminal after a program has left it in a bad state, like 'reset'
+// Unlike clear-terminal, this also resets the scroll region, character attributes and cursor visibility
+pub fn reset_terminal(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: reset-terminal");
+        return StatusCode::new(1);
+    }
+
+    if let Some(sequence) = terminal_reset_sequence(stdout().is_terminal()) {
+        print!("{}", sequence);
+        let _ = stdout().flush();
+    }
+
+    StatusCode::success()
+}
+
+// Picks the escape sequences that reset the terminal, or nothing if output isn't going to a terminal
+// A full reset (ESC c) is followed by the individual resets, for terminals that only partly support it
+pub fn terminal_reset_sequence(stdout_is_terminal: bool) -> Option<&'static str> {
+    if !stdout_is_terminal {
+        return None;
+    }
+
+    // Full reset, scroll region, character attributes, visible cursor, then clear the screen
+    Some("\x1Bc\x1B[r\x1B[0m\x1B[?25h\x1B[2J\x1B[1;1H")
+}
+
+// Creates an empty file, or with -c or -t, updates the times of an existing file like 'touch'
+// -c doesn't create the file if it's missing, and -t sets the times to '[[CC]YY]MMDDhhmm[.ss]' instead of now
+pub fn create_file(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: create-file [-c] [-t <[[CC]YY]MMDDhhmm[.ss]>] <path>";
+    let (mut no_create, mut timestamp, mut path) = (false, None, None);
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-c" => no_create = true,
+            "-t" => match args.next() {
+                Some(text) => timestamp = Some(text),
+                None => {
+                    eprintln!("{}", usage);
+                    return StatusCode::new(1);
+                }
+            },
+            _ if !arg.starts_with('-') && path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    if !no_create && timestamp.is_none() {
+        return match fs::File::create(path) {
+            Ok(_) => StatusCode::success(),
+            Err(_) => {
+                eprintln!("Failed to create file: '{}'", path);
+                StatusCode::new(2)
+            }
+        };
+    }
+
+    let time = match timestamp {
+        Some(text) => match DateTime::parse_touch_timestamp(text, DateTime::now().year) {
+            Some(time) => time.to_system_time(),
+            None => {
+                eprintln!("Invalid timestamp: '{}'", text);
+                return StatusCode::new(2);
+            }
+        },
+        None => SystemTime::now(),
+    };
+
+    if no_create && !Path::new(path).exists() {
+        return StatusCode::success();
+    }
+
+    // The file is opened without truncating it, so existing contents are kept
+    let file = match fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(_) => {
+            eprintln!("Failed to create file: '{}'", path);
+            return StatusCode::new(2);
+        }
+    };
+
+    match file.set_times(fs::FileTimes::new().set_accessed(time).set_modified(time)) {
+        Ok(_) => StatusCode::success(),
+        Err(_) => {
+            eprintln!("Failed to set file times: '{}'", path);
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Creates a directory, along with any missing parent directories with -p
+// -m sets the permissions of the directories it creates (such as '700'), rather than leaving them to the umask
+pub fn create_directory(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let parsed = FlagParser::new()
+        .flag("parents", &["-p", "--parents"])
+        .option("mode", &["-m", "--mode"])
+        .parse(&args);
+
+    let (parents, mode, path) = match parsed {
+        Ok(parsed) if parsed.positional.len() == 1 => (
+            parsed.has("parents"),
+            parsed.value("mode"),
+            parsed.positional[0],
+        ),
+        _ => {
+            eprintln!("Usage: create-directory [-p] [-m <mode>] <path>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let mode = match mode.map(parse_mode) {
+        Some(Some(mode)) => Some(mode),
+        Some(None) => {
+            eprintln!("Invalid mode: '{}'", mode.unwrap_or_default());
+            return StatusCode::new(2);
+        }
+        None => None,
+    };
+
+    let created = match parents {
+        true => create_missing_directories(Path::new(path)),
+        false => fs::create_dir(path).map(|_| vec![PathBuf::from(path)]),
+    };
+    let created = match created {
+        Ok(created) => created,
+        Err(_) => {
+            eprintln!("Failed to create directory: '{}'", path);
+            return StatusCode::new(2);
+        }
+    };
+
+    // Directories that already existed keep their permissions
+    // The innermost directory goes first, since a mode without search permission would lock out the ones inside it
+    for directory in created.iter().rev().filter(|_| mode.is_some()) {
+        if set_mode(directory, mode.unwrap_or_default()).is_err() {
+            eprintln!("Failed to set permissions: '{}'", directory.display());
+            return StatusCode::new(3);
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Parses permissions written in octal, like '755' or '0700'
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+// Creates a directory and any of its parents that are missing, returning the ones it created (outermost first)
+fn create_missing_directories(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.is_dir())
+        .collect();
+
+    let mut created = Vec::new();
+    for directory in missing.into_iter().rev() {
+        fs::create_dir(directory)?;
+        created.push(directory.to_path_buf());
+    }
+
+    Ok(created)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+pub fn delete_file(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args[..] {
+        [path] => match fs::remove_file(path) {
+            Ok(_) => StatusCode::success(),
+            Err(_) => {
+                eprintln!("Failed to delete file: '{}'", path);
+                StatusCode::new(2)
+            }
+        },
+        // Trashed files can be recovered later with 'restore-trash'
+        ["--trash", path] => {
+            let trash =
+                trash::trash_directory(context.home(), context.env().get_variable("XDG_DATA_HOME"));
+            match trash::move_to_trash(Path::new(path), &trash) {
+                Ok(_) => StatusCode::success(),
+                Err(_) => {
+                    eprintln!("Failed to move file to trash: '{}'", path);
+                    StatusCode::new(2)
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: delete-file [--trash] <path>");
+            StatusCode::new(1)
+        }
+    }
+}
+
+// Moves the most recently trashed file back to where it was deleted from
+pub fn restore_trash(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: restore-trash");
+        return StatusCode::new(1);
+    }
+
+    let trash = trash::trash_directory(context.home(), context.env().get_variable("XDG_DATA_HOME"));
+    match trash::restore_latest(&trash) {
+        Ok(restored) => {
+            println!("Restored '{}'", restored.display());
+            StatusCode::success()
+        }
+        Err(error) => {
+            eprintln!("Failed to restore from trash: {}", error);
+            StatusCode::new(2)
+        }
+    }
+}
+
+pub fn read_file(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    // --hex prints a hex dump instead, for files that aren't text
+    // --lines prints only a range of lines, such as '10:20', '10:' or ':20'
+    // --no-follow refuses to read through a symlink, and prints where it points instead
+    // --tabs expands tabs to spaces, with a tab stop every N columns
+    // --highlight colors each occurrence of a pattern (when colors are enabled)
+    // --encoding reads the file as UTF-8, UTF-16 or Latin-1, rather than detecting it from a byte order mark
+    // --squeeze-blank collapses each run of blank lines into one
+    // --number prefixes each line with its line number in the file, like 'cat -n'
+    // Text too long for the terminal is shown in $PAGER (or 'less'), unless --no-pager is given
+    let usage = "Usage: read-file [--no-follow] [--no-pager] [--hex | [--lines <start:end>] [--tabs <n>] [--highlight <pattern>] [--encoding <name>] [-s] [-n]] <path>";
+    let parsed = FlagParser::new()
+        .flag("hex", &["-x", "--hex"])
+        .option("lines", &["--lines"])
+        .option("tabs", &["--tabs"])
+        .option("highlight", &["--highlight"])
+        .option("encoding", &["--encoding"])
+        .flag("squeeze-blank", &["-s", "--squeeze-blank"])
+        .flag("number", &["-n", "--number"])
+        .flag("no-follow", &["--no-follow"])
+        .flag("no-pager", &["--no-pager"])
+        .option("follow-symlinks", &["--follow-symlinks"])
+        .parse(&args);
+
+    let parsed = match parsed {
+        Ok(parsed) if parsed.positional.len() == 1 => parsed,
+        _ => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+    let (hex, range, tabs, highlight, encoding) = (
+        parsed.has("hex"),
+        parsed.value("lines"),
+        parsed.value("tabs"),
+        parsed.value("highlight"),
+        parsed.value("encoding"),
+    );
+    let (squeeze_blank, number) = (parsed.has("squeeze-blank"), parsed.has("number"));
+    let no_follow = parsed.has("no-follow") || parsed.value("follow-symlinks") == Some("no");
+    let no_pager = parsed.has("no-pager");
+    let mut file_name = parsed.positional[0].to_string();
+
+    let formatted = range.is_some() || tabs.is_some() || highlight.is_some() || encoding.is_some();
+    if hex && (formatted || squeeze_blank || number) {
+        eprintln!("{}", usage);
+        return StatusCode::new(1);
+    }
+
+    let encoding = match encoding.map(TextEncoding::parse) {
+        Some(Some(encoding)) => Some(encoding),
+        Some(None) => {
+            eprintln!("Unknown encoding: '{}'", encoding.unwrap_or_default());
+            return StatusCode::new(2);
+        }
+        None => None,
+    };
+
+    let tab_width = match tabs.map(|tabs| tabs.parse::<usize>()) {
+        Some(Ok(width)) if (1..=MAX_TAB_WIDTH).contains(&width) => Some(width),
+        Some(_) => {
+            eprintln!("Invalid tab width: '{}'", tabs.unwrap_or_default());
+            return StatusCode::new(2);
+        }
+        None => None,
+    };
+
+    let (start, end) = match range.map(parse_line_range) {
+        Some(Some(range)) => range,
+        Some(None) => {
+            eprintln!("Invalid line range: '{}'", range.unwrap_or_default());
+            return StatusCode::new(2);
+        }
+        None => (1, None),
+    };
+
+    // Inside an archive, relative paths are read from the archive rather than the real filesystem
+    let mut archived = None;
+    if let Some(view) = &context.env().archive {
+        match view.locate(&file_name) {
+            archive::Location::Inside(path) => match view.archive.read(&path) {
+                Some(contents) => archived = Some(contents.to_vec()),
+                None => {
+                    eprintln!("Failed to open file: '{}'", file_name);
+                    return StatusCode::new(2);
+                }
+            },
+            archive::Location::Outside(path) => file_name = path,
+        }
+    }
+
+    if no_follow && archived.is_none() {
+        if let Some(target) = symlink_target(&file_name) {
+            eprintln!("Not following symlink: '{}'", file_name);
+            println!("{}", target.display());
+            return StatusCode::new(3);
+        }
+    }
+
+    let mut reader: Box<dyn BufRead> = match archived {
+        Some(contents) => Box::new(io::Cursor::new(contents)),
+        None => match fs::File::open(&file_name) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(_) => {
+                eprintln!("Failed to open file: '{}'", file_name);
+                return StatusCode::new(2);
+            }
+        },
+    };
+
+    if hex {
+        return match hex_dump(reader, &mut stdout().lock()) {
+            Ok(_) => StatusCode::success(),
+            Err(_) => {
+                eprintln!("Failed to read file: '{}'", file_name);
+                StatusCode::new(3)
+            }
+        };
+    }
+
+    // Text that isn't UTF-8 is converted as a whole, while UTF-8 is read as it's printed
+    let detected = reader
+        .fill_buf()
+        .map(TextEncoding::detect)
+        .unwrap_or_default();
+    let reader: Box<dyn BufRead> = match encoding.or(detected.map(|(encoding, _)| encoding)) {
+        None | Some(TextEncoding::Utf8) => {
+            if let Some((TextEncoding::Utf8, bom_length)) = detected {
+                reader.consume(bom_length);
+            }
+            reader
+        }
+        Some(encoding) => {
+            let mut bytes = Vec::new();
+            if reader.read_to_end(&mut bytes).is_err() {
+                eprintln!("Failed to read file: '{}'", file_name);
+                return StatusCode::new(3);
+            }
+            Box::new(io::Cursor::new(decode_text(&bytes, encoding).into_bytes()))
+        }
+    };
+
+    let highlight = highlight.map(|pattern| SearchPattern::new(pattern, false));
+    let format = LineFormat {
+        tab_width,
+        highlight: highlight.as_ref(),
+        color: colored::control::SHOULD_COLORIZE.should_colorize(),
+        squeeze_blank,
+        number,
+    };
+
+    // Output that isn't going to a terminal is never paged, so it can be streamed
+    let (height, is_terminal) = (editor::terminal_height(), stdout().is_terminal());
+    let height = match height {
+        Some(height) if is_terminal && !no_pager => height,
+        _ => {
+            return match print_line_range(reader, &mut stdout().lock(), start, end, &format) {
+                Ok(_) => StatusCode::success(),
+                Err(_) => {
+                    eprintln!("Failed to read file: '{}'", file_name);
+                    StatusCode::new(3)
+                }
+            };
+        }
+    };
+
+    let pager = context.env().get_variable("PAGER");
+    let mut output = PagedOutput::new(stdout().lock(), height, pager);
+    let result = print_line_range(reader, &mut output, start, end, &format);
+
+    // The pager closing early (like quitting 'less') isn't an error
+    match result.and(output.finish()) {
+        Ok(_) => StatusCode::success(),
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => StatusCode::success(),
+        Err(_) => {
+            eprintln!("Failed to read file: '{}'", file_name);
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Checks whether output should go through a pager: only in a terminal, and only if it doesn't fit on the screen
+pub fn should_page(line_count: usize, terminal_height: Option<usize>, is_terminal: bool) -> bool {
+    is_terminal && terminal_height.is_some_and(|height| line_count > height)
+}
+
+// The pager used when $PAGER isn't set (-R lets colors through)
+const DEFAULT_PAGER: &str = "less -R";
+
+// Starts a pager, such as $PAGER, that reads what it shows from a pipe
+fn start_pager(pager: Option<String>) -> Option<process::Child> {
+    let pager = pager
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_PAGER));
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or_default();
+
+    process::Command::new(program)
+        .args(words)
+        .stdin(process::Stdio::piped())
+        .spawn()
+        .ok()
+}
+
+// Output that is held back until it's clear whether it fits on the screen (see should_page())
+// Once it doesn't, a pager is started with what was held back and the rest is streamed to it,
+// so at most a screenful is kept in memory
+// If the pager can't be started, the output is written directly instead
+pub struct PagedOutput<W: Write> {
+    output: W,
+    height: usize,
+    // What has been written so far and how many lines it has, or None once the decision is made
+    held: Option<(Vec<u8>, usize)>,
+    pager_command: Option<String>,
+    pager: Option<process::Child>,
+}
+
+impl<W: Write> PagedOutput<W> {
+    pub fn new(output: W, height: usize, pager_command: Option<String>) -> Self {
+        Self {
+            output,
+            height,
+            held: Some((Vec::new(), 0)),
+            pager_command,
+            pager: None,
+        }
+    }
+
+    // Where output goes once it's known not to fit
+    fn destination(&mut self) -> &mut dyn Write {
+        match self.pager.as_mut().and_then(|pager| pager.stdin.as_mut()) {
+            Some(input) => input,
+            None => &mut self.output,
+        }
+    }
+
+    // Writes out anything still held back, since it all fits, or waits for the user to close the pager
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some((held, _)) = self.held.take() {
+            self.output.write_all(&held)?;
+            self.output.flush()?;
+        }
+
+        match self.pager.take() {
+            Some(mut pager) => {
+                drop(pager.stdin.take());
+                pager.wait().map(|_| ())
+            }
+            None => self.output.flush(),
+        }
+    }
+}
+
+impl<W: Write> Write for PagedOutput<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let (held, line_count) = match &mut self.held {
+            Some(held) => held,
+            None => return self.destination().write(bytes),
+        };
+
+        held.extend_from_slice(bytes);
+        *line_count += bytes.iter().filter(|byte| **byte == b'\n').count();
+        if should_page(*line_count, Some(self.height), true) {
+            let (held, _) = self.held.take().unwrap_or_default();
+            self.pager = start_pager(self.pager_command.take());
+            self.destination().write_all(&held)?;
+        }
+
+        Ok(bytes.len())
+    }
+
+    // Held back output stays held until finish(), since it might still fit
+    fn flush(&mut self) -> io::Result<()> {
+        match self.held {
+            Some(_) => Ok(()),
+            None => self.destination().flush(),
+        }
+    }
+}
+
+// Gets where a path points if it is a symlink itself (symlinks in its parent directories don't count)
+fn symlink_target(path: &str) -> Option<PathBuf> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::read_link(path).ok(),
+        _ => None,
+    }
+}
+
+// Parses a 1-based, inclusive line range like '10:20', where either side can be left out
+// A single number selects just that line
+pub fn parse_line_range(range: &str) -> Option<(usize, Option<usize>)> {
+    let (start, end) = match range.split_once(':') {
+        Some((start, end)) => (start, end),
+        None if !range.is_empty() => (range, range),
+        None => return None,
+    };
+
+    let start = match start {
+        "" => 1,
+        start => start.parse().ok().filter(|start| *start > 0)?,
+    };
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().ok().filter(|end| *end >= start)?),
+    };
+
+    Some((start, end))
+}
+
+// How 'read-file' changes each line before writing it
+#[derive(Debug, Default)]
+pub struct LineFormat<'a> {
+    // Tabs are expanded to spaces, with a tab stop every this many columns
+    pub tab_width: Option<usize>,
+    // Occurrences of this are highlighted, if colors are enabled
+    pub highlight: Option<&'a SearchPattern>,
+    pub color: bool,
+    // Runs of blank lines are written as a single blank line
+    pub squeeze_blank: bool,
+    // Each line written is numbered, counting up from the start line
+    // Like 'cat -sn', blank lines dropped by squeeze_blank aren't counted
+    pub number: bool,
+}
+
+impl LineFormat<'_> {
+    pub fn apply(&self, line: String) -> String {
+        let line = match self.tab_width {
+            Some(width) => expand_tabs(&line, width),
+            None => line,
+        };
+
+        match self.highlight {
+            Some(pattern) => highlight_matches(&line, pattern, self.color),
+            None => line,
+        }
+    }
+}
+
+// Writes the lines of the input from the start line to the end line (or the last line)
+// Each line is formatted as it is written, and reading stops as soon as the end line has been written
+// Bytes that aren't valid UTF-8 are replaced, rather than stopping the output
+pub fn print_line_range<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    start: usize,
+    end: Option<usize>,
+    format: &LineFormat,
+) -> io::Result<()> {
+    let lines = input
+        .split(b'\n')
+        .take(end.unwrap_or(usize::MAX))
+        .skip(start - 1)
+        .map(|line| {
+            line.map(|mut line| {
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                line
+            })
+        });
+    // Without --squeeze-blank no line counts as blank, so none are dropped
+    let is_blank = |line: &io::Result<Vec<u8>>| {
+        format.squeeze_blank && matches!(line, Ok(line) if line.is_empty())
+    };
+
+    for (number, line) in (start..).zip(squeeze_blank(lines, is_blank)) {
+        let line = format.apply(String::from_utf8_lossy(&line?).to_string());
+        match format.number {
+            true => writeln!(output, "{:>6}\t{}", number, line)?,
+            false => writeln!(output, "{}", line)?,
+        }
+    }
+
+    output.flush()
+}
+
+// Drops every blank line that directly follows another, so each run of blank lines becomes one
+pub fn squeeze_blank<I: Iterator>(
+    lines: I,
+    is_blank: impl Fn(&I::Item) -> bool,
+) -> impl Iterator<Item = I::Item> {
+    let mut previous_blank = false;
+    lines.filter(move |line| {
+        let blank = is_blank(line);
+        let squeezed = blank && previous_blank;
+        previous_blank = blank;
+        !squeezed
+    })
+}
+
+// The text encodings 'read-file' can convert from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl TextEncoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Self::Utf8),
+            "utf-16le" | "utf16le" => Some(Self::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Self::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Self::Latin1),
+            _ => None,
+        }
+    }
+
+    // Detects the encoding from the byte order mark at the start of some text, along with the mark's length
+    pub fn detect(bytes: &[u8]) -> Option<(Self, usize)> {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => Some((Self::Utf8, 3)),
+            [0xff, 0xfe, ..] => Some((Self::Utf16Le, 2)),
+            [0xfe, 0xff, ..] => Some((Self::Utf16Be, 2)),
+            _ => None,
+        }
+    }
+}
+
+// Converts text to UTF-8, skipping the encoding's byte order mark if there is one
+// Anything that can't be converted (like an unpaired UTF-16 surrogate) is replaced
+pub fn decode_text(bytes: &[u8], encoding: TextEncoding) -> String {
+    let bytes = match TextEncoding::detect(bytes) {
+        Some((detected, bom_length)) if detected == encoding => &bytes[bom_length..],
+        _ => bytes,
+    };
+
+    let utf16 = |to_unit: fn([u8; 2]) -> u16| {
+        let pairs = bytes.chunks_exact(2);
+        let odd_byte = (!pairs.remainder().is_empty()).then_some(char::REPLACEMENT_CHARACTER);
+        let units = pairs.map(|pair| to_unit([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .chain(odd_byte)
+            .collect()
+    };
+
+    match encoding {
+        TextEncoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+        TextEncoding::Utf16Le => utf16(u16::from_le_bytes),
+        TextEncoding::Utf16Be => utf16(u16::from_be_bytes),
+        // Every Latin-1 byte is the Unicode code point with the same number
+        TextEncoding::Latin1 => bytes.iter().map(|byte| char::from(*byte)).collect(),
+    }
+}
+
+// Colors each occurrence of a pattern in a line bold red, finding them the same way 'search' does
+// Without colors (or with an empty pattern), the line is returned unchanged
+pub fn highlight_matches(line: &str, pattern: &SearchPattern, color: bool) -> String {
+    if !color {
+        return line.to_string();
+    }
+
+    let mut highlighted = String::with_capacity(line.len());
+    let mut last = 0;
+    for range in pattern.find_all(line) {
+        highlighted.push_str(&line[last..range.start]);
+        highlighted.push_str(&line[range.clone()].red().bold().to_string());
+        last = range.end;
+    }
+
+    highlighted.push_str(&line[last..]);
+    highlighted
+}
+
+// The widest tab stop 'read-file --tabs' accepts, since every tab can become this many spaces
+const MAX_TAB_WIDTH: usize = 256;
+
+// Replaces each tab with enough spaces to reach the next tab stop, with a stop every width columns
+pub fn expand_tabs(line: &str, width: usize) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = width - column % width;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            c => {
+                expanded.push(c);
+                column += 1;
+            }
+        }
+    }
+
+    expanded
+}
+
+// The number of bytes shown on each line of a hex dump
+const HEX_DUMP_WIDTH: usize = 16;
+
+// Writes a hex dump of everything in the input, reading it one line's worth of bytes at a time
+pub fn hex_dump<R: Read, W: Write>(mut input: R, output: &mut W) -> io::Result<()> {
+    let mut buffer = [0; HEX_DUMP_WIDTH];
+    let mut offset = 0;
+
+    loop {
+        // A single read can return fewer bytes than asked for, so keep reading until the line is full
+        let mut filled = 0;
+        while filled < HEX_DUMP_WIDTH {
+            match input.read(&mut buffer[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+
+        if filled == 0 {
+            return output.flush();
+        }
+
+        writeln!(output, "{}", hex_dump_line(offset, &buffer[..filled]))?;
+        offset += filled;
+    }
+}
+
+// Formats up to 16 bytes like 'hexdump -C': the offset, the bytes in hex and the printable characters
+pub fn hex_dump_line(offset: usize, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for (index, byte) in bytes.iter().enumerate() {
+        // The bytes are split into two groups of 8 for readability
+        if index == HEX_DUMP_WIDTH / 2 {
+            hex.push(' ');
+        }
+        hex.push_str(&format!("{:02x} ", byte));
+    }
+
+    let ascii: String = bytes
+        .iter()
+        .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+            true => byte as char,
+            false => '.',
+        })
+        .collect();
+
+    format!("{:08x}  {:<50}|{}|", offset, hex, ascii)
+}
+
+// Renames files by replacing the first occurrence of some text in their names ('rename .txt .md *.txt')
+// Existing files are never overwritten unless -f is given, and each rename is reported
+pub fn rename(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let parsed = FlagParser::new()
+        .flag("force", &["-f", "--force"])
+        .parse(&args);
+
+    let (force, from, to, paths) = match parsed {
+        Ok(parsed) if parsed.positional.len() >= 3 && !parsed.positional[0].is_empty() => (
+            parsed.has("force"),
+            parsed.positional[0],
+            parsed.positional[1],
+            parsed.positional[2..].to_vec(),
+        ),
+        _ => {
+            eprintln!("Usage: rename [-f] <from> <to> <paths...>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let mut status_code = StatusCode::success();
+    for path in paths.iter().map(Path::new) {
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        let target = match name.and_then(|name| renamed_file_name(&name, from, to)) {
+            Some(new_name) => path.with_file_name(new_name),
+            // Names that don't contain the text are left alone
+            None => continue,
+        };
+
+        if path.symlink_metadata().is_err() {
+            eprintln!("Invalid path: '{}'", path.display());
+            status_code = StatusCode::new(2);
+        } else if target.symlink_metadata().is_ok() && !force {
+            eprintln!(
+                "Not overwriting existing file (use -f to overwrite): '{}'",
+                target.display()
+            );
+            status_code = StatusCode::new(2);
+        } else if fs::rename(path, &target).is_err() {
+            eprintln!("Failed to rename: '{}'", path.display());
+            status_code = StatusCode::new(3);
+        } else {
+            println!("'{}' -> '{}'", path.display(), target.display());
+        }
+    }
+
+    status_code
+}
+
+// Gets the new name for a file by replacing the first occurrence of some text in it
+// Returns None if the name doesn't contain the text, or the new name would be empty or a path
+pub fn renamed_file_name(name: &str, from: &str, to: &str) -> Option<String> {
+    if from.is_empty() || !name.contains(from) {
+        return None;
+    }
+
+    let renamed = name.replacen(from, to, 1);
+    match renamed.is_empty() || renamed.contains('/') || renamed == "." || renamed == ".." {
+        true => None,
+        false => Some(renamed),
+    }
+}
+
+// Copies a file, into a directory if the destination is one
+// -p also copies the permissions and modification time, so only the contents would otherwise differ
+// -r copies a directory and everything in it, merging into directories that already exist
+// --conflict decides what happens to files that already exist: overwrite (the default), skip or rename
+// --backup keeps files that would be overwritten, as 'file~' or (with --backup=numbered) 'file.N'
+pub fn copy_file(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: copy-file [-p] [-r] [--conflict <overwrite | skip | rename>] [--backup[=simple | numbered]] <source> <destination>";
+    let parsed = FlagParser::new()
+        .flag("preserve", &["-p", "--preserve"])
+        .flag("recursive", &["-r", "-R", "--recursive"])
+        .option("conflict", &["--conflict"])
+        .optional("backup", &["-b", "--backup"])
+        .parse(&args);
+
+    let (preserve, recursive, conflict, backup, source, destination) = match parsed {
+        Ok(parsed) if parsed.positional.len() == 2 => (
+            parsed.has("preserve"),
+            parsed.has("recursive"),
+            parsed.value("conflict"),
+            parsed.has("backup").then(|| parsed.value("backup")),
+            parsed.positional[0],
+            parsed.positional[1],
+        ),
+        _ => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    let backup = match backup.map(|scheme| (scheme, BackupScheme::parse(scheme))) {
+        Some((_, Some(scheme))) => Some(scheme),
+        Some((scheme, None)) => {
+            eprintln!("Invalid backup scheme: '{}'", scheme.unwrap_or_default());
+            return StatusCode::new(2);
+        }
+        None => None,
+    };
+
+    let policy = match conflict.map(ConflictPolicy::parse) {
+        Some(Some(policy)) => policy,
+        Some(None) => {
+            eprintln!(
+                "Invalid conflict policy: '{}'",
+                conflict.unwrap_or_default()
+            );
+            return StatusCode::new(2);
+        }
+        None => ConflictPolicy::Overwrite,
+    };
+
+    let source = Path::new(source);
+    if source.is_dir() && !recursive {
+        eprintln!("Is a directory (use -r to copy it): '{}'", source.display());
+        return StatusCode::new(2);
+    } else if !source.is_file() && !source.is_dir() {
+        eprintln!("Invalid file: '{}'", source.display());
+        return StatusCode::new(2);
+    }
+
+    let mut destination = PathBuf::from(destination);
+    if destination.is_dir() {
+        destination.push(source.file_name().unwrap_or_default());
+    }
+
+    // Copying a file onto itself would truncate it before it was read
+    if path::is_same_file(source, &destination) {
+        eprintln!(
+            "'{}' and '{}' are the same file",
+            source.display(),
+            destination.display()
+        );
+        return StatusCode::new(2);
+    }
+
+    if source.is_dir() && is_within(&destination, source) {
+        eprintln!(
+            "Cannot copy a directory into itself: '{}'",
+            source.display()
+        );
+        return StatusCode::new(2);
+    }
+
+    let mut copy_one = |from: &Path, to: &Path| {
+        // With -r, symlinks are copied as links rather than followed, like 'cp -r'
+        let is_link = recursive && from.is_symlink();
+
+        // Hard links inside a tree can still lead back to the file being copied
+        if !is_link && path::is_same_file(from, to) {
+            return Err(io::Error::other("source and destination are the same file"));
+        }
+
+        if let Some(scheme) = backup.filter(|_| to.exists() || to.is_symlink()) {
+            fs::rename(to, backup_path(to, scheme, |path| path.exists()))?;
+        }
+
+        if is_link {
+            // Whatever is in the way is a file (or a link), since directories are never overwritten
+            if to.exists() || to.is_symlink() {
+                fs::remove_file(to)?;
+            }
+            return create_symlink(&fs::read_link(from)?, to);
+        }
+
+        // Large files show their progress, as long as there's a terminal to show it on
+        let size = fs::metadata(from).map_or(0, |metadata| metadata.len());
+        match size >= PROGRESS_THRESHOLD && stderr().is_terminal() {
+            true => copy_file_with_progress(from, to, size)?,
+            false => fs::copy(from, to).map(|_| ())?,
+        }
+
+        match preserve {
+            true => copy_metadata(from, to),
+            false => Ok(()),
+        }
+    };
+
+    match merge_tree(source, &destination, policy, &mut copy_one) {
+        Ok(0) => StatusCode::success(),
+        Ok(_) => StatusCode::new(3),
+        Err(_) => {
+            eprintln!("Failed to copy file to '{}'", destination.display());
+            StatusCode::new(3)
+        }
+    }
+}
+
+// How 'copy-file --backup' names the old file that a copy would overwrite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupScheme {
+    // 'file~', replacing any older backup
+    Simple,
+    // 'file.1', 'file.2' and so on, keeping every backup
+    Numbered,
+}
+
+impl BackupScheme {
+    // Parses the value of --backup, where a missing value means the simple scheme
+    pub fn parse(text: Option<&str>) -> Option<Self> {
+        match text {
+            None | Some("simple") => Some(Self::Simple),
+            Some("numbered") => Some(Self::Numbered),
+            _ => None,
+        }
+    }
+}
+
+// Picks the path to move a file to before it is overwritten
+pub fn backup_path(path: &Path, scheme: BackupScheme, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let with_suffix = |suffix: String| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+
+    match scheme {
+        BackupScheme::Simple => with_suffix(String::from("~")),
+        BackupScheme::Numbered => (1..)
+            .map(|n| with_suffix(format!(".{}", n)))
+            .find(|candidate| !is_taken(candidate))
+            .unwrap(),
+    }
+}
+
+// Checks whether a path is (or would be, once created) the same as or beneath a directory
+fn is_within(path: &Path, directory: &Path) -> bool {
+    let existing = path.ancestors().find(|ancestor| ancestor.exists());
+    match (
+        existing.and_then(|a| a.canonicalize().ok()),
+        directory.canonicalize(),
+    ) {
+        (Some(path), Ok(directory)) => path.starts_with(directory),
+        _ => false,
+    }
+}
+
+// What copying (or moving) a tree does with files that already exist at the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    // Keep the existing file, and give the new one a numeric suffix
+    Rename,
+}
+
+impl ConflictPolicy {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "overwrite" => Some(Self::Overwrite),
+            "skip" => Some(Self::Skip),
+            "rename" => Some(Self::Rename),
+            _ => None,
+        }
+    }
+}
+
+// What is at a path before anything is copied there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathState {
+    Missing,
+    File,
+    Directory,
+}
+
+impl PathState {
+    pub fn of(path: &Path) -> Self {
+        if path.is_dir() {
+            Self::Directory
+        } else if path.exists() || path.is_symlink() {
+            Self::File
+        } else {
+            Self::Missing
+        }
+    }
+}
+
+// What to do with one path of the source tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictAction {
+    // Nothing is in the way
+    Create,
+    // A directory already exists, so its contents are merged with the source's
+    Merge,
+    // A file is written over
+    Replace,
+    Skip,
+    Rename,
+    // A file and a directory are in each other's way, and neither is removed to make room for the other
+    Mismatch,
+}
+
+// Decides what to do with a source path, given what is already at its destination
+// Directories always merge into existing directories, so the policy only applies to files
+// and to a file and directory that are in each other's way (which are never overwritten, like 'cp')
+pub fn resolve_conflict(
+    source: PathState,
+    destination: PathState,
+    policy: ConflictPolicy,
+) -> ConflictAction {
+    match (source, destination, policy) {
+        (_, PathState::Missing, _) => ConflictAction::Create,
+        (PathState::Directory, PathState::Directory, _) => ConflictAction::Merge,
+        (PathState::File, PathState::File, ConflictPolicy::Overwrite) => ConflictAction::Replace,
+        (_, _, ConflictPolicy::Overwrite) => ConflictAction::Mismatch,
+        (_, _, ConflictPolicy::Skip) => ConflictAction::Skip,
+        (_, _, ConflictPolicy::Rename) => ConflictAction::Rename,
+    }
+}
+
+// Copies a file or a whole directory tree to a destination, settling conflicts with the policy
+// Files are transferred by transfer_file, so commands that copy and move trees share the same merging
+// Symlinks aren't followed into, and a skipped directory skips everything inside it
+// Returns how many paths were left out because a file and a directory were in each other's way
+pub fn merge_tree(
+    source: &Path,
+    destination: &Path,
+    policy: ConflictPolicy,
+    transfer_file: &mut dyn FnMut(&Path, &Path) -> io::Result<()>,
+) -> io::Result<usize> {
+    // Where each source directory ended up, or None if it was skipped
+    let mut directories: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    let mut mismatches = 0;
+
+    for path in path::walk(source, false) {
+        let target = match path.parent().and_then(|parent| directories.get(parent)) {
+            Some(Some(parent)) => parent.join(path.file_name().unwrap_or_default()),
+            Some(None) => {
+                directories.insert(path, None);
+                continue;
+            }
+            None => destination.to_path_buf(),
+        };
+
+        let is_directory = path.is_dir() && !path.is_symlink();
+        let source_state = match is_directory {
+            true => PathState::Directory,
+            false => PathState::File,
+        };
+
+        let target = match resolve_conflict(source_state, PathState::of(&target), policy) {
+            ConflictAction::Create => Some(target),
+            ConflictAction::Merge => {
+                directories.insert(path, Some(target));
+                continue;
+            }
+            ConflictAction::Replace => Some(target),
+            ConflictAction::Skip => None,
+            ConflictAction::Mismatch => {
+                match is_directory {
+                    true => eprintln!(
+                        "Cannot overwrite non-directory with directory: '{}'",
+                        target.display()
+                    ),
+                    false => eprintln!(
+                        "Cannot overwrite directory with non-directory: '{}'",
+                        target.display()
+                    ),
+                }
+                mismatches += 1;
+                None
+            }
+            ConflictAction::Rename => {
+                let parent = target.parent().unwrap_or(Path::new("."));
+                let name = target.file_name().unwrap_or_default().to_string_lossy();
+                let name =
+                    trash::unique_trash_name(&name, |candidate| parent.join(candidate).exists());
+                Some(parent.join(name))
+            }
+        };
+
+        match (&target, is_directory) {
+            (Some(target), true) => fs::create_dir(target)?,
+            (Some(target), false) => transfer_file(&path, target)?,
+            (None, _) => (),
+        }
+
+        if is_directory {
+            directories.insert(path, target);
+        }
+    }
+
+    Ok(mismatches)
+}
+
+// Files at least this large (in bytes) show progress while they are copied
+const PROGRESS_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+// How much of a file is read and written at a time when showing progress
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+// Copies a file in chunks, showing how much has been copied on stderr
+// Like fs::copy(), the permissions of the source are copied too
+fn copy_file_with_progress(source: &Path, destination: &Path, size: u64) -> io::Result<()> {
+    let input = fs::File::open(source)?;
+    let mut output = fs::File::create(destination)?;
+
+    copy_with_progress(input, &mut output, size, &mut |copied, total| {
+        eprint!(
+            "\r{} / {} bytes ({}%)",
+            copied,
+            total,
+            copied * 100 / total.max(1)
+        );
+    })?;
+    eprintln!();
+
+    fs::set_permissions(destination, fs::metadata(source)?.permissions())
+}
+
+// Copies everything from the input to the output, reporting the bytes copied so far and the total
+// Reports are limited to a few per second, but the final count is always reported
+pub fn copy_with_progress<R: Read, W: Write>(
+    mut input: R,
+    output: &mut W,
+    total: u64,
+    report: &mut dyn FnMut(u64, u64),
+) -> io::Result<u64> {
+    let interval = Duration::from_millis(100);
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+    let mut copied = 0;
+    let mut last_report = Instant::now();
+
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+
+        output.write_all(&buffer[..read])?;
+        copied += read as u64;
+
+        if last_report.elapsed() >= interval {
+            report(copied, total);
+            last_report = Instant::now();
+        }
+    }
+
+    output.flush()?;
+    report(copied, total);
+    Ok(copied)
+}
+
+// Copies the permissions and access and modification times of one file to another
+pub fn copy_metadata(source: &Path, destination: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    let times = fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+
+    fs::OpenOptions::new()
+        .write(true)
+        .open(destination)?
+        .set_times(times)?;
+    // Set last, since the permissions may not allow the file to be opened for writing
+    fs::set_permissions(destination, metadata.permissions())
+}
+
+pub fn truncate(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let truncation = match args.len() {
+        0 => 1,
+        // ! This is copilot code, it is extremely unsafe
+        1 => match args[0].parse::<usize>() {
+            Ok(t) => t,
+            Err(_) => {
+                eprintln!("Invalid truncation length: '{}'", args[0]);
+                return StatusCode::new(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: truncate <length (default 1)>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let _ = context.cwd_mut().set_truncation(truncation);
+    StatusCode::success()
+}
+
+pub fn untruncate(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        let _ = context.cwd_mut().disable_truncation();
+        StatusCode::success()
+    } else {
+        eprintln!("Usage: untruncate");
+        StatusCode::new(1)
+    }
+}
+
+pub fn seq(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let mut numbers = Vec::new();
+    for arg in &args {
+        match arg.parse::<i64>() {
+            Ok(number) => numbers.push(number),
+            Err(_) => {
+                eprintln!("Invalid number: '{}'", arg);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let (first, last, step) = match numbers[..] {
+        [last] => (1, last, 1),
+        // Without an explicit step, count in whichever direction reaches the last number
+        [first, last] => (first, last, if first <= last { 1 } else { -1 }),
+        [first, step, last] => (first, last, step),
+        _ => {
+            eprintln!("Usage: seq [first] [step] <last>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let sequence = match Sequence::new(first, last, step) {
+        Some(sequence) => sequence,
+        None => {
+            eprintln!("Invalid range: {} to {} by {}", first, last, step);
+            return StatusCode::new(1);
+        }
+    };
+
+    for number in sequence {
+        println!("{}", number);
+    }
+
+    StatusCode::success()
+}
+
+// Iterator over an inclusive range of integers, counting up or down by a non-zero step
+#[derive(Debug)]
+pub struct Sequence {
+    next: Option<i64>,
+    last: i64,
+    step: i64,
+}
+
+impl Sequence {
+    // Returns None if the step is zero or can never reach the last number
+    pub fn new(first: i64, last: i64, step: i64) -> Option<Self> {
+        let empty = match step {
+            0 => true,
+            s if s > 0 => first > last,
+            _ => first < last,
+        };
+
+        match empty {
+            true => None,
+            false => Some(Self {
+                next: Some(first),
+                last,
+                step,
+            }),
+        }
+    }
+}
+
+impl Iterator for Sequence {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let current = self.next?;
+        // Stop once the next value would pass the last number (or overflow)
+        self.next = current
+            .checked_add(self.step)
+            .filter(|n| match self.step > 0 {
+                true => *n <= self.last,
+                false => *n >= self.last,
+            });
+
+        Some(current)
+    }
+}
+
+// Opens a file in $EDITOR (or vi), waiting until the editor exits
+fn edit_file(file: &Path) -> StatusCode {
+    // $EDITOR may contain arguments of its own, such as 'code --wait'
+    let editor = env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
+    let mut editor_words: Vec<&str> = editor.split_whitespace().collect();
+    let file_string = file.to_string_lossy().to_string();
+    editor_words.push(&file_string);
+    run_external(editor_words[0], &editor_words[1..])
+}
+
+// Writes some text to a new file in the temp directory for editing, like 'rush-rename-<pid>-<n>.txt'
+// The file is always newly created (readable only by the user), so a file or symlink that someone
+// else left at a predictable path is never written through
+fn create_edit_file(prefix: &str, extension: &str, contents: &str) -> io::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut attempt = 0;
+    loop {
+        let name = format!(
+            "{}-{}-{}-{}.{}",
+            prefix,
+            process::id(),
+            nanos,
+            attempt,
+            extension
+        );
+        let path = env::temp_dir().join(name);
+        match options.open(&path) {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())?;
+                return Ok(path);
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Opens the names of the matched files in $EDITOR and renames each file to its edited name
+pub fn bulk_rename(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let originals = match args.len() {
+        0 => directory_entries(Path::new(".")),
+        1 if path::is_glob(args[0]) => path::expand_glob(args[0], context.home())
+            .into_iter()
+            .map(PathBuf::from)
+            .collect(),
+        1 => match path::resolve(args[0], context.home()) {
+            Some(directory) => directory_entries(&directory),
+            None => {
+                eprintln!("Invalid path: '{}'", args[0]);
+                return StatusCode::new(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: bulk-rename <directory or glob (default .)>");
+            return StatusCode::new(1);
+        }
+    };
+
+    if originals.is_empty() {
+        eprintln!("No files to rename");
+        return StatusCode::new(2);
+    }
+
+    let names: Vec<String> = originals
+        .iter()
+        .map(|original| file_name_of(original))
+        .collect();
+
+    let list_path = match create_edit_file("rush-rename", "txt", &(names.join("\n") + "\n")) {
+        Ok(path) => path,
+        Err(_) => {
+            eprintln!(
+                "Failed to create file in temp directory: '{}'",
+                env::temp_dir().display()
+            );
+            return StatusCode::new(3);
+        }
+    };
+
+    let editor_status = edit_file(&list_path);
+
+    let edited = fs::read_to_string(&list_path);
+    let _ = fs::remove_file(&list_path);
+
+    if !editor_status.is_success() {
+        eprintln!("Editor exited unsuccessfully, no files were renamed");
+        return StatusCode::new(3);
+    }
+
+    let edited = match edited {
+        Ok(edited) => edited,
+        Err(_) => {
+            eprintln!("Failed to read file: '{}'", list_path.display());
+            return StatusCode::new(3);
+        }
+    };
+
+    let renames = match plan_renames(&originals, &edited) {
+        Ok(renames) => renames,
+        Err(message) => {
+            eprintln!("{}, no files were renamed", message);
+            return StatusCode::new(4);
+        }
+    };
+
+    let mut status_code = StatusCode::success();
+    for (from, to) in renames {
+        match fs::rename(&from, &to) {
+            Ok(_) => println!("{} -> {}", from.display(), to.display()),
+            Err(_) => {
+                eprintln!(
+                    "Failed to rename '{}' to '{}'",
+                    from.display(),
+                    to.display()
+                );
+                status_code = StatusCode::new(5);
+            }
+        }
+    }
+
+    status_code
+}
+
+// Pairs each original path with its edited name, skipping unchanged names
+// Fails if the number of names changed or if any new name would collide with another file
+pub fn plan_renames(
+    originals: &[PathBuf],
+    edited: &str,
+) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut new_names: Vec<&str> = edited.lines().collect();
+    // Editors commonly leave extra blank lines at the end of the file
+    while new_names.last().is_some_and(|name| name.trim().is_empty()) {
+        new_names.pop();
+    }
+
+    if new_names.len() != originals.len() {
+        return Err(format!(
+            "Expected {} names but found {}",
+            originals.len(),
+            new_names.len()
+        ));
+    }
+
+    let mut renames = Vec::new();
+    let mut targets = HashSet::new();
+
+    for (original, new_name) in originals.iter().zip(new_names) {
+        if new_name.is_empty() {
+            return Err(format!("Empty name given for '{}'", original.display()));
+        }
+        // A name can't move the file into another directory
+        if new_name.contains('/') || new_name == "." || new_name == ".." {
+            return Err(format!(
+                "Invalid name given for '{}': '{}'",
+                original.display(),
+                new_name
+            ));
+        }
+
+        let target = original.with_file_name(new_name);
+        if &target == original {
+            continue;
+        }
+
+        if target.exists() || !targets.insert(target.clone()) {
+            return Err(format!("'{}' already exists", target.display()));
+        }
+
+        renames.push((original.clone(), target));
+    }
+
+    Ok(renames)
+}
+
+// Recursively searches a directory, printing matching paths or running a command on each one
+pub fn find(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage =
+        "Usage: find <path (default .)> [-name <pattern>] [-type <f|d|l>] [-exec <command> {} ;]";
+    let mut args = args.into_iter().peekable();
+
+    let root = match args.peek() {
+        Some(arg) if !arg.starts_with('-') => args.next().unwrap(),
+        _ => ".",
+    };
+
+    let mut name_pattern = None;
+    let mut file_type = None;
+    let mut exec_command: Option<Vec<&str>> = None;
+
+    while let Some(arg) = args.next() {
+        match (arg, args.peek()) {
+            ("-name", Some(_)) => name_pattern = args.next(),
+            ("-type", Some(&("f" | "d" | "l"))) => file_type = args.next(),
+            ("-exec", Some(_)) => {
+                // Everything up to the terminating ';' is the command to run
+                let mut command = Vec::new();
+                let mut terminated = false;
+                for word in args.by_ref() {
+                    if word == ";" || word == "\\;" {
+                        terminated = true;
+                        break;
+                    }
+
+                    command.push(word);
+                }
+
+                if command.is_empty() || !terminated {
+                    eprintln!("{}", usage);
+                    return StatusCode::new(1);
+                }
+
+                exec_command = Some(command);
+            }
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let root_path = match root.starts_with('~') {
+        true => match path::resolve(root, context.home()) {
+            Some(path) => path,
+            None => {
+                eprintln!("Invalid path: '{}'", root);
+                return StatusCode::new(2);
+            }
+        },
+        false => PathBuf::from(root),
+    };
+
+    if fs::symlink_metadata(&root_path).is_err() {
+        eprintln!("Invalid path: '{}'", root);
+        return StatusCode::new(2);
+    }
+
+    let mut status_code = StatusCode::success();
+
+    for found in path::walk(&root_path, false) {
+        if let Some(pattern) = name_pattern {
+            if !path::glob_match(pattern, &file_name_of(&found)) {
+                continue;
+            }
+        }
+
+        if let Some(file_type) = file_type {
+            let metadata = match fs::symlink_metadata(&found) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+
+            let matches_type = match file_type {
+                "f" => metadata.is_file(),
+                "d" => metadata.is_dir(),
+                _ => metadata.file_type().is_symlink(),
+            };
+
+            if !matches_type {
+                continue;
+            }
+        }
+
+        let found = found.to_string_lossy().to_string();
+        match &exec_command {
+            Some(command) => {
+                // Substitute the matched path for every '{}' in the command
+                let words: Vec<String> = command.iter().map(|w| w.replace("{}", &found)).collect();
+                let command_args: Vec<&str> = words[1..].iter().map(|w| w.as_str()).collect();
+
+                match context.dispatch(&words[0], command_args) {
+                    Some(code) if code.is_success() => (),
+                    Some(_) => status_code = StatusCode::new(1),
+                    None => {
+                        eprintln!("Unknown command: {}", words[0].red());
+                        status_code = StatusCode::new(1);
+                    }
+                }
+            }
+            None => println!("{}", found),
+        }
+    }
+
+    status_code
+}
+
+// Prints the current local date and time, optionally using a '+'-prefixed format string
+pub fn date(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let format = match args.len() {
+        0 => String::from("%Y-%m-%d %H:%M:%S"),
+        _ => match args.join(" ").strip_prefix('+') {
+            Some(format) => format.to_string(),
+            None => {
+                eprintln!("Usage: date <+format>");
+                return StatusCode::new(1);
+            }
+        },
+    };
+
+    println!("{}", DateTime::now().format(&format));
+    StatusCode::success()
+}
+
+// Sorts the lines of a file (or stdin) and prints them
+pub fn sort(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: sort [-r] [-n] [-u] <path (default stdin)>";
+    let (mut reverse, mut numeric, mut unique) = (false, false, false);
+    let mut file_name = None;
+
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'r' => reverse = true,
+                        'n' => numeric = true,
+                        'u' => unique = true,
+                        _ => {
+                            eprintln!("{}", usage);
+                            return StatusCode::new(1);
+                        }
+                    }
+                }
+            }
+            _ if file_name.is_none() => file_name = Some(arg),
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let mut lines = match read_input_lines(file_name) {
+        Some(lines) => lines,
+        None => return StatusCode::new(2),
+    };
+
+    sort_lines(&mut lines, numeric, reverse, unique);
+    for line in lines {
+        println!("{}", line);
+    }
+
+    StatusCode::success()
+}
+
+// Sorts lines in place, optionally by their leading number, in reverse, or with duplicates removed
+// Lines with equal keys fall back to a plain comparison so the order is always deterministic
+pub fn sort_lines(lines: &mut Vec<String>, numeric: bool, reverse: bool, unique: bool) {
+    lines.sort_by(|a, b| {
+        let ordering = compare_sort_keys(a, b, numeric).then_with(|| a.cmp(b));
+        match reverse {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    });
+
+    // Like 'sort -u', uniqueness is decided by the sort key alone
+    if unique {
+        lines.dedup_by(|a, b| compare_sort_keys(a, b, numeric) == Ordering::Equal);
+    }
+}
+
+// Compares two lines either lexicographically or by their leading numbers
+pub fn compare_sort_keys(a: &str, b: &str, numeric: bool) -> Ordering {
+    match numeric {
+        true => leading_number(a).total_cmp(&leading_number(b)),
+        false => a.cmp(b),
+    }
+}
+
+// Parses the number at the start of a line the way 'sort -n' does,
+// ignoring leading blanks and treating lines without a number as 0
+pub fn leading_number(line: &str) -> f64 {
+    let line = line.trim_start();
+    let mut end = 0;
+    let mut seen_point = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '-' if index == 0 => (),
+            '.' if !seen_point => seen_point = true,
+            '0'..='9' => (),
+            _ => break,
+        }
+
+        end = index + c.len_utf8();
+    }
+
+    line[..end].parse().unwrap_or(0.0)
+}
+
+// Collapses consecutive duplicate lines of a file (or stdin) and prints them
+pub fn uniq(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: uniq [-c] [-d] <path (default stdin)>";
+    let (mut count, mut duplicates_only) = (false, false);
+    let mut file_name = None;
+
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'c' => count = true,
+                        'd' => duplicates_only = true,
+                        _ => {
+                            eprintln!("{}", usage);
+                            return StatusCode::new(1);
+                        }
+                    }
+                }
+            }
+            _ if file_name.is_none() => file_name = Some(arg),
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let lines = match read_input_lines(file_name) {
+        Some(lines) => lines,
+        None => return StatusCode::new(2),
+    };
+
+    for line in uniq_lines(lines, count, duplicates_only) {
+        println!("{}", line);
+    }
+
+    StatusCode::success()
+}
+
+// Produces the output lines of 'uniq', optionally prefixed with counts or limited to duplicated lines
+pub fn uniq_lines<I: IntoIterator<Item = String>>(
+    lines: I,
+    count: bool,
+    duplicates_only: bool,
+) -> Vec<String> {
+    collapse_duplicates(lines)
+        .into_iter()
+        .filter(|(_, occurrences)| !duplicates_only || *occurrences > 1)
+        .map(|(line, occurrences)| match count {
+            true => format!("{:>7} {}", occurrences, line),
+            false => line,
+        })
+        .collect()
+}
+
+// Groups runs of identical consecutive lines, pairing each distinct line with its run length
+pub fn collapse_duplicates<I: IntoIterator<Item = String>>(lines: I) -> Vec<(String, usize)> {
+    let mut groups: Vec<(String, usize)> = Vec::new();
+
+    for line in lines {
+        match groups.last_mut() {
+            Some((previous, occurrences)) if *previous == line => *occurrences += 1,
+            _ => groups.push((line, 1)),
+        }
+    }
+
+    groups
+}
+
+// Copies stdin to stdout and to each of the given files
+pub fn tee(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let append = args.first() == Some(&"-a");
+    let file_names = match append {
+        true => &args[1..],
+        false => &args[..],
+    };
+
+    if file_names.iter().any(|name| name.starts_with('-')) {
+        eprintln!("Usage: tee [-a] <paths...>");
+        return StatusCode::new(1);
+    }
+
+    let mut status_code = StatusCode::success();
+    let mut files = Vec::new();
+
+    // A file that can't be opened shouldn't stop the others from being written
+    for file_name in file_names {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(file_name);
+
+        match file {
+            Ok(file) => files.push(file),
+            Err(_) => {
+                eprintln!("Failed to open file: '{}'", file_name);
+                status_code = StatusCode::new(2);
+            }
+        }
+    }
+
+    if tee_copy(stdin().lock(), &mut stdout(), &mut files).is_err() {
+        eprintln!("Failed to copy input");
+        return StatusCode::new(3);
+    }
+
+    status_code
+}
+
+// Copies everything from the input to the output and to every file as it is read
+pub fn tee_copy<R: Read, W: Write>(
+    mut input: R,
+    output: &mut W,
+    files: &mut [fs::File],
+) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = input.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        output.write_all(&buffer[..read])?;
+        output.flush()?;
+
+        for file in files.iter_mut() {
+            file.write_all(&buffer[..read])?;
+        }
+    }
+}
+
+// Prints what a prompt format would look like right now, without changing $PROMPT
+pub fn render_prompt(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args.as_slice() {
+        [format] => {
+            println!("{}", context.shell.render_prompt(format));
+            StatusCode::success()
+        }
+        _ => {
+            eprintln!("Usage: render-prompt <format>");
+            StatusCode::new(1)
+        }
+    }
+}
+
+// Prints the lines of files (or stdin) that contain a pattern
+// -r searches every file beneath directories, and -a also searches files that look binary
+// -i ignores case, and -c prints how many lines matched in each file instead of the lines
+// -A, -B and -C also print that many lines after, before or around each match, with '--' between groups
+// Succeeds only if something matched, so it can be used as a condition
+pub fn search(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: search [-r] [-a] [-i] [-c] [-A <n>] [-B <n>] [-C <n>] <pattern> <paths (default stdin)...>";
+    let parsed = match FlagParser::new()
+        .flag("recursive", &["-r", "--recursive"])
+        .flag("text", &["-a", "--text"])
+        .flag("ignore-case", &["-i", "--ignore-case"])
+        .flag("count", &["-c", "--count"])
+        .option("after", &["-A", "--after-context"])
+        .option("before", &["-B", "--before-context"])
+        .option("context", &["-C", "--context"])
+        .parse(&args)
+    {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("{}\n{}", error, usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    let (recursive, include_binary) = (parsed.has("recursive"), parsed.has("text"));
+    let (ignore_case, count) = (parsed.has("ignore-case"), parsed.has("count"));
+    // -A and -B take precedence over -C for their side of each match
+    let context_lines = |name: &str| match parsed.value(name).or(parsed.value("context")) {
+        Some(lines) => lines.parse::<usize>().map_err(|_| lines),
+        None => Ok(0),
+    };
+    let context = match (context_lines("before"), context_lines("after")) {
+        (Ok(before), Ok(after)) => SearchContext { before, after },
+        (Err(lines), _) | (_, Err(lines)) => {
+            eprintln!("Invalid number of context lines: '{}'", lines);
+            return StatusCode::new(2);
+        }
+    };
+
+    let (pattern, paths) = match parsed.positional.split_first() {
+        Some((pattern, paths)) => (SearchPattern::new(pattern, ignore_case), paths),
+        None => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    if paths.is_empty() {
+        let lines = stdin().lock().lines().map_while(Result::ok);
+        let mut window = ContextWindow::new(&pattern, context);
+        let mut found = 0;
+        let mut output = Vec::new();
+        for line in lines {
+            window.push(line, &mut output);
+            for line in output.drain(..) {
+                if let SearchLine::Match(_) = line {
+                    found += 1;
+                }
+                if !count {
+                    println!("{}", line.format(None));
+                }
+            }
+        }
+        if count {
+            println!("{}", found);
+        }
+        return condition_status(Ok(found > 0));
+    }
+
+    let mut files = Vec::new();
+    let mut status_code = None;
+    for path in paths {
+        let path = PathBuf::from(path);
+        if path.is_dir() && !recursive {
+            eprintln!("Is a directory (use -r to search it): '{}'", path.display());
+            status_code = Some(StatusCode::new(2));
+        } else {
+            files.extend(search_targets(&path));
+        }
+    }
+
+    // File names are only shown when there could be more than one file
+    let show_names = recursive || files.len() > 1;
+
+    if count {
+        let (counts, unreadable) = count_matches(&files, &pattern, include_binary);
+        for file in unreadable {
+            eprintln!("Failed to read file: '{}'", file.display());
+            status_code = Some(StatusCode::new(2));
+        }
+        for (file, count) in &counts {
+            match show_names {
+                true => println!("{}:{}", file.display(), count),
+                false => println!("{}", count),
+            }
+        }
+
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        return match status_code {
+            Some(status_code) if total == 0 => status_code,
+            _ => condition_status(Ok(total > 0)),
+        };
+    }
+
+    let (matches, unreadable) = search_files(&files, &pattern, include_binary, show_names, context);
+    for file in unreadable {
+        eprintln!("Failed to read file: '{}'", file.display());
+        status_code = Some(StatusCode::new(2));
+    }
+    for line in &matches {
+        println!("{}", line);
+    }
+
+    match status_code {
+        Some(status_code) if matches.is_empty() => status_code,
+        _ => condition_status(Ok(!matches.is_empty())),
+    }
+}
+
+// Gets the files to search for a path: the path itself, or every file beneath it if it's a directory
+// Symlinks aren't followed, so links back up the tree can't cause a loop
+fn search_targets(path: &Path) -> Vec<PathBuf> {
+    match path.is_dir() {
+        true => path::walk(path, false)
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect(),
+        false => vec![path.to_path_buf()],
+    }
+}
+
+// What 'search' looks for in each line
+#[derive(Debug)]
+pub struct SearchPattern {
+    text: String,
+    ignore_case: bool,
+}
+
+impl SearchPattern {
+    pub fn new(text: &str, ignore_case: bool) -> Self {
+        let text = match ignore_case {
+            true => text.to_lowercase(),
+            false => text.to_string(),
+        };
+
+        Self { text, ignore_case }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        match self.ignore_case {
+            true => line.to_lowercase().contains(&self.text),
+            false => line.contains(&self.text),
+        }
+    }
+
+    // Finds where the pattern occurs in a line, as the byte ranges of each match (none overlap)
+    pub fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        if self.text.is_empty() {
+            return Vec::new();
+        }
+        if !self.ignore_case {
+            return line
+                .match_indices(&self.text)
+                .map(|(index, matched)| index..index + matched.len())
+                .collect();
+        }
+
+        // Lowercasing can change how long a character is, so each match is measured in the original line
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while let Some(c) = line[start..].chars().next() {
+            match self.match_length(&line[start..]) {
+                Some(length) => {
+                    ranges.push(start..start + length);
+                    start += length;
+                }
+                None => start += c.len_utf8(),
+            }
+        }
+        ranges
+    }
+
+    // Gets the length of the match at the start of some text, ignoring case
+    fn match_length(&self, text: &str) -> Option<usize> {
+        let mut lowered = String::new();
+        for (index, c) in text.char_indices() {
+            lowered.extend(c.to_lowercase());
+            if !self.text.starts_with(&lowered) {
+                return None;
+            }
+            if lowered.len() == self.text.len() {
+                return Some(index + c.len_utf8());
+            }
+        }
+        None
+    }
+}
+
+// Searches each file, returning the matching lines (prefixed with 'path:' if showing names)
+// along with the files that couldn't be read
+// With context, the lines around matches are included too (prefixed with 'path-'), and groups are separated by '--'
+pub fn search_files(
+    files: &[PathBuf],
+    pattern: &SearchPattern,
+    include_binary: bool,
+    show_names: bool,
+    context: SearchContext,
+) -> (Vec<String>, Vec<PathBuf>) {
+    let mut matches = Vec::new();
+    let mut unreadable = Vec::new();
+
+    for file in files {
+        match search_file_with_context(file, pattern, include_binary, context) {
+            Ok(Some(lines)) => {
+                // Groups from different files are never next to each other
+                if !context.is_empty() && !matches.is_empty() && !lines.is_empty() {
+                    matches.push(SearchLine::Separator.format(None));
+                }
+                let name = show_names.then_some(file.as_path());
+                matches.extend(lines.iter().map(|line| line.format(name)));
+            }
+            Ok(None) => (),
+            Err(_) => unreadable.push(file.clone()),
+        }
+    }
+
+    (matches, unreadable)
+}
+
+// How many lines 'search' shows before and after each match
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchContext {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl SearchContext {
+    pub fn is_empty(&self) -> bool {
+        self.before == 0 && self.after == 0
+    }
+}
+
+// A line shown by 'search'
+#[derive(Debug, PartialEq, Eq)]
+pub enum SearchLine {
+    Match(String),
+    // A line near a match, shown because of -A, -B or -C
+    Context(String),
+    // Goes between groups of lines that aren't next to each other
+    Separator,
+}
+
+impl SearchLine {
+    // Formats the line like grep, with the file name followed by ':' for matches and '-' for context
+    pub fn format(&self, file: Option<&Path>) -> String {
+        match (self, file) {
+            (Self::Match(line), Some(file)) => format!("{}:{}", file.display(), line),
+            (Self::Context(line), Some(file)) => format!("{}-{}", file.display(), line),
+            (Self::Match(line) | Self::Context(line), None) => line.clone(),
+            (Self::Separator, _) => String::from("--"),
+        }
+    }
+}
+
+// Picks out the matching lines of a stream along with the lines around them, one line at a time
+// The lines before a match are held back until it's known whether they're needed
+pub struct ContextWindow<'a> {
+    pattern: &'a SearchPattern,
+    context: SearchContext,
+    // The most recent lines that haven't been shown, which could come before a match
+    before: VecDeque<String>,
+    // How many more lines to show after the last match
+    pending_after: usize,
+    // The number of the last line shown, to tell whether a separator is needed before the next group
+    last_shown: Option<usize>,
+    line_number: usize,
+}
+
+impl<'a> ContextWindow<'a> {
+    pub fn new(pattern: &'a SearchPattern, context: SearchContext) -> Self {
+        Self {
+            pattern,
+            context,
+            before: VecDeque::new(),
+            pending_after: 0,
+            last_shown: None,
+            line_number: 0,
+        }
+    }
+
+    // Adds the next line of the stream, appending whatever it lets be shown to the output
+    pub fn push(&mut self, line: String, output: &mut Vec<SearchLine>) {
+        let number = self.line_number;
+        self.line_number += 1;
+
+        if self.pattern.matches(&line) {
+            let first = number - self.before.len();
+            if !self.context.is_empty() && self.last_shown.is_some_and(|last| first > last + 1) {
+                output.push(SearchLine::Separator);
+            }
+            output.extend(self.before.drain(..).map(SearchLine::Context));
+            output.push(SearchLine::Match(line));
+            self.pending_after = self.context.after;
+            self.last_shown = Some(number);
+        } else if self.pending_after > 0 {
+            output.push(SearchLine::Context(line));
+            self.pending_after -= 1;
+            self.last_shown = Some(number);
+        } else if self.context.before > 0 {
+            if self.before.len() == self.context.before {
+                self.before.pop_front();
+            }
+            self.before.push_back(line);
+        }
+    }
+}
+
+// Counts the matching lines in each file, along with the files that couldn't be read
+// Files skipped for looking binary aren't counted at all
+pub fn count_matches(
+    files: &[PathBuf],
+    pattern: &SearchPattern,
+    include_binary: bool,
+) -> (Vec<(PathBuf, usize)>, Vec<PathBuf>) {
+    let mut counts = Vec::new();
+    let mut unreadable = Vec::new();
+
+    for file in files {
+        match search_file(file, pattern, include_binary) {
+            Ok(Some(lines)) => counts.push((file.clone(), lines.len())),
+            Ok(None) => (),
+            Err(_) => unreadable.push(file.clone()),
+        }
+    }
+
+    (counts, unreadable)
+}
+
+// The number of bytes checked for a NUL byte when deciding whether a file is binary
+const BINARY_CHECK_LENGTH: usize = 8192;
+
+// Finds the lines of a file that contain a pattern
+// Returns None if the file looks binary (it has a NUL byte near the start) and binary files aren't included
+// Lines that aren't valid UTF-8 are matched with the invalid bytes replaced
+pub fn search_file(
+    path: &Path,
+    pattern: &SearchPattern,
+    include_binary: bool,
+) -> io::Result<Option<Vec<String>>> {
+    let lines = search_file_with_context(path, pattern, include_binary, SearchContext::default())?;
+    Ok(lines.map(|lines| {
+        lines
+            .into_iter()
+            .filter_map(|line| match line {
+                SearchLine::Match(line) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }))
+}
+
+// Finds the lines of a file that contain a pattern, along with the lines around them
+pub fn search_file_with_context(
+    path: &Path,
+    pattern: &SearchPattern,
+    include_binary: bool,
+    context: SearchContext,
+) -> io::Result<Option<Vec<SearchLine>>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    if !include_binary
+        && reader
+            .fill_buf()?
+            .iter()
+            .take(BINARY_CHECK_LENGTH)
+            .any(|&byte| byte == 0)
+    {
+        return Ok(None);
+    }
+
+    let mut window = ContextWindow::new(pattern, context);
+    let mut lines = Vec::new();
+    for line in reader.split(b'\n') {
+        window.push(String::from_utf8_lossy(&line?).into_owned(), &mut lines);
+    }
+
+    Ok(Some(lines))
+}
+
+// The details of a file shown by 'stat'
+pub struct FileInfo {
+    pub name: String,
+    pub size: u64,
+    pub kind: &'static str,
+    pub permissions: u32,
+    pub modified: SystemTime,
+}
+
+impl FileInfo {
+    // Reads the details of a file, only following it if it's a symlink when asked to
+    pub fn read(path: &str, follow_symlinks: bool) -> io::Result<Self> {
+        let metadata = match follow_symlinks {
+            true => fs::metadata(path)?,
+            false => fs::symlink_metadata(path)?,
+        };
+        let file_type = metadata.file_type();
+
+        let kind = if file_type.is_symlink() {
+            "symbolic link"
+        } else if file_type.is_dir() {
+            "directory"
+        } else if file_type.is_file() && metadata.len() == 0 {
+            "regular empty file"
+        } else if file_type.is_file() {
+            "regular file"
+        } else {
+            "special file"
+        };
+
+        Ok(Self {
+            name: path.to_string(),
+            size: metadata.len(),
+            kind,
+            permissions: permission_bits(&metadata),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permission_bits(metadata: &fs::Metadata) -> u32 {
+    match metadata.permissions().readonly() {
+        true => 0o444,
+        false => 0o644,
+    }
+}
+
+// The layout 'stat' uses when no format is given
+const DEFAULT_STAT_FORMAT: &str = "  File: %n\n  Size: %s\n  Type: %F\nAccess: %a\nModify: %y";
+
+// Prints details about each file, optionally in a custom layout with -c/--format
+// Symlinks are described themselves, unless -L asks for what they point to instead
+pub fn stat(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let parsed = FlagParser::new()
+        .option("format", &["-c", "--format"])
+        .flag("dereference", &["-L", "--dereference"])
+        .parse(&args);
+
+    let (format, dereference, paths) = match parsed {
+        Ok(parsed) if !parsed.positional.is_empty() => (
+            parsed.value("format").unwrap_or(DEFAULT_STAT_FORMAT),
+            parsed.has("dereference"),
+            parsed.positional,
+        ),
+        _ => {
+            eprintln!("Usage: stat [-L] [-c <format>] <paths...>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let mut status_code = StatusCode::success();
+    for path in paths {
+        match FileInfo::read(path, dereference) {
+            Ok(info) => println!("{}", format_file_info(format, &info)),
+            Err(_) if dereference && symlink_target(path).is_some() => {
+                eprintln!("Broken symlink: '{}'", path);
+                status_code = StatusCode::new(2);
+            }
+            Err(_) => {
+                eprintln!("Failed to read file information: '{}'", path);
+                status_code = StatusCode::new(2);
+            }
+        }
+    }
+
+    status_code
+}
+
+// Replaces the tokens in a 'stat' format: %n (name), %s (size in bytes), %F (file type),
+// %a (permissions in octal), %y (modification time), \n (newline) and %% (a literal '%')
+// Unknown tokens are passed through literally
+pub fn format_file_info(format: &str, info: &FileInfo) -> String {
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => match chars.next() {
+                Some('n') => formatted.push_str(&info.name),
+                Some('s') => formatted.push_str(&info.size.to_string()),
+                Some('F') => formatted.push_str(info.kind),
+                Some('a') => formatted.push_str(&format!("{:o}", info.permissions)),
+                Some('y') => formatted.push_str(
+                    &DateTime::from_system_time(info.modified).format("%Y-%m-%d %H:%M:%S"),
+                ),
+                Some('%') => formatted.push('%'),
+                Some(other) => {
+                    formatted.push('%');
+                    formatted.push(other);
+                }
+                None => formatted.push('%'),
+            },
+            '\\' => match chars.next() {
+                Some('n') => formatted.push('\n'),
+                Some(other) => {
+                    formatted.push('\\');
+                    formatted.push(other);
+                }
+                None => formatted.push('\\'),
+            },
+            c => formatted.push(c),
+        }
+    }
+
+    formatted
+}
+
+// Prints the last component of a path, optionally removing a suffix
+// With no path, transforms each line of stdin instead
+pub fn basename(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args.as_slice() {
+        [] => transform_stdin_lines(|line| base_name(line, None)),
+        [path] => {
+            println!("{}", base_name(path, None));
+            StatusCode::success()
+        }
+        [path, suffix] => {
+            println!("{}", base_name(path, Some(suffix)));
+            StatusCode::success()
+        }
+        _ => {
+            eprintln!("Usage: basename <path (default stdin)> [suffix]");
+            StatusCode::new(1)
+        }
+    }
+}
+
+// Prints each path with its last component removed
+// With no paths, transforms each line of stdin instead
+pub fn dirname(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        return transform_stdin_lines(dir_name);
+    }
+
+    for path in args {
+        println!("{}", dir_name(path));
+    }
+
+    StatusCode::success()
+}
+
+fn transform_stdin_lines(transform: impl Fn(&str) -> String) -> StatusCode {
+    match transform_lines(stdin().lock(), &mut stdout(), transform) {
+        Ok(_) => StatusCode::success(),
+        Err(_) => {
+            eprintln!("Failed to read input");
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Writes every line of the input to the output after transforming it
+pub fn transform_lines<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    transform: impl Fn(&str) -> String,
+) -> io::Result<()> {
+    for line in input.lines() {
+        writeln!(output, "{}", transform(&line?))?;
+    }
+
+    output.flush()
+}
+
+// Gets the last component of a path the way 'basename' does, ignoring trailing slashes
+// The suffix is only removed if something would be left afterwards
+pub fn base_name(path: &str, suffix: Option<&str>) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return String::from(if path.is_empty() { "" } else { "/" });
+    }
+
+    let name = trimmed.rsplit('/').next().unwrap_or(trimmed);
+    match suffix.and_then(|suffix| name.strip_suffix(suffix)) {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+// Gets everything before the last component of a path the way 'dirname' does
+pub fn dir_name(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return String::from(if path.is_empty() { "." } else { "/" });
+    }
+
+    match trimmed.rsplit_once('/') {
+        Some((parent, _)) => match parent.trim_end_matches('/') {
+            "" => String::from("/"),
+            parent => parent.to_string(),
+        },
+        None => String::from("."),
+    }
+}
+
+// Runs a command with arguments read from stdin appended to it
+pub fn xargs(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: xargs [-0] [-n <count>] <command> [arguments...]";
+    let mut null_separated = false;
+    let mut batch_size = None;
+    let mut args = args.into_iter().peekable();
+
+    // Options are only read up until the command name
+    while let Some(arg) = args.next_if(|a| a.starts_with('-')) {
+        match arg {
+            "-0" => null_separated = true,
+            "-n" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => batch_size = Some(n),
+                _ => {
+                    eprintln!("{}", usage);
+                    return StatusCode::new(1);
+                }
+            },
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    let command: Vec<&str> = args.collect();
+    if command.is_empty() {
+        eprintln!("{}", usage);
+        return StatusCode::new(1);
+    }
+
+    let mut input = String::new();
+    if stdin().lock().read_to_string(&mut input).is_err() {
+        eprintln!("Failed to read input");
+        return StatusCode::new(2);
+    }
+
+    let batches = batch_arguments(split_arguments(&input, null_separated), batch_size);
+    run_batches(context, &command, batches)
+}
+
+// Splits input into arguments on whitespace, or on NUL characters if requested
+pub fn split_arguments(input: &str, null_separated: bool) -> Vec<String> {
+    match null_separated {
+        true => input
+            .split('\0')
+            .filter(|a| !a.is_empty())
+            .map(String::from)
+            .collect(),
+        false => input.split_whitespace().map(String::from).collect(),
+    }
+}
+
+// Groups arguments into batches of at most batch_size, or one batch holding everything
+// There is always at least one (possibly empty) batch, so the command runs at least once
+pub fn batch_arguments(arguments: Vec<String>, batch_size: Option<usize>) -> Vec<Vec<String>> {
+    match batch_size {
+        Some(size) if !arguments.is_empty() => {
+            arguments.chunks(size).map(|chunk| chunk.to_vec()).collect()
+        }
+        _ => vec![arguments],
+    }
+}
+
+// Dispatches a command once per batch with the batch appended to its arguments
+// Returns a failure if any of the invocations failed
+fn run_batches(context: &mut Context, command: &[&str], batches: Vec<Vec<String>>) -> StatusCode {
+    let mut status_code = StatusCode::success();
+
+    for batch in batches {
+        let mut command_args = command[1..].to_vec();
+        command_args.extend(batch.iter().map(|a| a.as_str()));
+
+        match context.dispatch(command[0], command_args) {
+            Some(code) if code.is_success() => (),
+            Some(_) => status_code = StatusCode::new(1),
+            None => {
+                eprintln!("Unknown command: {}", command[0].red());
+                return StatusCode::new(127);
+            }
+        }
+    }
+
+    status_code
+}
+
+// Prints selected fields or characters from each line of a file (or stdin)
+pub fn cut(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: cut [-d <delimiter>] -f <fields> | -c <characters> <path (default stdin)>";
+    let mut delimiter = '\t';
+    let mut fields = None;
+    let mut characters = None;
+    let mut file_name = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Option values may either be attached ('-f1,2') or the following argument ('-f 1,2')
+        let (option, value) = match arg.char_indices().nth(2) {
+            Some((index, _)) if arg.starts_with('-') => arg.split_at(index),
+            _ => (arg, ""),
+        };
+
+        let value = match (option, value) {
+            ("-d" | "-f" | "-c", "") => args.next(),
+            ("-d" | "-f" | "-c", value) => Some(value),
+            _ => None,
+        };
+
+        match (option, value) {
+            ("-d", Some(value)) if value.chars().count() == 1 => {
+                delimiter = value.chars().next().unwrap()
+            }
+            ("-f", Some(value)) if parse_cut_list(value).is_some() => {
+                fields = parse_cut_list(value)
+            }
+            ("-c", Some(value)) if parse_cut_list(value).is_some() => {
+                characters = parse_cut_list(value)
+            }
+            (_, None) if !arg.starts_with('-') && file_name.is_none() => file_name = Some(arg),
+            _ => {
+                eprintln!("{}", usage);
+                return StatusCode::new(1);
+            }
+        }
+    }
+
+    // Exactly one of the field or character selections must be given
+    if fields.is_some() == characters.is_some() {
+        eprintln!("{}", usage);
+        return StatusCode::new(1);
+    }
+
+    let reader = match open_input(file_name) {
+        Some(reader) => reader,
+        None => return StatusCode::new(2),
+    };
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => {
+                eprintln!("Failed to read input");
+                return StatusCode::new(2);
+            }
+        };
+
+        match (&fields, &characters) {
+            (Some(fields), _) => println!("{}", cut_fields(&line, delimiter, fields)),
+            (_, Some(characters)) => println!("{}", cut_characters(&line, characters)),
+            _ => (),
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Parses a comma-separated list of 1-based positions and ranges ('1', '2-4', '3-', '-2')
+// into inclusive (start, end) pairs, with usize::MAX standing in for an open end
+pub fn parse_cut_list(list: &str) -> Option<Vec<(usize, usize)>> {
+    let mut ranges = Vec::new();
+
+    for item in list.split(',') {
+        let range = match item.split_once('-') {
+            Some(("", "")) => return None,
+            Some((start, end)) => {
+                let start = match start {
+                    "" => 1,
+                    _ => start.parse().ok()?,
+                };
+                let end = match end {
+                    "" => usize::MAX,
+                    _ => end.parse().ok()?,
+                };
+
+                (start, end)
+            }
+            None => {
+                let position = item.parse().ok()?;
+                (position, position)
+            }
+        };
+
+        if range.0 == 0 || range.0 > range.1 {
+            return None;
+        }
+
+        ranges.push(range);
+    }
+
+    Some(ranges)
+}
+
+// Checks whether a 1-based position falls within any of the ranges
+fn in_cut_ranges(position: usize, ranges: &[(usize, usize)]) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&position))
+}
+
+// Selects the delimited fields of a line that fall within the ranges, keeping their original order
+// Fields past the end of the line are simply left out
+pub fn cut_fields(line: &str, delimiter: char, ranges: &[(usize, usize)]) -> String {
+    line.split(delimiter)
+        .enumerate()
+        .filter(|(index, _)| in_cut_ranges(index + 1, ranges))
+        .map(|(_, field)| field)
+        .collect::<Vec<&str>>()
+        .join(&delimiter.to_string())
+}
+
+// Selects the characters of a line that fall within the ranges
+pub fn cut_characters(line: &str, ranges: &[(usize, usize)]) -> String {
+    line.chars()
+        .enumerate()
+        .filter(|(index, _)| in_cut_ranges(index + 1, ranges))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+// Opens a file for buffered reading, or stdin if no file is given
+// Prints an error and returns None if the file could not be opened
+fn open_input(file_name: Option<&str>) -> Option<Box<dyn BufRead>> {
+    match file_name {
+        Some(file_name) => match fs::File::open(file_name) {
+            Ok(file) => Some(Box::new(BufReader::new(file))),
+            Err(_) => {
+                eprintln!("Failed to open file: '{}'", file_name);
+                None
+            }
+        },
+        None => Some(Box::new(stdin().lock())),
+    }
+}
+
+// Reads every line from a file, or from stdin if no file is given
+// Prints an error and returns None if the input could not be read
+fn read_input_lines(file_name: Option<&str>) -> Option<Vec<String>> {
+    let reader = open_input(file_name)?;
+
+    match reader.lines().collect() {
+        Ok(lines) => Some(lines),
+        Err(_) => {
+            eprintln!("Failed to read input");
+            None
+        }
+    }
+}
+
+// Defines, lists, or persists user aliases
+pub fn alias(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (save, args) = match args.first() {
+        Some(&"--save") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    // With no definition, list every alias
+    if args.is_empty() && !save {
+        for (name, definition) in &context.shell.aliases {
+            println!("{}", config::alias_line(name, definition));
+        }
+
+        return StatusCode::success();
+    }
+
+    // Definitions may span multiple words ('alias ll=list-directory -a')
+    let words = args.join(" ");
+    let (name, definition) = match words.split_once('=') {
+        Some((name, definition)) if !name.is_empty() && !name.contains(' ') => {
+            (name.to_string(), strip_quotes(definition).to_string())
+        }
+        // A lone name prints that alias
+        None if args.len() == 1 && !save => match context.shell.aliases.get(args[0]) {
+            Some(definition) => {
+                println!("{}", config::alias_line(args[0], definition));
+                return StatusCode::success();
+            }
+            None => {
+                eprintln!("Unknown alias: '{}'", args[0]);
+                return StatusCode::new(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: alias [--save] <name>=<command>");
+            return StatusCode::new(1);
+        }
+    };
+
+    if save {
+        let config_path = config::config_path(context.home());
+        if config::save_alias(&config_path, &name, &definition).is_err() {
+            eprintln!("Failed to save alias to '{}'", config_path.display());
+            return StatusCode::new(3);
+        }
+    }
+
+    context.shell.aliases.insert(name, definition);
+    StatusCode::success()
+}
+
+// Names a directory so that 'change-directory @name' can jump to it, optionally persisting it
+pub fn bookmark(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (save, args) = match args.first() {
+        Some(&"--save") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    let (name, path) = match args {
+        [name, path] => (name.strip_prefix('@').unwrap_or(name), *path),
+        _ => {
+            eprintln!("Usage: bookmark [--save] <name> <path>");
+            return StatusCode::new(1);
+        }
+    };
+
+    if !config::is_bookmark_name(name) {
+        eprintln!("Invalid bookmark name: '{}'", name);
+        return StatusCode::new(2);
+    }
+
+    let directory = match path::resolve(path, context.home()) {
+        Some(directory) if directory.is_dir() => directory,
+        _ => {
+            eprintln!("Invalid path: '{}'", path);
+            return StatusCode::new(2);
+        }
+    };
+
+    if save {
+        let config_path = config::config_path(context.home());
+        if config::save_bookmark(&config_path, name, &directory).is_err() {
+            eprintln!("Failed to save bookmark to '{}'", config_path.display());
+            return StatusCode::new(3);
+        }
+    }
+
+    context.shell.bookmarks.insert(name.to_string(), directory);
+    StatusCode::success()
+}
+
+// Lists the bookmarked directories
+pub fn bookmarks(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: bookmarks");
+        return StatusCode::new(1);
+    }
+
+    let width = context.shell.bookmarks.keys().map(|name| name.len()).max();
+    for (name, directory) in &context.shell.bookmarks {
+        let name = format!("@{}", name);
+        // The names are padded so that the directories line up
+        println!(
+            "{:<width$}  {}",
+            name,
+            directory.display(),
+            width = width.unwrap_or_default() + 1
+        );
+    }
+
+    StatusCode::success()
+}
+
+// Resolves a bookmark reference without its '@', like 'proj' or 'proj/src'
+pub fn resolve_bookmark(bookmarks: &BTreeMap<String, PathBuf>, reference: &str) -> Option<PathBuf> {
+    let (name, rest) = reference.split_once('/').unwrap_or((reference, ""));
+    bookmarks.get(name).map(|directory| directory.join(rest))
+}
+
+// Exports variables to external commands, or prints every exported variable with '-p'
+pub fn export(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() || args == ["-p"] {
+        for (name, value) in context.env().exported_variables() {
+            println!("{}", export_declaration(&name, &value));
+        }
+
+        return StatusCode::success();
+    }
+
+    for arg in &args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+
+        if !parser::is_variable_name(name) {
+            eprintln!("Invalid variable name: '{}'", name);
+            return StatusCode::new(2);
+        }
+
+        context.env_mut().export_variable(name, value);
+    }
+
+    StatusCode::success()
+}
+
+// Formats a variable as an 'export' command that can be sourced to restore it
+pub fn export_declaration(name: &str, value: &str) -> String {
+    format!("export {}={}", name, parser::quote(value))
+}
+
+// Runs every command in a file within the current shell
+pub fn source(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.len() != 1 {
+        eprintln!("Usage: source <path>");
+        return StatusCode::new(1);
+    }
+
+    let path = match path::resolve(args[0], context.home()) {
+        Some(path) => path,
+        None => {
+            eprintln!("Invalid path: '{}'", args[0]);
+            return StatusCode::new(2);
+        }
+    };
+
+    match context.shell.source_script(&path) {
+        Ok(status) => StatusCode::new(status),
+        Err(error) => match error.downcast_ref::<ShellError>() {
+            Some(ShellError::RecursiveSource(_)) => {
+                eprintln!("{}", error);
+                StatusCode::new(3)
+            }
+            _ => {
+                eprintln!("Failed to read file: '{}'", args[0]);
+                StatusCode::new(2)
+            }
+        },
+    }
+}
+
+// Sets variables that only exist until the sourced script setting them finishes ('local NAME=value')
+// A name without a value is set to an empty string
+pub fn local(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        eprintln!("Usage: local <name[=value]...>");
+        return StatusCode::new(1);
+    }
+
+    for arg in args {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        if !parser::is_variable_name(name) {
+            eprintln!("Invalid variable name: '{}'", name);
+            return StatusCode::new(2);
+        }
+
+        if let Err(error) = context.env_mut().set_local_variable(name, value) {
+            eprintln!("{}", error);
+            return StatusCode::new(3);
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Lists the shell's background jobs
+pub fn jobs(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: jobs");
+        return StatusCode::new(1);
+    }
+
+    for job in context.shell.jobs.iter() {
+        let state = match job.state {
+            JobState::Running => "Running",
+            JobState::Stopped => "Stopped",
+        };
+
+        println!(
+            "[{}] {}  {}  {}",
+            job.id,
+            job.child.id(),
+            state,
+            job.command
+        );
+    }
+
+    StatusCode::success()
+}
+
+// Prints its arguments according to a format string, without adding a trailing newline
+pub fn printf(_context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (format, arguments) = match args.split_first() {
+        Some((format, arguments)) => (format, arguments),
+        None => {
+            eprintln!("Usage: printf <format> [arguments...]");
+            return StatusCode::new(1);
+        }
+    };
+
+    match format_printf(format, arguments) {
+        Ok(formatted) => {
+            print!("{}", formatted);
+            let _ = stdout().flush();
+            StatusCode::success()
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            StatusCode::new(1)
+        }
+    }
+}
+
+// The widest field (and longest precision) 'printf' accepts, since the output is padded out to it
+const MAX_PRINTF_WIDTH: usize = 4096;
+
+// Formats arguments using a printf-style format string supporting %s, %d, %i, %x, %X and %%,
+// with optional '-' (left-align) and '0' (zero-pad) flags, width, and precision,
+// as well as the \n, \t and \r escapes
+// Like POSIX printf, the format is reused until every argument has been consumed,
+// and missing arguments are treated as empty strings (or zero)
+pub fn format_printf(format: &str, arguments: &[&str]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut arguments = arguments.iter();
+
+    loop {
+        let mut consumed_any = false;
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => output.push('\n'),
+                    Some('t') => output.push('\t'),
+                    Some('r') => output.push('\r'),
+                    Some('\\') | None => output.push('\\'),
+                    Some(other) => {
+                        output.push('\\');
+                        output.push(other);
+                    }
+                },
+                '%' => {
+                    let mut left_align = false;
+                    let mut zero_pad = false;
+                    while let Some(flag) = chars.next_if(|c| *c == '-' || *c == '0') {
+                        match flag {
+                            '-' => left_align = true,
+                            _ => zero_pad = true,
+                        }
+                    }
+
+                    let mut width = String::new();
+                    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                        width.push(digit);
+                    }
+                    let width = match width.as_str() {
+                        "" => 0,
+                        digits => match digits.parse() {
+                            Ok(width) if width <= MAX_PRINTF_WIDTH => width,
+                            _ => return Err(format!("Invalid field width: '{}'", digits)),
+                        },
+                    };
+
+                    let mut precision = None;
+                    if chars.next_if_eq(&'.').is_some() {
+                        let mut digits = String::new();
+                        while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                            digits.push(digit);
+                        }
+
+                        precision = match digits.as_str() {
+                            "" => Some(0),
+                            digits => match digits.parse() {
+                                Ok(precision) if precision <= MAX_PRINTF_WIDTH => Some(precision),
+                                _ => return Err(format!("Invalid precision: '{}'", digits)),
+                            },
+                        };
+                    }
+
+                    let conversion = match chars.next() {
+                        Some('%') => {
+                            output.push('%');
+                            continue;
+                        }
+                        Some(conversion @ ('s' | 'd' | 'i' | 'x' | 'X')) => conversion,
+                        Some(other) => {
+                            return Err(format!("Invalid format specifier: '%{}'", other))
+                        }
+                        None => return Err(String::from("Incomplete format specifier")),
+                    };
+
+                    let argument = arguments.next();
+                    consumed_any |= argument.is_some();
+                    let argument = argument.copied().unwrap_or_default();
+
+                    let mut value = match conversion {
+                        's' => match precision {
+                            Some(precision) => argument.chars().take(precision).collect(),
+                            None => argument.to_string(),
+                        },
+                        _ => {
+                            let number = match argument {
+                                "" => 0,
+                                _ => argument
+                                    .parse::<i64>()
+                                    .map_err(|_| format!("Invalid number: '{}'", argument))?,
+                            };
+
+                            let digits = match conversion {
+                                'x' => format!("{:x}", number.unsigned_abs()),
+                                'X' => format!("{:X}", number.unsigned_abs()),
+                                _ => number.unsigned_abs().to_string(),
+                            };
+
+                            // For numbers, the precision is the minimum number of digits
+                            let digits = format!("{:0>1$}", digits, precision.unwrap_or(0));
+                            match number < 0 {
+                                true => format!("-{}", digits),
+                                false => digits,
+                            }
+                        }
+                    };
+
+                    let padding = width.saturating_sub(value.chars().count());
+                    if left_align {
+                        value.push_str(&" ".repeat(padding));
+                    } else if zero_pad && conversion != 's' {
+                        let split = match value.starts_with('-') {
+                            true => 1,
+                            false => 0,
+                        };
+                        value.insert_str(split, &"0".repeat(padding));
+                    } else {
+                        value.insert_str(0, &" ".repeat(padding));
+                    }
+
+                    output.push_str(&value);
+                }
+                c => output.push(c),
+            }
+        }
+
+        // Stop once the arguments run out, or if the format doesn't consume any
+        if !consumed_any || arguments.len() == 0 {
+            return Ok(output);
+        }
+    }
+}
+
+// Removes one pair of matching single or double quotes surrounding a string
+fn strip_quotes(string: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = string
+            .strip_prefix(quote)
+            .and_then(|s| s.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+
+    string
+}
+
+// Gets the sorted, non-hidden entries of a directory
+fn directory_entries(directory: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    entries.sort();
+    entries
+}
+
+// Gets the final component of a path as a String
+fn file_name_of(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+// Stops the running function or sourced script, with a status (by default, that of the last command)
+pub fn r#return(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let status = match args[..] {
+        [] => context.shell.status(),
+        [status] => match status.parse() {
+            Ok(status) => status,
+            Err(_) => {
+                eprintln!("Invalid status: '{}'", status);
+                return StatusCode::new(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: return [status]");
+            return StatusCode::new(1);
+        }
+    };
+
+    if context.shell.frame_depth == 0 {
+        eprintln!("return: only meaningful inside a function or sourced script");
+        return StatusCode::new(2);
+    }
+
+    context.shell.returning = true;
+    StatusCode::new(status)
+}
+
+// Stops the innermost running loop
+pub fn r#break(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    control_loop(context, args, LoopControl::Break, "break")
+}
+
+// Skips to the next iteration of the innermost running loop
+pub fn r#continue(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    control_loop(context, args, LoopControl::Continue, "continue")
+}
+
+fn control_loop(
+    context: &mut Context,
+    args: Vec<&str>,
+    control: LoopControl,
+    name: &str,
+) -> StatusCode {
+    if !args.is_empty() {
+        eprintln!("Usage: {}", name);
+        return StatusCode::new(1);
+    }
+
+    if context.shell.loop_depth == 0 {
+        eprintln!("{}: only meaningful inside a loop", name);
+        return StatusCode::new(2);
+    }
+
+    context.shell.loop_control = Some(control);
+    StatusCode::success()
+}
+
+// Sets a shell variable, or prints every shell variable when run without arguments
+// Unlike 'export', the variable isn't passed on to external commands
+pub fn set(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    match args.as_slice() {
+        // Tracing prints each command (after expansion) before it runs
+        ["-x"] => context.shell.trace = true,
+        ["+x"] => context.shell.trace = false,
+        // Typing a directory's name on its own changes into it
+        ["-o", "autocd"] => context.shell.autocd = true,
+        ["+o", "autocd"] => context.shell.autocd = false,
+        [] => {
+            for (name, value) in context.env().variables() {
+                println!("{}={}", name, parser::quote(&value));
+            }
+        }
+        [name, value] => {
+            if !parser::is_variable_name(name) {
+                eprintln!("Invalid variable name: '{}'", name);
+                return StatusCode::new(2);
+            }
+
+            context.env_mut().set_variable(name, value);
+        }
+        _ => {
+            eprintln!("Usage: set [-x | +x | -o autocd | +o autocd | <name> <value>]");
+            return StatusCode::new(1);
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Lists history entries with '-l', or opens them in $EDITOR and runs the edited commands
+// Without a range, the last command is edited (or the last 16 are listed)
+pub fn fc(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (listing, range_args) = match args.first() {
+        Some(&"-l") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    if range_args.len() > 2 {
+        eprintln!("Usage: fc [-l] [first] [last]");
+        return StatusCode::new(1);
+    }
+
+    let history = &context.shell.history;
+    if history.is_empty() {
+        eprintln!("History is empty");
+        return StatusCode::new(2);
+    }
+
+    let (first, last) = match parse_history_range(range_args, history.len(), listing) {
+        Ok(range) => range,
+        Err(message) => {
+            eprintln!("{}", message);
+            return StatusCode::new(2);
+        }
+    };
+
+    let entries: Vec<(usize, String)> = history
+        .iter()
+        .filter(|(number, _)| (first..=last).contains(number))
+        .map(|(number, entry)| (number, entry.to_string()))
+        .collect();
+
+    if listing {
+        for (number, entry) in entries {
+            println!("{:>5}  {}", number, entry);
+        }
+
+        return StatusCode::success();
+    }
+
+    let commands: Vec<String> = entries.into_iter().map(|(_, entry)| entry).collect();
+    let edit_path = match create_edit_file("rush-fc", "sh", &(commands.join("\n") + "\n")) {
+        Ok(path) => path,
+        Err(_) => {
+            eprintln!(
+                "Failed to create file in temp directory: '{}'",
+                env::temp_dir().display()
+            );
+            return StatusCode::new(3);
+        }
+    };
+
+    let editor_status = edit_file(&edit_path);
+    let edited = fs::read_to_string(&edit_path);
+    let _ = fs::remove_file(&edit_path);
+
+    if !editor_status.is_success() {
+        eprintln!("Editor exited unsuccessfully, no commands were run");
+        return StatusCode::new(3);
+    }
+
+    let edited = match edited {
+        Ok(edited) => edited,
+        Err(_) => {
+            eprintln!("Failed to read file: '{}'", edit_path.display());
+            return StatusCode::new(3);
+        }
+    };
+
+    // Show what's about to run, like other shells do
+    print!("{}", edited);
+    context.shell.interpret(edited);
+    match context.shell.succeeded() {
+        true => StatusCode::success(),
+        false => StatusCode::new(4),
+    }
+}
+
+// Turns fc's range arguments into an inclusive range of history entry numbers
+// Negative numbers count back from the most recent entry (-1 is the last entry)
+// Without a 'last' argument, listing runs to the most recent entry while editing only uses 'first'
+pub fn parse_history_range(
+    args: &[&str],
+    length: usize,
+    listing: bool,
+) -> Result<(usize, usize), String> {
+    let resolve = |arg: &str| -> Result<usize, String> {
+        let number: i64 = arg
+            .parse()
+            .map_err(|_| format!("Invalid history entry: '{}'", arg))?;
+        let resolved = match number < 0 {
+            true => length as i64 + 1 + number,
+            false => number,
+        };
+
+        match resolved >= 1 && resolved <= length as i64 {
+            true => Ok(resolved as usize),
+            false => Err(format!("History entry out of range: '{}'", arg)),
+        }
+    };
+
+    let (first, last) = match args {
+        [] if listing => (length.saturating_sub(15).max(1), length),
+        [] => (length, length),
+        [first] if listing => (resolve(first)?, length),
+        [first] => {
+            let first = resolve(first)?;
+            (first, first)
+        }
+        [first, last] => (resolve(first)?, resolve(last)?),
+        _ => return Err(String::from("Too many arguments")),
+    };
+
+    // Ranges given backwards are treated the same as forwards ones
+    Ok((first.min(last), first.max(last)))
+}
+
+// Sends a signal (SIGTERM by default) to background jobs ('%N') or processes (by PID)
+pub fn kill(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !cfg!(unix) {
+        eprintln!("kill is not supported on this platform");
+        return StatusCode::new(3);
+    }
+
+    let (signal, targets) = match args.split_first() {
+        Some((flag, targets)) if flag.len() > 1 && flag.starts_with('-') => {
+            match jobs::parse_signal(&flag[1..]) {
+                Some(signal) => (signal, targets),
+                None => {
+                    eprintln!("Unknown signal: '{}'", &flag[1..]);
+                    return StatusCode::new(2);
+                }
+            }
+        }
+        _ => (jobs::parse_signal("TERM").unwrap_or(15), &args[..]),
+    };
+
+    if targets.is_empty() {
+        eprintln!("Usage: kill [-<signal>] <%job or pid...>");
+        return StatusCode::new(1);
+    }
+
+    let mut status_code = StatusCode::success();
+    for target in targets {
+        let pid = match resolve_kill_target(&mut context.shell.jobs, target) {
+            Ok(pid) => pid,
+            Err(message) => {
+                eprintln!("{}", message);
+                status_code = StatusCode::new(2);
+                continue;
+            }
+        };
+
+        if let Err(error) = jobs::send_signal(pid, signal) {
+            eprintln!("Failed to send signal to '{}': {}", target, error);
+            status_code = StatusCode::new(3);
+            continue;
+        }
+
+        // Keep the job table in sync when a job is stopped or resumed
+        let job = context.shell.jobs.find_by_pid(pid);
+        if let (Some(job), Some(state)) = (job, jobs::state_after_signal(signal)) {
+            job.state = state;
+        }
+    }
+
+    status_code
+}
+
+// Resolves a job specification ('%N') or plain process ID into a process ID
+fn resolve_kill_target(table: &mut jobs::JobTable, target: &str) -> Result<u32, String> {
+    if target.starts_with('%') {
+        return match jobs::parse_job_spec(target).and_then(|id| table.get_mut(id)) {
+            Some(job) => Ok(job.child.id()),
+            None => Err(format!("No such job: '{}'", target)),
+        };
+    }
+
+    target
+        .parse()
+        .map_err(|_| format!("Invalid process ID: '{}'", target))
+}
+
+// Waits for the given background jobs ('%N'), or every job if none are given
+// Returns the exit status of the last job waited for
+pub fn wait(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let finished = match args.is_empty() {
+        true => context.shell.jobs.wait_all(),
+        false => {
+            let mut finished = Vec::new();
+            for arg in &args {
+                let job = jobs::parse_job_spec(arg).and_then(|id| context.shell.jobs.wait(id));
+                match job {
+                    Some(job) => finished.push(job),
+                    None => {
+                        eprintln!("No such job: '{}'", arg);
+                        return StatusCode::new(2);
+                    }
+                }
+            }
+
+            finished
+        }
+    };
+
+    match finished.last() {
+        // A job killed by a signal has no exit code, so treat it as a generic failure
+        Some((_, Ok(status))) => StatusCode::new(status.code().unwrap_or(1)),
+        Some((job, Err(_))) => {
+            eprintln!("Failed to wait for job {}", job.id);
+            StatusCode::new(3)
+        }
+        None => StatusCode::success(),
+    }
+}
+
+// Prints what a command line expands to, without running it
+// With '-v', the words after each stage (aliases, variables and globs) are shown
+pub fn expand(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (verbose, words) = match args.first() {
+        Some(&"-v") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    if words.is_empty() {
+        eprintln!("Usage: expand [-v] <command line>");
+        return StatusCode::new(1);
+    }
+
+    // The command line is usually quoted so that the shell doesn't expand it before 'expand' sees it
+    let statements = match script::parse(&words.join(" ")) {
+        Ok(statements) => statements,
+        Err(error) => {
+            eprintln!("{}", error);
+            return StatusCode::new(2);
+        }
+    };
+
+    for command in script::simple_commands(&statements) {
+        let stages = match expansion::expand_stages(
+            command.to_vec(),
+            &context.shell.aliases,
+            &|name| context.shell.lookup_variable(name),
+            &context.shell.positional_parameters,
+            context.home(),
+        ) {
+            Ok(stages) => stages,
+            Err(error) => {
+                eprintln!("{}", error);
+                return StatusCode::new(3);
+            }
+        };
+
+        if verbose {
+            let words: Vec<String> = command.iter().map(|word| word.to_string()).collect();
+            let aliases: Vec<String> = stages.aliases.iter().map(|word| word.to_string()).collect();
+            println!("words:     {}", words.join(" "));
+            println!("aliases:   {}", aliases.join(" "));
+            println!("variables: {}", quote_words(&stages.variables));
+            println!("globs:     {}", quote_words(&stages.globs));
+        } else {
+            println!("{}", quote_words(&stages.globs));
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Prints text with its variables expanded, as if it were in double quotes, without running anything
+// The text is usually single-quoted so the shell doesn't expand it first ('expand-vars '$HOME is ${HOME}'')
+pub fn expand_vars(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if args.is_empty() {
+        eprintln!("Usage: expand-vars <text...>");
+        return StatusCode::new(1);
+    }
+
+    match parser::variable_word(&args.join(" ")) {
+        Ok(word) => {
+            println!(
+                "{}",
+                word.expand(&|name| context.shell.lookup_variable(name))
+            );
+            StatusCode::success()
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            StatusCode::new(2)
+        }
+    }
+}
+
+// Joins words into a line that the shell would split back into the same words
+fn quote_words(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| parser::quote(word))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Lists the commands entered so far, or saves them to a file with '--export'
+pub fn history(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (path, format) = match args.as_slice() {
+        [] => {
+            // Like other shells, times are only shown when $HISTTIMEFORMAT is set
+            let time_format = context.env().get_variable("HISTTIMEFORMAT");
+            let history = &context.shell.history;
+            for number in 1..=history.len() {
+                if let Some(entry) = history.format_entry(number, time_format.as_deref()) {
+                    println!("{}", entry);
+                }
+            }
+
+            return StatusCode::success();
+        }
+        ["-c"] => {
+            context.shell.history.clear();
+            return rewrite_history_file(context);
+        }
+        ["-d", entries] => {
+            let deleted = history::parse_entry_range(entries)
+                .is_some_and(|(first, last)| context.shell.history.delete(first, last));
+            if !deleted {
+                eprintln!("History entry out of range: '{}'", entries);
+                return StatusCode::new(2);
+            }
+
+            return rewrite_history_file(context);
+        }
+        ["--export", path] => (path, "plain"),
+        ["--export", path, "--format", format] | ["--format", format, "--export", path] => {
+            (path, *format)
+        }
+        _ => {
+            eprintln!("Usage: history [-c | -d <n[:m]> | --export <path> [--format plain|json]]");
+            return StatusCode::new(1);
+        }
+    };
+
+    let format = match ExportFormat::from_name(format) {
+        Some(format) => format,
+        None => {
+            eprintln!("Invalid format: '{}' (expected 'plain' or 'json')", format);
+            return StatusCode::new(2);
+        }
+    };
+
+    match fs::write(path, context.shell.history.export(format)) {
+        Ok(_) => StatusCode::success(),
+        Err(_) => {
+            eprintln!("Failed to write file: '{}'", path);
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Saves the history file after 'history -c' or 'history -d', so deleted commands don't come back next session
+fn rewrite_history_file(context: &Context) -> StatusCode {
+    match context.shell.rewrite_history_file() {
+        Ok(_) => StatusCode::success(),
+        Err(_) => {
+            let path = history::history_path(context.home());
+            eprintln!("Failed to write history file: '{}'", path.display());
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Prints the extended help page for a command, or lists the commands that have one
+pub fn man(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let commands = &context.shell.commands;
+
+    match args.as_slice() {
+        [] => {
+            let mut names: Vec<&String> = commands
+                .commands_with_help()
+                .into_iter()
+                .map(|command| command.true_name())
+                .collect();
+            names.sort();
+
+            for name in names {
+                println!("{}", name);
+            }
+
+            StatusCode::success()
+        }
+        [name] => match commands
+            .resolve(name)
+            .and_then(|command| Some((command, command.help()?)))
+        {
+            Some((command, page)) => {
+                print!("{}", page.render(command.true_name(), command.aliases()));
+                StatusCode::success()
+            }
+            None => {
+                eprintln!("No manual entry for '{}'", name);
+                StatusCode::new(2)
+            }
+        },
+        _ => {
+            eprintln!("Usage: man [command]");
+            StatusCode::new(1)
+        }
+    }
+}
+
+// Lists the builtin commands, or with --search only those whose name, aliases or description contain a keyword
+// Fails if nothing matches the keyword, so it can be used as a condition
+pub fn help(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let all_commands = context.shell.commands.commands();
+    let mut commands: Vec<&Command> = match args[..] {
+        [] => all_commands.iter().collect(),
+        ["--search" | "-s", keyword] => search_commands(all_commands, keyword),
+        _ => {
+            eprintln!("Usage: help [--search <keyword>]");
+            return StatusCode::new(1);
+        }
+    };
+
+    if commands.is_empty() {
+        eprintln!("No commands match '{}'", args[1]);
+        return StatusCode::new(1);
+    }
+
+    commands.sort_by_key(|command| command.true_name());
+    let names: Vec<String> = commands
+        .iter()
+        .map(|command| match command.aliases() {
+            [] => command.true_name().clone(),
+            aliases => format!("{} ({})", command.true_name(), aliases.join(", ")),
+        })
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+
+    for (name, command) in names.iter().zip(&commands) {
+        match command.description() {
+            Some(description) => println!("{:<width$}  {}", name, description, width = width),
+            None => println!("{}", name),
+        }
+    }
+
+    StatusCode::success()
+}
+
+// Runs an external command in the background, immune to the hangup sent when the terminal closes
+// Output that would go to the terminal is appended to 'nohup.out' instead
+pub fn nohup(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !cfg!(unix) {
+        eprintln!("nohup is not supported on this platform");
+        return StatusCode::new(3);
+    }
+
+    let (command_name, command_args) = match args.split_first() {
+        Some((command_name, command_args)) => (*command_name, command_args),
+        None => {
+            eprintln!("Usage: nohup <command> [arguments...]");
+            return StatusCode::new(1);
+        }
+    };
+
+    if context.shell.commands.is_builtin(command_name) {
+        eprintln!("Builtin commands cannot be run with nohup");
+        return StatusCode::new(2);
+    }
+
+    let search_path = context.env().get_variable("PATH").unwrap_or_default();
+    let binary = match path::find_executable(command_name, &search_path) {
+        Some(binary) => binary,
+        None => {
+            eprintln!("Unknown command: {}", command_name.red());
+            return StatusCode::new(2);
+        }
+    };
+
+    let output = match jobs::nohup_output_path(
+        stdout().is_terminal(),
+        context.cwd().absolute(),
+        context.home(),
+        &|path| jobs::open_for_append(path).is_ok(),
+    ) {
+        Ok(output) => output,
+        Err(message) => {
+            eprintln!("{}", message);
+            return StatusCode::new(3);
+        }
+    };
+    if let Some(output) = &output {
+        eprintln!("nohup: appending output to '{}'", output.display());
+    }
+
+    match jobs::spawn_nohup(&binary, command_args, output.as_deref()) {
+        Ok(child) => {
+            let pid = child.id();
+            let command = args.join(" ");
+            let id = context.shell.jobs.add(child, &command);
+            println!("[{}] {}", id, pid);
+            StatusCode::success()
+        }
+        Err(_) => {
+            eprintln!("Failed to run '{}'", command_name);
+            StatusCode::new(3)
+        }
+    }
+}
+
+// Replaces the shell with an external command, so the shell doesn't linger as its parent
+// Without a command there's nothing to replace the shell with, so it does nothing
+pub fn exec(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (command_name, command_args) = match args.split_first() {
+        Some((command_name, command_args)) => (*command_name, command_args),
+        None => return StatusCode::success(),
+    };
+
+    let search_path = context.env().get_variable("PATH").unwrap_or_default();
+    match resolve_exec_target(
+        command_name,
+        &search_path,
+        context.shell.commands.is_builtin(command_name),
+    ) {
+        Ok(binary) => exec_external(binary, command_args),
+        Err(message) => {
+            eprintln!("{}", message);
+            StatusCode::new(127)
+        }
+    }
+}
+
+// Runs an external command with a modified environment, or prints the environment if no command is given
+// -i starts from an empty environment instead of the exported variables, and NAME=value arguments are added on top
+// The shell's own variables are never changed
+pub fn env(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (clean, args) = match args.split_first() {
+        Some((&"-i", rest)) => (true, rest),
+        _ => (false, args.as_slice()),
+    };
+
+    let mut variables = match clean {
+        true => Vec::new(),
+        false => context.env().exported_variables(),
+    };
+
+    let mut args = args.iter();
+    let mut command = None;
+    for arg in args.by_ref() {
+        match arg.split_once('=') {
+            Some((name, value)) if parser::is_variable_name(name) => {
+                variables.retain(|(existing, _)| existing != name);
+                variables.push((name.to_string(), value.to_string()));
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("Usage: env [-i] [NAME=value]... [command [args...]]");
+                return StatusCode::new(1);
+            }
+            _ => {
+                command = Some(*arg);
+                break;
+            }
+        }
+    }
+
+    let command_name = match command {
+        Some(command_name) => command_name,
+        None => {
+            for (name, value) in variables {
+                println!("{}={}", name, value);
+            }
+            return StatusCode::success();
+        }
+    };
+    let command_args: Vec<&str> = args.copied().collect();
+
+    // The command is looked up using the shell's PATH, since a clean environment has none
+    let search_path = context.env().get_variable("PATH").unwrap_or_default();
+    match path::find_executable(command_name, &search_path) {
+        Some(binary) => run_external_with_env(binary, &command_args, &variables),
+        None => {
+            eprintln!("Unknown command: {}", command_name.red());
+            StatusCode::new(127)
+        }
+    }
+}
+
+// Shows what a command name refers to: an alias, a builtin, or an executable in $PATH
+// With -a, every match is listed in the order they would be tried, instead of only the one that would run
+pub fn which(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let (all, names) = match args.first() {
+        Some(&"-a") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    if names.is_empty() {
+        eprintln!("Usage: which [-a] <command...>");
+        return StatusCode::new(1);
+    }
+
+    let mut status = StatusCode::success();
+    for name in names {
+        let locations = command_locations(context, name, all);
+        if locations.is_empty() {
+            eprintln!("{} not found", name.red());
+            status = StatusCode::new(1);
+        }
+
+        for location in locations {
+            println!("{}", location);
+        }
+    }
+
+    status
+}
+
+// Describes everything a command name could run, in the order the shell tries them
+// Unless all matches are wanted, only the first one is returned
+fn command_locations(context: &Context, name: &str, all: bool) -> Vec<String> {
+    let mut locations = Vec::new();
+    if let Some(definition) = context.shell.aliases.get(name) {
+        locations.push(format!("{}: aliased to {}", name, definition));
+    }
+
+    if context.shell.functions.contains_key(name) {
+        locations.push(format!("{}: shell function", name));
+    }
+
+    if let Some(true_name) = context.shell.commands.true_name_of(name) {
+        match true_name == name {
+            true => locations.push(format!("{}: shell builtin", name)),
+            false => locations.push(format!("{}: shell builtin ({})", name, true_name)),
+        }
+    }
+
+    let search_path = context.env().get_variable("PATH").unwrap_or_default();
+    match all {
+        true => locations.extend(
+            path::find_all_executables(name, &search_path)
+                .iter()
+                .map(|binary| binary.display().to_string()),
+        ),
+        false if locations.is_empty() => locations.extend(
+            path::find_executable(name, &search_path).map(|binary| binary.display().to_string()),
+        ),
+        false => locations.truncate(1),
+    }
+
+    locations
+}
+
+// Finds the binary that 'exec' should replace the shell with
+// Builtins run inside the shell process, so they can't replace it
+fn resolve_exec_target(
+    command_name: &str,
+    search_path: &str,
+    is_builtin: bool,
+) -> Result<PathBuf, String> {
+    if is_builtin {
+        return Err(format!(
+            "Builtin commands cannot be run with exec: '{}'",
+            command_name
+        ));
+    }
+
+    path::find_executable(command_name, search_path)
+        .ok_or_else(|| format!("Unknown command: {}", command_name.red()))
+}
+
+// Reports the size, used space and available space of the filesystem containing a path (default .)
+pub fn df(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    if !cfg!(unix) {
+        eprintln!("df is not supported on this platform");
+        return StatusCode::new(3);
+    }
+
+    let (human_readable, path_arg) = match args.as_slice() {
+        [] => (false, "."),
+        ["-h"] => (true, "."),
+        ["-h", path] => (true, *path),
+        [path] if !path.starts_with('-') => (false, *path),
+        _ => {
+            eprintln!("Usage: df [-h] <path (default .)>");
+            return StatusCode::new(1);
+        }
+    };
+
+    let path = match path::resolve(path_arg, context.home()) {
+        Some(path) => path,
+        None => {
+            eprintln!("Invalid path: '{}'", path_arg);
+            return StatusCode::new(2);
+        }
+    };
+
+    let usage = match disk::filesystem_usage(&path) {
+        Ok(usage) => usage,
+        Err(_) => {
+            eprintln!("Failed to read filesystem information: '{}'", path_arg);
+            return StatusCode::new(3);
+        }
+    };
+
+    println!(
+        "{:>14} {:>14} {:>14} {:>5}  Path",
+        "Size", "Used", "Available", "Use%"
+    );
+    println!(
+        "{:>14} {:>14} {:>14} {:>4}%  {}",
+        disk::format_bytes(usage.total, human_readable),
+        disk::format_bytes(usage.used, human_readable),
+        disk::format_bytes(usage.available, human_readable),
+        usage.percent_used(),
+        path.display()
+    );
+
+    StatusCode::success()
+}
+
+// Creates a hard link to a file, or a symbolic link with '-s'
+// With '-f', an existing file at the link's path is replaced
+pub fn ln(context: &mut Context, args: Vec<&str>) -> StatusCode {
+    let usage = "Usage: ln [-s] [-f] <target> <link>";
+    let mut symbolic = false;
+    let mut force = false;
+    let mut paths = Vec::new();
+
+    for arg in &args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        's' => symbolic = true,
+                        'f' => force = true,
+                        _ => {
+                            eprintln!("{}", usage);
+                            return StatusCode::new(1);
+                        }
+                    }
+                }
+            }
+            _ => paths.push(*arg),
+        }
+    }
+
+    let (target_arg, link_arg) = match paths.as_slice() {
+        [target, link] => (*target, *link),
+        _ => {
+            eprintln!("{}", usage);
+            return StatusCode::new(1);
+        }
+    };
+
+    let link = match path::resolve_new(link_arg, context.home()) {
+        Some(link) => link,
+        None => {
+            eprintln!("Invalid path: '{}'", link_arg);
+            return StatusCode::new(2);
+        }
+    };
+
+    // Symbolic links store their target as written, since relative targets are relative to the link itself
+    let target = match symbolic {
+        true => link.parent().map_or_else(
+            || PathBuf::from(target_arg),
+            |parent| parent.join(target_arg),
+        ),
+        false => match path::resolve(target_arg, context.home()) {
+            Some(target) => target,
+            None => {
+                eprintln!("Invalid path: '{}'", target_arg);
+                return StatusCode::new(2);
+            }
+        },
+    };
+
+    // The target is checked before anything is removed, so that a link can't replace the file it points to
+    // (an existing symlink can always be replaced, since removing it leaves the file alone)
+    if let Ok(metadata) = fs::symlink_metadata(&link) {
+        if !force {
+            eprintln!("File already exists: '{}' (use -f to replace it)", link_arg);
+            return StatusCode::new(2);
+        }
+
+        if !metadata.file_type().is_symlink() && path::is_same_file(&target, &link) {
+            eprintln!("'{}' and '{}' are the same file", target_arg, link_arg);
+            return StatusCode::new(2);
+        }
+
+        if fs::remove_file(&link).is_err() {
+            eprintln!("Failed to remove file: '{}'", link_arg);
+            return StatusCode::new(3);
+        }
+    }
+
+    let result = match symbolic {
+        true => create_symlink(Path::new(target_arg), &link),
+        false => fs::hard_link(target, &link),
+    };
+
+    match result {
+        Ok(_) => StatusCode::success(),
+        Err(error) => {
+            eprintln!("{}", link_error_message(&error, target_arg, link_arg));
+            StatusCode::new(3)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+// Explains why a link couldn't be created
+// Hard links can't span filesystems, so that case suggests a symbolic link instead
+fn link_error_message(error: &io::Error, target: &str, link: &str) -> String {
+    match error.kind() {
+        io::ErrorKind::CrossesDevices => format!(
+            "Cannot hard link across filesystems: '{}' -> '{}' (use -s for a symbolic link)",
+            link, target
+        ),
+        _ => format!("Failed to create link: '{}' -> '{}'", link, target),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::Shell;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_command_test_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = test(&mut context, vec!["1", "-lt", "2"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_test_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(test(&mut context, Vec::new()), StatusCode::new(1));
+        assert_eq!(test(&mut context, vec!["a", "=", "b"]), StatusCode::new(1));
+        assert_eq!(
+            test(&mut context, vec!["a", "-eq", "1"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_command_bracket() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(
+            bracket(&mut context, vec!["-n", "x", "]"]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            bracket(&mut context, vec!["-z", "x", "]"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(bracket(&mut context, vec!["-n", "x"]), StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_evaluate_condition_files() {
+        let dir = temp_dir("evaluate-condition-files");
+        let file = dir.join("file.txt");
+        let empty = dir.join("empty.txt");
+        fs::write(&file, "contents").unwrap();
+        fs::write(&empty, "").unwrap();
+
+        let dir = dir.to_string_lossy().to_string();
+        let file = file.to_string_lossy().to_string();
+        let empty = empty.to_string_lossy().to_string();
+
+        assert_eq!(evaluate_condition(&["-e", &file]), Ok(true));
+        assert_eq!(evaluate_condition(&["-e", "/invalid/path"]), Ok(false));
+        assert_eq!(evaluate_condition(&["-f", &file]), Ok(true));
+        assert_eq!(evaluate_condition(&["-f", &dir]), Ok(false));
+        assert_eq!(evaluate_condition(&["-d", &dir]), Ok(true));
+        assert_eq!(evaluate_condition(&["-d", &file]), Ok(false));
+        assert_eq!(evaluate_condition(&["-s", &file]), Ok(true));
+        assert_eq!(evaluate_condition(&["-s", &empty]), Ok(false));
+        assert_eq!(evaluate_condition(&["-L", &file]), Ok(false));
+    }
+
+    #[test]
+    fn test_evaluate_condition_strings() {
+        assert_eq!(evaluate_condition(&["-z", ""]), Ok(true));
+        assert_eq!(evaluate_condition(&["-z", "x"]), Ok(false));
+        assert_eq!(evaluate_condition(&["-n", "x"]), Ok(true));
+        assert_eq!(evaluate_condition(&["-n", ""]), Ok(false));
+        assert_eq!(evaluate_condition(&["a", "=", "a"]), Ok(true));
+        assert_eq!(evaluate_condition(&["a", "=", "b"]), Ok(false));
+        assert_eq!(evaluate_condition(&["a", "!=", "b"]), Ok(true));
+        assert_eq!(evaluate_condition(&["a", "!=", "a"]), Ok(false));
+        assert_eq!(evaluate_condition(&["word"]), Ok(true));
+        assert_eq!(evaluate_condition(&[""]), Ok(false));
+        assert_eq!(evaluate_condition(&[]), Ok(false));
+    }
+
+    #[test]
+    fn test_evaluate_condition_integers() {
+        assert_eq!(evaluate_condition(&["3", "-eq", "3"]), Ok(true));
+        assert_eq!(evaluate_condition(&["3", "-ne", "3"]), Ok(false));
+        assert_eq!(evaluate_condition(&["-1", "-lt", "2"]), Ok(true));
+        assert_eq!(evaluate_condition(&["2", "-le", "2"]), Ok(true));
+        assert_eq!(evaluate_condition(&["10", "-gt", "9"]), Ok(true));
+        assert_eq!(evaluate_condition(&["9", "-ge", "10"]), Ok(false));
+        assert!(evaluate_condition(&["nine", "-gt", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_condition_negation_and_errors() {
+        assert_eq!(evaluate_condition(&["!", "-z", ""]), Ok(false));
+        assert_eq!(evaluate_condition(&["!", "1", "-eq", "2"]), Ok(true));
+        // A binary operator in the middle of three arguments wins over a leading '!'
+        assert_eq!(evaluate_condition(&["!", "=", "!"]), Ok(true));
+        assert_eq!(evaluate_condition(&["!", "!=", "!"]), Ok(false));
+        assert_eq!(evaluate_condition(&["!", "-n", ""]), Ok(true));
+        assert!(evaluate_condition(&["-q", "x"]).is_err());
+        assert!(evaluate_condition(&["a", "-q", "b"]).is_err());
+        assert!(evaluate_condition(&["a", "b", "c", "d"]).is_err());
+    }
+
+    #[test]
+    fn test_command_exit_success() {
+        // * This is a placeholder test because the exit command
+        // * will exit the program, effectively ending the test
+    }
+
+    #[test]
+    fn test_command_trap_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(trap(&mut context, vec!["set RUSH_TRAP_ORDER first", "EXIT"]).is_success());
+        assert!(trap(&mut context, vec!["set RUSH_TRAP_ORDER second", "0"]).is_success());
+        assert!(trap(&mut context, Vec::new()).is_success());
+        assert_eq!(context.shell.exit_traps.len(), 2);
+
+        // The most recent trap runs first, so the first one has the last word
+        shell.shutdown();
+        assert_eq!(
+            shell.lookup_variable("RUSH_TRAP_ORDER").as_deref(),
+            Some("first")
+        );
+        assert!(shell.exit_traps.is_empty());
+
+        let mut context = Context::new(&mut shell);
+        trap(&mut context, vec!["set RUSH_TRAP_ORDER again", "EXIT"]);
+        assert!(trap(&mut context, vec!["-", "EXIT"]).is_success());
+        shell.shutdown();
+        assert_eq!(
+            shell.lookup_variable("RUSH_TRAP_ORDER").as_deref(),
+            Some("first")
+        );
+    }
+
+    #[test]
+    fn test_command_trap_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(trap(&mut context, vec!["true"]), StatusCode::new(1));
+        assert_eq!(trap(&mut context, vec!["true", "INT"]), StatusCode::new(2));
+        assert!(context.shell.exit_traps.is_empty());
+    }
+
+    #[test]
+    fn test_command_true_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = r#true(&mut context, vec!["ignored", "arguments"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_false_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = r#false(&mut context, vec!["ignored", "arguments"]);
+
+        assert_eq!(status_code, StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_command_working_directory_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = working_directory(&mut context, Vec::new());
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_change_directory_success_1() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = change_directory(&mut context, vec!["/"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_change_directory_success_2() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = change_directory(&mut context, vec!["~"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_change_directory_success_3() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        change_directory(&mut context, vec!["~"]);
+        // ! This is not guaranteed to exist on the tester's system
+        let status_code = change_directory(&mut context, vec!["Documents"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_change_directory_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = change_directory(&mut context, vec!["/invalid/path"]);
+
+        assert_eq!(status_code, StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_command_list_directory_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = list_directory(&mut context, Vec::new());
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_list_directory_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = list_directory(&mut context, vec!["/invalid/path"]);
+
+        assert_eq!(status_code, StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_command_go_back_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        context.env_mut().change_to("/", false).unwrap();
+        let status_code = go_back(&mut context, Vec::new());
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_go_back_prints_directory() {
+        let dir = temp_dir("go-back-print").canonicalize().unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        context
+            .env_mut()
+            .change_to(&dir.to_string_lossy(), false)
+            .unwrap();
+        context.env_mut().change_to("/", false).unwrap();
+
+        let mut output = Vec::new();
+        assert!(go_back_to_previous(&mut context, &mut output).is_success());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}\n", dir.display())
+        );
+        assert_eq!(context.cwd().absolute(), &dir);
+
+        // 'cd -' switches back again
+        assert!(change_directory(&mut context, vec!["-"]).is_success());
+        assert_eq!(context.cwd().absolute(), Path::new("/"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_go_back_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = go_back(&mut context, Vec::new());
+
+        assert_eq!(status_code, StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_command_truncate_success_1() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = truncate(&mut context, Vec::new());
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_truncate_success_2() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = truncate(&mut context, vec!["10"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_truncate_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = truncate(&mut context, vec!["-10"]);
+
+        assert_eq!(status_code, StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_sequence_last_only() {
+        let sequence: Vec<i64> = Sequence::new(1, 5, 1).unwrap().collect();
+        assert_eq!(sequence, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_sequence_first_and_last() {
+        let sequence: Vec<i64> = Sequence::new(2, 8, 1).unwrap().collect();
+        assert_eq!(sequence, vec![2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_sequence_step() {
+        let sequence: Vec<i64> = Sequence::new(0, 10, 2).unwrap().collect();
+        assert_eq!(sequence, vec![0, 2, 4, 6, 8, 10]);
+
+        let sequence: Vec<i64> = Sequence::new(0, 9, 4).unwrap().collect();
+        assert_eq!(sequence, vec![0, 4, 8]);
+    }
+
+    #[test]
+    fn test_sequence_descending() {
+        let sequence: Vec<i64> = Sequence::new(3, -3, -3).unwrap().collect();
+        assert_eq!(sequence, vec![3, 0, -3]);
+    }
+
+    #[test]
+    fn test_sequence_empty() {
+        assert!(Sequence::new(5, 1, 1).is_none());
+        assert!(Sequence::new(1, 5, -1).is_none());
+        assert!(Sequence::new(1, 5, 0).is_none());
+    }
+
+    #[test]
+    fn test_command_seq_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(seq(&mut context, vec!["5"]), StatusCode::success());
+        assert_eq!(seq(&mut context, vec!["2", "8"]), StatusCode::success());
+        assert_eq!(
+            seq(&mut context, vec!["0", "2", "10"]),
+            StatusCode::success()
+        );
+        assert_eq!(seq(&mut context, vec!["8", "2"]), StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_seq_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(seq(&mut context, vec!["0"]), StatusCode::new(1));
+        assert_eq!(seq(&mut context, vec!["1", "0", "5"]), StatusCode::new(1));
+        assert_eq!(seq(&mut context, vec!["five"]), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_plan_renames_success() {
+        let dir = temp_dir("plan-renames-success");
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::write(dir.join("b.txt"), "").unwrap();
+        let originals = vec![dir.join("a.txt"), dir.join("b.txt")];
+
+        let renames = plan_renames(&originals, "x.txt\nb.txt\n\n").unwrap();
+        assert_eq!(renames, vec![(dir.join("a.txt"), dir.join("x.txt"))]);
+    }
+
+    #[test]
+    fn test_plan_renames_line_count_changed() {
+        let dir = temp_dir("plan-renames-count");
+        let originals = vec![dir.join("a.txt"), dir.join("b.txt")];
+
+        assert!(plan_renames(&originals, "a.txt\n").is_err());
+        assert!(plan_renames(&originals, "a.txt\nb.txt\nc.txt\n").is_err());
+    }
+
+    #[test]
+    fn test_plan_renames_collision() {
+        let dir = temp_dir("plan-renames-collision");
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::write(dir.join("b.txt"), "").unwrap();
+        fs::write(dir.join("c.txt"), "").unwrap();
+        let originals = vec![dir.join("a.txt"), dir.join("b.txt")];
+
+        // Two files renamed to the same name
+        assert!(plan_renames(&originals, "x.txt\nx.txt\n").is_err());
+        // A file renamed onto one that already exists
+        assert!(plan_renames(&originals, "c.txt\nb.txt\n").is_err());
+    }
+
+    #[test]
+    fn test_plan_renames_invalid_name() {
+        let dir = temp_dir("plan-renames-invalid");
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "").unwrap();
+        let originals = vec![dir.join("a.txt")];
+
+        for name in ["nested/a.txt", "../a.txt", "/tmp/a.txt", "..", "."] {
+            assert_eq!(
+                plan_renames(&originals, name),
+                Err(format!(
+                    "Invalid name given for '{}': '{}'",
+                    dir.join("a.txt").display(),
+                    name
+                ))
+            );
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_create_edit_file() {
+        let first = create_edit_file("rush-test-edit", "txt", "a\nb\n").unwrap();
+        let second = create_edit_file("rush-test-edit", "txt", "").unwrap();
+        assert_ne!(first, second);
+        assert!(first.to_string_lossy().ends_with(".txt"));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "a\nb\n");
+
+        let _ = fs::remove_file(&first);
+        let _ = fs::remove_file(&second);
+    }
+
+    #[test]
+    fn test_command_find_success() {
+        let dir = temp_dir("find-success");
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("nested").join("b.txt"), "b").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let dir = dir.to_string_lossy().to_string();
+        let status_code = find(&mut context, vec![&dir, "-name", "*.txt"]);
+
+        assert_eq!(status_code, StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_find_exec_success() {
+        let dir = temp_dir("find-exec-success");
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("nested").join("b.txt"), "b").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let root = dir.to_string_lossy().to_string();
+        // The copies don't match the pattern, so they aren't found again while walking
+        let args = vec![
+            &root,
+            "-name",
+            "*.txt",
+            "-exec",
+            "copy-file",
+            "{}",
+            "{}.copy",
+            ";",
+        ];
+        let status_code = find(&mut context, args);
+
+        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(fs::read_to_string(dir.join("a.txt.copy")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(dir.join("nested").join("b.txt.copy")).unwrap(),
+            "b"
+        );
+        assert!(!dir.join("a.txt.copy.copy").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_find_exec_fail() {
+        let dir = temp_dir("find-exec-fail");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let dir = dir.to_string_lossy().to_string();
+
+        // Any failing invocation makes the overall status a failure
+        let args = vec![&dir, "-type", "f", "-exec", "false", "{}", ";"];
+        assert_eq!(find(&mut context, args), StatusCode::new(1));
+
+        // A missing ';' terminator is a usage error
+        let args = vec![&dir, "-exec", "read-file", "{}"];
+        assert_eq!(find(&mut context, args), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_command_date_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(date(&mut context, Vec::new()), StatusCode::success());
+        assert_eq!(date(&mut context, vec!["+%Y-%m-%d"]), StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_date_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = date(&mut context, vec!["%Y-%m-%d"]);
+
+        assert_eq!(status_code, StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_leading_number() {
+        assert_eq!(leading_number("42 apples"), 42.0);
+        assert_eq!(leading_number("  -3.5kg"), -3.5);
+        assert_eq!(leading_number("1.2.3"), 1.2);
+        assert_eq!(leading_number("apples"), 0.0);
+        assert_eq!(leading_number("-"), 0.0);
+    }
+
+    #[test]
+    fn test_sort_lines_numeric() {
+        let mut lines: Vec<String> = vec!["10", "9", "-1", "x", "100"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        sort_lines(&mut lines, true, false, false);
+        assert_eq!(lines, vec!["-1", "x", "9", "10", "100"]);
+
+        sort_lines(&mut lines, true, true, false);
+        assert_eq!(lines, vec!["100", "10", "9", "x", "-1"]);
+    }
+
+    #[test]
+    fn test_sort_lines_unique() {
+        let mut lines: Vec<String> = vec!["pear", "apple", "pear", "fig", "apple"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        sort_lines(&mut lines, false, false, true);
+        assert_eq!(lines, vec!["apple", "fig", "pear"]);
+
+        // Numerically equal lines count as duplicates
+        let mut lines: Vec<String> = vec!["2", "02", "1"].into_iter().map(String::from).collect();
+        sort_lines(&mut lines, true, false, true);
+        assert_eq!(lines, vec!["1", "02"]);
+    }
+
+    #[test]
+    fn test_command_sort_file_success() {
+        let dir = temp_dir("sort-file-success");
+        let file = dir.join("lines.txt");
+        fs::write(&file, "b\na\nc\n").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let file = file.to_string_lossy().to_string();
+
+        assert_eq!(
+            sort(&mut context, vec!["-ru", &file]),
+            StatusCode::success()
+        );
+    }
+
+    #[test]
+    fn test_command_sort_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(sort(&mut context, vec!["-z"]), StatusCode::new(1));
+        assert_eq!(
+            sort(&mut context, vec!["/invalid/path"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_collapse_duplicates() {
+        let lines = vec!["a", "a", "b", "a", "c", "c", "c"];
+        let groups = collapse_duplicates(lines.into_iter().map(String::from));
+
+        let expected = vec![("a", 2), ("b", 1), ("a", 1), ("c", 3)];
+        let expected: Vec<(String, usize)> = expected
+            .into_iter()
+            .map(|(l, n)| (l.to_string(), n))
+            .collect();
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn test_collapse_duplicates_empty() {
+        assert!(collapse_duplicates(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_uniq_lines_count() {
+        let lines = vec!["a", "a", "b"].into_iter().map(String::from);
+        assert_eq!(
+            uniq_lines(lines, true, false),
+            vec!["      2 a", "      1 b"]
+        );
+    }
+
+    #[test]
+    fn test_uniq_lines_duplicates_only() {
+        let lines = vec!["a", "a", "b", "c", "c"].into_iter().map(String::from);
+        assert_eq!(uniq_lines(lines, false, true), vec!["a", "c"]);
+
+        let lines = vec!["a", "a", "b"].into_iter().map(String::from);
+        assert_eq!(uniq_lines(lines, true, true), vec!["      2 a"]);
+    }
+
+    #[test]
+    fn test_command_uniq_success() {
+        let dir = temp_dir("uniq-success");
+        let file = dir.join("lines.txt");
+        fs::write(&file, "a\na\nb\n").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let file = file.to_string_lossy().to_string();
+
+        assert_eq!(uniq(&mut context, vec!["-c", &file]), StatusCode::success());
+        assert_eq!(
+            uniq(&mut context, vec!["-cd", &file]),
+            StatusCode::success()
+        );
+    }
+
+    #[test]
+    fn test_command_uniq_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(uniq(&mut context, vec!["-x"]), StatusCode::new(1));
+        assert_eq!(
+            uniq(&mut context, vec!["/invalid/path"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_tee_copy() {
+        let dir = temp_dir("tee-copy");
+        let mut files = vec![
+            fs::File::create(dir.join("one.txt")).unwrap(),
+            fs::File::create(dir.join("two.txt")).unwrap(),
+        ];
+
+        let input = "first line\nsecond line\n";
+        let mut output = Vec::new();
+        tee_copy(input.as_bytes(), &mut output, &mut files).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+        assert_eq!(fs::read_to_string(dir.join("one.txt")).unwrap(), input);
+        assert_eq!(fs::read_to_string(dir.join("two.txt")).unwrap(), input);
+    }
+
+    #[test]
+    fn test_command_tee_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(tee(&mut context, vec!["-x", "out.txt"]), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_split_arguments() {
+        assert_eq!(split_arguments(" a  b\nc\t", false), vec!["a", "b", "c"]);
+        assert_eq!(
+            split_arguments("with space\0second\0", true),
+            vec!["with space", "second"]
+        );
+    }
+
+    #[test]
+    fn test_batch_arguments() {
+        let arguments: Vec<String> = vec!["a", "b", "c", "d", "e"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let batches = batch_arguments(arguments.clone(), Some(2));
+        assert_eq!(batches, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+        let batches = batch_arguments(arguments.clone(), None);
+        assert_eq!(batches, vec![arguments]);
+
+        let batches = batch_arguments(Vec::new(), Some(2));
+        assert_eq!(batches, vec![Vec::<String>::new()]);
+    }
+
+    #[test]
+    fn test_run_batches() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let batches = vec![vec![String::from("a")], vec![String::from("b")]];
+
+        assert_eq!(
+            run_batches(&mut context, &["true"], batches.clone()),
+            StatusCode::success()
+        );
+        assert_eq!(
+            run_batches(&mut context, &["false"], batches.clone()),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            run_batches(&mut context, &["invalid-command"], batches),
+            StatusCode::new(127)
+        );
+    }
+
+    #[test]
+    fn test_parse_cut_list() {
+        assert_eq!(parse_cut_list("2"), Some(vec![(2, 2)]));
+        assert_eq!(parse_cut_list("1,3-4"), Some(vec![(1, 1), (3, 4)]));
+        assert_eq!(parse_cut_list("-2,5-"), Some(vec![(1, 2), (5, usize::MAX)]));
+        assert_eq!(parse_cut_list("0"), None);
+        assert_eq!(parse_cut_list("4-2"), None);
+        assert_eq!(parse_cut_list("-"), None);
+        assert_eq!(parse_cut_list("a"), None);
+    }
+
+    #[test]
+    fn test_cut_fields_single() {
+        let ranges = parse_cut_list("2").unwrap();
+        assert_eq!(cut_fields("root:x:0:0", ':', &ranges), "x");
+        // Fields that don't exist come out empty
+        assert_eq!(cut_fields("root", ':', &ranges), "");
+    }
+
+    #[test]
+    fn test_cut_fields_multiple() {
+        let ranges = parse_cut_list("3,1").unwrap();
+        assert_eq!(cut_fields("a,b,c,d", ',', &ranges), "a,c");
+
+        let ranges = parse_cut_list("2-").unwrap();
+        assert_eq!(cut_fields("a\tb\tc", '\t', &ranges), "b\tc");
+    }
+
+    #[test]
+    fn test_cut_characters() {
+        let ranges = parse_cut_list("1-3,6").unwrap();
+        assert_eq!(cut_characters("abcdefgh", &ranges), "abcf");
+        assert_eq!(cut_characters("ab", &ranges), "ab");
+    }
+
+    #[test]
+    fn test_command_cut_success() {
+        let dir = temp_dir("cut-success");
+        let file = dir.join("table.txt");
+        fs::write(&file, "a:b:c\nd:e:f\n").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let file = file.to_string_lossy().to_string();
+
+        assert_eq!(
+            cut(&mut context, vec!["-d", ":", "-f", "1,3", &file]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            cut(&mut context, vec!["-c2-", &file]),
+            StatusCode::success()
+        );
+    }
+
+    #[test]
+    fn test_command_cut_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(cut(&mut context, vec!["-f", "0"]), StatusCode::new(1));
+        assert_eq!(cut(&mut context, vec!["-d", "::"]), StatusCode::new(1));
+        assert_eq!(cut(&mut context, vec!["-\u{e9}"]), StatusCode::new(1));
+        assert_eq!(
+            cut(&mut context, vec!["-\u{e9}1", "-f", "1"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            cut(&mut context, vec!["-f", "1", "/invalid/path"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_command_alias_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        let status_code = alias(&mut context, vec!["ll='list-directory", "-a'"]);
+        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(
+            context.shell.aliases.get("ll"),
+            Some(&String::from("list-directory -a"))
+        );
+
+        assert_eq!(alias(&mut context, vec!["ll"]), StatusCode::success());
+        assert_eq!(alias(&mut context, Vec::new()), StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_alias_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(alias(&mut context, vec!["=oops"]), StatusCode::new(1));
+        assert_eq!(alias(&mut context, vec!["undefined"]), StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_command_bookmark_success() {
+        let dir = temp_dir("bookmark-success");
+        fs::create_dir(dir.join("src")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let path = dir.to_str().unwrap();
+        assert!(bookmark(&mut context, vec!["proj", path]).is_success());
+        assert!(bookmark(&mut context, vec!["@other", "/"]).is_success());
+        assert!(bookmarks(&mut context, Vec::new()).is_success());
+
+        let table = &context.shell.bookmarks;
+        assert_eq!(table.get("other"), Some(&PathBuf::from("/")));
+        assert_eq!(resolve_bookmark(table, "proj"), Some(dir.join("")));
+        assert_eq!(resolve_bookmark(table, "proj/src"), Some(dir.join("src")));
+        assert_eq!(resolve_bookmark(table, "missing"), None);
+
+        assert!(change_directory(&mut context, vec!["@proj/src"]).is_success());
+        assert_eq!(context.cwd().absolute(), &dir.join("src"));
+        assert!(change_directory(&mut context, vec!["@other"]).is_success());
+
+        // Saved bookmarks come back when the config file is sourced
+        let config_path = dir.join(".rushrc");
+        config::save_bookmark(&config_path, "proj", &dir).unwrap();
+        let mut shell = Shell::new().unwrap();
+        shell.source_file(&config_path).unwrap();
+        assert_eq!(shell.bookmarks.get("proj"), Some(&dir));
+    }
+
+    #[test]
+    fn test_command_bookmark_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(bookmark(&mut context, vec!["proj"]), StatusCode::new(1));
+        assert_eq!(bookmark(&mut context, vec!["a/b", "/"]), StatusCode::new(2));
+        // Names have to survive being written to (and read back from) the config file
+        for name in ["my proj", "it's", "\"quoted\"", "tab\there", "", "@"] {
+            assert_eq!(bookmark(&mut context, vec![name, "/"]), StatusCode::new(2));
+        }
+        assert!(bookmark(&mut context, vec!["my-proj_2", "/"]).is_success());
+        assert_eq!(
+            bookmark(&mut context, vec!["proj", "/rush-no-such-dir"]),
+            StatusCode::new(2)
+        );
+        assert_eq!(bookmarks(&mut context, vec!["extra"]), StatusCode::new(1));
+        assert_eq!(
+            change_directory(&mut context, vec!["@rush-no-such-bookmark"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_saved_alias_reloads() {
+        let dir = temp_dir("saved-alias-reloads");
+        let config_path = dir.join(".rushrc");
+        fs::write(&config_path, "# existing config\ntruncate 3\n").unwrap();
+
+        config::save_alias(&config_path, "ll", "list-directory -a").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        shell.source_file(&config_path).unwrap();
+        assert_eq!(
+            shell.aliases.get("ll"),
+            Some(&String::from("list-directory -a"))
+        );
+
+        let config = fs::read_to_string(&config_path).unwrap();
+        assert!(config.starts_with("# existing config\ntruncate 3\n"));
+    }
+
+    #[test]
+    fn test_command_change_directory_cdpath_success() {
+        let dir = temp_dir("cdpath-success");
+        fs::create_dir(dir.join("cdpath-target")).unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let cdpath = format!("/invalid/path:{}", dir.display());
+        context.env_mut().set_variable("CDPATH", &cdpath);
+
+        let status_code = change_directory(&mut context, vec!["cdpath-target"]);
+        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(
+            context.cwd().absolute(),
+            &dir.join("cdpath-target").canonicalize().unwrap()
+        );
+
+        // Hidden directories are found too, since only './' and '../' make a path explicitly relative
+        fs::create_dir(dir.join(".config")).unwrap();
+        let status_code = change_directory(&mut context, vec![".config"]);
+        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(
+            context.cwd().absolute(),
+            &dir.join(".config").canonicalize().unwrap()
+        );
+
+        change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_change_directory_cdpath_fail() {
+        let dir = temp_dir("cdpath-fail");
+        fs::create_dir(dir.join("cdpath-target")).unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        context
+            .env_mut()
+            .set_variable("CDPATH", &dir.to_string_lossy());
+
+        // Explicitly relative paths bypass $CDPATH
+        let status_code = change_directory(&mut context, vec!["./cdpath-target"]);
+        assert_eq!(status_code, StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_command_export_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        let status_code = export(&mut context, vec!["RUSH_TEST_EXPORT=value"]);
+        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(env::var("RUSH_TEST_EXPORT").unwrap(), "value");
+        assert_eq!(export(&mut context, vec!["-p"]), StatusCode::success());
+    }
+
+    #[test]
+    fn test_command_export_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(export(&mut context, vec!["1INVALID=x"]), StatusCode::new(2));
+    }
+
+    #[test]
+    fn test_export_declaration_round_trip() {
+        let value = "two words and 'quotes' with $dollar";
+        let declaration = export_declaration("RUSH_TEST_ROUND_TRIP", value);
+
+        let dir = temp_dir("export-round-trip");
+        let script = dir.join("exports.sh");
+        fs::write(&script, format!("{}\n", declaration)).unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let script = script.to_string_lossy().to_string();
+        assert_eq!(source(&mut context, vec![&script]), StatusCode::success());
+
+        assert_eq!(
+            context.env().get_variable("RUSH_TEST_ROUND_TRIP"),
+            Some(String::from(value))
+        );
+        assert_eq!(env::var("RUSH_TEST_ROUND_TRIP").unwrap(), value);
+    }
+
+    #[test]
+    fn test_command_delete_file_trash_success() {
+        let dir = temp_dir("delete-file-trash");
+        let file = dir.join("trashed.txt");
+        fs::write(&file, "contents").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let data_home = dir.join("data");
+        context
+            .env_mut()
+            .set_variable("XDG_DATA_HOME", &data_home.to_string_lossy());
+
+        let file_name = file.to_string_lossy().to_string();
+        let status_code = delete_file(&mut context, vec!["--trash", &file_name]);
+        assert_eq!(status_code, StatusCode::success());
+        assert!(!file.exists());
+        assert!(data_home.join("Trash/files/trashed.txt").exists());
+
+        assert_eq!(
+            restore_trash(&mut context, Vec::new()),
+            StatusCode::success()
+        );
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_format_printf_strings_and_numbers() {
+        assert_eq!(
+            format_printf("%s is %d\\n", &["rush", "42"]).unwrap(),
+            "rush is 42\n"
+        );
+        assert_eq!(
+            format_printf("%x %X %%", &["255", "255"]).unwrap(),
+            "ff FF %"
+        );
+        assert_eq!(format_printf("no arguments", &[]).unwrap(), "no arguments");
+    }
+
+    #[test]
+    fn test_format_printf_width_and_precision() {
+        assert_eq!(format_printf("[%5s]", &["ab"]).unwrap(), "[   ab]");
+        assert_eq!(format_printf("[%-5s]", &["ab"]).unwrap(), "[ab   ]");
+        assert_eq!(format_printf("[%.2s]", &["abcdef"]).unwrap(), "[ab]");
+        assert_eq!(format_printf("[%05d]", &["-42"]).unwrap(), "[-0042]");
+        assert_eq!(format_printf("[%.3d]", &["7"]).unwrap(), "[007]");
+        assert_eq!(format_printf("[%.s]", &["ab"]).unwrap(), "[]");
+
+        // Widths and precisions are capped, rather than padding out to any size
+        assert_eq!(format_printf("%4096s", &[""]).unwrap().len(), 4096);
+        assert_eq!(
+            format_printf("%4097s", &[""]),
+            Err(String::from("Invalid field width: '4097'"))
+        );
+        assert_eq!(
+            format_printf("%.99999999999999999999d", &["1"]),
+            Err(String::from("Invalid precision: '99999999999999999999'"))
+        );
+    }
+
+    #[test]
+    fn test_format_printf_cycles_arguments() {
+        assert_eq!(
+            format_printf("%s=%d;", &["a", "1", "b", "2", "c"]).unwrap(),
+            "a=1;b=2;c=0;"
+        );
+    }
+
+    #[test]
+    fn test_format_printf_invalid() {
+        assert!(format_printf("%q", &["x"]).is_err());
+        assert!(format_printf("%d", &["x"]).is_err());
+        assert!(format_printf("trailing %", &[]).is_err());
+    }
+
+    #[test]
+    fn test_command_printf_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(printf(&mut context, Vec::new()), StatusCode::new(1));
+        assert_eq!(printf(&mut context, vec!["%z"]), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_command_set_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status = set(&mut context, vec!["GREETING", "hello there"]);
+        assert!(status.is_success());
+        assert_eq!(
+            context.env().get_variable("GREETING"),
+            Some(String::from("hello there"))
+        );
+
+        assert!(set(&mut context, vec!["-o", "autocd"]).is_success());
+        assert!(context.shell.autocd);
+        assert!(set(&mut context, vec!["+o", "autocd"]).is_success());
+        assert!(!context.shell.autocd);
+    }
+
+    #[test]
+    fn test_command_set_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!set(&mut context, vec!["1NAME", "value"]).is_success());
+        assert!(!set(&mut context, vec!["NAME"]).is_success());
+    }
+
+    #[test]
+    fn test_command_break_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!r#break(&mut context, vec![]).is_success());
+        assert!(!r#continue(&mut context, vec![]).is_success());
+    }
+
+    #[test]
+    fn test_parse_history_range() {
+        assert_eq!(parse_history_range(&[], 20, false), Ok((20, 20)));
+        assert_eq!(parse_history_range(&[], 20, true), Ok((5, 20)));
+        assert_eq!(parse_history_range(&[], 3, true), Ok((1, 3)));
+        assert_eq!(parse_history_range(&["10"], 20, false), Ok((10, 10)));
+        assert_eq!(parse_history_range(&["10"], 20, true), Ok((10, 20)));
+        assert_eq!(parse_history_range(&["10", "15"], 20, false), Ok((10, 15)));
+        assert_eq!(parse_history_range(&["15", "10"], 20, true), Ok((10, 15)));
+        assert_eq!(parse_history_range(&["-3", "-1"], 20, true), Ok((18, 20)));
+
+        assert!(parse_history_range(&["0"], 20, false).is_err());
+        assert!(parse_history_range(&["21"], 20, false).is_err());
+        assert!(parse_history_range(&["-21"], 20, false).is_err());
+        assert!(parse_history_range(&["ten"], 20, false).is_err());
+    }
+
+    #[test]
+    fn test_command_fc_success() {
+        let mut shell = Shell::new().unwrap();
+        shell.history.add("true");
+        shell.history.add("false");
+        let mut context = Context::new(&mut shell);
+        assert!(fc(&mut context, vec!["-l"]).is_success());
+        assert!(fc(&mut context, vec!["-l", "-1"]).is_success());
+    }
+
+    #[test]
+    fn test_command_fc_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!fc(&mut context, vec!["-l"]).is_success());
+        context.shell.history.add("true");
+        assert!(!fc(&mut context, vec!["-l", "5"]).is_success());
+        assert!(!fc(&mut context, vec!["1", "1", "1"]).is_success());
+    }
+
+    #[test]
+    fn test_command_kill_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!kill(&mut context, vec![]).is_success());
+        assert!(!kill(&mut context, vec!["%1"]).is_success());
+        assert!(!kill(&mut context, vec!["-BOGUS", "1"]).is_success());
+        assert!(!kill(&mut context, vec!["not-a-pid"]).is_success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_wait_success() {
+        let mut shell = Shell::new().unwrap();
+        let child = jobs::spawn_background(Path::new("false"), &[]).unwrap();
+        shell.jobs.add(child, "false");
+        let mut context = Context::new(&mut shell);
+
+        // The status of the job is passed on
+        assert_eq!(wait(&mut context, vec!["%1"]), StatusCode::new(1));
+        assert_eq!(context.shell.jobs.iter().count(), 0);
+        assert!(wait(&mut context, vec![]).is_success());
+    }
+
+    #[test]
+    fn test_command_wait_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!wait(&mut context, vec!["%1"]).is_success());
+        assert!(!wait(&mut context, vec!["1"]).is_success());
+    }
+
+    #[test]
+    fn test_command_expand_success() {
+        let mut shell = Shell::new().unwrap();
+        shell.aliases.insert(
+            String::from("ll"),
+            String::from("list-directory $EXPAND_DIR"),
+        );
+        shell.environment.set_variable("EXPAND_DIR", "/tmp");
+        let mut context = Context::new(&mut shell);
+
+        assert!(expand(&mut context, vec!["-v", "ll; if true; then ll -a; fi"]).is_success());
+        // Nothing was run, so no variables changed
+        assert!(expand(&mut context, vec!["set EXPAND_DIR /"]).is_success());
+        assert_eq!(
+            context.env().get_variable("EXPAND_DIR"),
+            Some(String::from("/tmp"))
+        );
+    }
+
+    #[test]
+    fn test_command_expand_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!expand(&mut context, vec![]).is_success());
+        assert!(!expand(&mut context, vec!["if true"]).is_success());
+    }
+
+    #[test]
+    fn test_command_history_success() {
+        let dir = temp_dir("history-export");
+        let mut shell = Shell::new().unwrap();
+        shell.history.add("true");
+        let mut context = Context::new(&mut shell);
+        let export_path = dir.join("history.json");
+        let export_path = export_path.to_str().unwrap();
+
+        assert!(history(&mut context, vec![]).is_success());
+        assert!(history(
+            &mut context,
+            vec!["--export", export_path, "--format", "json"]
+        )
+        .is_success());
+        assert!(fs::read_to_string(export_path)
+            .unwrap()
+            .contains("\"command\": \"true\""));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_history_fail() {
+        let dir = temp_dir("history-export-fail");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let export_path = dir.join("history.txt");
+        let export_path = export_path.to_str().unwrap();
+
+        assert!(!history(&mut context, vec!["--export"]).is_success());
+        assert!(!history(
+            &mut context,
+            vec!["--export", export_path, "--format", "xml"]
+        )
+        .is_success());
+        // The parent directory doesn't exist, so the file can't be written
+        let missing = dir.join("missing").join("history.txt");
+        assert!(!history(&mut context, vec!["--export", missing.to_str().unwrap()]).is_success());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_man_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(man(&mut context, vec![]).is_success());
+        assert!(man(&mut context, vec!["sort"]).is_success());
+        // Aliases find the same page
+        assert!(man(&mut context, vec!["cd"]).is_success());
+
+        // Every page belongs to a registered command
+        for (name, _) in crate::manual::pages() {
+            assert!(man(&mut context, vec![name]).is_success(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_command_man_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!man(&mut context, vec!["not-a-command"]).is_success());
+        assert!(!man(&mut context, vec!["working-directory"]).is_success());
+        assert!(!man(&mut context, vec!["sort", "cut"]).is_success());
+    }
+
+    #[test]
+    fn test_command_nohup_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!nohup(&mut context, vec![]).is_success());
+        assert!(!nohup(&mut context, vec!["true"]).is_success());
+        assert!(!nohup(&mut context, vec!["rush-no-such-command"]).is_success());
+    }
+
+    #[test]
+    fn test_resolve_exec_target() {
+        let search_path = env::var("PATH").unwrap_or_default();
+        assert!(resolve_exec_target("sh", &search_path, false).is_ok());
+        assert!(resolve_exec_target("sh", &search_path, true).is_err());
+        assert!(resolve_exec_target("rush-no-such-command", &search_path, false).is_err());
+        assert!(resolve_exec_target("sh", "", false).is_err());
+    }
+
+    #[test]
+    fn test_command_exec_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(exec(&mut context, vec![]).is_success());
+    }
+
+    #[test]
+    fn test_command_exec_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        // Neither of these replace the test process, since they fail before exec is called
+        assert_eq!(
+            exec(&mut context, vec!["rush-no-such-command"]),
+            StatusCode::new(127)
+        );
+        assert_eq!(exec(&mut context, vec!["true"]), StatusCode::new(127));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_df_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(df(&mut context, vec!["/"]).is_success());
+        assert!(df(&mut context, vec!["-h", "/"]).is_success());
+    }
+
+    #[test]
+    fn test_command_df_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!df(&mut context, vec!["-x"]).is_success());
+        assert!(!df(&mut context, vec!["/rush-no-such-path"]).is_success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_ln_success() {
+        let dir = temp_dir("ln");
+        let target = dir.join("target.txt");
+        fs::write(&target, "contents").unwrap();
+        let hard = dir.join("hard.txt");
+        let symbolic = dir.join("symbolic.txt");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        let status = ln(
+            &mut context,
+            vec![target.to_str().unwrap(), hard.to_str().unwrap()],
+        );
+        assert!(status.is_success());
+        assert!(!fs::symlink_metadata(&hard)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&hard).unwrap(), "contents");
+
+        let status = ln(
+            &mut context,
+            vec!["-s", "target.txt", symbolic.to_str().unwrap()],
+        );
+        assert!(status.is_success());
+        assert_eq!(fs::read_link(&symbolic).unwrap(), Path::new("target.txt"));
+        assert_eq!(fs::read_to_string(&symbolic).unwrap(), "contents");
+
+        // -f replaces the existing link
+        let status = ln(
+            &mut context,
+            vec!["-sf", "hard.txt", symbolic.to_str().unwrap()],
+        );
+        assert!(status.is_success());
+        assert_eq!(fs::read_link(&symbolic).unwrap(), Path::new("hard.txt"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_ln_fail() {
+        let dir = temp_dir("ln-fail");
+        let target = dir.join("target.txt");
+        fs::write(&target, "").unwrap();
+        let existing = dir.join("existing.txt");
+        fs::write(&existing, "").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert!(!ln(&mut context, vec![target.to_str().unwrap()]).is_success());
+        assert!(!ln(&mut context, vec!["-x", "a", "b"]).is_success());
+        // Hard links need an existing target
+        let missing = dir.join("missing.txt");
+        let link = dir.join("link.txt");
+        assert!(!ln(
+            &mut context,
+            vec![missing.to_str().unwrap(), link.to_str().unwrap()]
+        )
+        .is_success());
+        // Existing files are only replaced with -f
+        assert!(!ln(
+            &mut context,
+            vec![target.to_str().unwrap(), existing.to_str().unwrap()]
+        )
+        .is_success());
+
+        // -f never removes the link before the target has been checked
+        fs::write(&target, "contents").unwrap();
+        let target_path = target.to_str().unwrap();
+        assert_eq!(
+            ln(&mut context, vec!["-f", target_path, target_path]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            ln(&mut context, vec!["-sf", "target.txt", target_path]),
+            StatusCode::new(2)
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "contents");
+        assert_eq!(
+            ln(
+                &mut context,
+                vec!["-f", missing.to_str().unwrap(), existing.to_str().unwrap()]
+            ),
+            StatusCode::new(2)
+        );
+        assert!(existing.exists());
+        // A hard link to the target is the same file too
+        let hard = dir.join("hard.txt");
+        fs::hard_link(&target, &hard).unwrap();
+        assert_eq!(
+            ln(
+                &mut context,
+                vec!["-f", target_path, hard.to_str().unwrap()]
+            ),
+            StatusCode::new(2)
+        );
+        assert!(hard.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_ln_across_filesystems() {
+        use std::os::unix::fs::MetadataExt;
+
+        let message = link_error_message(&io::Error::from_raw_os_error(libc::EXDEV), "a", "b");
+        assert!(message.starts_with("Cannot hard link across filesystems"));
+        assert!(
+            link_error_message(&io::Error::from(io::ErrorKind::PermissionDenied), "a", "b")
+                .starts_with("Failed to create link")
+        );
+
+        // /dev/shm is usually a separate tmpfs, which makes for a real cross-filesystem link
+        let dir = temp_dir("ln-cross");
+        let target = dir.join("target.txt");
+        fs::write(&target, "").unwrap();
+        let other = Path::new("/dev/shm");
+        let same_device = |other: &Path| {
+            fs::metadata(other).ok().map(|m| m.dev()) == fs::metadata(&dir).ok().map(|m| m.dev())
+        };
+        if other.is_dir() && !same_device(other) {
+            let link = other.join(format!("rush-test-ln-{}", process::id()));
+            let mut shell = Shell::new().unwrap();
+            let mut context = Context::new(&mut shell);
+            let status = ln(
+                &mut context,
+                vec![target.to_str().unwrap(), link.to_str().unwrap()],
+            );
+            assert_eq!(status, StatusCode::new(3));
+            assert!(!link.exists());
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_change_directory_recent() {
+        let dir = temp_dir("cd-recent");
+        let first = dir.join("first");
+        let second = dir.join("second");
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert!(!change_directory(&mut context, vec!["--"]).is_success());
+        assert!(change_directory(&mut context, vec![first.to_str().unwrap()]).is_success());
+        assert!(change_directory(&mut context, vec![second.to_str().unwrap()]).is_success());
+        assert!(change_directory(&mut context, vec!["--"]).is_success());
+
+        // The most recently left directory is number 1
+        let first = first.canonicalize().unwrap();
+        assert_eq!(context.env().recent_directories().get(1), Some(&first));
+        assert!(change_directory(&mut context, vec!["-1"]).is_success());
+        assert_eq!(context.cwd().absolute(), &first);
+
+        // A directory that no longer exists leaves the working directory alone
+        let second = second.canonicalize().unwrap();
+        assert_eq!(context.env().recent_directories().get(1), Some(&second));
+        fs::remove_dir(&second).unwrap();
+        assert_eq!(
+            change_directory(&mut context, vec!["-1"]),
+            StatusCode::new(3)
+        );
+        assert_eq!(context.cwd().absolute(), &first);
+
+        assert!(!change_directory(&mut context, vec!["-9"]).is_success());
+        // Leave the directory before it is removed, since the working directory is shared by every test
+        change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_env_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        context
+            .env_mut()
+            .export_variable("RUSH_ENV_TEST", Some("visible"));
+        let check = r#"test -z "$RUSH_ENV_TEST""#;
+
+        // The exported variable is inherited normally, but not by a clean environment
+        assert!(!env(&mut context, vec!["sh", "-c", check]).is_success());
+        assert!(env(&mut context, vec!["-i", "sh", "-c", check]).is_success());
+        assert!(env(
+            &mut context,
+            vec![
+                "-i",
+                "RUSH_ENV_OVERLAY=set",
+                "sh",
+                "-c",
+                r#"test "$RUSH_ENV_OVERLAY" = set"#
+            ]
+        )
+        .is_success());
+        assert_eq!(
+            env(&mut context, vec!["-i", "sh", "-c", "exit 4"]),
+            StatusCode::new(4)
+        );
+
+        // The shell's variables are left untouched
+        assert_eq!(context.env().get_variable("RUSH_ENV_OVERLAY"), None);
+        assert!(env(&mut context, vec![]).is_success());
+    }
+
+    #[test]
+    fn test_command_env_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!env(&mut context, vec!["-x"]).is_success());
+        assert_eq!(
+            env(&mut context, vec!["-i", "rush-no-such-command"]),
+            StatusCode::new(127)
+        );
+    }
+
+    #[test]
+    fn test_base_name() {
+        assert_eq!(base_name("/usr/lib/libc.so", None), "libc.so");
+        assert_eq!(base_name("/usr/lib/", None), "lib");
+        assert_eq!(base_name("notes.txt", Some(".txt")), "notes");
+        assert_eq!(base_name(".txt", Some(".txt")), ".txt");
+        assert_eq!(base_name("///", None), "/");
+        assert_eq!(base_name("", None), "");
+    }
+
+    #[test]
+    fn test_dir_name() {
+        assert_eq!(dir_name("/usr/lib/libc.so"), "/usr/lib");
+        assert_eq!(dir_name("/usr/lib/"), "/usr");
+        assert_eq!(dir_name("/usr"), "/");
+        assert_eq!(dir_name("a//b"), "a");
+        assert_eq!(dir_name("file"), ".");
+        assert_eq!(dir_name("/"), "/");
+        assert_eq!(dir_name(""), ".");
+    }
+
+    #[test]
+    fn test_transform_lines() {
+        let input = io::Cursor::new("/home/user/notes.txt\nsrc/main.rs\nfile\n");
+
+        let mut output = Vec::new();
+        transform_lines(input.clone(), &mut output, dir_name).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "/home/user\nsrc\n.\n");
+
+        let mut output = Vec::new();
+        transform_lines(input, &mut output, |line| base_name(line, None)).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "notes.txt\nmain.rs\nfile\n"
+        );
+    }
+
+    #[test]
+    fn test_command_basename_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(basename(&mut context, vec!["/a/b.txt", ".txt"]).is_success());
+        assert!(dirname(&mut context, vec!["/a/b.txt", "c"]).is_success());
+    }
+
+    #[test]
+    fn test_command_basename_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!basename(&mut context, vec!["a", "b", "c"]).is_success());
+    }
+
+    #[test]
+    fn test_command_render_prompt_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(render_prompt(&mut context, vec!["\\u on \\w\\n\\$ "]).is_success());
+    }
+
+    #[test]
+    fn test_command_render_prompt_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!render_prompt(&mut context, vec![]).is_success());
+        assert!(!render_prompt(&mut context, vec!["a", "b"]).is_success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_create_file_success() {
+        let dir = temp_dir("create-file");
+        let file = dir.join("file.txt");
+        let file_name = file.to_str().unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        // -c never creates the file
+        assert!(create_file(&mut context, vec!["-c", file_name]).is_success());
+        assert!(!file.exists());
+
+        fs::write(&file, "contents").unwrap();
+        assert!(create_file(&mut context, vec!["-t", "202304111736.07", file_name]).is_success());
+        let expected = DateTime::parse_touch_timestamp("202304111736.07", 0)
+            .unwrap()
+            .to_system_time();
+        assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), expected);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "contents");
+
+        assert!(
+            create_file(&mut context, vec!["-c", "-t", "200001010000", file_name]).is_success()
+        );
+        let expected = DateTime::parse_touch_timestamp("200001010000", 0)
+            .unwrap()
+            .to_system_time();
+        assert_eq!(fs::metadata(&file).unwrap().modified().unwrap(), expected);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_create_file_fail() {
+        let dir = temp_dir("create-file-fail");
+        let file = dir.join("file.txt");
+        let file_name = file.to_str().unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(create_file(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(create_file(&mut context, vec!["-t"]), StatusCode::new(1));
+        assert_eq!(
+            create_file(&mut context, vec!["-t", "yesterday", file_name]),
+            StatusCode::new(2)
+        );
+        assert!(!file.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_indicator() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("file-indicator");
+        let plain = dir.join("plain.txt");
+        let script = dir.join("script.sh");
+        let link = dir.join("link");
+        fs::write(&plain, "").unwrap();
+        fs::write(&script, "").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink(&script, &link).unwrap();
+
+        assert_eq!(file_indicator(&plain), None);
+        assert_eq!(file_indicator(&script), Some('*'));
+        // A symlink is marked as a symlink even if it points to an executable
+        assert_eq!(file_indicator(&link), Some('@'));
+        assert_eq!(file_indicator(&dir), Some('/'));
+        assert_eq!(file_indicator(&dir.join("missing")), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_list_directory_classify_success() {
+        let dir = temp_dir("list-directory");
+        fs::write(dir.join("file.txt"), "").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(list_directory(&mut context, vec![dir.to_str().unwrap()]).is_success());
+        assert!(list_directory(&mut context, vec!["-F", dir.to_str().unwrap()]).is_success());
+        assert!(
+            list_directory(&mut context, vec![dir.to_str().unwrap(), "--classify"]).is_success()
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_directory_classify_indicators() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("list-directory-classify");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("plain.txt"), "").unwrap();
+        fs::write(dir.join("script.sh"), "").unwrap();
+        fs::set_permissions(dir.join("script.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink(dir.join("script.sh"), dir.join("link")).unwrap();
+
+        let options = ListingOptions {
+            classify: true,
+            ..Default::default()
+        };
+        let (directories, files) = listing_entries(&dir, &options, None).unwrap();
+        assert_eq!(directories.len(), 1);
+        assert!(directories[0].contains("sub/"));
+        assert_eq!(files, ["link@", "plain.txt", "script.sh*"]);
+
+        // Without -F, only directories are marked
+        let (_, files) = listing_entries(&dir, &ListingOptions::default(), None).unwrap();
+        assert_eq!(files, ["link", "plain.txt", "script.sh"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_list_directory_classify_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(!list_directory(&mut context, vec!["/", "/"]).is_success());
+        assert!(!list_directory(&mut context, vec!["-F", "/rush-no-such-path"]).is_success());
+    }
+
+    #[test]
+    fn test_hex_dump_line() {
+        assert_eq!(
+            hex_dump_line(0, b"Hello, world!\n\x00\xff"),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|"
+        );
+        assert_eq!(
+            hex_dump_line(0x10, b"abc"),
+            "00000010  61 62 63                                          |abc|"
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let mut output = Vec::new();
+        hex_dump(&b"0123456789abcdefXYZ"[..], &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             00000010  58 59 5a                                          |XYZ|\n"
+        );
+
+        let mut output = Vec::new();
+        hex_dump(&b""[..], &mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_command_read_file_hex() {
+        let dir = temp_dir("read-file-hex");
+        let file = dir.join("binary");
+        fs::write(&file, [0x00, 0x80, 0xff, 0xfe]).unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert!(read_file(&mut context, vec!["--hex", file.to_str().unwrap()]).is_success());
+        // Invalid UTF-8 is replaced instead of panicking
+        assert!(read_file(&mut context, vec![file.to_str().unwrap()]).is_success());
+        assert_eq!(read_file(&mut context, vec!["-x"]), StatusCode::new(1));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_change_directory_create() {
+        let dir = temp_dir("cd-create");
+        let nested = dir.join("a").join("b");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        // Without -p, a missing directory is still an error
+        assert_eq!(
+            change_directory(&mut context, vec![nested.to_str().unwrap()]),
+            StatusCode::new(2)
+        );
+        assert!(!nested.exists());
+
+        assert!(change_directory(&mut context, vec!["-p", nested.to_str().unwrap()]).is_success());
+        assert!(nested.is_dir());
+        assert_eq!(context.cwd().absolute(), &nested.canonicalize().unwrap());
+
+        // An existing directory is just changed into
+        assert!(
+            change_directory(&mut context, vec!["--create", dir.to_str().unwrap()]).is_success()
+        );
+
+        fs::write(dir.join("file"), "").unwrap();
+        let blocked = dir.join("file").join("c");
+        assert_eq!(
+            change_directory(&mut context, vec!["-p", blocked.to_str().unwrap()]),
+            StatusCode::new(3)
+        );
+
+        // Leave the directory before it is removed, since the working directory is shared by every test
+        change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("10:20"), Some((10, Some(20))));
+        assert_eq!(parse_line_range("10:"), Some((10, None)));
+        assert_eq!(parse_line_range(":20"), Some((1, Some(20))));
+        assert_eq!(parse_line_range(":"), Some((1, None)));
+        assert_eq!(parse_line_range("7"), Some((7, Some(7))));
+        assert_eq!(parse_line_range("0:5"), None);
+        assert_eq!(parse_line_range("5:4"), None);
+        assert_eq!(parse_line_range("a:b"), None);
+        assert_eq!(parse_line_range("-1:"), None);
+        assert_eq!(parse_line_range(""), None);
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let error = SearchPattern::new("error", false);
+        let highlighted = "error".red().bold();
+        assert_eq!(
+            highlight_matches("an error, another error", &error, true),
+            format!("an {}, another {}", highlighted, highlighted)
+        );
+        assert_eq!(
+            highlight_matches("error", &error, true),
+            highlighted.to_string()
+        );
+        assert_eq!(highlight_matches("no matches", &error, true), "no matches");
+        // Without colors, matches are left plain
+        assert_eq!(highlight_matches("an error", &error, false), "an error");
+        let empty = SearchPattern::new("", false);
+        assert_eq!(highlight_matches("an error", &empty, true), "an error");
+
+        // Matching ignores case the same way as 'search', keeping the line's own case
+        let ignoring_case = SearchPattern::new("ERROR", true);
+        assert_eq!(
+            highlight_matches("An Error", &ignoring_case, true),
+            format!("An {}", "Error".red().bold())
+        );
+        assert_eq!(ignoring_case.find_all("İ error, ERROR"), [3..8, 10..15]);
+
+        let b = SearchPattern::new("b", false);
+        let format = LineFormat {
+            tab_width: Some(4),
+            highlight: Some(&b),
+            color: true,
+            ..LineFormat::default()
+        };
+        assert_eq!(
+            format.apply(String::from("a\tb")),
+            format!("a   {}", "b".red().bold())
+        );
+    }
+
+    #[test]
+    fn test_print_line_range() {
+        let dir = temp_dir("line-range");
+        let file = dir.join("lines.txt");
+        let contents: String = (1..=30)
+            .map(|number| format!("line {}\n", number))
+            .collect();
+        fs::write(&file, contents).unwrap();
+
+        let select = |range: &str| {
+            let (start, end) = parse_line_range(range).unwrap();
+            let reader = BufReader::new(fs::File::open(&file).unwrap());
+            let mut output = Vec::new();
+            print_line_range(reader, &mut output, start, end, &LineFormat::default()).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(select("2:4"), "line 2\nline 3\nline 4\n");
+        assert_eq!(select("29:"), "line 29\nline 30\n");
+        assert_eq!(select(":2"), "line 1\nline 2\n");
+        assert_eq!(select("30:40"), "line 30\n");
+        assert_eq!(select("31:"), "");
+        assert_eq!(select("12"), "line 12\n");
+        assert_eq!(select(":").lines().count(), 30);
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let file_name = file.to_str().unwrap();
+        assert!(read_file(&mut context, vec!["--lines", "100:", file_name]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--lines", "4:2", file_name]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["--lines", file_name]),
+            StatusCode::new(1)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_search_file() {
+        let dir = temp_dir("search-file");
+        let text = dir.join("text.txt");
+        let binary = dir.join("binary");
+        fs::write(&text, "needle one\nhay\nneedle two\n").unwrap();
+        fs::write(&binary, b"needle\0\xff\n").unwrap();
+        let needle = SearchPattern::new("needle", false);
+
+        assert_eq!(
+            search_file(&text, &needle, false).unwrap(),
+            Some(vec![String::from("needle one"), String::from("needle two")])
+        );
+        assert_eq!(
+            search_file(&text, &SearchPattern::new("missing", false), false).unwrap(),
+            Some(Vec::new())
+        );
+        assert_eq!(search_file(&binary, &needle, false).unwrap(), None);
+        assert_eq!(
+            search_file(&binary, &needle, true).unwrap(),
+            Some(vec![String::from("needle\0\u{fffd}")])
+        );
+        assert!(search_file(&dir.join("missing"), &needle, false).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_search_success() {
+        let dir = temp_dir("search-tree");
+        fs::create_dir_all(dir.join("a").join("b")).unwrap();
+        fs::write(dir.join("top.txt"), "needle\n").unwrap();
+        fs::write(dir.join("a").join("b").join("deep.txt"), "hay\nneedle\n").unwrap();
+        fs::write(dir.join("a").join("binary"), b"needle\0").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("a").join("loop")).unwrap();
+        let files = search_targets(&dir);
+        let needle = SearchPattern::new("needle", false);
+        let no_context = SearchContext::default();
+        let (matches, unreadable) = search_files(&files, &needle, false, true, no_context);
+        assert_eq!(
+            matches,
+            [
+                format!(
+                    "{}:needle",
+                    dir.join("a").join("b").join("deep.txt").display()
+                ),
+                format!("{}:needle", dir.join("top.txt").display()),
+            ]
+        );
+        assert!(unreadable.is_empty());
+        let (matches, _) = search_files(&files, &needle, true, true, no_context);
+        assert_eq!(matches.len(), 3);
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let root = dir.to_str().unwrap();
+
+        assert!(search(&mut context, vec!["-r", "needle", root]).is_success());
+        assert!(search(&mut context, vec!["-r", "-a", "needle", root]).is_success());
+        let deep = dir.join("a").join("b").join("deep.txt");
+        assert!(search(&mut context, vec!["needle", deep.to_str().unwrap()]).is_success());
+        assert_eq!(
+            search(&mut context, vec!["-r", "missing", root]),
+            StatusCode::new(1)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_search_fail() {
+        let dir = temp_dir("search-fail");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(search(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(search(&mut context, vec!["-x", "a"]), StatusCode::new(1));
+        // Directories need -r
+        assert_eq!(
+            search(&mut context, vec!["needle", dir.to_str().unwrap()]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            search(&mut context, vec!["needle", "/rush-no-such-file"]),
+            StatusCode::new(2)
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_search_count() {
+        let dir = temp_dir("search-count");
+        let one = dir.join("one.txt");
+        let two = dir.join("two.txt");
+        fs::write(&one, "Needle\nhay\nneedle\nneedle again\n").unwrap();
+        fs::write(&two, "hay\n").unwrap();
+
+        let files = vec![one.clone()];
+        let (counts, _) = count_matches(&files, &SearchPattern::new("needle", false), false);
+        assert_eq!(counts, [(one.clone(), 2)]);
+        // -i counts the lines that only match without case
+        let (counts, _) = count_matches(&files, &SearchPattern::new("NEEDLE", true), false);
+        assert_eq!(counts, [(one.clone(), 3)]);
+
+        let files = vec![one.clone(), two.clone(), dir.join("missing")];
+        let (counts, unreadable) =
+            count_matches(&files, &SearchPattern::new("needle", true), false);
+        assert_eq!(counts, [(one.clone(), 3), (two.clone(), 0)]);
+        assert_eq!(unreadable, [dir.join("missing")]);
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let (one, two) = (one.to_str().unwrap(), two.to_str().unwrap());
+        assert!(search(&mut context, vec!["-c", "needle", one]).is_success());
+        assert!(search(&mut context, vec!["--count", "-i", "NEEDLE", one, two]).is_success());
+        // Nothing matching in any file fails, like a search without -c
+        assert_eq!(
+            search(&mut context, vec!["-c", "needle", two]),
+            StatusCode::new(1)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_format_file_info() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_681_234_567);
+        let info = FileInfo {
+            name: String::from("notes.txt"),
+            size: 1024,
+            kind: "regular file",
+            permissions: 0o644,
+            modified,
+        };
+
+        assert_eq!(format_file_info("%n %s %a", &info), "notes.txt 1024 644");
+        assert_eq!(
+            format_file_info("%F: %n\\n100%% %q %", &info),
+            "regular file: notes.txt\n100% %q %"
+        );
+        assert_eq!(
+            format_file_info("%y", &info),
+            DateTime::from_system_time(modified).format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_stat_success() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("stat");
+        let file = dir.join("file.txt");
+        fs::write(&file, "hello").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let info = FileInfo::read(file.to_str().unwrap(), false).unwrap();
+        assert_eq!(format_file_info("%s %F %a", &info), "5 regular file 640");
+        let info = FileInfo::read(dir.to_str().unwrap(), false).unwrap();
+        assert_eq!(info.kind, "directory");
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(stat(&mut context, vec![file.to_str().unwrap()]).is_success());
+        assert!(stat(&mut context, vec!["-c", "%n %s", file.to_str().unwrap()]).is_success());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_stat_dereference() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("stat-dereference");
+        let file = dir.join("file.txt");
+        let link = dir.join("link");
+        let broken = dir.join("broken");
+        fs::write(&file, "hello").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), &broken).unwrap();
+        let (link, broken) = (link.to_str().unwrap(), broken.to_str().unwrap());
+
+        let link_info = FileInfo::read(link, false).unwrap();
+        assert_eq!(link_info.kind, "symbolic link");
+        // Following the link describes the file it points to, under the link's name
+        let target_info = FileInfo::read(link, true).unwrap();
+        assert_eq!(
+            format_file_info("%n %s %F %a", &target_info),
+            format!("{} 5 regular file 640", link)
+        );
+        assert!(FileInfo::read(broken, true).is_err());
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(stat(&mut context, vec!["-L", link]).is_success());
+        assert!(stat(&mut context, vec![broken]).is_success());
+        assert_eq!(stat(&mut context, vec!["-L", broken]), StatusCode::new(2));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_stat_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(stat(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(stat(&mut context, vec!["-c", "%n"]), StatusCode::new(1));
+        assert_eq!(
+            stat(&mut context, vec!["/rush-no-such-file"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_change_directory_symlinks() {
+        let dir = temp_dir("cd-symlinks").canonicalize().unwrap();
+        let target = dir.join("target");
+        let link = dir.join("link");
+        fs::create_dir_all(target.join("inner")).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        // Logical by default, keeping the symlink in the path
+        assert!(change_directory(&mut context, vec![link.to_str().unwrap()]).is_success());
+        assert_eq!(context.cwd().absolute(), &link);
+        assert_eq!(env::var("PWD").unwrap(), link.to_str().unwrap());
+        assert!(change_directory(&mut context, vec!["-L", "inner"]).is_success());
+        assert_eq!(context.cwd().absolute(), &link.join("inner"));
+        // '..' goes back through the symlink
+        assert!(change_directory(&mut context, vec![".."]).is_success());
+        assert_eq!(context.cwd().absolute(), &link);
+
+        assert!(change_directory(&mut context, vec!["-P", link.to_str().unwrap()]).is_success());
+        assert_eq!(context.cwd().absolute(), &target);
+        assert!(change_directory(&mut context, vec!["-P", "inner"]).is_success());
+        assert_eq!(context.cwd().absolute(), &target.join("inner"));
+
+        assert!(!change_directory(&mut context, vec!["-P"]).is_success());
+        // Leave the directory before it is removed, since the working directory is shared by every test
+        change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_list_directory_git() {
+        let dir = temp_dir("list-directory-git");
+        fs::create_dir(dir.join(".git")).unwrap();
+        fs::write(dir.join("file.txt"), "").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        // A repository with no index yet has nothing tracked
+        assert!(list_directory(&mut context, vec!["--git", dir.to_str().unwrap()]).is_success());
+        // Outside of a repository, nothing is annotated
+        assert!(list_directory(&mut context, vec!["--git", "/"]).is_success());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_copy_file_success() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("copy-file");
+        let source = dir.join("source.sh");
+        fs::write(&source, "echo hi").unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o750)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        let plain = dir.join("plain.sh");
+        assert!(copy_file(
+            &mut context,
+            vec![source.to_str().unwrap(), plain.to_str().unwrap()]
+        )
+        .is_success());
+        assert_eq!(fs::read_to_string(&plain).unwrap(), "echo hi");
+        assert_ne!(fs::metadata(&plain).unwrap().modified().unwrap(), modified);
+
+        let preserved = dir.join("preserved.sh");
+        assert!(copy_file(
+            &mut context,
+            vec!["-p", source.to_str().unwrap(), preserved.to_str().unwrap()]
+        )
+        .is_success());
+        let metadata = fs::metadata(&preserved).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        assert_eq!(metadata.modified().unwrap(), modified);
+
+        // Copying into a directory keeps the file name
+        fs::create_dir(dir.join("inner")).unwrap();
+        assert!(copy_file(
+            &mut context,
+            vec![
+                source.to_str().unwrap(),
+                dir.join("inner").to_str().unwrap()
+            ]
+        )
+        .is_success());
+        assert!(dir.join("inner").join("source.sh").is_file());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_resolve_conflict() {
+        use ConflictAction::*;
+        use PathState::{Directory, File, Missing};
+
+        for policy in [
+            ConflictPolicy::Overwrite,
+            ConflictPolicy::Skip,
+            ConflictPolicy::Rename,
+        ] {
+            assert_eq!(resolve_conflict(File, Missing, policy), Create);
+            assert_eq!(resolve_conflict(Directory, Missing, policy), Create);
+            assert_eq!(resolve_conflict(Directory, Directory, policy), Merge);
+        }
+
+        assert_eq!(
+            resolve_conflict(File, File, ConflictPolicy::Overwrite),
+            Replace
+        );
+        // A file and a directory never replace each other
+        assert_eq!(
+            resolve_conflict(Directory, File, ConflictPolicy::Overwrite),
+            Mismatch
+        );
+        assert_eq!(
+            resolve_conflict(File, Directory, ConflictPolicy::Overwrite),
+            Mismatch
+        );
+        assert_eq!(resolve_conflict(File, File, ConflictPolicy::Skip), Skip);
+        assert_eq!(
+            resolve_conflict(File, Directory, ConflictPolicy::Skip),
+            Skip
+        );
+        assert_eq!(resolve_conflict(File, File, ConflictPolicy::Rename), Rename);
+        assert_eq!(ConflictPolicy::parse("skip"), Some(ConflictPolicy::Skip));
+        assert_eq!(ConflictPolicy::parse("merge"), None);
+    }
+
+    #[test]
+    fn test_backup_path() {
+        let file = Path::new("/data/notes.txt");
+        let taken = |path: &Path| path.to_str() == Some("/data/notes.txt.1");
+
+        assert_eq!(
+            backup_path(file, BackupScheme::Simple, taken),
+            PathBuf::from("/data/notes.txt~")
+        );
+        assert_eq!(
+            backup_path(file, BackupScheme::Numbered, taken),
+            PathBuf::from("/data/notes.txt.2")
+        );
+        assert_eq!(
+            backup_path(file, BackupScheme::Numbered, |_| false),
+            PathBuf::from("/data/notes.txt.1")
+        );
+        assert_eq!(BackupScheme::parse(None), Some(BackupScheme::Simple));
+        assert_eq!(
+            BackupScheme::parse(Some("numbered")),
+            Some(BackupScheme::Numbered)
+        );
+        assert_eq!(BackupScheme::parse(Some("t")), None);
+    }
+
+    #[test]
+    fn test_command_copy_file_backup() {
+        let dir = temp_dir("copy-file-backup");
+        let source = dir.join("source.txt");
+        let destination = dir.join("destination.txt");
+        fs::write(&destination, "first").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let (from, to) = (source.to_str().unwrap(), destination.to_str().unwrap());
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+
+        fs::write(&source, "second").unwrap();
+        assert!(copy_file(&mut context, vec!["--backup", from, to]).is_success());
+        assert_eq!(read(&dir.join("destination.txt~")), "first");
+        assert_eq!(read(&destination), "second");
+
+        for contents in ["third", "fourth"] {
+            fs::write(&source, contents).unwrap();
+            assert!(copy_file(&mut context, vec!["--backup=numbered", from, to]).is_success());
+        }
+        assert_eq!(read(&dir.join("destination.txt.1")), "second");
+        assert_eq!(read(&dir.join("destination.txt.2")), "third");
+        assert_eq!(read(&destination), "fourth");
+
+        assert_eq!(
+            copy_file(&mut context, vec!["--backup=sometimes", from, to]),
+            StatusCode::new(2)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_merge_tree_policies() {
+        let dir = temp_dir("merge-tree");
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("same.txt"), "new").unwrap();
+        fs::write(source.join("nested").join("added.txt"), "added").unwrap();
+        fs::write(source.join("nested").join("deep.txt"), "new").unwrap();
+
+        let destination = |name: &str| {
+            let destination = dir.join(name);
+            fs::create_dir_all(destination.join("nested")).unwrap();
+            fs::write(destination.join("same.txt"), "old").unwrap();
+            fs::write(destination.join("nested").join("deep.txt"), "old").unwrap();
+            fs::write(destination.join("nested").join("kept.txt"), "kept").unwrap();
+            destination
+        };
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        let copy = &mut |from: &Path, to: &Path| fs::copy(from, to).map(|_| ());
+
+        // Every policy merges the directories, keeping what only the destination had
+        let overwritten = destination("overwrite");
+        merge_tree(&source, &overwritten, ConflictPolicy::Overwrite, copy).unwrap();
+        assert_eq!(read(overwritten.join("same.txt")), "new");
+        assert_eq!(read(overwritten.join("nested").join("deep.txt")), "new");
+        assert_eq!(read(overwritten.join("nested").join("added.txt")), "added");
+        assert_eq!(read(overwritten.join("nested").join("kept.txt")), "kept");
+
+        let skipped = destination("skip");
+        merge_tree(&source, &skipped, ConflictPolicy::Skip, copy).unwrap();
+        assert_eq!(read(skipped.join("same.txt")), "old");
+        assert_eq!(read(skipped.join("nested").join("deep.txt")), "old");
+        assert_eq!(read(skipped.join("nested").join("added.txt")), "added");
+
+        let renamed = destination("rename");
+        merge_tree(&source, &renamed, ConflictPolicy::Rename, copy).unwrap();
+        assert_eq!(read(renamed.join("same.txt")), "old");
+        assert_eq!(read(renamed.join("same.txt.1")), "new");
+        assert_eq!(read(renamed.join("nested").join("deep.txt")), "old");
+        assert_eq!(read(renamed.join("nested").join("deep.txt.1")), "new");
+        assert_eq!(read(renamed.join("nested").join("added.txt")), "added");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_copy_file_type_mismatch() {
+        let dir = temp_dir("copy-file-mismatch");
+        let source = dir.join("s");
+        fs::create_dir_all(source.join("dir")).unwrap();
+        fs::write(source.join("x"), "file").unwrap();
+        fs::write(source.join("dir").join("inside"), "inside").unwrap();
+        fs::write(source.join("other"), "other").unwrap();
+        let destination = dir.join("d");
+        fs::create_dir_all(destination.join("s").join("x")).unwrap();
+        fs::write(destination.join("s").join("x").join("important"), "keep").unwrap();
+        fs::write(destination.join("s").join("dir"), "also keep").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        let status = copy_file(
+            &mut context,
+            vec![
+                "-r",
+                source.to_str().unwrap(),
+                destination.to_str().unwrap(),
+            ],
+        );
+        assert_eq!(status, StatusCode::new(3));
+        let copied = destination.join("s");
+        assert_eq!(
+            fs::read_to_string(copied.join("x").join("important")).unwrap(),
+            "keep"
+        );
+        assert_eq!(fs::read_to_string(copied.join("dir")).unwrap(), "also keep");
+        // Everything else is still copied
+        assert_eq!(fs::read_to_string(copied.join("other")).unwrap(), "other");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_copy_file_recursive_symlinks() {
+        let dir = temp_dir("copy-file-symlinks");
+        let source = dir.join("tree");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("file.txt"), "contents").unwrap();
+        std::os::unix::fs::symlink("sub", source.join("to-directory")).unwrap();
+        std::os::unix::fs::symlink("file.txt", source.join("to-file")).unwrap();
+        std::os::unix::fs::symlink("missing", source.join("broken")).unwrap();
+        let target = dir.join("target");
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let args = vec!["-r", source.to_str().unwrap(), target.to_str().unwrap()];
+
+        // Copying again replaces the links that the first copy made
+        for _ in 0..2 {
+            assert!(copy_file(&mut context, args.clone()).is_success());
+            for (name, link) in [
+                ("to-directory", "sub"),
+                ("to-file", "file.txt"),
+                ("broken", "missing"),
+            ] {
+                let copied = target.join(name);
+                assert!(copied.is_symlink(), "{}", name);
+                assert_eq!(fs::read_link(&copied).unwrap(), Path::new(link));
+            }
+        }
+        assert_eq!(
+            fs::read_to_string(target.join("file.txt")).unwrap(),
+            "contents"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_copy_file_recursive() {
+        let dir = temp_dir("copy-file-recursive");
+        let source = dir.join("tree");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("sub").join("file.txt"), "contents").unwrap();
+        let target = dir.join("target");
+        fs::create_dir(&target).unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let (source_arg, target_arg) = (source.to_str().unwrap(), target.to_str().unwrap());
+
+        assert_eq!(
+            copy_file(&mut context, vec![source_arg, target_arg]),
+            StatusCode::new(2)
+        );
+        assert!(copy_file(&mut context, vec!["-r", source_arg, target_arg]).is_success());
+        let copied = target.join("tree").join("sub").join("file.txt");
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "contents");
+
+        fs::write(&copied, "changed").unwrap();
+        let args = vec!["-r", "--conflict", "skip", source_arg, target_arg];
+        assert!(copy_file(&mut context, args).is_success());
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "changed");
+
+        let args = vec!["-r", "--conflict=newest", source_arg, target_arg];
+        assert_eq!(copy_file(&mut context, args), StatusCode::new(2));
+        // A directory can't be copied into itself
+        let inside = source.join("sub");
+        let args = vec!["-r", source_arg, inside.to_str().unwrap()];
+        assert_eq!(copy_file(&mut context, args), StatusCode::new(2));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_copy_file_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(copy_file(&mut context, vec!["a"]), StatusCode::new(1));
+        assert_eq!(
+            copy_file(&mut context, vec!["-x", "a", "b"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            copy_file(&mut context, vec!["/rush-no-such-file", "/tmp"]),
+            StatusCode::new(2)
+        );
+    }
+
+    #[test]
+    fn test_command_copy_file_same_file() {
+        let dir = temp_dir("copy-file-same");
+        let file = dir.join("a.txt");
+        fs::write(&file, "contents").unwrap();
+        let hard = dir.join("b.txt");
+        fs::hard_link(&file, &hard).unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let path = file.to_str().unwrap();
+
+        assert_eq!(
+            copy_file(&mut context, vec![path, path]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            copy_file(&mut context, vec![path, dir.to_str().unwrap()]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            copy_file(&mut context, vec![path, hard.to_str().unwrap()]),
+            StatusCode::new(2)
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "contents");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_expand_vars_success() {
+        let mut shell = Shell::new().unwrap();
+        shell.environment.set_variable("RUSH_EXPAND_TEST", "value");
+        let word = parser::variable_word("[$RUSH_EXPAND_TEST] [$RUSH_EXPAND_UNSET] $?").unwrap();
+        assert_eq!(
+            word.expand(&|name| shell.lookup_variable(name)),
+            "[value] [] 0"
+        );
+
+        let mut context = Context::new(&mut shell);
+        assert!(expand_vars(&mut context, vec!["$RUSH_EXPAND_TEST", "is", "set"]).is_success());
+    }
+
+    #[test]
+    fn test_command_expand_vars_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(expand_vars(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(expand_vars(&mut context, vec!["${A"]), StatusCode::new(2));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_which_success() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::test_utils::temp_dir("which");
+        let mut binaries = Vec::new();
+        for directory in ["first", "second"] {
+            let binary = dir.join(directory).join("rush-which-test");
+            fs::create_dir_all(dir.join(directory)).unwrap();
+            fs::write(&binary, "").unwrap();
+            fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+            binaries.push(binary.display().to_string());
+        }
+
+        let mut shell = Shell::new().unwrap();
+        let search_path = format!("{0}/first:{0}/second", dir.display());
+        shell.environment.set_variable("PATH", &search_path);
+        shell.aliases.insert(
+            String::from("rush-which-test"),
+            String::from("list-directory"),
+        );
+        let mut context = Context::new(&mut shell);
+
+        let locations = command_locations(&context, "rush-which-test", true);
+        assert_eq!(locations[0], "rush-which-test: aliased to list-directory");
+        assert_eq!(&locations[1..], &binaries[..]);
+        assert_eq!(
+            command_locations(&context, "rush-which-test", false).len(),
+            1
+        );
+
+        context.shell.aliases.clear();
+        assert_eq!(
+            command_locations(&context, "rush-which-test", false),
+            &binaries[..1]
+        );
+        assert_eq!(
+            command_locations(&context, "list-directory", true),
+            ["list-directory: shell builtin"]
+        );
+        assert!(which(&mut context, vec!["-a", "rush-which-test", "ls"]).is_success());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_which_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(which(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(
+            which(&mut context, vec!["-a", "rush-which-missing"]),
+            StatusCode::new(1)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_read_file_no_follow() {
+        let dir = temp_dir("read-file-no-follow");
+        let file = dir.join("target");
+        let link = dir.join("link");
+        fs::write(&file, "contents\n").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(symlink_target(link.to_str().unwrap()), Some(file.clone()));
+        assert_eq!(symlink_target(file.to_str().unwrap()), None);
+
+        // Symlinks are followed by default
+        assert!(read_file(&mut context, vec![link.to_str().unwrap()]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--no-follow", link.to_str().unwrap()]),
+            StatusCode::new(3)
+        );
+        assert!(read_file(&mut context, vec!["--no-follow", file.to_str().unwrap()]).is_success());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_reset_terminal_success() {
+        assert_eq!(terminal_reset_sequence(false), None);
+        let sequence = terminal_reset_sequence(true).unwrap();
+        assert!(sequence.starts_with("\x1Bc"));
+        assert!(sequence.contains("\x1B[?25h"));
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(reset_terminal(&mut context, Vec::new()).is_success());
+    }
+
+    #[test]
+    fn test_command_reset_terminal_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(
+            reset_terminal(&mut context, vec!["now"]),
+            StatusCode::new(1)
+        );
+    }
+
+    #[test]
+    fn test_columnize() {
+        let names: Vec<String> = [
+            "apple",
+            "banana",
+            "cherry",
+            "date",
+            "elderberry",
+            "fig",
+            "grape",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+        // Names run down each column before moving across
+        assert_eq!(
+            columnize(&names, 30),
+            [
+                "apple   date        grape",
+                "banana  elderberry",
+                "cherry  fig",
+            ]
+        );
+        assert_eq!(columnize(&names, 80), [names.join("  ")]);
+        assert_eq!(columnize(&names, 5), names);
+        assert!(columnize(&[], 80).is_empty());
+
+        // Color sequences don't count towards the width
+        let colored = vec![format!("{}", "dir/".bright_green()), String::from("file")];
+        assert_eq!(columnize(&colored, 10), [format!("{}  file", colored[0])]);
+    }
+
+    #[test]
+    fn test_command_history_delete() {
+        let mut shell = Shell::new().unwrap();
+        for command in ["a", "b", "c", "d"] {
+            shell.history.add(command);
+        }
+        let mut context = Context::new(&mut shell);
+
+        assert!(history(&mut context, vec!["-d", "2"]).is_success());
+        assert_eq!(context.shell.history.get(2), Some("c"));
+        assert_eq!(history(&mut context, vec!["-d", "4"]), StatusCode::new(2));
+        assert_eq!(history(&mut context, vec!["-d", "x"]), StatusCode::new(2));
+        assert!(history(&mut context, vec!["-c"]).is_success());
+        assert!(context.shell.history.is_empty());
+    }
+
+    #[test]
+    fn test_copy_with_progress() {
+        // More than one chunk, and not a whole number of them
+        let data: Vec<u8> = (0..COPY_CHUNK_SIZE * 2 + 123).map(|i| i as u8).collect();
+        let mut output = Vec::new();
+        let mut reports = Vec::new();
+
+        let copied = copy_with_progress(
+            &data[..],
+            &mut output,
+            data.len() as u64,
+            &mut |copied, total| reports.push((copied, total)),
+        )
+        .unwrap();
+
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(output, data);
+        assert_eq!(reports.last(), Some(&(copied, copied)));
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+        let mut output = Vec::new();
+        let mut reports = Vec::new();
+        assert_eq!(
+            copy_with_progress(&b""[..], &mut output, 0, &mut |copied, total| reports
+                .push((copied, total)))
+            .unwrap(),
+            0
+        );
+        assert_eq!(reports, [(0, 0)]);
+    }
+
+    #[test]
+    fn test_command_assert_success() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(assert(&mut context, vec!["-d", "/", "root is missing"]).is_success());
+        assert!(assert(&mut context, vec!["1", "-lt", "2"]).is_success());
+        assert!(assert(&mut context, vec!["-n", "text"]).is_success());
+    }
+
+    #[test]
+    fn test_command_assert_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(
+            assert(&mut context, vec!["-f", "/invalid/path", "config missing"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            assert(&mut context, vec!["a", "=", "b"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            assert(&mut context, vec!["1", "-lt", "x", "y"]),
+            StatusCode::new(2)
+        );
+        assert_eq!(assert(&mut context, vec![]), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_command_local_success() {
+        let dir = temp_dir("local");
+        let script = dir.join("script.rush");
+        fs::write(
+            &script,
+            "local RUSH_LOCAL_TEST=inner\nexport RUSH_LOCAL_SEEN=$RUSH_LOCAL_TEST\n",
+        )
+        .unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert!(source(&mut context, vec![script.to_str().unwrap()]).is_success());
+        assert_eq!(
+            context.env().get_variable("RUSH_LOCAL_SEEN").as_deref(),
+            Some("inner")
+        );
+        assert_eq!(context.env().get_variable("RUSH_LOCAL_TEST"), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_local_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(local(&mut context, vec![]), StatusCode::new(1));
+        // Outside a sourced script there is no scope to set the variable in
+        assert_eq!(
+            local(&mut context, vec!["RUSH_LOCAL_TEST=1"]),
+            StatusCode::new(3)
+        );
+
+        context.env_mut().push_scope();
+        assert_eq!(local(&mut context, vec!["1X=1"]), StatusCode::new(2));
+        assert!(local(&mut context, vec!["RUSH_LOCAL_TEST"]).is_success());
+        context.env_mut().pop_scope();
+    }
+
+    #[test]
+    fn test_command_return_success() {
+        let dir = temp_dir("return");
+        let script = dir.join("script.rush");
+        fs::write(&script, "true\nreturn 4\nexport RUSH_RETURN_TEST=1\n").unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert_eq!(
+            source(&mut context, vec![script.to_str().unwrap()]),
+            StatusCode::new(4)
+        );
+        assert_eq!(context.env().get_variable("RUSH_RETURN_TEST"), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_command_return_fail() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert_eq!(r#return(&mut context, vec![]), StatusCode::new(2));
+        assert!(!context.shell.returning);
+
+        context.shell.frame_depth = 1;
+        assert_eq!(r#return(&mut context, vec!["x"]), StatusCode::new(2));
+        assert_eq!(r#return(&mut context, vec!["1", "2"]), StatusCode::new(1));
+    }
+
+    #[test]
+    fn test_command_list_directory_flags() {
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(list_directory(&mut context, vec!["-1F", "/"]).is_success());
+        assert!(list_directory(&mut context, vec!["/", "-1"]).is_success());
+        assert_eq!(
+            list_directory(&mut context, vec!["-x", "/"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            list_directory(&mut context, vec!["--git=yes", "/"]),
+            StatusCode::new(1)
+        );
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        assert_eq!(expand_tabs("\ta", 4), "    a");
+        assert_eq!(expand_tabs("ab\tc", 4), "ab  c");
+        assert_eq!(expand_tabs("abcd\te", 4), "abcd    e");
+        assert_eq!(expand_tabs("a\tb\tc", 8), "a       b       c");
+        assert_eq!(expand_tabs("\t\t", 2), "    ");
+        assert_eq!(expand_tabs("no tabs", 4), "no tabs");
+    }
+
+    #[test]
+    fn test_command_read_file_tabs() {
+        let dir = temp_dir("read-file-tabs");
+        let file = dir.join("tabs.txt");
+        fs::write(&file, "\tindented\n").unwrap();
+        let file = file.to_str().unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        assert!(read_file(&mut context, vec!["--tabs", "4", file]).is_success());
+        assert!(read_file(&mut context, vec!["--tabs=2", "--lines", "1", file]).is_success());
+        assert!(read_file(&mut context, vec!["--highlight", "dent", file]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--hex", "--highlight", "dent", file]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["--tabs", "0", file]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["--tabs", "x", file]),
+            StatusCode::new(2)
+        );
+        assert!(read_file(&mut context, vec!["--tabs", "256", file]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--tabs", "99999999999999", file]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["--hex", "--tabs", "4", file]),
+            StatusCode::new(1)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_listing_summary() {
+        assert_eq!(listing_summary(3, 7), "3 directories, 7 files");
+        assert_eq!(listing_summary(1, 1), "1 directory, 1 file");
+        assert_eq!(listing_summary(0, 0), "0 directories, 0 files");
+
+        let dir = temp_dir("list-directory-summary");
+        for name in ["a", "b", ".hidden-dir"] {
+            fs::create_dir(dir.join(name)).unwrap();
+        }
+        for name in ["x.txt", "y.txt", "z.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let (directories, files) = listing_entries(&dir, &ListingOptions::default(), None).unwrap();
+        assert_eq!(
+            listing_summary(directories.len(), files.len()),
+            "2 directories, 3 files"
+        );
+        assert_eq!(files, ["x.txt", "y.txt", "z.txt"]);
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        assert!(
+            list_directory(&mut context, vec!["--summary", dir.to_str().unwrap()]).is_success()
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_list_directory_dereference() {
+        let dir = temp_dir("list-directory-dereference");
+        fs::create_dir(dir.join("real")).unwrap();
+        fs::write(dir.join("real").join("inside.txt"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        // Symlinks to directories are only shown as directories when dereferencing
+        let (directories, files) = listing_entries(&dir, &ListingOptions::default(), None).unwrap();
+        assert_eq!(directories, [format!("{}", "real/".bright_green())]);
+        assert_eq!(files, ["link"]);
+        let options = ListingOptions {
+            dereference: true,
+            ..Default::default()
         };
-    }
+        let (directories, files) = listing_entries(&dir, &options, None).unwrap();
+        assert_eq!(directories.len(), 2);
+        assert!(files.is_empty());
 
-    directories.sort();
-    files.sort();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let link = dir.join("link");
+        let link = link.to_str().unwrap();
+        assert!(list_directory(&mut context, vec![link]).is_success());
+        assert!(list_directory(&mut context, vec!["-L", link]).is_success());
+        assert!(list_directory(&mut context, vec!["--dereference", link]).is_success());
 
-    for directory in directories {
-        println!("{}", directory);
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    for file in files {
-        println!("{}", file);
-    }
+    #[test]
+    fn test_command_list_directory_long() {
+        let dir = temp_dir("list-directory-long");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.txt"), "").unwrap();
+        fs::write(dir.join("a.txt"), "").unwrap();
+        let an_hour_later = SystemTime::now() + Duration::from_secs(3600 + 30);
 
-    StatusCode::success()
-}
+        let options = ListingOptions {
+            times: Some(TimeStyle::Relative(an_hour_later)),
+            ..Default::default()
+        };
+        let (directories, files) = listing_entries(&dir, &options, None).unwrap();
+        assert_eq!(
+            directories,
+            [format!("{:<16}  {}", "1 hour ago", "sub/".bright_green())]
+        );
+        // Entries are still sorted by name
+        assert_eq!(
+            files,
+            [
+                format!("{:<16}  a.txt", "1 hour ago"),
+                format!("{:<16}  b.txt", "1 hour ago")
+            ]
+        );
 
-// TODO: Find a better name for this
-pub fn go_back(context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        let prev_dir = match context.env().previous_working_directory.clone() {
-            Some(dir) => dir,
-            None => {
-                eprintln!("No previous working directory available");
-                return StatusCode::new(2);
-            }
-        }
-        .to_string_lossy()
-        .to_string();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let path = dir.to_str().unwrap();
+        assert!(list_directory(&mut context, vec!["-l", path]).is_success());
+        assert!(list_directory(&mut context, vec!["-l", "--relative-time", path]).is_success());
+        assert_eq!(
+            list_directory(&mut context, vec!["--relative-time", path]),
+            StatusCode::new(1)
+        );
 
-        match context.env_mut().set_path(prev_dir.as_str()) {
-            Ok(_) => {
-                context.env_mut().update_process_env_vars();
-                StatusCode::success()
-            }
-            Err(_) => {
-                eprintln!("Invalid path: '{}'", prev_dir);
-                StatusCode::new(3)
-            }
-        }
-    } else {
-        eprintln!("Usage: go-back");
-        StatusCode::new(1)
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-pub fn clear_terminal(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        // * "Magic" ANSI escape sequence to clear the terminal
-        print!("\x1B[2J\x1B[1;1H");
-        StatusCode::success()
-    } else {
-        eprintln!("Usage: clear-terminal");
-        StatusCode::new(1)
+    #[test]
+    fn test_command_change_directory_archive_success() {
+        let dir = temp_dir("archive-success");
+        let tar = crate::archive::tests::fake_tar(&[
+            ("docs/", None),
+            ("docs/guide.md", Some(b"# Guide\n")),
+            ("README.md", Some(b"hello\n")),
+        ]);
+        fs::write(dir.join("project.tar"), tar).unwrap();
+        let tar_path = dir.join("project.tar").canonicalize().unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let status_code = change_directory(&mut context, vec![tar_path.to_str().unwrap()]);
+        assert_eq!(status_code, StatusCode::success());
+
+        let view = context.env().archive.as_ref().unwrap();
+        assert_eq!(view.virtual_path(), tar_path);
+        let (directories, files) = archive_listing(view, "", &[]).unwrap();
+        assert_eq!(directories, [format!("{}", "docs/".bright_green())]);
+        assert_eq!(files, ["README.md"]);
+
+        assert_eq!(
+            list_directory(&mut context, vec!["docs"]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["README.md"]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            change_directory(&mut context, vec!["docs"]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["guide.md"]),
+            StatusCode::success()
+        );
+        assert_eq!(
+            context.env().archive.as_ref().unwrap().virtual_path(),
+            tar_path.join("docs")
+        );
+
+        // Going above the top of the archive leaves it for the directory that holds it
+        assert_eq!(
+            change_directory(&mut context, vec!["../.."]),
+            StatusCode::success()
+        );
+        assert!(context.env().archive.is_none());
+        assert_eq!(context.cwd().absolute(), tar_path.parent().unwrap());
+        change_directory(&mut context, vec!["/"]);
+
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-pub fn create_file(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 1 {
-        match fs::File::create(args[0]) {
-            Ok(_) => StatusCode::success(),
-            Err(_) => {
-                eprintln!("Failed to create file: '{}'", args[0]);
-                StatusCode::new(2)
-            }
-        }
-    } else {
-        eprintln!("Usage: create-file <path>");
-        StatusCode::new(1)
+    #[test]
+    fn test_command_change_directory_archive_fail() {
+        let dir = temp_dir("archive-fail");
+        let tar = crate::archive::tests::fake_tar(&[("README.md", Some(b"hello\n"))]);
+        fs::write(dir.join("project.tar"), tar).unwrap();
+        fs::write(dir.join("broken.tar"), "not an archive").unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let broken = dir.join("broken.tar");
+        let status_code = change_directory(&mut context, vec![broken.to_str().unwrap()]);
+        assert_eq!(status_code, StatusCode::new(3));
+
+        let project = dir.join("project.tar");
+        change_directory(&mut context, vec![project.to_str().unwrap()]);
+        assert_eq!(
+            change_directory(&mut context, vec!["README.md"]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            list_directory(&mut context, vec!["missing"]),
+            StatusCode::new(2)
+        );
+        assert_eq!(read_file(&mut context, vec!["missing"]), StatusCode::new(2));
+        change_directory(&mut context, vec!["/"]);
+
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-pub fn create_directory(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 1 {
-        match fs::create_dir(args[0]) {
-            Ok(_) => StatusCode::success(),
-            Err(_) => {
-                eprintln!("Failed to create directory: '{}'", args[0]);
-                StatusCode::new(2)
-            }
+    #[test]
+    fn test_command_list_directory_ignore() {
+        let dir = temp_dir("list-directory-ignore");
+        fs::create_dir(dir.join("target")).unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        for name in ["main.rs", "main.o", "notes.txt", ".hidden"] {
+            fs::write(dir.join(name), "").unwrap();
         }
-    } else {
-        eprintln!("Usage: create-directory <path>");
-        StatusCode::new(1)
+
+        let list = |ignore: &[&str]| {
+            let options = ListingOptions {
+                ignore: ignore.iter().map(|pattern| pattern.to_string()).collect(),
+                ..Default::default()
+            };
+            listing_entries(&dir, &options, None).unwrap()
+        };
+        let directory = |name: &str| format!("{}/", name).bright_green().to_string();
+
+        let (directories, files) = list(&["*.o"]);
+        assert_eq!(directories, [directory("src"), directory("target")]);
+        assert_eq!(files, ["main.rs", "notes.txt"]);
+        // Each pattern filters on its own, and hidden names stay hidden
+        let (directories, files) = list(&["*.o", "target", "n?tes.*"]);
+        assert_eq!(directories, [directory("src")]);
+        assert_eq!(files, ["main.rs"]);
+
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let path = dir.to_str().unwrap();
+        assert!(
+            list_directory(&mut context, vec!["--ignore", "*.o", "-I", "src", path]).is_success()
+        );
+        assert!(list_directory(&mut context, vec!["--ignore=*.rs", path]).is_success());
+        assert_eq!(
+            list_directory(&mut context, vec![path, "--ignore"]),
+            StatusCode::new(1)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-pub fn delete_file(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 1 {
-        match fs::remove_file(args[0]) {
-            Ok(_) => StatusCode::success(),
-            Err(_) => {
-                eprintln!("Failed to delete file: '{}'", args[0]);
-                StatusCode::new(2)
-            }
-        }
-    } else {
-        eprintln!("Usage: delete-file <path>");
-        StatusCode::new(1)
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(
+            TextEncoding::detect(&[0xff, 0xfe, b'h', 0]),
+            Some((TextEncoding::Utf16Le, 2))
+        );
+        assert_eq!(
+            TextEncoding::detect(&[0xef, 0xbb, 0xbf, b'h']),
+            Some((TextEncoding::Utf8, 3))
+        );
+        assert_eq!(TextEncoding::detect(b"hi"), None);
+
+        let le = [
+            0xff, 0xfe, b'h', 0, 0xe9, 0, b'\n', 0, 0x3d, 0xd8, 0x00, 0xde,
+        ];
+        assert_eq!(
+            decode_text(&le, TextEncoding::Utf16Le),
+            "h\u{e9}\n\u{1f600}"
+        );
+        let be = [0xfe, 0xff, 0, b'h', 0, b'i'];
+        assert_eq!(decode_text(&be, TextEncoding::Utf16Be), "hi");
+        // Unpaired surrogates and a trailing odd byte are replaced
+        assert_eq!(
+            decode_text(&[0x3d, 0xd8, b'a'], TextEncoding::Utf16Le),
+            "\u{fffd}\u{fffd}"
+        );
+        assert_eq!(decode_text(&[b'a', 0xe9], TextEncoding::Latin1), "a\u{e9}");
+        assert_eq!(decode_text(&[b'a', 0xe9], TextEncoding::Utf8), "a\u{fffd}");
+
+        assert_eq!(TextEncoding::parse("UTF-16LE"), Some(TextEncoding::Utf16Le));
+        assert_eq!(TextEncoding::parse("latin1"), Some(TextEncoding::Latin1));
+        assert_eq!(TextEncoding::parse("ebcdic"), None);
     }
-}
 
-pub fn read_file(_context: &mut Context, args: Vec<&str>) -> StatusCode {
-    let file_name = match args.len() {
-        1 => args[0].to_string(),
-        _ => {
-            eprintln!("Usage: read-file <path>");
-            return StatusCode::new(1);
+    #[test]
+    fn test_command_read_file_encoding() {
+        let dir = temp_dir("read-file-encoding");
+        let file = dir.join("utf16.txt");
+        let mut contents = vec![0xff, 0xfe];
+        for unit in "caf\u{e9}\r\nline 2\n".encode_utf16() {
+            contents.extend(unit.to_le_bytes());
         }
-    };
+        fs::write(&file, &contents).unwrap();
 
-    let file = match fs::File::open(&file_name) {
-        Ok(file) => file,
-        Err(_) => {
-            eprintln!("Failed to open file: '{}'", file_name);
-            return StatusCode::new(2);
-        }
-    };
+        let decoded = decode_text(&contents, TextEncoding::Utf16Le);
+        let mut output = Vec::new();
+        let format = LineFormat::default();
+        print_line_range(decoded.as_bytes(), &mut output, 1, None, &format).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "caf\u{e9}\nline 2\n");
 
-    let reader = BufReader::new(file);
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let path = file.to_str().unwrap();
+        assert!(read_file(&mut context, vec![path]).is_success());
+        assert!(read_file(&mut context, vec!["--lines", "2", path]).is_success());
+        assert!(read_file(&mut context, vec!["--encoding", "latin1", path]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--encoding", "ebcdic", path]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            read_file(&mut context, vec!["--hex", "--encoding", "utf-8", path]),
+            StatusCode::new(1)
+        );
 
-    for line in reader.lines() {
-        let line = line.expect("Failed to read line");
-        println!("{}", line);
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    StatusCode::success()
-}
+    #[test]
+    fn test_command_create_directory_success() {
+        let dir = temp_dir("create-directory");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
 
-pub fn truncate(context: &mut Context, args: Vec<&str>) -> StatusCode {
-    let truncation = match args.len() {
-        0 => 1,
-        // ! This is copilot code, it is extremely unsafe
-        1 => match args[0].parse::<usize>() {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("Invalid truncation length: '{}'", args[0]);
-                return StatusCode::new(2);
-            }
-        },
-        _ => {
-            eprintln!("Usage: truncate <length (default 1)>");
-            return StatusCode::new(1);
-        }
-    };
+        let single = dir.join("single");
+        assert!(create_directory(&mut context, vec![single.to_str().unwrap()]).is_success());
+        assert!(single.is_dir());
 
-    context.cwd_mut().set_truncation(truncation);
-    StatusCode::success()
-}
+        let nested = dir.join("a").join("b").join("c");
+        assert!(create_directory(&mut context, vec!["-p", nested.to_str().unwrap()]).is_success());
+        assert!(nested.is_dir());
+        // -p doesn't mind the directory already existing
+        assert!(create_directory(&mut context, vec!["-p", nested.to_str().unwrap()]).is_success());
 
-pub fn untruncate(context: &mut Context, args: Vec<&str>) -> StatusCode {
-    if args.len() == 0 {
-        context.cwd_mut().disable_truncation();
-        StatusCode::success()
-    } else {
-        eprintln!("Usage: untruncate");
-        StatusCode::new(1)
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::shell::Shell;
+    #[test]
+    fn test_command_create_directory_fail() {
+        let dir = temp_dir("create-directory-fail");
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+        let nested = dir.join("a").join("b");
+        let nested = nested.to_str().unwrap();
+
+        assert_eq!(create_directory(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(
+            create_directory(&mut context, vec![nested]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            create_directory(&mut context, vec!["-m", "799", nested]),
+            StatusCode::new(2)
+        );
+        assert_eq!(
+            create_directory(&mut context, vec!["-m", "17777", nested]),
+            StatusCode::new(2)
+        );
+        assert!(!Path::new(nested).exists());
 
+        assert_eq!(parse_mode("755"), Some(0o755));
+        assert_eq!(parse_mode("0700"), Some(0o700));
+        assert_eq!(parse_mode("rwx"), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
     #[test]
-    fn test_command_test_success() {
+    fn test_command_create_directory_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("create-directory-mode");
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = test(&mut context, Vec::new());
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
 
-        assert_eq!(status_code, StatusCode::success());
+        let private = dir.join("private");
+        let status = create_directory(&mut context, vec!["-m", "700", private.to_str().unwrap()]);
+        assert!(status.is_success());
+        assert_eq!(mode(&private), 0o700);
+
+        // With -p, every directory it creates gets the mode, but existing ones are left alone
+        let before = mode(&dir);
+        let nested = dir.join("x").join("y");
+        let status = create_directory(
+            &mut context,
+            vec!["-p", "-m", "0750", nested.to_str().unwrap()],
+        );
+        assert!(status.is_success());
+        assert_eq!(mode(&dir.join("x")), 0o750);
+        assert_eq!(mode(&nested), 0o750);
+        assert_eq!(mode(&dir), before);
+
+        // A mode that can't be searched through still reaches the innermost directory
+        let locked = dir.join("a").join("b");
+        let status = create_directory(
+            &mut context,
+            vec!["-p", "-m", "600", locked.to_str().unwrap()],
+        );
+        assert!(status.is_success());
+        assert_eq!(mode(&dir.join("a")), 0o600);
+        fs::set_permissions(dir.join("a"), fs::Permissions::from_mode(0o700)).unwrap();
+        assert_eq!(mode(&locked), 0o600);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_exit_success() {
-        // * This is a placeholder test because the exit command
-        // * will exit the program, effectively ending the test
+    fn test_lines_with_context() {
+        let needle = SearchPattern::new("needle", false);
+        let lines_with_context = |lines: Vec<String>, pattern, context| {
+            let mut window = ContextWindow::new(pattern, context);
+            let mut output = Vec::new();
+            for line in lines {
+                window.push(line, &mut output);
+            }
+            output
+        };
+        let lines = |text: &str| text.lines().map(String::from).collect::<Vec<_>>();
+        let around = |before, after| SearchContext { before, after };
+        let context = |line: &str| SearchLine::Context(line.to_string());
+        let matched = |line: &str| SearchLine::Match(line.to_string());
+
+        // -C 1 around a single match
+        assert_eq!(
+            lines_with_context(lines("a\nb\nneedle\nc\nd"), &needle, around(1, 1)),
+            [context("b"), matched("needle"), context("c")]
+        );
+        // Groups that overlap or touch are joined, and others are separated
+        assert_eq!(
+            lines_with_context(
+                lines("needle 1\na\nneedle 2\nb\nc\nd\nneedle 3"),
+                &needle,
+                around(1, 1)
+            ),
+            [
+                matched("needle 1"),
+                context("a"),
+                matched("needle 2"),
+                context("b"),
+                SearchLine::Separator,
+                context("d"),
+                matched("needle 3"),
+            ]
+        );
+        // -A and -B only look one way, and lines past the start or end are left out
+        assert_eq!(
+            lines_with_context(lines("needle\na\nb\nc"), &needle, around(0, 2)),
+            [matched("needle"), context("a"), context("b")]
+        );
+        assert_eq!(
+            lines_with_context(lines("a\nb\nc\nneedle"), &needle, around(2, 0)),
+            [context("b"), context("c"), matched("needle")]
+        );
+        // Without context there are no separators
+        assert_eq!(
+            lines_with_context(lines("needle\na\nneedle"), &needle, around(0, 0)),
+            [matched("needle"), matched("needle")]
+        );
+
+        assert_eq!(context("x").format(Some(Path::new("f.txt"))), "f.txt-x");
+        assert_eq!(matched("x").format(Some(Path::new("f.txt"))), "f.txt:x");
+        assert_eq!(SearchLine::Separator.format(None), "--");
     }
 
     #[test]
-    fn test_command_working_directory_success() {
+    fn test_command_search_context() {
+        let dir = temp_dir("search-context");
+        let first = dir.join("first.txt");
+        let second = dir.join("second.txt");
+        fs::write(&first, "a\nneedle\nb\nc\n").unwrap();
+        fs::write(&second, "needle\nd\n").unwrap();
+
+        let needle = SearchPattern::new("needle", false);
+        let files = [first.clone(), second.clone()];
+        let (lines, _) = search_files(
+            &files,
+            &needle,
+            false,
+            true,
+            SearchContext {
+                before: 1,
+                after: 1,
+            },
+        );
+        assert_eq!(
+            lines,
+            [
+                format!("{}-a", first.display()),
+                format!("{}:needle", first.display()),
+                format!("{}-b", first.display()),
+                String::from("--"),
+                format!("{}:needle", second.display()),
+                format!("{}-d", second.display()),
+            ]
+        );
+
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = working_directory(&mut context, Vec::new());
+        let path = first.to_str().unwrap();
+        assert!(search(&mut context, vec!["-C", "1", "needle", path]).is_success());
+        assert!(search(&mut context, vec!["-A", "2", "-B", "0", "needle", path]).is_success());
+        assert_eq!(
+            search(&mut context, vec!["-C", "1", "missing", path]),
+            StatusCode::new(1)
+        );
+        assert_eq!(
+            search(&mut context, vec!["-C", "x", "needle", path]),
+            StatusCode::new(2)
+        );
+        assert_eq!(search(&mut context, vec!["-A"]), StatusCode::new(1));
+        // A huge context is only as large as the lines that are actually kept
+        assert!(search(&mut context, vec!["-B", "100000000000", "needle", path]).is_success());
+        assert!(search(&mut context, vec!["-C1", "-A", "0", "needle", path]).is_success());
 
-        assert_eq!(status_code, StatusCode::success());
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_change_directory_success_1() {
+    fn test_command_jump_success() {
+        let dir = temp_dir("jump");
+        let (alpha, beta) = (dir.join("alpha-project"), dir.join("beta-project"));
+        fs::create_dir(&alpha).unwrap();
+        fs::create_dir(&beta).unwrap();
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = change_directory(&mut context, vec!["/"]);
 
-        assert_eq!(status_code, StatusCode::success());
+        // Visiting with change-directory records the directories
+        for directory in [&alpha, &beta, &alpha] {
+            assert!(change_directory(&mut context, vec![directory.to_str().unwrap()]).is_success());
+        }
+        assert!(change_directory(&mut context, vec!["/"]).is_success());
+
+        assert!(jump(&mut context, vec!["project"]).is_success());
+        assert_eq!(context.cwd().absolute(), &alpha.canonicalize().unwrap());
+        // The working directory is skipped, so the same query moves on to the next best match
+        assert!(jump(&mut context, vec!["project"]).is_success());
+        assert_eq!(context.cwd().absolute(), &beta.canonicalize().unwrap());
+        assert!(jump(&mut context, vec!["ALPHA"]).is_success());
+        assert_eq!(context.cwd().absolute(), &alpha.canonicalize().unwrap());
+
+        let _ = change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_change_directory_success_2() {
+    fn test_directory_changes_are_recorded() {
+        let dir = temp_dir("jump-recorded").canonicalize().unwrap();
+        let (alpha, beta) = (dir.join("alpha"), dir.join("beta"));
+        fs::create_dir(&alpha).unwrap();
+        fs::create_dir(&beta).unwrap();
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = change_directory(&mut context, vec!["~"]);
+        let visits = |context: &Context, directory: &Path| {
+            context
+                .shell
+                .directories
+                .directories()
+                .iter()
+                .find(|visits| visits.path == directory)
+                .map_or(0, |visits| visits.count)
+        };
 
-        assert_eq!(status_code, StatusCode::success());
+        assert!(change_directory(&mut context, vec![alpha.to_str().unwrap()]).is_success());
+        assert!(change_directory(&mut context, vec![beta.to_str().unwrap()]).is_success());
+        // 'cd -', 'go-back' and 'cd -N' all count as visits
+        assert!(change_directory(&mut context, vec!["-"]).is_success());
+        assert_eq!(visits(&context, &alpha), 2);
+        assert!(go_back(&mut context, vec![]).is_success());
+        assert_eq!(visits(&context, &beta), 2);
+        assert!(change_directory(&mut context, vec!["-1"]).is_success());
+        assert_eq!(visits(&context, &alpha), 3);
+
+        let _ = change_directory(&mut context, vec!["/"]);
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_change_directory_success_3() {
+    fn test_command_jump_fail() {
+        let dir = temp_dir("jump-fail");
+        let gone = dir.join("gone");
+        fs::create_dir(&gone).unwrap();
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        change_directory(&mut context, vec!["~"]);
-        // ! This is not guaranteed to exist on the tester's system
-        let status_code = change_directory(&mut context, vec!["Documents"]);
 
-        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(jump(&mut context, vec![]), StatusCode::new(1));
+        assert_eq!(
+            jump(&mut context, vec!["rush-never-visited"]),
+            StatusCode::new(2)
+        );
+
+        // Directories that have been deleted are forgotten
+        assert!(change_directory(&mut context, vec![gone.to_str().unwrap()]).is_success());
+        assert!(change_directory(&mut context, vec!["/"]).is_success());
+        fs::remove_dir(&gone).unwrap();
+        assert_eq!(jump(&mut context, vec!["gone"]), StatusCode::new(2));
+        assert!(context
+            .shell
+            .directories
+            .directories()
+            .iter()
+            .all(|visits| !visits.path.ends_with("gone")));
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_change_directory_fail() {
+    fn test_exit_warning() {
+        assert_eq!(exit_warning(0, true, false, false), None);
+        assert_eq!(
+            exit_warning(1, true, false, false),
+            Some(String::from("There is 1 running job"))
+        );
+        assert_eq!(
+            exit_warning(3, true, false, false),
+            Some(String::from("There are 3 running jobs"))
+        );
+        // A second 'exit' straight after the warning, or -f, exits anyway
+        assert_eq!(exit_warning(3, true, true, false), None);
+        assert_eq!(exit_warning(3, true, false, true), None);
+        // So does a script
+        assert_eq!(exit_warning(3, false, false, false), None);
+    }
+
+    #[test]
+    fn test_shell_is_interactive() {
         let mut shell = Shell::new().unwrap();
-        let mut context = Context::new(&mut shell);
-        let status_code = change_directory(&mut context, vec!["/invalid/path"]);
+        shell.interactive = true;
+        assert!(shell.is_interactive());
 
-        assert_eq!(status_code, StatusCode::new(2));
+        // A sourced script isn't interactive, even when the shell running it is
+        shell.sourcing.push(PathBuf::from("/script.rush"));
+        assert!(!shell.is_interactive());
+        shell.sourcing.clear();
+
+        shell.interactive = false;
+        assert!(!shell.is_interactive());
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_command_list_directory_success() {
+    fn test_command_exit_with_jobs() {
         let mut shell = Shell::new().unwrap();
+        shell.interactive = true;
+        let child = process::Command::new("sleep").arg("5").spawn().unwrap();
+        shell.jobs.add(child, "sleep 5");
+        assert_eq!(shell.jobs.running_count(), 1);
+
+        // The first 'exit' refuses, and another command in between cancels the second chance
+        shell.interpret(String::from("exit"));
+        assert_eq!(shell.status(), 1);
+        assert!(shell.exit_pending);
+        shell.interpret(String::from("true"));
+        assert!(!shell.exit_pending);
+        shell.interpret(String::from("quit"));
+        assert!(shell.exit_pending);
+
         let mut context = Context::new(&mut shell);
-        let status_code = list_directory(&mut context, Vec::new());
+        assert_eq!(exit(&mut context, vec!["now"]), StatusCode::new(1));
 
-        assert_eq!(status_code, StatusCode::success());
+        let pids: Vec<u32> = shell.jobs.iter().map(|job| job.child.id()).collect();
+        for pid in pids {
+            let _ = jobs::send_signal(pid, libc::SIGKILL);
+        }
+        shell.jobs.wait_all();
+        assert_eq!(shell.jobs.running_count(), 0);
     }
 
     #[test]
-    fn test_command_list_directory_fail() {
+    fn test_should_page() {
+        // Only output longer than the terminal is paged
+        assert!(should_page(51, Some(50), true));
+        assert!(!should_page(50, Some(50), true));
+        assert!(!should_page(3, Some(50), true));
+        // Never when the output isn't a terminal, or the height isn't known
+        assert!(!should_page(1000, Some(50), false));
+        assert!(!should_page(1000, None, true));
+    }
+
+    #[test]
+    fn test_paged_output() {
+        let write_lines = |count: usize, pager: &str| {
+            let mut output = PagedOutput::new(Vec::new(), 3, Some(pager.to_string()));
+            for number in 1..=count {
+                writeln!(output, "{}", number).unwrap();
+            }
+            output.flush().unwrap();
+            let held = output
+                .held
+                .as_ref()
+                .map(|(held, lines)| (held.len(), *lines));
+            output.finish().unwrap();
+            (held, String::from_utf8(output.output).unwrap())
+        };
+
+        // Output that fits is held back, then written when it's finished
+        assert_eq!(
+            write_lines(3, "cat"),
+            (Some((6, 3)), String::from("1\n2\n3\n"))
+        );
+        // Longer output stops being held after the line that doesn't fit, and goes to the pager
+        #[cfg(unix)]
+        assert_eq!(write_lines(5, "cat"), (None, String::new()));
+        // Without a pager, everything is written directly, in order
+        assert_eq!(
+            write_lines(5, "rush-no-such-pager"),
+            (None, String::from("1\n2\n3\n4\n5\n"))
+        );
+    }
+
+    #[test]
+    fn test_command_list_directory_sizes() {
+        let dir = temp_dir("list-directory-sizes");
+        fs::write(dir.join("big"), vec![0; 1536]).unwrap();
+        fs::write(dir.join("small"), "abc").unwrap();
+
+        let list = |sizes| {
+            let options = ListingOptions {
+                sizes: Some(sizes),
+                ..Default::default()
+            };
+            listing_entries(&dir, &options, None).unwrap().1
+        };
+
+        // Sizes are right-aligned, whatever their width
+        assert_eq!(list(SizeStyle::Bytes), ["1536  big", "   3  small"]);
+        assert_eq!(list(SizeStyle::Human), ["1.5K  big", "   3  small"]);
+
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = list_directory(&mut context, vec!["/invalid/path"]);
+        let path = dir.to_str().unwrap();
+        assert!(list_directory(&mut context, vec!["-l", "-h", path]).is_success());
+        assert!(list_directory(&mut context, vec!["-lh", "--relative-time", path]).is_success());
+        assert_eq!(
+            list_directory(&mut context, vec!["-h", path]),
+            StatusCode::new(1)
+        );
 
-        assert_eq!(status_code, StatusCode::new(2));
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_go_back_success() {
+    fn test_command_list_directory_recursive() {
+        let dir = temp_dir("list-directory-recursive");
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("README"), "").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("src/nested/mod.rs"), "").unwrap();
+        fs::write(dir.join(".hidden/secret"), "").unwrap();
+        fs::write(dir.join("target/build"), "").unwrap();
+        // A link back to the top of the tree mustn't be followed forever
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("src/loop")).unwrap();
+
+        let sections = |dereference: bool| -> Vec<(String, Vec<String>)> {
+            let options = ListingOptions {
+                dereference,
+                ignore: vec![String::from("tar*")],
+                ..Default::default()
+            };
+            let mut sections = Vec::new();
+            recursive_listing(&dir, "tree", &options, None, &mut |header, entries| {
+                sections.push((header, entries.unwrap().1))
+            });
+            sections
+        };
+        let headers = |sections: Vec<(String, Vec<String>)>| -> Vec<String> {
+            sections.into_iter().map(|(header, _)| header).collect()
+        };
+
+        assert_eq!(
+            sections(false),
+            [
+                (String::from("tree"), vec![String::from("README")]),
+                (String::from("tree/docs"), vec![]),
+                (
+                    String::from("tree/src"),
+                    vec![String::from("loop"), String::from("main.rs")]
+                ),
+                (
+                    String::from("tree/src/nested"),
+                    vec![String::from("mod.rs")]
+                ),
+            ]
+        );
+        // With -L the link is listed as a directory, but isn't descended into again
+        #[cfg(unix)]
+        assert_eq!(
+            headers(sections(true)),
+            [
+                "tree",
+                "tree/docs",
+                "tree/src",
+                "tree/src/loop",
+                "tree/src/nested"
+            ]
+        );
+
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        context.env_mut().set_path("/");
-        let status_code = go_back(&mut context, Vec::new());
+        let path = dir.to_str().unwrap();
+        assert!(list_directory(&mut context, vec!["-R", path]).is_success());
+        assert!(list_directory(&mut context, vec!["--recursive", "-L", "-1", path]).is_success());
 
-        assert_eq!(status_code, StatusCode::success());
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_go_back_fail() {
+    fn test_renamed_file_name() {
+        assert_eq!(
+            renamed_file_name("notes.txt", ".txt", ".md"),
+            Some(String::from("notes.md"))
+        );
+        // Only the first occurrence is replaced
+        assert_eq!(
+            renamed_file_name("a-a-a", "a", "b"),
+            Some(String::from("b-a-a"))
+        );
+        assert_eq!(
+            renamed_file_name("IMG_1.jpg", "IMG_", ""),
+            Some(String::from("1.jpg"))
+        );
+        assert_eq!(renamed_file_name("notes.md", ".txt", ".md"), None);
+        assert_eq!(renamed_file_name("notes", "", "x"), None);
+        // Names can't become empty or move the file into another directory
+        assert_eq!(renamed_file_name("x", "x", ""), None);
+        assert_eq!(renamed_file_name("a.txt", "a", "dir/a"), None);
+        assert_eq!(renamed_file_name("a", "a", ".."), None);
+    }
+
+    #[test]
+    fn test_command_rename_success() {
+        let dir = temp_dir("rename");
+        for name in ["a.txt", "b.txt", "c.log"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = go_back(&mut context, Vec::new());
 
-        assert_eq!(status_code, StatusCode::new(2));
+        let paths: Vec<String> = ["a.txt", "b.txt", "c.log"]
+            .iter()
+            .map(|name| dir.join(name).to_string_lossy().to_string())
+            .collect();
+        let mut args = vec![".txt", ".md"];
+        args.extend(paths.iter().map(String::as_str));
+        assert!(rename(&mut context, args).is_success());
+
+        assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), "a.txt");
+        assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), "b.txt");
+        assert!(!dir.join("a.txt").exists());
+        // Files that don't contain the text are skipped
+        assert!(dir.join("c.log").exists());
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_truncate_success_1() {
+    fn test_command_rename_fail() {
+        let dir = temp_dir("rename-fail");
+        for name in ["a.txt", "b.txt", "b.md"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = truncate(&mut context, Vec::new());
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let (a, b) = (path("a.txt"), path("b.txt"));
 
-        assert_eq!(status_code, StatusCode::success());
+        assert_eq!(
+            rename(&mut context, vec![".txt", ".md"]),
+            StatusCode::new(1)
+        );
+
+        // The existing target is kept, while the other file is still renamed
+        assert_eq!(
+            rename(&mut context, vec![".txt", ".md", &a, &b]),
+            StatusCode::new(2)
+        );
+        assert!(dir.join("a.md").exists());
+        assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), "b.md");
+        assert!(dir.join("b.txt").exists());
+
+        // -f overwrites it
+        assert!(rename(&mut context, vec!["-f", ".txt", ".md", &b]).is_success());
+        assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), "b.txt");
+
+        let missing = path("missing.txt");
+        assert_eq!(
+            rename(&mut context, vec![".txt", ".md", &missing]),
+            StatusCode::new(2)
+        );
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn test_command_truncate_success_2() {
+    fn test_command_help() {
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = truncate(&mut context, vec!["10"]);
 
-        assert_eq!(status_code, StatusCode::success());
+        assert!(help(&mut context, vec![]).is_success());
+        assert!(help(&mut context, vec!["--search", "file"]).is_success());
+        assert!(help(&mut context, vec!["-s", "History"]).is_success());
+        assert_eq!(
+            help(&mut context, vec!["--search", "rush-no-such-keyword"]),
+            StatusCode::new(1)
+        );
+        assert_eq!(help(&mut context, vec!["--search"]), StatusCode::new(1));
     }
 
     #[test]
-    fn test_command_truncate_fail() {
+    fn test_squeeze_blank() {
+        let squeeze = |lines: &[&'static str]| -> Vec<&'static str> {
+            squeeze_blank(lines.iter().copied(), |line| line.is_empty()).collect()
+        };
+
+        assert_eq!(squeeze(&["a", "", "", "", "b"]), ["a", "", "b"]);
+        assert_eq!(
+            squeeze(&["", "", "a", "", "b", "", ""]),
+            ["", "a", "", "b", ""]
+        );
+        assert_eq!(squeeze(&["a", "b"]), ["a", "b"]);
+        assert!(squeeze(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_command_read_file_squeeze_blank() {
+        let dir = temp_dir("read-file-squeeze");
+        let file = dir.join("spaced.txt");
+        fs::write(&file, "one\n\n\n\r\ntwo\n\nthree\n").unwrap();
+
+        let read = |squeeze_blank: bool, number: bool| {
+            let format = LineFormat {
+                squeeze_blank,
+                number,
+                ..LineFormat::default()
+            };
+            let reader = BufReader::new(fs::File::open(&file).unwrap());
+            let mut output = Vec::new();
+            print_line_range(reader, &mut output, 1, None, &format).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(read(false, false), "one\n\n\n\ntwo\n\nthree\n");
+        assert_eq!(read(true, false), "one\n\ntwo\n\nthree\n");
+        // Like 'cat -sn', the lines written are numbered rather than the lines read
+        assert_eq!(
+            read(true, true),
+            "     1\tone\n     2\t\n     3\ttwo\n     4\t\n     5\tthree\n"
+        );
+        assert_eq!(
+            read(false, true),
+            "     1\tone\n     2\t\n     3\t\n     4\t\n     5\ttwo\n     6\t\n     7\tthree\n"
+        );
+
         let mut shell = Shell::new().unwrap();
         let mut context = Context::new(&mut shell);
-        let status_code = truncate(&mut context, vec!["-10"]);
+        let path = file.to_str().unwrap();
+        assert!(read_file(&mut context, vec!["-s", "-n", "--no-pager", path]).is_success());
+        assert_eq!(
+            read_file(&mut context, vec!["--hex", "--squeeze-blank", path]),
+            StatusCode::new(1)
+        );
 
-        assert_eq!(status_code, StatusCode::new(2));
+        let _ = fs::remove_dir_all(&dir);
     }
 }
@@ -1,10 +1,14 @@
 #![allow(dead_code, unused_variables)]
 
+use std::ffi::OsStr;
 use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
 
 use crate::builtins;
 use crate::environment::Environment;
-use crate::path::Path;
+use crate::manual::{self, HelpPage};
+use crate::path::{self, Path};
 use crate::shell::Shell;
 
 // Represents a command that can be run by the prompt
@@ -12,6 +16,8 @@ pub struct Command {
     true_name: String,
     aliases: Vec<String>,
     runnable: Runnable,
+    // The extended help shown by 'man', if the command has any
+    help: Option<HelpPage>,
 }
 
 impl Command {
@@ -23,17 +29,49 @@ impl Command {
             true_name,
             aliases,
             runnable,
+            help: None,
         }
     }
 
     pub fn true_name(&self) -> &String {
         &self.true_name
     }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn help(&self) -> Option<&HelpPage> {
+        self.help.as_ref()
+    }
+
+    // Gets a one-line description of what the command is for, from its help page
+    pub fn description(&self) -> Option<&'static str> {
+        self.help.as_ref().map(|page| page.purpose)
+    }
+}
+
+// Finds the commands whose name, aliases or description contain a keyword, ignoring case
+pub fn search_commands<'a>(commands: &'a [Command], keyword: &str) -> Vec<&'a Command> {
+    let keyword = keyword.to_lowercase();
+    let contains = |text: &str| text.to_lowercase().contains(&keyword);
+
+    commands
+        .iter()
+        .filter(|command| {
+            contains(&command.true_name)
+                || command.aliases.iter().any(|alias| contains(alias))
+                || command.description().is_some_and(contains)
+        })
+        .collect()
 }
 
+// Function signature shared by all builtin commands
+type Builtin = Box<dyn Fn(&mut Context, Vec<&str>) -> StatusCode>;
+
 // Represents either an internal command or an external binary that can be invoked by a command
 enum Runnable {
-    Internal(Box<dyn Fn(&mut Context, Vec<&str>) -> StatusCode>),
+    Internal(Builtin),
     External(PathBuf),
 }
 
@@ -51,13 +89,64 @@ impl Runnable {
     fn run(&self, context: &mut Context, arguments: Vec<&str>) -> StatusCode {
         match self {
             Runnable::Internal(command_function) => command_function(context, arguments),
-            Runnable::External(path) => {
-                todo!()
-            }
+            Runnable::External(path) => run_external(path.as_os_str(), &arguments),
         }
     }
 }
 
+// Runs an external binary to completion, inheriting the shell's standard streams
+// Returns 127 (like other shells) if the binary could not be started at all
+pub fn run_external<S: AsRef<OsStr>>(program: S, arguments: &[&str]) -> StatusCode {
+    let program = program.as_ref();
+    match process::Command::new(program).args(arguments).status() {
+        // A process killed by a signal has no exit code, so treat it as a generic failure
+        Ok(status) => StatusCode::new(status.code().unwrap_or(1)),
+        Err(_) => {
+            eprintln!("Failed to run '{}'", program.to_string_lossy());
+            StatusCode::new(127)
+        }
+    }
+}
+
+// Runs an external binary with exactly the given environment, rather than inheriting the shell's
+pub fn run_external_with_env<S: AsRef<OsStr>>(
+    program: S,
+    arguments: &[&str],
+    variables: &[(String, String)],
+) -> StatusCode {
+    let program = program.as_ref();
+    match process::Command::new(program)
+        .args(arguments)
+        .env_clear()
+        .envs(variables.iter().map(|(name, value)| (name, value)))
+        .status()
+    {
+        Ok(status) => StatusCode::new(status.code().unwrap_or(1)),
+        Err(_) => {
+            eprintln!("Failed to run '{}'", program.to_string_lossy());
+            StatusCode::new(127)
+        }
+    }
+}
+
+// Replaces the shell process with an external binary, so the binary takes over the shell's process ID
+// Only returns if the binary could not be started, in which case it returns 127 like other shells
+#[cfg(unix)]
+pub fn exec_external<S: AsRef<OsStr>>(program: S, arguments: &[&str]) -> StatusCode {
+    use std::os::unix::process::CommandExt;
+
+    let program = program.as_ref();
+    let error = process::Command::new(program).args(arguments).exec();
+    eprintln!("Failed to run '{}': {}", program.to_string_lossy(), error);
+    StatusCode::new(127)
+}
+
+#[cfg(not(unix))]
+pub fn exec_external<S: AsRef<OsStr>>(program: S, arguments: &[&str]) -> StatusCode {
+    eprintln!("exec is not supported on this platform");
+    StatusCode::new(127)
+}
+
 // Wrapper struct around all of the data that could be needed for any command to run
 // For instance, a command like 'truncate' may need to access the working directory, whereas
 // a command like 'exit' may not need any data at all, but the data needs to be available in all cases
@@ -73,7 +162,7 @@ impl<'a> Context<'a> {
 
     // Shortcut for accessing Context.shell.environment.home
     pub fn home(&self) -> &PathBuf {
-        &self.shell.environment.home()
+        self.shell.environment.home()
     }
 
     // Shortcut for accessing Context.shell.environment
@@ -95,6 +184,13 @@ impl<'a> Context<'a> {
     pub fn cwd_mut(&mut self) -> &mut Path {
         &mut self.shell.environment.working_directory
     }
+
+    // Shortcut for dispatching another command through Context.shell.commands
+    // Returns None if the command does not exist
+    pub fn dispatch(&mut self, command_name: &str, command_args: Vec<&str>) -> Option<StatusCode> {
+        let commands = Rc::clone(&self.shell.commands);
+        commands.dispatch(command_name, command_args, self)
+    }
 }
 
 // Represents the status/exit code of a command
@@ -115,6 +211,10 @@ impl StatusCode {
     pub fn is_success(&self) -> bool {
         self.code == 0
     }
+
+    pub fn code(&self) -> i32 {
+        self.code
+    }
 }
 
 // Represents a collection of commands
@@ -129,11 +229,14 @@ impl Default for CommandManager {
         let mut manager = Self::new();
 
         manager.add_command("test", vec!["t"], Runnable::internal(builtins::test));
+        manager.add_command("[", vec![], Runnable::internal(builtins::bracket));
         manager.add_command(
             "exit",
             vec!["quit", "q"],
             Runnable::internal(builtins::exit),
         );
+        manager.add_command("true", vec![], Runnable::internal(builtins::r#true));
+        manager.add_command("false", vec![], Runnable::internal(builtins::r#false));
         manager.add_command(
             "working-directory",
             vec!["pwd", "wd"],
@@ -172,7 +275,7 @@ impl Default for CommandManager {
         );
         manager.add_command(
             "delete-file",
-            vec!["delete", "remove", "rm", "del", "df"],
+            vec!["delete", "remove", "rm", "del"],
             Runnable::internal(builtins::delete_file),
         );
         manager.add_command(
@@ -180,6 +283,12 @@ impl Default for CommandManager {
             vec!["read", "cat", "rf"],
             Runnable::internal(builtins::read_file),
         );
+        manager.add_command(
+            "copy-file",
+            vec!["copy", "cp"],
+            Runnable::internal(builtins::copy_file),
+        );
+        manager.add_command("rename", vec![], Runnable::internal(builtins::rename));
         manager.add_command(
             "truncate",
             vec!["trunc"],
@@ -191,6 +300,81 @@ impl Default for CommandManager {
             Runnable::internal(builtins::untruncate),
         );
 
+        manager.add_command("seq", vec![], Runnable::internal(builtins::seq));
+        manager.add_command(
+            "bulk-rename",
+            vec!["brn"],
+            Runnable::internal(builtins::bulk_rename),
+        );
+        manager.add_command("find", vec![], Runnable::internal(builtins::find));
+        manager.add_command("date", vec![], Runnable::internal(builtins::date));
+        manager.add_command("sort", vec![], Runnable::internal(builtins::sort));
+        manager.add_command("uniq", vec![], Runnable::internal(builtins::uniq));
+        manager.add_command("tee", vec![], Runnable::internal(builtins::tee));
+        manager.add_command("xargs", vec![], Runnable::internal(builtins::xargs));
+        manager.add_command("cut", vec![], Runnable::internal(builtins::cut));
+        manager.add_command("alias", vec![], Runnable::internal(builtins::alias));
+        manager.add_command("trap", vec![], Runnable::internal(builtins::trap));
+        manager.add_command("bookmark", vec![], Runnable::internal(builtins::bookmark));
+        manager.add_command("bookmarks", vec![], Runnable::internal(builtins::bookmarks));
+        manager.add_command("jump", vec!["z"], Runnable::internal(builtins::jump));
+        manager.add_command("export", vec![], Runnable::internal(builtins::export));
+        manager.add_command("source", vec!["."], Runnable::internal(builtins::source));
+        manager.add_command(
+            "restore-trash",
+            vec!["untrash"],
+            Runnable::internal(builtins::restore_trash),
+        );
+        manager.add_command("jobs", vec![], Runnable::internal(builtins::jobs));
+        manager.add_command("printf", vec![], Runnable::internal(builtins::printf));
+        manager.add_command("break", vec![], Runnable::internal(builtins::r#break));
+        manager.add_command("continue", vec![], Runnable::internal(builtins::r#continue));
+        manager.add_command("set", vec![], Runnable::internal(builtins::set));
+        manager.add_command("fc", vec![], Runnable::internal(builtins::fc));
+        manager.add_command("kill", vec![], Runnable::internal(builtins::kill));
+        manager.add_command("wait", vec![], Runnable::internal(builtins::wait));
+        manager.add_command("expand", vec![], Runnable::internal(builtins::expand));
+        manager.add_command("history", vec![], Runnable::internal(builtins::history));
+        manager.add_command("man", vec![], Runnable::internal(builtins::man));
+        manager.add_command("help", vec![], Runnable::internal(builtins::help));
+        manager.add_command("nohup", vec![], Runnable::internal(builtins::nohup));
+        manager.add_command("exec", vec![], Runnable::internal(builtins::exec));
+        manager.add_command("df", vec![], Runnable::internal(builtins::df));
+        manager.add_command("ln", vec!["link"], Runnable::internal(builtins::ln));
+        manager.add_command(
+            "recent-directories",
+            vec!["recent-dirs"],
+            Runnable::internal(builtins::recent_directories),
+        );
+        manager.add_command("env", vec![], Runnable::internal(builtins::env));
+        manager.add_command("basename", vec![], Runnable::internal(builtins::basename));
+        manager.add_command("dirname", vec![], Runnable::internal(builtins::dirname));
+        manager.add_command(
+            "render-prompt",
+            vec![],
+            Runnable::internal(builtins::render_prompt),
+        );
+        manager.add_command("search", vec!["grep"], Runnable::internal(builtins::search));
+        manager.add_command("stat", vec![], Runnable::internal(builtins::stat));
+        manager.add_command(
+            "expand-vars",
+            vec![],
+            Runnable::internal(builtins::expand_vars),
+        );
+        manager.add_command("which", vec![], Runnable::internal(builtins::which));
+        manager.add_command(
+            "reset-terminal",
+            vec!["reset"],
+            Runnable::internal(builtins::reset_terminal),
+        );
+        manager.add_command("assert", vec![], Runnable::internal(builtins::assert));
+        manager.add_command("local", vec![], Runnable::internal(builtins::local));
+        manager.add_command("return", vec![], Runnable::internal(builtins::r#return));
+
+        for (name, page) in manual::pages() {
+            manager.set_help(name, page);
+        }
+
         manager
     }
 }
@@ -208,9 +392,33 @@ impl CommandManager {
             .push(Command::new(true_name, aliases, runnable));
     }
 
+    // Attaches an extended help page to a command
+    fn set_help(&mut self, true_name: &str, page: HelpPage) {
+        if let Some(command) = self
+            .commands
+            .iter_mut()
+            .find(|command| command.true_name == true_name)
+        {
+            command.help = Some(page);
+        }
+    }
+
+    // Gets every command that has a help page
+    pub fn commands_with_help(&self) -> Vec<&Command> {
+        self.commands
+            .iter()
+            .filter(|command| command.help.is_some())
+            .collect()
+    }
+
+    // Gets every builtin command, in the order they were added
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
     // Resolves a command name to a command
     // Returns None if the command is not found
-    fn resolve(&self, command_name: &str) -> Option<&Command> {
+    pub fn resolve(&self, command_name: &str) -> Option<&Command> {
         for command in &self.commands {
             if command.true_name == command_name {
                 return Some(command);
@@ -226,7 +434,28 @@ impl CommandManager {
         None
     }
 
+    // Gets the true name of a builtin command from its name or one of its aliases
+    pub fn true_name_of(&self, command_name: &str) -> Option<&String> {
+        self.resolve(command_name).map(Command::true_name)
+    }
+
+    // Gets the names and aliases of every builtin command
+    pub fn names(&self) -> Vec<String> {
+        self.commands
+            .iter()
+            .flat_map(|command| std::iter::once(&command.true_name).chain(&command.aliases))
+            .cloned()
+            .collect()
+    }
+
+    // Checks whether a name refers to a builtin command (or one of its aliases)
+    pub fn is_builtin(&self, command_name: &str) -> bool {
+        self.resolve(command_name).is_some()
+    }
+
     // Resolves and dispatches a command to the appropriate function or external binary
+    // Builtins take priority over binaries found in $PATH
+    // With 'set -o autocd', a lone directory name that isn't a command changes into that directory
     // If the command does not exist, returns None
     // ? How should I consume the Context to ensure that it is not used after the command is run?
     pub fn dispatch(
@@ -239,6 +468,80 @@ impl CommandManager {
             return Some(command.runnable.run(context, command_args));
         }
 
+        let search_path = context.env().get_variable("PATH").unwrap_or_default();
+        if let Some(binary) = path::find_executable(command_name, &search_path) {
+            return Some(Runnable::external(binary).run(context, command_args));
+        }
+
+        if context.shell.autocd
+            && command_args.is_empty()
+            && path::resolve(command_name, context.home()).is_some_and(|path| path.is_dir())
+        {
+            return self.dispatch("change-directory", vec![command_name], context);
+        }
+
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_names_are_unique() {
+        // A repeated name would silently run whichever command was registered first
+        let mut names = CommandManager::default().names();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+
+    #[test]
+    fn test_search_commands() {
+        let manager = CommandManager::default();
+        let search = |keyword: &str| -> Vec<String> {
+            let mut names: Vec<String> = search_commands(manager.commands(), keyword)
+                .iter()
+                .map(|command| command.true_name().clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // 'fc' only mentions the history in its description
+        assert_eq!(search("HISTORY"), ["fc", "history"]);
+        // Aliases match too
+        assert!(search("cp").contains(&String::from("copy-file")));
+        let files = search("File");
+        assert!(files.len() > 2);
+        assert!(files.contains(&String::from("read-file")));
+        assert!(files.contains(&String::from("delete-file")));
+        assert!(search("rush-no-such-keyword").is_empty());
+    }
+
+    #[test]
+    fn test_autocd() {
+        let dir = crate::test_utils::temp_dir("autocd");
+        let dir = dir.canonicalize().unwrap();
+        let name = dir.to_str().unwrap();
+        let mut shell = Shell::new().unwrap();
+        let mut context = Context::new(&mut shell);
+
+        // Off by default, so the directory isn't a command
+        assert_eq!(context.dispatch(name, vec![]), None);
+
+        context.shell.autocd = true;
+        assert_eq!(context.dispatch(name, vec![]), Some(StatusCode::success()));
+        assert_eq!(context.cwd().absolute(), &dir);
+
+        // Real commands and directories given arguments aren't affected
+        assert_eq!(context.dispatch("/", vec!["x"]), None);
+        assert!(context.dispatch("true", vec![]).unwrap().is_success());
+        assert_eq!(context.cwd().absolute(), &dir);
+
+        context.dispatch("change-directory", vec!["/"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
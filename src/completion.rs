@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::Path;
+
+// Commands whose arguments can only be directories, so only directories are offered for them
+const DIRECTORY_COMMANDS: [&str; 1] = ["change-directory"];
+
+// Works out the completions for the word before the cursor
+// Returns where the word starts (as a byte index into the line) along with the candidates,
+// which replace the whole word and are already escaped for the command line
+// The first word of a command completes to command names, and later words complete to paths
+// resolve_command maps a command name (or alias) to its true name, so that 'cd' is treated like 'change-directory'
+pub fn complete(
+    line: &str,
+    command_names: &[String],
+    resolve_command: &dyn Fn(&str) -> Option<String>,
+    working_directory: &Path,
+    home_directory: &Path,
+) -> (usize, Vec<String>) {
+    let start = word_start(line);
+    let prefix = unescape(&line[start..]);
+    // Only the words of the current command matter, not those before a ';' or '&'
+    let command_start = line[..start].rfind([';', '&']).map_or(0, |index| index + 1);
+    let previous_words: Vec<&str> = line[command_start..start].split_whitespace().collect();
+
+    let candidates = match previous_words.first() {
+        None => {
+            let mut names: Vec<String> = command_names
+                .iter()
+                .filter(|name| name.starts_with(&prefix))
+                .cloned()
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        }
+        Some(command) => {
+            let directories_only = resolve_command(command)
+                .is_some_and(|name| DIRECTORY_COMMANDS.contains(&name.as_str()));
+            complete_path(&prefix, working_directory, home_directory, directories_only)
+        }
+    };
+
+    (
+        start,
+        candidates
+            .iter()
+            .map(|candidate| escape(candidate))
+            .collect(),
+    )
+}
+
+// Lists the paths that start with a prefix, appending '/' to directories
+// A leading '~' refers to the home directory and is kept in the completions
+// Hidden files are only offered if the prefix's file name starts with a '.'
+pub fn complete_path(
+    prefix: &str,
+    working_directory: &Path,
+    home_directory: &Path,
+    directories_only: bool,
+) -> Vec<String> {
+    let (directory_part, file_prefix) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+
+    let directory = match directory_part {
+        "" => working_directory.to_path_buf(),
+        _ => match directory_part.strip_prefix('~') {
+            Some(rest) => home_directory.join(rest.trim_start_matches('/')),
+            None => working_directory.join(directory_part),
+        },
+    };
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut completions: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(file_prefix)
+                || (name.starts_with('.') && !file_prefix.starts_with('.'))
+            {
+                return None;
+            }
+
+            // Follow symbolic links, so that links to directories count as directories
+            let is_directory = fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir());
+            match (is_directory, directories_only) {
+                (true, _) => Some(format!("{}{}/", directory_part, name)),
+                (false, false) => Some(format!("{}{}", directory_part, name)),
+                (false, true) => None,
+            }
+        })
+        .collect();
+
+    completions.sort();
+    completions
+}
+
+// Gets the longest prefix shared by every candidate
+pub fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix = match candidates.first() {
+        Some(first) => first.clone(),
+        None => return String::new(),
+    };
+
+    for candidate in &candidates[1..] {
+        let length = prefix
+            .chars()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        prefix.truncate(length);
+    }
+
+    prefix
+}
+
+// Finds where the last word of the line starts, treating backslash-escaped whitespace as part of the word
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c.is_whitespace() || c == ';' || c == '&' => start = index + c.len_utf8(),
+            _ => (),
+        }
+    }
+
+    start
+}
+
+// Escapes the characters that the tokenizer would otherwise treat specially
+fn escape(word: &str) -> String {
+    let mut escaped = String::new();
+    for c in word.chars() {
+        if c.is_whitespace() || "\\'\"$;&#*?".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+// Removes the backslash escapes from a partially typed word
+fn unescape(word: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn resolve(name: &str) -> Option<String> {
+        match name {
+            "cd" | "change-directory" => Some(String::from("change-directory")),
+            "cat" | "read-file" => Some(String::from("read-file")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_complete_cd_only_offers_directories() {
+        let dir = temp_dir("complete-cd");
+        fs::create_dir(dir.join("docs")).unwrap();
+        fs::create_dir(dir.join("downloads")).unwrap();
+        fs::write(dir.join("dog.txt"), "").unwrap();
+        fs::write(dir.join("other"), "").unwrap();
+
+        let names = Vec::new();
+        let (start, candidates) = complete("cd do", &names, &resolve, &dir, &dir);
+        assert_eq!(start, 3);
+        assert_eq!(candidates, ["docs/", "downloads/"]);
+
+        let (_, candidates) = complete("change-directory do", &names, &resolve, &dir, &dir);
+        assert_eq!(candidates, ["docs/", "downloads/"]);
+
+        // Other commands are offered files too
+        let (_, candidates) = complete("cat do", &names, &resolve, &dir, &dir);
+        assert_eq!(candidates, ["docs/", "dog.txt", "downloads/"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_complete_path_home_and_hidden() {
+        let dir = temp_dir("complete-home");
+        fs::create_dir(dir.join("projects")).unwrap();
+        fs::create_dir(dir.join(".config")).unwrap();
+        fs::write(dir.join("notes"), "").unwrap();
+
+        let elsewhere = Path::new("/");
+        assert_eq!(complete_path("~/p", elsewhere, &dir, true), ["~/projects/"]);
+        assert_eq!(
+            complete_path("~/", elsewhere, &dir, false),
+            ["~/notes", "~/projects/"]
+        );
+        assert_eq!(complete_path("~/.", elsewhere, &dir, true), ["~/.config/"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_complete_command_names() {
+        let names = vec![
+            String::from("cd"),
+            String::from("change-directory"),
+            String::from("create-file"),
+        ];
+        let (start, candidates) =
+            complete("true; c", &names, &resolve, Path::new("/"), Path::new("/"));
+        assert_eq!(start, 6);
+        assert_eq!(candidates, ["cd", "change-directory", "create-file"]);
+    }
+
+    #[test]
+    fn test_complete_escapes() {
+        let dir = temp_dir("complete-escape");
+        fs::write(dir.join("my file"), "").unwrap();
+
+        let (start, candidates) = complete("cat my\\ f", &[], &resolve, &dir, &dir);
+        assert_eq!(start, 4);
+        assert_eq!(candidates, ["my\\ file"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let candidates = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&candidates(&["docs/", "downloads/"])), "do");
+        assert_eq!(common_prefix(&candidates(&["same"])), "same");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::parser;
+
+// Name of the file (in the home directory) whose commands are run when the shell starts
+const CONFIG_FILE_NAME: &str = ".rushrc";
+
+// Gets the path of the config file for a given home directory
+pub fn config_path(home: &Path) -> PathBuf {
+    home.join(CONFIG_FILE_NAME)
+}
+
+// Formats an alias definition the way it is written to the config file
+pub fn alias_line(name: &str, definition: &str) -> String {
+    format!("alias {}={}", name, parser::quote(definition))
+}
+
+// Gets the name of the alias defined by a config line, if it defines one
+fn alias_name(line: &str) -> Option<&str> {
+    let definition = line.trim_start().strip_prefix("alias ")?;
+    let (name, _) = definition.trim_start().split_once('=')?;
+    Some(name)
+}
+
+// Checks whether a bookmark name is made up of letters, digits, '_' and '-'
+// Anything else (like spaces or quotes) couldn't be read back from the line bookmark_line() writes
+pub fn is_bookmark_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Formats a bookmark the way it is written to the config file
+pub fn bookmark_line(name: &str, path: &Path) -> String {
+    format!(
+        "bookmark {} {}",
+        name,
+        parser::quote(&path.to_string_lossy())
+    )
+}
+
+// Gets the name of the bookmark defined by a config line, if it defines one
+fn bookmark_name(line: &str) -> Option<&str> {
+    let definition = line.trim_start().strip_prefix("bookmark ")?;
+    let (name, _) = definition.trim_start().split_once(' ')?;
+    Some(name)
+}
+
+// Inserts or replaces a line in the config text, leaving every other line untouched
+// The line replaces the one defining the same name, or else is placed after the last line
+// defining anything of its kind, or at the end if there are none
+fn update_section(
+    config: &str,
+    name: &str,
+    new_line: String,
+    line_name: fn(&str) -> Option<&str>,
+) -> String {
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+
+    match lines.iter().position(|line| line_name(line) == Some(name)) {
+        Some(index) => lines[index] = new_line,
+        None => match lines.iter().rposition(|line| line_name(line).is_some()) {
+            Some(index) => lines.insert(index + 1, new_line),
+            None => lines.push(new_line),
+        },
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Inserts or replaces an alias definition in the config text
+pub fn update_alias_section(config: &str, name: &str, definition: &str) -> String {
+    update_section(config, name, alias_line(name, definition), alias_name)
+}
+
+// Inserts or replaces a bookmark in the config text
+pub fn update_bookmark_section(config: &str, name: &str, path: &Path) -> String {
+    update_section(config, name, bookmark_line(name, path), bookmark_name)
+}
+
+// Rewrites the config file, creating it if it doesn't exist
+// The new contents are written to a temporary file first so a failed write can't truncate the config
+// A symlinked config (as dotfile managers set up) is rewritten where it points, rather than replaced by a file
+fn update_config(path: &Path, update: impl FnOnce(&str) -> String) -> io::Result<()> {
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let config = match fs::read_to_string(path) {
+        Ok(config) => config,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, update(&config))?;
+    fs::rename(&temporary_path, path)
+}
+
+// Persists an alias to the config file
+pub fn save_alias(path: &Path, name: &str, definition: &str) -> io::Result<()> {
+    update_config(path, |config| {
+        update_alias_section(config, name, definition)
+    })
+}
+
+// Persists a bookmark to the config file
+pub fn save_bookmark(path: &Path, name: &str, bookmark: &Path) -> io::Result<()> {
+    update_config(path, |config| {
+        update_bookmark_section(config, name, bookmark)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_update_alias_section_append() {
+        let config = "truncate 3\n";
+        let expected = "truncate 3\nalias ll=list-directory\n";
+        assert_eq!(
+            update_alias_section(config, "ll", "list-directory"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_update_alias_section_insert_after_aliases() {
+        let config = "alias a='b'\n# comment\nalias c='d'\ntruncate 3\n";
+        let expected = "alias a='b'\n# comment\nalias c='d'\nalias e='f g'\ntruncate 3\n";
+        assert_eq!(update_alias_section(config, "e", "f g"), expected);
+    }
+
+    #[test]
+    fn test_update_alias_section_replace() {
+        let config = "alias a='b'\nalias ab='c'\ntruncate 3\n";
+        let expected = "alias a='b'\nalias ab=d\ntruncate 3\n";
+        assert_eq!(update_alias_section(config, "ab", "d"), expected);
+    }
+
+    #[test]
+    fn test_update_bookmark_section() {
+        let config = "alias proj='b'\nbookmark proj '/old'\ntruncate 3\n";
+        // Aliases with the same name are left alone
+        let expected = "alias proj='b'\nbookmark proj '/new dir'\ntruncate 3\n";
+        let replaced = update_bookmark_section(config, "proj", Path::new("/new dir"));
+        assert_eq!(replaced, expected);
+
+        let expected = "alias proj='b'\nbookmark proj '/old'\nbookmark docs /d\ntruncate 3\n";
+        assert_eq!(
+            update_bookmark_section(config, "docs", Path::new("/d")),
+            expected
+        );
+    }
+
+    // Reads a config line back the way the shell would when sourcing it, with every variable set
+    fn read_back(line: &str) -> Vec<String> {
+        parser::tokenize(line)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                parser::Token::Word(word) => word.expand(&|_| Some(String::from("expanded"))),
+                token => panic!("unexpected token {:?}", token),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bookmark_line_round_trip() {
+        for path in ["/tmp/bk/it's $USER", "/a \"b\" 'c' `d` \\e", "/plain"] {
+            assert_eq!(
+                read_back(&bookmark_line("docs", Path::new(path))),
+                ["bookmark", "docs", path],
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_alias_line_round_trip() {
+        for definition in [
+            "list-directory -a 'x y' $HOME",
+            "echo \"it's\" `date`",
+            "ll",
+        ] {
+            assert_eq!(
+                read_back(&alias_line("ll", definition)),
+                ["alias", &format!("ll={}", definition)],
+                "{}",
+                definition
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_alias_through_symlink() {
+        let dir = temp_dir("config-symlink");
+        let dotfiles = dir.join("dotfiles");
+        fs::create_dir(&dotfiles).unwrap();
+        fs::write(dotfiles.join("rushrc"), "truncate 3\n").unwrap();
+        let path = config_path(&dir);
+        std::os::unix::fs::symlink(dotfiles.join("rushrc"), &path).unwrap();
+
+        save_alias(&path, "ll", "list-directory -a").unwrap();
+        assert!(path.is_symlink());
+        assert_eq!(
+            fs::read_to_string(dotfiles.join("rushrc")).unwrap(),
+            "truncate 3\nalias ll='list-directory -a'\n"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
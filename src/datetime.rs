@@ -0,0 +1,374 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// Represents a calendar date and time of day, already adjusted to a timezone
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    // Converts seconds since the Unix epoch into a DateTime, shifted by an offset (in seconds) from UTC
+    pub fn from_timestamp(timestamp: i64, utc_offset: i64) -> Self {
+        let local = timestamp + utc_offset;
+        let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let seconds_of_day = local.rem_euclid(SECONDS_PER_DAY) as u32;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day / 60 % 60,
+            second: seconds_of_day % 60,
+        }
+    }
+
+    // Converts a SystemTime into a DateTime in the local timezone
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let timestamp = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(error) => -(error.duration().as_secs() as i64),
+        };
+
+        Self::from_timestamp(timestamp, local_utc_offset(timestamp))
+    }
+
+    // Gets the current date and time in the local timezone
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    // Parses a timestamp in the '[[CC]YY]MMDDhhmm[.ss]' format used by 'touch -t'
+    // A missing year means the current year, and a two-digit year means 1969-2068 like POSIX
+    pub fn parse_touch_timestamp(text: &str, current_year: i64) -> Option<Self> {
+        let (digits, second) = match text.split_once('.') {
+            Some((digits, second)) if second.len() == 2 => (digits, parse_digits(second)?),
+            Some(_) => return None,
+            None => (text, 0),
+        };
+
+        // Checked before slicing, so that the byte offsets below always fall on character boundaries
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        let (year, rest) = match digits.len() {
+            8 => (current_year, digits),
+            10 => {
+                let year = parse_digits(&digits[..2])? as i64;
+                (
+                    if year < 69 { 2000 + year } else { 1900 + year },
+                    &digits[2..],
+                )
+            }
+            12 => (parse_digits(&digits[..4])? as i64, &digits[4..]),
+            _ => return None,
+        };
+
+        let time = Self {
+            year,
+            month: parse_digits(&rest[..2])?,
+            day: parse_digits(&rest[2..4])?,
+            hour: parse_digits(&rest[4..6])?,
+            minute: parse_digits(&rest[6..8])?,
+            second,
+        };
+
+        let valid = (1..=12).contains(&time.month)
+            && time.day >= 1
+            && time.day <= days_in_month(time.year, time.month)
+            && time.hour < 24
+            && time.minute < 60
+            && time.second < 60;
+        valid.then_some(time)
+    }
+
+    // Converts the DateTime back into seconds since the Unix epoch, given its offset (in seconds) from UTC
+    pub fn to_timestamp(self, utc_offset: i64) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let seconds_of_day = (self.hour * 3600 + self.minute * 60 + self.second) as i64;
+        days * SECONDS_PER_DAY + seconds_of_day - utc_offset
+    }
+
+    // Converts a DateTime in the local timezone into a SystemTime
+    pub fn to_system_time(self) -> SystemTime {
+        // The offset is looked up at the UTC reading of the time, which is close enough to pick the right zone rules
+        let timestamp = self.to_timestamp(local_utc_offset(self.to_timestamp(0)));
+        match timestamp >= 0 {
+            true => UNIX_EPOCH + Duration::from_secs(timestamp as u64),
+            false => UNIX_EPOCH - Duration::from_secs(timestamp.unsigned_abs()),
+        }
+    }
+
+    // Formats the DateTime using strftime-like specifiers (%Y %m %d %H %M %S %%)
+    // Unknown specifiers are passed through literally
+    pub fn format(&self, format: &str) -> String {
+        let mut formatted = String::new();
+        let mut chars = format.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                formatted.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('Y') => formatted.push_str(&format!("{:04}", self.year)),
+                Some('m') => formatted.push_str(&format!("{:02}", self.month)),
+                Some('d') => formatted.push_str(&format!("{:02}", self.day)),
+                Some('H') => formatted.push_str(&format!("{:02}", self.hour)),
+                Some('M') => formatted.push_str(&format!("{:02}", self.minute)),
+                Some('S') => formatted.push_str(&format!("{:02}", self.second)),
+                Some('%') => formatted.push('%'),
+                Some(other) => {
+                    formatted.push('%');
+                    formatted.push(other);
+                }
+                None => formatted.push('%'),
+            }
+        }
+
+        formatted
+    }
+}
+
+// Converts a number of days since the Unix epoch into a (year, month, day) date
+// * Based on Howard Hinnant's public domain 'civil_from_days' algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// Converts a (year, month, day) date into a number of days since the Unix epoch
+// * Based on Howard Hinnant's public domain 'days_from_civil' algorithm
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Parses a string made up only of ASCII digits
+fn parse_digits(text: &str) -> Option<u32> {
+    match !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+// Gets the local timezone's offset from UTC (in seconds) at a given time
+#[cfg(unix)]
+fn local_utc_offset(timestamp: i64) -> i64 {
+    let time = timestamp as libc::time_t;
+    // SAFETY: libc::tm is plain old data, so an all-zero value is valid
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: Both pointers are valid for the duration of the call, and localtime_r is thread-safe
+    let result = unsafe { libc::localtime_r(&time, &mut tm) };
+
+    match result.is_null() {
+        true => 0,
+        false => tm.tm_gmtoff as i64,
+    }
+}
+
+#[cfg(not(unix))]
+fn local_utc_offset(_timestamp: i64) -> i64 {
+    0
+}
+
+// Describes how long before now a time was, in its largest whole unit, like '3 days ago'
+// Times less than 10 seconds ago (or in the future, from clock skew) are 'just now'
+pub fn relative_time(time: SystemTime, now: SystemTime) -> String {
+    let seconds = now
+        .duration_since(time)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let day = SECONDS_PER_DAY as u64;
+    let units = [
+        ("year", 365 * day),
+        ("month", 30 * day),
+        ("week", 7 * day),
+        ("day", day),
+        ("hour", 3600),
+        ("minute", 60),
+        ("second", 1),
+    ];
+
+    if seconds < 10 {
+        return String::from("just now");
+    }
+
+    let (unit, length) = units
+        .into_iter()
+        .find(|(_, length)| seconds >= *length)
+        .unwrap_or(("second", 1));
+    match seconds / length {
+        1 => format!("1 {} ago", unit),
+        count => format!("{} {}s ago", count, unit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_681_234_567);
+        let ago = |seconds: u64| relative_time(now - Duration::from_secs(seconds), now);
+
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(9), "just now");
+        assert_eq!(ago(45), "45 seconds ago");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3 * 3600 + 59), "3 hours ago");
+        assert_eq!(ago(86_400), "1 day ago");
+        assert_eq!(ago(3 * 86_400), "3 days ago");
+        assert_eq!(ago(15 * 86_400), "2 weeks ago");
+        assert_eq!(ago(400 * 86_400), "1 year ago");
+        // Times in the future don't count as ago
+        assert_eq!(
+            relative_time(now + Duration::from_secs(60), now),
+            "just now"
+        );
+    }
+
+    #[test]
+    fn test_from_timestamp() {
+        let epoch = DateTime::from_timestamp(0, 0);
+        assert_eq!((epoch.year, epoch.month, epoch.day), (1970, 1, 1));
+
+        let leap_day = DateTime::from_timestamp(951_827_696, 0);
+        assert_eq!((leap_day.year, leap_day.month, leap_day.day), (2000, 2, 29));
+        assert_eq!(
+            (leap_day.hour, leap_day.minute, leap_day.second),
+            (12, 34, 56)
+        );
+
+        let before_epoch = DateTime::from_timestamp(-SECONDS_PER_DAY * 365, 0);
+        assert_eq!(
+            (before_epoch.year, before_epoch.month, before_epoch.day),
+            (1969, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_from_timestamp_with_offset() {
+        // 17:36:07 UTC is 13:36:07 at UTC-4
+        let time = DateTime::from_timestamp(1_681_234_567, -4 * 3600);
+        assert_eq!(time.format("%H:%M:%S"), "13:36:07");
+    }
+
+    #[test]
+    fn test_to_timestamp() {
+        for timestamp in [0, 951_827_696, 1_681_234_567, -SECONDS_PER_DAY * 365] {
+            assert_eq!(
+                DateTime::from_timestamp(timestamp, 0).to_timestamp(0),
+                timestamp
+            );
+        }
+        let time = DateTime::from_timestamp(1_681_234_567, -4 * 3600);
+        assert_eq!(time.to_timestamp(-4 * 3600), 1_681_234_567);
+    }
+
+    #[test]
+    fn test_parse_touch_timestamp() {
+        let time = |year, month, day, hour, minute, second| DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+
+        assert_eq!(
+            DateTime::parse_touch_timestamp("202304111736.07", 2000),
+            Some(time(2023, 4, 11, 17, 36, 7))
+        );
+        assert_eq!(
+            DateTime::parse_touch_timestamp("2304111736", 2000),
+            Some(time(2023, 4, 11, 17, 36, 0))
+        );
+        assert_eq!(
+            DateTime::parse_touch_timestamp("6912312359", 2000),
+            Some(time(1969, 12, 31, 23, 59, 0))
+        );
+        assert_eq!(
+            DateTime::parse_touch_timestamp("02291200", 2024),
+            Some(time(2024, 2, 29, 12, 0, 0))
+        );
+
+        for invalid in [
+            "",
+            "0229120",
+            "02291200",
+            "202313011200",
+            "202304311200",
+            "202304112400",
+            "202304111760",
+            "202304111736.7",
+            "202304111736.60",
+            "2023041117a6",
+            "+02304111736",
+            // Multi-byte characters can make the length look right
+            "1\u{e9}34567",
+            "12\u{e9}456789",
+            "2023041117\u{e9}",
+            "202304111736.\u{e9}",
+        ] {
+            assert_eq!(
+                DateTime::parse_touch_timestamp(invalid, 2023),
+                None,
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let time = DateTime::from_timestamp(1_681_234_567, 0);
+        assert_eq!(time.format("%Y-%m-%d"), "2023-04-11");
+        assert_eq!(time.format("%d/%m/%Y %H:%M:%S"), "11/04/2023 17:36:07");
+    }
+
+    #[test]
+    fn test_format_passes_through_unknown_specifiers() {
+        let time = DateTime::from_timestamp(1_681_234_567, 0);
+        assert_eq!(time.format("%Q %Y 100%% %"), "%Q 2023 100% %");
+    }
+}
@@ -0,0 +1,135 @@
+use std::io;
+use std::path::Path;
+
+// The space on the filesystem that contains a path, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemUsage {
+    pub total: u64,
+    pub used: u64,
+    // The space available to unprivileged users, which excludes space reserved for root
+    pub available: u64,
+}
+
+impl FilesystemUsage {
+    // Gets the percentage of the space that's in use, rounded up like df does
+    pub fn percent_used(&self) -> u64 {
+        let usable = self.used + self.available;
+        match usable {
+            0 => 0,
+            _ => (self.used * 100).div_ceil(usable),
+        }
+    }
+}
+
+// Gets the usage of the filesystem containing a path
+#[cfg(unix)]
+pub fn filesystem_usage(path: &Path) -> io::Result<FilesystemUsage> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: path is a valid null-terminated string and statvfs() fills in stats when it succeeds
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stats.assume_init()
+    };
+
+    let block_size = stats.f_frsize as u64;
+    let total = stats.f_blocks as u64 * block_size;
+    let free = stats.f_bfree as u64 * block_size;
+
+    Ok(FilesystemUsage {
+        total,
+        used: total.saturating_sub(free),
+        available: stats.f_bavail as u64 * block_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn filesystem_usage(_path: &Path) -> io::Result<FilesystemUsage> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+// Formats a number of bytes, either exactly or in human-readable form (such as 1.5K or 23M)
+// Human-readable sizes use powers of 1024 and show one decimal place below 10, like coreutils
+pub fn format_bytes(bytes: u64, human_readable: bool) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if !human_readable || bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    size /= 1024.0;
+    // Sizes just under the next unit would round up to 1024, so they use the next unit instead
+    while size.ceil() >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    // Round up, so that sizes are never understated
+    match size < 10.0 {
+        true => {
+            let rounded = (size * 10.0).ceil() / 10.0;
+            match rounded < 10.0 {
+                true => format!("{:.1}{}", rounded, UNITS[unit]),
+                false => format!("{}{}", rounded.ceil(), UNITS[unit]),
+            }
+        }
+        false => format!("{}{}", size.ceil(), UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(123_456, false), "123456");
+        assert_eq!(format_bytes(0, true), "0");
+        assert_eq!(format_bytes(1023, true), "1023");
+        assert_eq!(format_bytes(1024, true), "1.0K");
+        assert_eq!(format_bytes(1536, true), "1.5K");
+        assert_eq!(format_bytes(1025, true), "1.1K");
+        assert_eq!(format_bytes(10 * 1024, true), "10K");
+        assert_eq!(format_bytes(10_239, true), "10K");
+        assert_eq!(format_bytes(23 * 1024 * 1024, true), "23M");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024 / 2, true), "1.5G");
+        assert_eq!(format_bytes(u64::MAX, true), "16E");
+        // The largest unit that fits is used, even for sizes just under it
+        assert_eq!(format_bytes(1024 * 1024 - 1, true), "1.0M");
+        assert_eq!(format_bytes(999 * 1024 * 1024, true), "999M");
+        assert_eq!(format_bytes(5 * 1024_u64.pow(4), true), "5.0T");
+        assert_eq!(format_bytes(1024_u64.pow(5) + 1, true), "1.1P");
+    }
+
+    #[test]
+    fn test_percent_used() {
+        let usage = |used, available| FilesystemUsage {
+            total: used + available,
+            used,
+            available,
+        };
+
+        assert_eq!(usage(0, 0).percent_used(), 0);
+        assert_eq!(usage(50, 50).percent_used(), 50);
+        assert_eq!(usage(1, 199).percent_used(), 1);
+        assert_eq!(usage(100, 0).percent_used(), 100);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filesystem_usage() {
+        let usage = filesystem_usage(Path::new("/")).unwrap();
+        assert!(usage.total >= usage.used);
+        assert!(filesystem_usage(Path::new("/rush-no-such-path")).is_err());
+    }
+}
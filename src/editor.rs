@@ -0,0 +1,643 @@
+use std::io::{self, stdin, stdout, Read, Write};
+
+use colored::Colorize;
+
+use crate::completion;
+
+pub use terminal::height as terminal_height;
+pub use terminal::width as terminal_width;
+
+// Provides the shell-specific parts of line editing, such as completions
+pub trait Helper {
+    // Gets the completions for the word before the cursor, along with where that word starts (as a byte index)
+    fn complete(&self, line: &str) -> (usize, Vec<String>);
+
+    // Gets a suggestion for the rest of the line, shown faintly after the cursor
+    fn hint(&self, _line: &str) -> Option<String> {
+        None
+    }
+
+    // Finds the most recent history entry containing a query, searching back from a position (or the newest entry)
+    // Returns the entry's position along with its text, so Ctrl-R can continue from just before it
+    fn search_history(&self, _query: &str, _start: Option<usize>) -> Option<(usize, String)> {
+        None
+    }
+}
+
+// The text being edited, along with the position of the cursor (in characters)
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    // Gets the text before the cursor
+    pub fn before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.chars.len()
+    }
+
+    // Replaces all of the text, leaving the cursor at the end
+    pub fn set_text(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    // Adds text at the cursor
+    pub fn insert_str(&mut self, text: &str) {
+        text.chars().for_each(|c| self.insert(c));
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    // Deletes the character before the cursor
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    // Deletes the character under the cursor
+    pub fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    // Deletes everything before the cursor
+    pub fn clear_before_cursor(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    // Deletes everything after the cursor
+    pub fn clear_after_cursor(&mut self) {
+        self.chars.truncate(self.cursor);
+    }
+
+    // Replaces the text between a character index and the cursor, leaving the cursor after the new text
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let start = start.min(self.cursor);
+        self.chars.splice(start..self.cursor, text.chars());
+        self.cursor = start + text.chars().count();
+    }
+
+    // Applies Tab completion, returning the candidates to show if the line couldn't be completed any further
+    pub fn complete(&mut self, helper: &dyn Helper) -> Vec<String> {
+        let before = self.before_cursor();
+        let (start, candidates) = helper.complete(&before);
+        let start_char = before[..start].chars().count();
+        let word = &before[start..];
+
+        match candidates.as_slice() {
+            [] => Vec::new(),
+            [candidate] => {
+                self.replace_before_cursor(start_char, candidate);
+                // Directories are left open so that completion can continue into them
+                if !candidate.ends_with('/') {
+                    self.insert(' ');
+                }
+                Vec::new()
+            }
+            _ => {
+                let prefix = completion::common_prefix(&candidates);
+                if prefix.len() > word.len() {
+                    self.replace_before_cursor(start_char, &prefix);
+                    Vec::new()
+                } else {
+                    candidates
+                }
+            }
+        }
+    }
+}
+
+// Reads a line of input, letting the user edit it and complete words with Tab
+// Falls back to reading a plain line when stdin isn't an interactive terminal
+// Returns None once the end of input has been reached, and an empty string (without a line break) if the line was cancelled with Ctrl-C
+pub fn read_line(prompt: &str, helper: &dyn Helper) -> io::Result<Option<String>> {
+    let raw_mode = match terminal::RawMode::enable() {
+        Some(raw_mode) => raw_mode,
+        None => {
+            print!("{}", prompt);
+            stdout().flush()?;
+            let mut line = String::new();
+            return match stdin().read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line)),
+            };
+        }
+    };
+
+    let result = edit_line(prompt, helper);
+    drop(raw_mode);
+    result
+}
+
+fn edit_line(prompt: &str, helper: &dyn Helper) -> io::Result<Option<String>> {
+    let mut buffer = LineBuffer::new();
+    let mut stdout = stdout();
+    // Only the last line of the prompt is redrawn while editing
+    let prompt_line = prompt.rsplit('\n').next().unwrap_or_default();
+
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()?;
+
+    loop {
+        let key = match read_key()? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        // Only suggest once the cursor is at the end, since the suggestion continues the line
+        let hint = match buffer.at_end() {
+            true => current_hint(helper, &buffer),
+            false => None,
+        };
+
+        match key {
+            Key::Enter => {
+                writeln!(stdout)?;
+                return Ok(Some(buffer.text() + "\n"));
+            }
+            Key::Char(c) => buffer.insert(c),
+            Key::Backspace => buffer.backspace(),
+            Key::Delete => buffer.delete(),
+            Key::Left => buffer.move_left(),
+            // Moving right at the end of the line accepts the suggestion
+            Key::Right => match hint {
+                Some(hint) => buffer.insert_str(&hint),
+                None => buffer.move_right(),
+            },
+            Key::Home => buffer.move_home(),
+            Key::End => buffer.move_end(),
+            Key::ClearBeforeCursor => buffer.clear_before_cursor(),
+            Key::ClearAfterCursor => buffer.clear_after_cursor(),
+            Key::Interrupt => {
+                writeln!(stdout, "^C")?;
+                return Ok(Some(String::new()));
+            }
+            Key::EndOfInput if buffer.is_empty() => {
+                writeln!(stdout)?;
+                return Ok(None);
+            }
+            Key::EndOfInput => buffer.delete(),
+            Key::Tab => {
+                let candidates = buffer.complete(helper);
+                if !candidates.is_empty() {
+                    // Show the options below the line, then start the prompt again underneath them
+                    write!(stdout, "\n{}\n{}", candidates.join("  "), prompt)?;
+                }
+            }
+            Key::SearchHistory => match search_history(&mut stdout, helper)? {
+                SearchOutcome::Accept(line) => {
+                    redraw(&mut stdout, prompt_line, &LineBuffer::new(), None)?;
+                    writeln!(stdout, "{}", line)?;
+                    return Ok(Some(line + "\n"));
+                }
+                SearchOutcome::Edit(line) => buffer.set_text(&line),
+                SearchOutcome::Cancel => (),
+                SearchOutcome::EndOfInput => return Ok(None),
+            },
+            Key::Unknown => (),
+        }
+
+        let hint = match buffer.at_end() {
+            true => current_hint(helper, &buffer),
+            false => None,
+        };
+        redraw(&mut stdout, prompt_line, &buffer, hint.as_deref())?;
+    }
+}
+
+enum SearchOutcome {
+    // Run the matched line
+    Accept(String),
+    // Go back to editing, starting from the matched line
+    Edit(String),
+    // Go back to editing the original line
+    Cancel,
+    EndOfInput,
+}
+
+// Incrementally searches back through the history (Ctrl-R), showing the best match as the query is typed
+// Ctrl-R again moves on to older matches, Enter runs the match, Ctrl-C cancels and any other key edits the match
+fn search_history(stdout: &mut impl Write, helper: &dyn Helper) -> io::Result<SearchOutcome> {
+    let mut query = String::new();
+    let mut found: Option<(usize, String)> = None;
+
+    loop {
+        let text = found.as_ref().map_or("", |(_, text)| text.as_str());
+        write!(stdout, "\r(reverse-i-search)`{}': {}\x1b[K", query, text)?;
+        stdout.flush()?;
+
+        let key = match read_key()? {
+            Some(key) => key,
+            None => return Ok(SearchOutcome::EndOfInput),
+        };
+
+        match key {
+            Key::Char(c) => {
+                query.push(c);
+                found = helper.search_history(&query, None);
+            }
+            Key::Backspace => {
+                query.pop();
+                found = helper.search_history(&query, None);
+            }
+            // Keep showing the oldest match once there are no more
+            Key::SearchHistory => {
+                let older = found
+                    .as_ref()
+                    .and_then(|(index, _)| index.checked_sub(1))
+                    .and_then(|start| helper.search_history(&query, Some(start)));
+                if older.is_some() {
+                    found = older;
+                }
+            }
+            Key::Interrupt => return Ok(SearchOutcome::Cancel),
+            Key::Enter => {
+                return Ok(match found {
+                    Some((_, text)) => SearchOutcome::Accept(text),
+                    None => SearchOutcome::Cancel,
+                })
+            }
+            _ => {
+                return Ok(match found {
+                    Some((_, text)) => SearchOutcome::Edit(text),
+                    None => SearchOutcome::Cancel,
+                })
+            }
+        }
+    }
+}
+
+// Gets the part of the helper's suggestion that hasn't been typed yet
+fn current_hint(helper: &dyn Helper, buffer: &LineBuffer) -> Option<String> {
+    let text = buffer.text();
+    helper
+        .hint(&text)
+        .and_then(|suggestion| suggestion.strip_prefix(&text).map(String::from))
+        .filter(|hint| !hint.is_empty())
+}
+
+// Counts the characters that take up space on the screen, skipping ANSI color sequences
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1B' => {
+                // Color sequences end with a letter, such as '\x1B[32m'
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+// Rewrites the current line and moves the terminal cursor to the buffer's cursor
+// A hint is shown faintly after the text, without moving the cursor past it
+fn redraw(
+    stdout: &mut impl Write,
+    prompt_line: &str,
+    buffer: &LineBuffer,
+    hint: Option<&str>,
+) -> io::Result<()> {
+    // Clearing before the prompt keeps a right-aligned prompt segment, which the text then covers as it grows
+    write!(stdout, "\r\x1b[K{}{}", prompt_line, buffer.text())?;
+    let mut after_cursor = buffer.chars.len() - buffer.cursor;
+    if let Some(hint) = hint {
+        write!(stdout, "{}", hint.dimmed())?;
+        after_cursor += hint.chars().count();
+    }
+
+    if after_cursor > 0 {
+        write!(stdout, "\x1b[{}D", after_cursor)?;
+    }
+    stdout.flush()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    ClearBeforeCursor,
+    ClearAfterCursor,
+    SearchHistory,
+    Interrupt,
+    EndOfInput,
+    Unknown,
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// Reads a single key press, decoding escape sequences and multi-byte characters
+fn read_key() -> io::Result<Option<Key>> {
+    let byte = match read_byte()? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        127 | 8 => Key::Backspace,
+        1 => Key::Home,
+        5 => Key::End,
+        3 => Key::Interrupt,
+        4 => Key::EndOfInput,
+        11 => Key::ClearAfterCursor,
+        18 => Key::SearchHistory,
+        21 => Key::ClearBeforeCursor,
+        27 => read_escape_sequence()?,
+        byte if byte < 32 => Key::Unknown,
+        byte => {
+            // The number of leading 1 bits gives the length of a UTF-8 character
+            let length = (byte.leading_ones() as usize).max(1);
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.extend(read_byte()?);
+            }
+
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+
+    Ok(Some(key))
+}
+
+fn read_escape_sequence() -> io::Result<Key> {
+    if read_byte()? != Some(b'[') {
+        return Ok(Key::Unknown);
+    }
+
+    let key = match read_byte()? {
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(b'3') => match read_byte()? {
+            Some(b'~') => Key::Delete,
+            _ => Key::Unknown,
+        },
+        _ => Key::Unknown,
+    };
+
+    Ok(key)
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::mem::MaybeUninit;
+
+    // Puts the terminal into raw mode for as long as it exists, so key presses can be read one at a time
+    pub struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        // Returns None if stdin isn't a terminal
+        pub fn enable() -> Option<Self> {
+            // SAFETY: tcgetattr() fully initializes the termios struct when it succeeds
+            let original = unsafe {
+                if libc::isatty(libc::STDIN_FILENO) != 1 {
+                    return None;
+                }
+
+                let mut original = MaybeUninit::uninit();
+                if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                    return None;
+                }
+                original.assume_init()
+            };
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+
+            // SAFETY: raw is a valid termios struct copied from the original settings
+            match unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } {
+                0 => Some(Self { original }),
+                _ => None,
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: original holds the settings returned by tcgetattr()
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+
+    // Gets the number of columns in the terminal that stdout is connected to
+    pub fn width() -> Option<usize> {
+        let size = window_size()?;
+        (size.ws_col > 0).then_some(size.ws_col as usize)
+    }
+
+    // Gets the number of rows in the terminal that stdout is connected to
+    pub fn height() -> Option<usize> {
+        let size = window_size()?;
+        (size.ws_row > 0).then_some(size.ws_row as usize)
+    }
+
+    fn window_size() -> Option<libc::winsize> {
+        let mut size = MaybeUninit::<libc::winsize>::uninit();
+        // SAFETY: TIOCGWINSZ fills in the winsize struct when it succeeds
+        unsafe {
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+                return None;
+            }
+            Some(size.assume_init())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    // Raw mode isn't supported here, so input is always read a line at a time
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<Self> {
+            None
+        }
+    }
+
+    pub fn width() -> Option<usize> {
+        None
+    }
+
+    pub fn height() -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedCompletions(Vec<String>);
+
+    impl Helper for FixedCompletions {
+        fn complete(&self, line: &str) -> (usize, Vec<String>) {
+            let start = line.rfind(' ').map_or(0, |index| index + 1);
+            let candidates = self
+                .0
+                .iter()
+                .filter(|candidate| candidate.starts_with(&line[start..]))
+                .cloned()
+                .collect();
+            (start, candidates)
+        }
+    }
+
+    fn buffer(text: &str) -> LineBuffer {
+        let mut buffer = LineBuffer::new();
+        text.chars().for_each(|c| buffer.insert(c));
+        buffer
+    }
+
+    #[test]
+    fn test_line_buffer_editing() {
+        let mut buffer = buffer("hllo");
+        buffer.move_home();
+        buffer.move_right();
+        buffer.insert('e');
+        assert_eq!(buffer.text(), "hello");
+        assert_eq!(buffer.before_cursor(), "he");
+
+        buffer.move_end();
+        buffer.backspace();
+        buffer.move_left();
+        buffer.delete();
+        assert_eq!(buffer.text(), "hel");
+
+        buffer.move_left();
+        buffer.clear_after_cursor();
+        assert_eq!(buffer.text(), "he");
+        buffer.clear_before_cursor();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_line_buffer_set_text() {
+        let mut buffer = buffer("old");
+        buffer.move_home();
+        buffer.set_text("new text");
+        assert_eq!(buffer.text(), "new text");
+        assert!(buffer.at_end());
+    }
+
+    #[test]
+    fn test_line_buffer_complete() {
+        let helper = FixedCompletions(vec![
+            String::from("docs/"),
+            String::from("downloads/"),
+            String::from("notes.txt"),
+        ]);
+
+        let mut single = buffer("cd n");
+        assert!(single.complete(&helper).is_empty());
+        assert_eq!(single.text(), "cd notes.txt ");
+
+        let mut directory = buffer("cd doc");
+        assert!(directory.complete(&helper).is_empty());
+        assert_eq!(directory.text(), "cd docs/");
+
+        // Several matches complete to their common prefix, then get listed
+        let mut several = buffer("cd d");
+        assert!(several.complete(&helper).is_empty());
+        assert_eq!(several.text(), "cd do");
+        assert_eq!(several.complete(&helper), ["docs/", "downloads/"]);
+        assert_eq!(several.text(), "cd do");
+    }
+
+    struct HistoryHint(&'static str);
+
+    impl Helper for HistoryHint {
+        fn complete(&self, _line: &str) -> (usize, Vec<String>) {
+            (0, Vec::new())
+        }
+
+        fn hint(&self, line: &str) -> Option<String> {
+            Some(self.0.to_string()).filter(|entry| entry.starts_with(line))
+        }
+    }
+
+    #[test]
+    fn test_current_hint() {
+        let helper = HistoryHint("list-directory -a");
+        assert_eq!(
+            current_hint(&helper, &buffer("list")),
+            Some(String::from("-directory -a"))
+        );
+        assert_eq!(current_hint(&helper, &buffer("list-directory -a")), None);
+        assert_eq!(current_hint(&helper, &buffer("read")), None);
+    }
+}
@@ -1,10 +1,11 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 
 use anyhow::Result;
 
+use crate::archive::ArchiveView;
 use crate::errors::ShellError;
 use crate::path::Path;
 
@@ -13,9 +14,52 @@ pub struct Environment {
     user: String,
     home: PathBuf,
     pub working_directory: Path,
-    // ? Should this just be a single path or should it store a history?
-    pub previous_working_directory: Option<PathBuf>,
+    // Only changed through change_to(), so that it always agrees with recent_directories
+    previous_working_directory: Option<PathBuf>,
+    // Directories that were recently left, for 'cd --' and 'cd -N'
+    recent_directories: RecentDirectories,
     custom_variables: HashMap<String, String>,
+    // Local variables, innermost scope last, which shadow the shell's other variables
+    scopes: Vec<HashMap<String, String>>,
+    // The archive being browsed after 'change-directory' into one, on top of the working directory
+    pub archive: Option<ArchiveView>,
+}
+
+// The number of directories kept in the recent directories list
+const RECENT_DIRECTORY_LIMIT: usize = 10;
+
+// A bounded list of recently visited directories, most recent first, without duplicates
+#[derive(Debug, Default)]
+pub struct RecentDirectories {
+    directories: VecDeque<PathBuf>,
+}
+
+impl RecentDirectories {
+    // Records that a directory was left for another one
+    // The new working directory is dropped from the list, since there's no need to jump to it
+    pub fn visit(&mut self, previous: PathBuf, current: &PathBuf) {
+        self.directories
+            .retain(|directory| directory != &previous && directory != current);
+        if &previous != current {
+            self.directories.push_front(previous);
+        }
+        self.directories.truncate(RECENT_DIRECTORY_LIMIT);
+    }
+
+    // Gets a directory by its number in the list, starting from 1
+    pub fn get(&self, number: usize) -> Option<&PathBuf> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.directories.get(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.directories.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directories.is_empty()
+    }
 }
 
 impl Environment {
@@ -29,7 +73,10 @@ impl Environment {
             home,
             working_directory,
             previous_working_directory: None,
+            recent_directories: RecentDirectories::default(),
             custom_variables: HashMap::new(),
+            scopes: Vec::new(),
+            archive: None,
         })
     }
 
@@ -38,6 +85,7 @@ impl Environment {
     pub fn update_process_env_vars(&self) -> Result<()> {
         std::env::set_var("USER", &self.user);
         std::env::set_var("HOME", &self.home);
+        std::env::set_var("PWD", self.working_directory.absolute());
         std::env::set_current_dir(self.working_directory.absolute())
             .map_err(|_| ShellError::FailedToUpdateEnvironmentVariables.into())
     }
@@ -50,13 +98,136 @@ impl Environment {
         &self.home
     }
 
-    // Sets the current working directory and stores the previous working directory
-    pub fn set_path(&mut self, new_path: &str) -> Result<()> {
-        let previous_path = self.working_directory.absolute().clone();
-        self.working_directory.set_path(new_path)?;
-        self.previous_working_directory = Some(previous_path);
+    // Gets a variable, searching local scopes from the innermost outwards, then the shell's own variables,
+    // then those inherited from the parent process
+    pub fn get_variable(&self, name: &str) -> Option<String> {
+        let local = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+        match local.or_else(|| self.custom_variables.get(name)) {
+            Some(value) => Some(value.clone()),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    // Sets a shell variable, shadowing any inherited variable with the same name
+    // A variable that is local to a scope is updated in that scope
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        let variables = match self.scope_containing(name) {
+            Some(index) => &mut self.scopes[index],
+            None => &mut self.custom_variables,
+        };
+        variables.insert(name.to_string(), value.to_string());
+    }
+
+    // Removes a shell variable (the innermost one, if it is local), returning its previous value
+    // Variables inherited from the parent process are left untouched
+    pub fn unset_variable(&mut self, name: &str) -> Option<String> {
+        match self.scope_containing(name) {
+            Some(index) => self.scopes[index].remove(name),
+            None => self.custom_variables.remove(name),
+        }
+    }
+
+    fn scope_containing(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(name))
+    }
+
+    // Starts a new scope for local variables, such as while a script is being sourced
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    // Ends the innermost scope, dropping its local variables
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // Sets a variable in the innermost scope, shadowing any variable with the same name until the scope ends
+    // Fails if there is no scope to set it in
+    pub fn set_local_variable(&mut self, name: &str, value: &str) -> Result<()> {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name.to_string(), value.to_string());
+                Ok(())
+            }
+            None => Err(ShellError::NoLocalScope.into()),
+        }
+    }
+
+    // Gets the shell's own variables as they are currently visible (local variables included), sorted by name
+    pub fn variables(&self) -> Vec<(String, String)> {
+        let mut visible = self.custom_variables.clone();
+        for scope in &self.scopes {
+            visible.extend(
+                scope
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+
+        let mut variables: Vec<(String, String)> = visible.into_iter().collect();
+        variables.sort();
+        variables
+    }
+
+    // Sets a variable and exports it to the process environment, so external commands inherit it
+    // If no value is given, the variable's current value is exported
+    pub fn export_variable(&mut self, name: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.set_variable(name, value);
+        }
+
+        if let Some(value) = self.get_variable(name) {
+            std::env::set_var(name, value);
+        }
+    }
+
+    // Gets every exported variable (including those inherited from the parent process), sorted by name
+    // Names and values that aren't valid UTF-8 are converted lossily rather than left out
+    pub fn exported_variables(&self) -> Vec<(String, String)> {
+        let mut variables: Vec<(String, String)> = std::env::vars_os()
+            .map(|(name, value)| {
+                (
+                    name.to_string_lossy().into_owned(),
+                    value.to_string_lossy().into_owned(),
+                )
+            })
+            .collect();
+        variables.sort();
+        variables
+    }
+
+    pub fn previous_working_directory(&self) -> Option<&PathBuf> {
+        self.previous_working_directory.as_ref()
+    }
+
+    pub fn recent_directories(&self) -> &RecentDirectories {
+        &self.recent_directories
+    }
 
-        Ok(())
+    // Changes the working directory, which every directory-changing builtin goes through
+    // The previous directory and the recent directories are updated together, along with $PWD
+    // Symlinks in the path are kept, like 'cd -L', unless a physical path is wanted, like 'cd -P'
+    // Nothing is updated unless the process's working directory could be changed as well
+    pub fn change_to(&mut self, new_path: &str, physical: bool) -> Result<()> {
+        let mut working_directory = self.working_directory.clone();
+        match physical {
+            true => working_directory.set_physical_path(new_path)?,
+            false => working_directory.set_path(new_path)?,
+        }
+        std::env::set_current_dir(working_directory.absolute())
+            .map_err(|_| ShellError::FailedToUpdateEnvironmentVariables)?;
+
+        let previous_path = std::mem::replace(&mut self.working_directory, working_directory)
+            .absolute()
+            .clone();
+        self.recent_directories
+            .visit(previous_path.clone(), self.working_directory.absolute());
+        self.previous_working_directory = Some(previous_path);
+        // Changing to a real directory always leaves any archive
+        self.archive = None;
+        self.update_process_env_vars()
     }
 }
 
@@ -64,3 +235,144 @@ impl Environment {
 fn get_parent_env_var(var_name: &str) -> Result<String> {
     std::env::var(var_name).map_err(|_| ShellError::MissingExternalEnvironmentVariables.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(directories: &RecentDirectories) -> Vec<&str> {
+        directories
+            .iter()
+            .map(|path| path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_recent_directories_order() {
+        let mut directories = RecentDirectories::default();
+        directories.visit(PathBuf::from("/a"), &PathBuf::from("/b"));
+        directories.visit(PathBuf::from("/b"), &PathBuf::from("/c"));
+        directories.visit(PathBuf::from("/c"), &PathBuf::from("/d"));
+        assert_eq!(paths(&directories), ["/c", "/b", "/a"]);
+        assert_eq!(directories.get(1), Some(&PathBuf::from("/c")));
+        assert_eq!(directories.get(0), None);
+        assert_eq!(directories.get(4), None);
+
+        // Revisiting a directory moves it to the front instead of duplicating it,
+        // and the directory being entered is removed from the list
+        directories.visit(PathBuf::from("/d"), &PathBuf::from("/a"));
+        assert_eq!(paths(&directories), ["/d", "/c", "/b"]);
+    }
+
+    #[test]
+    fn test_recent_directories_limit() {
+        let mut directories = RecentDirectories::default();
+        for number in 0..=RECENT_DIRECTORY_LIMIT {
+            directories.visit(
+                PathBuf::from(format!("/{}", number)),
+                &PathBuf::from("/current"),
+            );
+        }
+
+        assert_eq!(directories.iter().count(), RECENT_DIRECTORY_LIMIT);
+        assert_eq!(
+            directories.get(1),
+            Some(&PathBuf::from(format!("/{}", RECENT_DIRECTORY_LIMIT)))
+        );
+    }
+
+    #[test]
+    fn test_change_to_records_previous_directory() {
+        let mut environment = Environment::new().unwrap();
+        environment.change_to("/tmp", false).unwrap();
+        environment.change_to("/", true).unwrap();
+
+        let previous = environment.previous_working_directory().cloned();
+        assert_eq!(previous.as_ref(), environment.recent_directories().get(1));
+
+        // A failed change leaves everything as it was
+        assert!(environment.change_to("/invalid/path", false).is_err());
+        assert_eq!(environment.previous_working_directory().cloned(), previous);
+    }
+
+    #[test]
+    fn test_local_variable_scopes() {
+        let mut environment = Environment::new().unwrap();
+        environment.set_variable("RUSH_SCOPE_TEST", "global");
+        assert!(environment
+            .set_local_variable("RUSH_SCOPE_TEST", "local")
+            .is_err());
+
+        environment.push_scope();
+        environment
+            .set_local_variable("RUSH_SCOPE_TEST", "local")
+            .unwrap();
+        assert_eq!(
+            environment.get_variable("RUSH_SCOPE_TEST").as_deref(),
+            Some("local")
+        );
+
+        // Setting a local variable updates it in its scope, leaving the shadowed one alone
+        environment.push_scope();
+        environment.set_variable("RUSH_SCOPE_TEST", "updated");
+        environment.pop_scope();
+        assert_eq!(
+            environment.get_variable("RUSH_SCOPE_TEST").as_deref(),
+            Some("updated")
+        );
+
+        environment.pop_scope();
+        assert_eq!(
+            environment.get_variable("RUSH_SCOPE_TEST").as_deref(),
+            Some("global")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exported_variables_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        std::env::set_var("RUSH_NON_UTF8_TEST", OsStr::from_bytes(b"a\xffb"));
+        let environment = Environment::new().unwrap();
+        let variables = environment.exported_variables();
+        std::env::remove_var("RUSH_NON_UTF8_TEST");
+
+        assert!(variables.contains(&(
+            String::from("RUSH_NON_UTF8_TEST"),
+            String::from("a\u{fffd}b")
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_change_to_unenterable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Root can enter any directory, so there's nothing to check
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let dir = crate::test_utils::temp_dir("change-to-unenterable");
+        let locked = dir.join("locked");
+        std::fs::create_dir(&locked).unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut environment = Environment::new().unwrap();
+        environment.change_to(dir.to_str().unwrap(), false).unwrap();
+        let previous = environment.previous_working_directory().cloned();
+        let recent: Vec<PathBuf> = environment.recent_directories().iter().cloned().collect();
+
+        // The directory exists, but changing into it fails, so nothing moves
+        assert!(environment.change_to("locked", false).is_err());
+        assert_eq!(environment.working_directory.absolute(), &dir);
+        assert_eq!(environment.previous_working_directory().cloned(), previous);
+        assert!(environment.recent_directories().iter().eq(recent.iter()));
+
+        environment.change_to("/", false).unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
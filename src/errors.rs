@@ -18,8 +18,75 @@ pub enum ShellError {
     FailedToFlushStdout,
     #[error("Failed to read from stdin")]
     FailedToReadStdin,
+    #[error("Failed to read file")]
+    FailedToReadFile,
     #[error("Directory does not exist")]
     UnknownDirectory,
+    #[error("Local variables can only be set inside a sourced script")]
+    NoLocalScope,
+    #[error("Script is already being sourced: '{0}'")]
+    RecursiveSource(String),
     #[error("Unknown error")]
     Uncategorized,
 }
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unterminated quote")]
+    UnterminatedQuote,
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Unexpected '{0}'")]
+    UnexpectedToken(String),
+}
+
+impl ParseError {
+    // Checks whether the error could be fixed by adding more input (such as a closing quote or 'fi')
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, Self::UnterminatedQuote | Self::UnexpectedEnd)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ExpansionError {
+    #[error("Alias loop detected: {}", .0.join(" -> "))]
+    AliasLoop(Vec<String>),
+    #[error("{0}: {1}")]
+    UnsetParameter(String, String),
+    #[error("$(({0})): {1}")]
+    Arithmetic(String, ArithmeticError),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArithmeticError {
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Integer overflow")]
+    Overflow,
+    #[error("Unexpected '{0}'")]
+    UnexpectedToken(String),
+    #[error("Unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("Variable is not an integer: '{0}'")]
+    NotAnInteger(String),
+    #[error("Expression nested too deeply")]
+    TooDeep,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HistoryExpansionError {
+    #[error("No previous command for '{0}'")]
+    NoPreviousCommand(String),
+    #[error("The previous command has no argument for '{0}'")]
+    MissingArgument(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("Unknown flag: '{0}'")]
+    UnknownFlag(String),
+    #[error("Missing value for flag: '{0}'")]
+    MissingValue(String),
+    #[error("Flag doesn't take a value: '{0}'")]
+    UnexpectedValue(String),
+}
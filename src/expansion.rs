@@ -0,0 +1,492 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::arithmetic;
+use crate::errors::ExpansionError;
+use crate::parser::{self, Parameter, Token, Word, WordPart};
+use crate::path;
+
+// The words of a command after each stage of expansion, in the order the stages are applied
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExpansionStages {
+    pub aliases: Vec<Word>,
+    // The names of the aliases that were expanded, in the order they were applied
+    pub applied_aliases: Vec<String>,
+    pub variables: Vec<String>,
+    pub globs: Vec<String>,
+}
+
+// Runs every expansion stage over a command's words, keeping the result of each stage
+// The arguments are the positional parameters that $@ expands to
+// Nothing is executed, so this is safe to use for previews
+pub fn expand_stages(
+    words: Vec<Word>,
+    aliases: &BTreeMap<String, String>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    arguments: &[String],
+    home_directory: &Path,
+) -> Result<ExpansionStages, ExpansionError> {
+    let (aliases, applied_aliases) = expand_aliases(words, aliases)?;
+    let variables = expand_variables(&aliases, lookup, arguments);
+    let globs = expand_globs(&aliases, variables.clone(), home_directory);
+
+    Ok(ExpansionStages {
+        aliases,
+        applied_aliases,
+        variables: variables.concat(),
+        globs,
+    })
+}
+
+// Replaces the command name with its alias definition, repeating for aliases that refer to other aliases
+// Self-referencing aliases (like 'ls=ls -a') stop at the command they shadow, but aliases that
+// lead back to an earlier alias (like 'a=b' and 'b=a') are reported as a loop
+// Only an unquoted command name is expanded, so quoting the name ('ls') skips the alias
+// Like bash, an alias whose definition ends in a space (like 'sudo=sudo ') lets the word after it be expanded too
+// Returns the expanded words along with the names of the aliases applied, in order
+pub fn expand_aliases(
+    mut words: Vec<Word>,
+    aliases: &BTreeMap<String, String>,
+) -> Result<(Vec<Word>, Vec<String>), ExpansionError> {
+    let mut position = 0;
+    let mut applied = Vec::new();
+
+    while position < words.len() {
+        let mut chain: Vec<String> = Vec::new();
+        // The position just after the words the alias expanded to
+        let mut end = position + 1;
+        let mut expand_next = false;
+
+        while let Some(name) = words
+            .get(position)
+            .and_then(Word::as_plain)
+            .map(String::from)
+        {
+            let definition = match aliases.get(&name) {
+                Some(definition) => definition,
+                None => break,
+            };
+
+            if chain.contains(&name) {
+                if chain.last() == Some(&name) {
+                    break;
+                }
+
+                chain.push(name);
+                return Err(ExpansionError::AliasLoop(chain));
+            }
+            chain.push(name);
+
+            let expanded_words = alias_words(definition);
+            end = end - 1 + expanded_words.len();
+            expand_next = definition.ends_with(' ');
+            words.splice(position..position + 1, expanded_words);
+
+            // An alias that expands to nothing leaves no command name of its own to expand
+            if end == position {
+                break;
+            }
+        }
+
+        applied.append(&mut chain);
+        if !expand_next {
+            break;
+        }
+        position = end;
+    }
+
+    Ok((words, applied))
+}
+
+// Describes which alias a command used, such as '(ll -> list-directory -la)', given the aliases expand_aliases() applied
+// Returns None if the command didn't use an alias
+pub fn alias_note(original: &[Word], expanded: &[Word], applied: &[String]) -> Option<String> {
+    let name = original.first()?;
+    if applied.is_empty() {
+        return None;
+    }
+
+    // The arguments after the alias name are carried over unchanged at the end
+    let alias_length = expanded.len().checked_sub(original.len() - 1)?;
+    let definition: Vec<String> = expanded[..alias_length]
+        .iter()
+        .map(Word::to_string)
+        .collect();
+
+    Some(format!("({} -> {})", name, definition.join(" ")))
+}
+
+// Splits an alias definition into words, following the same quoting rules as the command line
+fn alias_words(definition: &str) -> Vec<Word> {
+    match parser::tokenize(definition) {
+        Ok(tokens) => tokens
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect(),
+        Err(_) => definition.split_whitespace().map(Word::literal).collect(),
+    }
+}
+
+// Finds the variables that ${NAME:=word} sets, and the first ${NAME:?message} whose variable is unset or empty
+// The assignments need to be made before the words are expanded, and an error stops the command from running
+pub fn check_parameters(
+    words: &[Word],
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>, ExpansionError> {
+    let mut assignments: Vec<(String, String)> = Vec::new();
+
+    for part in words.iter().flat_map(|word| &word.parts) {
+        let text = match part {
+            WordPart::Variable(text) => text,
+            _ => continue,
+        };
+
+        // Later references see the variables set by earlier ones
+        let lookup = |name: &str| match assignments.iter().rev().find(|(n, _)| n == name) {
+            Some((_, value)) => Some(value.clone()),
+            None => lookup(name),
+        };
+        let parameter = Parameter::parse(text);
+        let is_unset = lookup(parameter.name()).is_none_or(|value| value.is_empty());
+
+        match parameter {
+            Parameter::Assign(name, _) if is_unset && parser::is_variable_name(name) => {
+                let value = parameter.value(&lookup).unwrap_or_default();
+                assignments.push((name.to_string(), value));
+            }
+            Parameter::Error(name, message) if is_unset => {
+                let message = match message.is_empty() {
+                    true => String::from("parameter is unset or empty"),
+                    false => parser::expand_text(message, &lookup),
+                };
+                return Err(ExpansionError::UnsetParameter(name.to_string(), message));
+            }
+            _ => (),
+        }
+    }
+
+    Ok(assignments)
+}
+
+// Evaluates every arithmetic expansion in a command's words, to report the first one that fails (such as
+// dividing by zero) instead of running the command with an empty word
+pub fn check_arithmetic(
+    words: &[Word],
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<(), ExpansionError> {
+    for part in words.iter().flat_map(|word| &word.parts) {
+        if let WordPart::Arithmetic(expression) = part {
+            if let Err(error) = arithmetic::evaluate(expression, lookup) {
+                return Err(ExpansionError::Arithmetic(expression.clone(), error));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Replaces the variables in each word with their values, giving the words that each one became
+// Most words stay a single word, but $@ becomes a word for each of the arguments (see Word::expand_fields)
+pub fn expand_variables(
+    words: &[Word],
+    lookup: &dyn Fn(&str) -> Option<String>,
+    arguments: &[String],
+) -> Vec<Vec<String>> {
+    words
+        .iter()
+        .map(|word| word.expand_fields(lookup, arguments))
+        .collect()
+}
+
+// Replaces each word that has unquoted wildcards with the paths it matches
+// The values are what each word became after variable expansion, and a pattern without any matches is kept as it is
+pub fn expand_globs(
+    words: &[Word],
+    values: Vec<Vec<String>>,
+    home_directory: &Path,
+) -> Vec<String> {
+    let mut expanded = Vec::new();
+
+    for (word, values) in words.iter().zip(values) {
+        for value in values {
+            let matches = match word.has_glob() {
+                true => path::expand_glob(&value, home_directory),
+                false => Vec::new(),
+            };
+
+            match matches.is_empty() {
+                true => expanded.push(value),
+                false => expanded.extend(matches),
+            }
+        }
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ArithmeticError;
+
+    fn words(line: &str) -> Vec<Word> {
+        parser::tokenize(line)
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn aliases(definitions: &[(&str, &str)]) -> BTreeMap<String, String> {
+        definitions
+            .iter()
+            .map(|(name, definition)| (name.to_string(), definition.to_string()))
+            .collect()
+    }
+
+    // Expands aliases, leaving out which ones were applied
+    fn expand_aliases_words(
+        words: Vec<Word>,
+        aliases: &BTreeMap<String, String>,
+    ) -> Result<Vec<Word>, ExpansionError> {
+        expand_aliases(words, aliases).map(|(words, _)| words)
+    }
+
+    #[test]
+    fn test_expand_aliases() {
+        let aliases = aliases(&[("ll", "list-directory -a")]);
+        assert_eq!(
+            expand_aliases_words(words("ll /tmp"), &aliases).unwrap(),
+            words("list-directory -a /tmp")
+        );
+        // Only the command name is expanded
+        assert_eq!(
+            expand_aliases_words(words("cd ll"), &aliases).unwrap(),
+            words("cd ll")
+        );
+        // Quoting the command name skips the alias
+        assert_eq!(
+            expand_aliases_words(words("'ll'"), &aliases).unwrap(),
+            words("'ll'")
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_chained() {
+        let aliases = aliases(&[("l", "ll -x"), ("ll", "list-directory")]);
+        assert_eq!(
+            expand_aliases_words(words("l"), &aliases).unwrap(),
+            words("list-directory -x")
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_self_referencing() {
+        let aliases = aliases(&[("ls", "ls -a")]);
+        assert_eq!(
+            expand_aliases_words(words("ls"), &aliases).unwrap(),
+            words("ls -a")
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_trailing_space() {
+        let aliases = aliases(&[
+            ("sudo", "sudo "),
+            ("time", "time -p "),
+            ("ll", "list-directory -a"),
+        ]);
+        assert_eq!(
+            expand_aliases_words(words("sudo ll /tmp"), &aliases).unwrap(),
+            words("sudo list-directory -a /tmp")
+        );
+        // The rule carries on for as long as each alias ends in a space
+        assert_eq!(
+            expand_aliases_words(words("sudo time ll"), &aliases).unwrap(),
+            words("sudo time -p list-directory -a")
+        );
+        // Without a trailing space, the next word is left alone
+        assert_eq!(
+            expand_aliases_words(words("ll ll"), &aliases).unwrap(),
+            words("list-directory -a ll")
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_empty() {
+        let aliases = aliases(&[("a", ""), ("b", ""), ("c", "a"), ("s", " ")]);
+        // The word after an empty alias isn't its command name, so it isn't expanded
+        assert_eq!(
+            expand_aliases_words(words("a b"), &aliases).unwrap(),
+            words("b")
+        );
+        assert_eq!(
+            expand_aliases_words(words("c b x"), &aliases).unwrap(),
+            words("b x")
+        );
+        assert!(expand_aliases_words(words("a"), &aliases)
+            .unwrap()
+            .is_empty());
+        // Unless the alias ends in a space
+        assert_eq!(
+            expand_aliases_words(words("s b x"), &aliases).unwrap(),
+            words("x")
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_loop() {
+        let aliases = aliases(&[("a", "b"), ("b", "c -x"), ("c", "a")]);
+        let error = expand_aliases_words(words("b"), &aliases).unwrap_err();
+        assert_eq!(
+            error,
+            ExpansionError::AliasLoop(["b", "c", "a", "b"].map(String::from).to_vec())
+        );
+        assert_eq!(error.to_string(), "Alias loop detected: b -> c -> a -> b");
+
+        let aliases = self::aliases(&[("a", "b"), ("b", "a")]);
+        assert_eq!(
+            expand_aliases_words(words("a"), &aliases)
+                .unwrap_err()
+                .to_string(),
+            "Alias loop detected: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn test_expand_stages() {
+        let aliases = aliases(&[("greet", "printf '%s $NAME' $GREETING")]);
+        let lookup = |name: &str| match name {
+            "GREETING" => Some(String::from("hello there")),
+            "DIR" => Some(String::from("/nonexistent-rush-dir")),
+            _ => None,
+        };
+
+        let arguments = ["a b", "c"].map(String::from);
+
+        let stages = expand_stages(
+            words("greet $DIR/*.txt $@"),
+            &aliases,
+            &lookup,
+            &arguments,
+            Path::new("/"),
+        )
+        .unwrap();
+        assert_eq!(
+            stages.aliases,
+            words("printf '%s $NAME' $GREETING $DIR/*.txt $@")
+        );
+        assert_eq!(stages.applied_aliases, ["greet"]);
+        assert_eq!(
+            stages.variables,
+            [
+                "printf",
+                "%s $NAME",
+                "hello there",
+                "/nonexistent-rush-dir/*.txt",
+                "a b",
+                "c"
+            ]
+        );
+        // Patterns that match nothing are left alone
+        assert_eq!(stages.globs, stages.variables);
+    }
+
+    #[test]
+    fn test_check_parameters() {
+        let lookup = |name: &str| match name {
+            "SET" => Some(String::from("value")),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let check = |line: &str| check_parameters(&words(line), &lookup);
+
+        assert_eq!(check("echo $SET ${UNSET:-x} ${SET:=y}"), Ok(vec![]));
+        assert_eq!(
+            check("echo ${UNSET:=x} ${EMPTY:=$SET} ${UNSET:=z}"),
+            Ok(vec![
+                (String::from("UNSET"), String::from("x")),
+                (String::from("EMPTY"), String::from("value")),
+            ])
+        );
+        // Special parameters can't be assigned to, so they only use the word
+        assert_eq!(check("echo ${1:=x}"), Ok(vec![]));
+
+        assert_eq!(check("echo ${SET:?unused}"), Ok(vec![]));
+        assert_eq!(
+            check("echo ${UNSET:?needs a value for $SET}"),
+            Err(ExpansionError::UnsetParameter(
+                String::from("UNSET"),
+                String::from("needs a value for value")
+            ))
+        );
+        assert_eq!(
+            check("echo ${EMPTY:?}").unwrap_err().to_string(),
+            "EMPTY: parameter is unset or empty"
+        );
+        // A variable set earlier in the command satisfies a later check
+        assert_eq!(
+            check("echo ${UNSET:=x} ${UNSET:?missing}"),
+            Ok(vec![(String::from("UNSET"), String::from("x"))])
+        );
+    }
+
+    #[test]
+    fn test_check_arithmetic() {
+        let lookup = |name: &str| (name == "N").then(|| String::from("3"));
+        let check = |line: &str| check_arithmetic(&words(line), &lookup);
+
+        assert_eq!(check("echo $((N * 2)) \"$((N % 2))\""), Ok(()));
+        let error = check("echo $((1 + 1)) $((N / (N - 3)))").unwrap_err();
+        assert_eq!(
+            error,
+            ExpansionError::Arithmetic(
+                String::from("N / (N - 3)"),
+                ArithmeticError::DivisionByZero
+            )
+        );
+        assert_eq!(error.to_string(), "$((N / (N - 3))): Division by zero");
+    }
+
+    #[test]
+    fn test_alias_note() {
+        let aliases = aliases(&[("ll", "list-directory -la"), ("l", "ll")]);
+
+        let original = words("ll /tmp");
+        let (expanded, applied) = expand_aliases(original.clone(), &aliases).unwrap();
+        assert_eq!(
+            alias_note(&original, &expanded, &applied),
+            Some(String::from("(ll -> list-directory -la)"))
+        );
+
+        let original = words("l");
+        let (expanded, applied) = expand_aliases(original.clone(), &aliases).unwrap();
+        assert_eq!(
+            alias_note(&original, &expanded, &applied),
+            Some(String::from("(l -> list-directory -la)"))
+        );
+
+        let original = words("cd /tmp");
+        let (expanded, applied) = expand_aliases(original.clone(), &aliases).unwrap();
+        assert_eq!(alias_note(&original, &expanded, &applied), None);
+
+        // An alias that shadows the command it runs still counts as used
+        let aliases = self::aliases(&[("ls", "ls --color"), ("sudo", "sudo "), ("l", "ls")]);
+        let original = words("ls /tmp");
+        let (expanded, applied) = expand_aliases(original.clone(), &aliases).unwrap();
+        assert_eq!(applied, ["ls"]);
+        assert_eq!(
+            alias_note(&original, &expanded, &applied),
+            Some(String::from("(ls -> ls --color)"))
+        );
+
+        let (_, applied) = expand_aliases(words("sudo l"), &aliases).unwrap();
+        assert_eq!(applied, ["sudo", "l", "ls"]);
+    }
+}
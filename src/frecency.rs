@@ -0,0 +1,326 @@
+// Keeps track of how often and how recently directories are visited, so that 'jump' can
+// change to a directory from just part of its path
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Name of the file (in the home directory) that visited directories are saved to between sessions
+const DIRECTORIES_FILE_NAME: &str = ".rush_directories";
+
+// How many directories are remembered, after which the ones least likely to be jumped to are forgotten
+const MAX_DIRECTORIES: usize = 1000;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+// Gets the path of the visited directories file for a given home directory
+pub fn directories_path(home: &Path) -> PathBuf {
+    home.join(DIRECTORIES_FILE_NAME)
+}
+
+// Gets the current time in seconds since the Unix epoch, which is how visits are timed
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// A directory that has been visited, along with how many times and when it was last visited
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Visits {
+    pub path: PathBuf,
+    pub count: u32,
+    // In seconds since the Unix epoch
+    pub last_visit: u64,
+}
+
+impl Visits {
+    // Scores the directory by how often it has been visited, weighted by how recently
+    pub fn frecency(&self, now: u64) -> f64 {
+        let weight = match now.saturating_sub(self.last_visit) {
+            age if age < HOUR => 4.0,
+            age if age < DAY => 2.0,
+            age if age < WEEK => 0.5,
+            _ => 0.25,
+        };
+
+        f64::from(self.count) * weight
+    }
+}
+
+// The directories that have been visited
+#[derive(Debug, Default)]
+pub struct DirectoryStore {
+    directories: Vec<Visits>,
+}
+
+impl DirectoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reads the directories saved in a file, with one 'count<TAB>last visit<TAB>path' line each
+    // A missing file is treated as no visits, and lines that can't be read are skipped
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::new()),
+            Err(error) => return Err(error),
+        };
+
+        let directories = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                Some(Visits {
+                    count: fields.next()?.parse().ok()?,
+                    last_visit: fields.next()?.parse().ok()?,
+                    path: PathBuf::from(fields.next()?),
+                })
+            })
+            .collect();
+
+        Ok(Self { directories })
+    }
+
+    // Writes the directories to a file, in the format load() reads
+    // The file is replaced in one go, so another shell reading it never sees it half written
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents: String = self
+            .directories
+            .iter()
+            .filter_map(|visits| {
+                let line = format!(
+                    "{}\t{}\t{}\n",
+                    visits.count,
+                    visits.last_visit,
+                    saved_text(&visits.path)?
+                );
+                Some(line)
+            })
+            .collect();
+
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(format!(".{}.tmp", process::id()));
+        fs::write(&temporary_path, contents)?;
+        fs::rename(&temporary_path, path)
+    }
+
+    // Records a visit to a directory at a time
+    // Directories that can't be saved as a line of the file aren't recorded
+    pub fn visit(&mut self, directory: &Path, now: u64) {
+        if saved_text(directory).is_none() {
+            return;
+        }
+
+        match self
+            .directories
+            .iter_mut()
+            .find(|visits| visits.path == directory)
+        {
+            Some(visits) => {
+                visits.count = visits.count.saturating_add(1);
+                visits.last_visit = now;
+            }
+            None => self.directories.push(Visits {
+                path: directory.to_path_buf(),
+                count: 1,
+                last_visit: now,
+            }),
+        }
+
+        if self.directories.len() > MAX_DIRECTORIES {
+            self.directories.sort_by(|a, b| {
+                b.frecency(now)
+                    .total_cmp(&a.frecency(now))
+                    .then(b.last_visit.cmp(&a.last_visit))
+            });
+            self.directories.truncate(MAX_DIRECTORIES);
+        }
+    }
+
+    // Forgets a directory, such as one that no longer exists
+    pub fn remove(&mut self, directory: &Path) {
+        self.directories.retain(|visits| visits.path != directory);
+    }
+
+    pub fn directories(&self) -> &[Visits] {
+        &self.directories
+    }
+}
+
+// Gets the text a path is saved as, if it's valid UTF-8 without newlines (which would start another line)
+fn saved_text(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains('\n'))
+}
+
+// Finds the directories whose paths contain every word of a query (ignoring case), best first
+// Directories with the same score are ordered by when they were last visited
+pub fn rank<'a>(directories: &'a [Visits], query: &[&str], now: u64) -> Vec<&'a Path> {
+    let query: Vec<String> = query.iter().map(|word| word.to_lowercase()).collect();
+    let mut matches: Vec<&Visits> = directories
+        .iter()
+        .filter(|visits| {
+            let path = visits.path.to_string_lossy().to_lowercase();
+            query.iter().all(|word| path.contains(word.as_str()))
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        b.frecency(now)
+            .total_cmp(&a.frecency(now))
+            .then(b.last_visit.cmp(&a.last_visit))
+    });
+    matches
+        .into_iter()
+        .map(|visits| visits.path.as_path())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn visits(path: &str, count: u32, age: u64) -> Visits {
+        Visits {
+            path: PathBuf::from(path),
+            count,
+            last_visit: NOW - age,
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let directories = [
+            visits("/home/user/projects/rush", 10, 2 * DAY),
+            visits("/home/user/projects/rush-site", 3, 10 * 60),
+            visits("/home/user/notes", 60, 30 * DAY),
+            visits("/tmp/Rush-build", 1, 5 * DAY),
+        ];
+        let rank = |query: &[&str]| -> Vec<String> {
+            rank(&directories, query, NOW)
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect()
+        };
+
+        // A few visits just now count for more than many visits a few days ago
+        assert_eq!(
+            rank(&["rush"]),
+            [
+                "/home/user/projects/rush-site",
+                "/home/user/projects/rush",
+                "/tmp/Rush-build"
+            ]
+        );
+        // Frequent visits still win over a long time
+        assert_eq!(rank(&["user"])[0], "/home/user/notes");
+        // Every word has to match
+        assert_eq!(
+            rank(&["projects", "site"]),
+            ["/home/user/projects/rush-site"]
+        );
+        assert!(rank(&["missing"]).is_empty());
+    }
+
+    #[test]
+    fn test_frecency() {
+        assert_eq!(visits("/a", 2, 0).frecency(NOW), 8.0);
+        assert_eq!(visits("/a", 2, 2 * HOUR).frecency(NOW), 4.0);
+        assert_eq!(visits("/a", 2, 2 * DAY).frecency(NOW), 1.0);
+        assert_eq!(visits("/a", 2, 2 * WEEK).frecency(NOW), 0.5);
+    }
+
+    #[test]
+    fn test_store_visits() {
+        let mut store = DirectoryStore::new();
+        store.visit(Path::new("/a"), NOW - 10);
+        store.visit(Path::new("/b"), NOW - 5);
+        store.visit(Path::new("/a"), NOW);
+        assert_eq!(
+            store.directories(),
+            [visits("/a", 2, 0), visits("/b", 1, 5)]
+        );
+
+        let dir = crate::test_utils::temp_dir("directories");
+        let file = dir.join("directories");
+        store.save(&file).unwrap();
+        let loaded = DirectoryStore::load(&file).unwrap();
+        assert_eq!(loaded.directories(), store.directories());
+
+        store.remove(Path::new("/a"));
+        assert_eq!(store.directories(), [visits("/b", 1, 5)]);
+
+        // A count that can't go any higher (say, from an edited file) stays where it is
+        let mut saturated = DirectoryStore {
+            directories: vec![visits("/c", u32::MAX, 0)],
+        };
+        saturated.visit(Path::new("/c"), NOW);
+        assert_eq!(saturated.directories()[0].count, u32::MAX);
+
+        let _ = fs::remove_file(&file);
+        assert!(DirectoryStore::load(&file)
+            .unwrap()
+            .directories()
+            .is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_store_unsaveable_paths() {
+        let mut store = DirectoryStore::new();
+        // A newline would start a line of its own in the file, which could add a made-up visit
+        store.visit(Path::new("/a\n99\t0\t/injected"), NOW);
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+            store.visit(Path::new(OsStr::from_bytes(b"/b\xff")), NOW);
+        }
+        store.visit(Path::new("/with\ttab"), NOW);
+        assert_eq!(store.directories(), [visits("/with\ttab", 1, 0)]);
+
+        let dir = temp_dir("directories-unsaveable");
+        let file = dir.join("directories");
+        store.save(&file).unwrap();
+        assert_eq!(
+            DirectoryStore::load(&file).unwrap().directories(),
+            store.directories()
+        );
+        // Only the file itself is left, with no temporary file beside it
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_store_limit() {
+        let mut store = DirectoryStore::new();
+        store.visit(Path::new("/often"), NOW - WEEK);
+        store.visit(Path::new("/often"), NOW - WEEK);
+        for index in 0..MAX_DIRECTORIES {
+            store.visit(Path::new(&format!("/once/{}", index)), NOW - 2 * WEEK);
+        }
+
+        // The directory least likely to be jumped to is forgotten, rather than the newest
+        assert_eq!(store.directories().len(), MAX_DIRECTORIES);
+        assert!(store
+            .directories()
+            .iter()
+            .any(|visits| visits.path == Path::new("/often")));
+        store.visit(Path::new("/new"), NOW);
+        assert_eq!(store.directories().len(), MAX_DIRECTORIES);
+        assert!(store
+            .directories()
+            .iter()
+            .any(|visits| visits.path == Path::new("/new")));
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Whether git knows about a file
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GitStatus {
+    Tracked,
+    Untracked,
+}
+
+// A git working tree, along with the paths in its index (relative to the root of the working tree)
+// The index is read directly, so the git binary isn't needed
+pub struct Repository {
+    root: PathBuf,
+    tracked: HashSet<PathBuf>,
+}
+
+impl Repository {
+    // Finds the repository containing a directory by looking for a '.git' directory in it or its parents
+    // Returns None outside of a repository, or if the index is in a format that isn't understood
+    pub fn discover(directory: &Path) -> Option<Self> {
+        let root = find_root(directory)?;
+        // A repository with nothing added yet has no index
+        let tracked = match fs::read(root.join(".git").join("index")) {
+            Ok(index) => parse_index(&index)?,
+            Err(_) => Vec::new(),
+        };
+
+        Some(Self {
+            root,
+            tracked: tracked.into_iter().map(PathBuf::from).collect(),
+        })
+    }
+
+    // Gets whether a file is tracked, counting a directory as tracked if anything inside it is
+    // Returns None for paths outside of the working tree
+    pub fn status(&self, path: &Path) -> Option<GitStatus> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+
+        match self
+            .tracked
+            .iter()
+            .any(|tracked| tracked.starts_with(relative))
+        {
+            true => Some(GitStatus::Tracked),
+            false => Some(GitStatus::Untracked),
+        }
+    }
+}
+
+// Finds the root of the working tree that contains a directory
+pub fn find_root(directory: &Path) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .find(|ancestor| ancestor.join(".git").is_dir())
+        .map(Path::to_path_buf)
+}
+
+// Reads the paths of every entry in a git index file
+// Only versions 2 and 3 are supported, since version 4 compresses the paths
+pub fn parse_index(index: &[u8]) -> Option<Vec<String>> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            index.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    if index.get(..4)? != b"DIRC" || !(2..=3).contains(&read_u32(4)?) {
+        return None;
+    }
+
+    let mut paths = Vec::new();
+    let mut offset = 12;
+    for _ in 0..read_u32(8)? {
+        // Each entry starts with 62 bytes of file information, ending with a 16-bit flags field
+        let flags = u16::from_be_bytes(index.get(offset + 60..offset + 62)?.try_into().ok()?);
+        let mut path_start = offset + 62;
+        // Extended entries (version 3 only) have a second flags field
+        if flags & 0x4000 != 0 {
+            path_start += 2;
+        }
+
+        let path_length = index
+            .get(path_start..)?
+            .iter()
+            .position(|&byte| byte == 0)?;
+        paths.push(
+            String::from_utf8_lossy(&index[path_start..path_start + path_length]).into_owned(),
+        );
+
+        // Entries are padded with 1 to 8 NUL bytes so their length is a multiple of 8
+        let entry_length = path_start + path_length - offset;
+        offset += (entry_length + 8) & !7;
+    }
+
+    Some(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a version 2 index containing the given paths, with every other field zeroed
+    fn fake_index(paths: &[&str]) -> Vec<u8> {
+        let mut index = b"DIRC".to_vec();
+        index.extend(2u32.to_be_bytes());
+        index.extend((paths.len() as u32).to_be_bytes());
+
+        for path in paths {
+            let mut entry = vec![0; 60];
+            entry.extend((path.len() as u16).to_be_bytes());
+            entry.extend(path.as_bytes());
+            let padded_length = (entry.len() + 8) & !7;
+            entry.resize(padded_length, 0);
+            index.extend(entry);
+        }
+
+        // A real index ends with a checksum, which is ignored
+        index.extend([0; 20]);
+        index
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let paths = ["README.md", "src/main.rs", "src/a"];
+        assert_eq!(parse_index(&fake_index(&paths)).unwrap(), paths);
+        assert_eq!(parse_index(&fake_index(&[])).unwrap(), Vec::<String>::new());
+
+        assert_eq!(parse_index(b"DIRC"), None);
+        assert_eq!(parse_index(b"not an index"), None);
+        // A truncated index is rejected rather than read past the end
+        let index = fake_index(&paths);
+        assert_eq!(parse_index(&index[..40]), None);
+    }
+
+    #[test]
+    fn test_repository_status() {
+        let root = crate::test_utils::temp_dir("git");
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("src").join("nested")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(
+            root.join(".git").join("index"),
+            fake_index(&["README.md", "src/nested/lib.rs"]),
+        )
+        .unwrap();
+
+        let repository = Repository::discover(&root.join("src").join("nested")).unwrap();
+        assert_eq!(repository.root, root);
+
+        let status = |path: &str| repository.status(&root.join(path));
+        assert_eq!(status("README.md"), Some(GitStatus::Tracked));
+        assert_eq!(status("notes.txt"), Some(GitStatus::Untracked));
+        assert_eq!(status("src"), Some(GitStatus::Tracked));
+        assert_eq!(status("src/nested/lib.rs"), Some(GitStatus::Tracked));
+        assert_eq!(status("src/nested/other.rs"), Some(GitStatus::Untracked));
+        assert_eq!(status("build"), Some(GitStatus::Untracked));
+        // 'READ' is only a prefix of a tracked name, not a parent directory
+        assert_eq!(status("READ"), Some(GitStatus::Untracked));
+        assert_eq!(repository.status(Path::new("/elsewhere")), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
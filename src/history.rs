@@ -0,0 +1,853 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::datetime::DateTime;
+use crate::errors::HistoryExpansionError;
+use crate::parser::{self, Token};
+
+// Name of the file (in the home directory) that commands are saved to between sessions
+const HISTORY_FILE_NAME: &str = ".rush_history";
+// The number of commands kept in memory ($HISTSIZE) and in the history file ($HISTFILESIZE) by default
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+// Stands in for the newlines inside a command (like a multi-line 'if'), so each command is one line of the file
+// The ASCII record separator is used because commands practically never contain it
+const NEWLINE_MARKER: char = '\u{1e}';
+
+// Reads a history size from a variable like $HISTSIZE, using the default if it's unset or not a number
+pub fn parse_history_size(value: Option<&str>) -> usize {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
+// Gets the path of the history file for a given home directory
+pub fn history_path(home: &Path) -> PathBuf {
+    home.join(HISTORY_FILE_NAME)
+}
+
+// Checks whether a command line runs 'fc', which isn't added to history (like in bash)
+// so that running 'fc' again edits the command before it, rather than 'fc' itself
+pub fn is_fc_command(line: &str) -> bool {
+    matches!(
+        parser::tokenize(line).as_deref(),
+        Ok([Token::Word(word), ..]) if word.as_plain() == Some("fc")
+    )
+}
+
+// Checks whether a $HISTCONTROL value (a colon-separated list of options) asks for duplicates to be erased
+pub fn erases_duplicates(history_control: &str) -> bool {
+    history_control
+        .split(':')
+        .any(|option| option == "erasedups")
+}
+
+// Removes repeated commands, keeping only the most recent occurrence of each
+// The remaining commands stay in the order they were last run
+pub fn erase_duplicates(commands: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut unique: Vec<String> = commands
+        .into_iter()
+        .rev()
+        .filter(|command| seen.insert(command.clone()))
+        .collect();
+    unique.reverse();
+    unique
+}
+
+// Reads the commands saved in a history file, oldest first
+// A missing file is treated as an empty history
+pub fn load_file(path: &Path, erase_duplicate_commands: bool) -> io::Result<Vec<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let commands: Vec<String> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.replace(NEWLINE_MARKER, "\n"))
+        .collect();
+
+    Ok(match erase_duplicate_commands {
+        true => erase_duplicates(commands),
+        false => commands,
+    })
+}
+
+// Saves a command to the end of a history file, creating the file if it doesn't exist
+// The oldest commands are dropped so that the file keeps at most max_commands ($HISTFILESIZE)
+pub fn append_to_file(path: &Path, command: &str, max_commands: usize) -> io::Result<()> {
+    let command = command.trim();
+    if command.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = history_file_options().append(true).open(path)?;
+    writeln!(
+        file,
+        "{}",
+        command.replace('\n', &NEWLINE_MARKER.to_string())
+    )?;
+
+    trim_file(path, max_commands)
+}
+
+// Replaces a history file with the commands in memory, such as after some were deleted with 'history -d'
+pub fn write_file(path: &Path, history: &History) -> io::Result<()> {
+    let mut file = history_file_options()
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    for (_, command) in history.iter() {
+        writeln!(
+            file,
+            "{}",
+            command.replace('\n', &NEWLINE_MARKER.to_string())
+        )?;
+    }
+    Ok(())
+}
+
+// Options for writing a history file, which is created readable only by its owner (like bash's)
+// since commands can contain passwords and other secrets
+fn history_file_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+// Drops the oldest commands from a history file until it has at most max_commands
+// The file is only rewritten if it has too many (each command is a single line)
+fn trim_file(path: &Path, max_commands: usize) -> io::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    if lines.len() <= max_commands {
+        return Ok(());
+    }
+
+    let kept = &lines[lines.len() - max_commands..];
+    let mut trimmed = kept.join("\n");
+    if !kept.is_empty() {
+        trimmed.push('\n');
+    }
+    fs::write(path, trimmed)
+}
+
+// The file formats that history can be exported as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    // One command per line
+    Plain,
+    // An array of objects, one per command, with the time it was run (in seconds since the Unix epoch)
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(Self::Plain),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+// Parses the entries given to 'history -d', either a single number ('5') or an inclusive range ('3:7')
+pub fn parse_entry_range(text: &str) -> Option<(usize, usize)> {
+    let (first, last) = text.split_once(':').unwrap_or((text, text));
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+// Keeps track of the commands the user has entered (and when), oldest first
+// Entries are numbered from 1, like in other shells
+pub struct History {
+    entries: Vec<(String, SystemTime)>,
+    // The most entries kept, after which the oldest are dropped ($HISTSIZE)
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            limit: DEFAULT_HISTORY_SIZE,
+        }
+    }
+
+    // Changes the most entries kept, dropping the oldest ones if there are already too many
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.limit);
+        self.entries.drain(..excess);
+    }
+
+    // Adds a command to the history, ignoring blank commands
+    pub fn add(&mut self, command: &str) {
+        self.add_at(command, SystemTime::now());
+    }
+
+    // Adds a command that was run at a specific time
+    pub fn add_at(&mut self, command: &str, time: SystemTime) {
+        let command = command.trim();
+        if !command.is_empty() {
+            self.entries.push((command.to_string(), time));
+            self.trim();
+        }
+    }
+
+    // Gets an entry by its number
+    pub fn get(&self, number: usize) -> Option<&str> {
+        self.entry(number).map(|(command, _)| command.as_str())
+    }
+
+    fn entry(&self, number: usize) -> Option<&(String, SystemTime)> {
+        match number {
+            0 => None,
+            _ => self.entries.get(number - 1),
+        }
+    }
+
+    // Formats an entry for the 'history' listing
+    // With a time format (like $HISTTIMEFORMAT), the entry is prefixed with the formatted time it was run
+    pub fn format_entry(&self, number: usize, time_format: Option<&str>) -> Option<String> {
+        let (command, time) = self.entry(number)?;
+        let time = match time_format {
+            Some(format) => DateTime::from_system_time(*time).format(format),
+            None => String::new(),
+        };
+
+        Some(format!("{:>5}  {}{}", number, time, command))
+    }
+
+    // Deletes the entries from the first number to the last (inclusive), so later entries are renumbered
+    // Returns false without deleting anything if any of the numbers is out of range
+    pub fn delete(&mut self, first: usize, last: usize) -> bool {
+        if first == 0 || last < first || last > self.entries.len() {
+            return false;
+        }
+
+        self.entries.drain(first - 1..last);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Gets the most recent entry
+    pub fn last(&self) -> Option<&str> {
+        self.get(self.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Iterates over the entries along with their numbers
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, (command, _))| (index + 1, command.as_str()))
+    }
+
+    // Formats every entry for saving to a file
+    pub fn export(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Plain => self
+                .entries
+                .iter()
+                .map(|(command, _)| format!("{}\n", command))
+                .collect(),
+            ExportFormat::Json => {
+                let objects: Vec<String> = self
+                    .entries
+                    .iter()
+                    .map(|(command, time)| {
+                        let seconds = time
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |duration| duration.as_secs());
+                        format!(
+                            "  {{\"command\": {}, \"time\": {}}}",
+                            json_string(command),
+                            seconds
+                        )
+                    })
+                    .collect();
+
+                match objects.is_empty() {
+                    true => String::from("[]\n"),
+                    false => format!("[\n{}\n]\n", objects.join(",\n")),
+                }
+            }
+        }
+    }
+}
+
+// Finds the most recent history entry that starts with what's been typed so far, for suggesting as the user types
+// Nothing is suggested for an empty line, or when the line already matches an entry exactly
+pub fn suggest_from_history(prefix: &str, history: &History) -> Option<String> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    history
+        .entries
+        .iter()
+        .rev()
+        .map(|(command, _)| command)
+        .find(|command| command.starts_with(prefix) && command.len() > prefix.len())
+        .cloned()
+}
+
+// Searches backwards from an entry number for the most recent entry containing a query, for Ctrl-R
+// Searching again from one before the previous match moves on to older matches
+pub fn reverse_search(
+    query: &str,
+    start_index: usize,
+    history: &History,
+) -> Option<(usize, String)> {
+    if query.is_empty() {
+        return None;
+    }
+
+    (1..=start_index.min(history.len()))
+        .rev()
+        .find(|number| {
+            history
+                .get(*number)
+                .is_some_and(|command| command.contains(query))
+        })
+        .and_then(|number| Some((number, history.get(number)?.to_string())))
+}
+
+// Replaces '!$' with the last word of the previous command and '!^' with its first argument, like bash
+// The words are inserted as they were written, so their quotes and variables still apply
+// Nothing inside single quotes is replaced
+pub fn expand_last_arguments(
+    line: &str,
+    previous: Option<&str>,
+) -> Result<String, HistoryExpansionError> {
+    let mut expanded = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    let (mut in_single_quotes, mut in_double_quotes) = (false, false);
+
+    while let Some(c) = chars.next() {
+        let designator = match (c, chars.peek()) {
+            ('!', Some(&designator @ ('$' | '^'))) if !in_single_quotes => designator,
+            _ => {
+                in_single_quotes ^= c == '\'' && !in_double_quotes;
+                in_double_quotes ^= c == '"' && !in_single_quotes;
+                expanded.push(c);
+                continue;
+            }
+        };
+        chars.next();
+
+        let name = format!("!{}", designator);
+        let previous =
+            previous.ok_or_else(|| HistoryExpansionError::NoPreviousCommand(name.clone()))?;
+        let words: Vec<String> = parser::tokenize(previous)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        let word = match designator {
+            '$' => words.last(),
+            _ => words.get(1),
+        };
+        expanded.push_str(word.ok_or(HistoryExpansionError::MissingArgument(name))?);
+    }
+
+    Ok(expanded)
+}
+
+// Quotes a string as a JSON string literal
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_expand_last_arguments() {
+        let previous = Some("make-directory -p 'my dir' $HOME/x");
+        let expand = |line: &str| expand_last_arguments(line, previous);
+
+        assert_eq!(expand("cd !$").unwrap(), "cd ${HOME}/x");
+        assert_eq!(expand("echo !^ !$").unwrap(), "echo -p ${HOME}/x");
+        assert_eq!(expand("echo 'not !$' !").unwrap(), "echo 'not !$' !");
+        assert_eq!(
+            expand("echo \"it's !$\"").unwrap(),
+            "echo \"it's ${HOME}/x\""
+        );
+        assert_eq!(expand("no designators").unwrap(), "no designators");
+        assert_eq!(
+            expand_last_arguments("cd !$", Some("mv a 'b c'")).unwrap(),
+            "cd 'b c'"
+        );
+        // With only a command name, '!$' is the name itself but there's no '!^'
+        assert_eq!(expand_last_arguments("!$", Some("ls")).unwrap(), "ls");
+        assert_eq!(
+            expand_last_arguments("!^", Some("ls")),
+            Err(HistoryExpansionError::MissingArgument(String::from("!^")))
+        );
+        assert_eq!(
+            expand_last_arguments("cd !$", None),
+            Err(HistoryExpansionError::NoPreviousCommand(String::from("!$")))
+        );
+        assert_eq!(expand_last_arguments("cd", None).unwrap(), "cd");
+    }
+
+    #[test]
+    fn test_history_add_and_get() {
+        let mut history = History::new();
+        history.add("first");
+        history.add("   ");
+        history.add("second\n");
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0), None);
+        assert_eq!(history.get(1), Some("first"));
+        assert_eq!(history.get(2), Some("second"));
+        assert_eq!(history.get(3), None);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            [(1, "first"), (2, "second")]
+        );
+    }
+
+    // A minimal JSON reader, just enough to check that exports parse back
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    fn parse_json(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars);
+        assert!(chars.all(char::is_whitespace), "trailing characters");
+        value
+    }
+
+    fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Json {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.next().expect("unexpected end") {
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next().expect("unterminated string") {
+                        '"' => break Json::String(string),
+                        '\\' => match chars.next().unwrap() {
+                            'n' => string.push('\n'),
+                            'r' => string.push('\r'),
+                            't' => string.push('\t'),
+                            'u' => {
+                                let code: String = chars.by_ref().take(4).collect();
+                                string.push(
+                                    char::from_u32(u32::from_str_radix(&code, 16).unwrap())
+                                        .unwrap(),
+                                );
+                            }
+                            c => string.push(c),
+                        },
+                        c => string.push(c),
+                    }
+                }
+            }
+            '[' => {
+                let mut items = Vec::new();
+                loop {
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    match chars.peek() {
+                        Some(']') => {
+                            chars.next();
+                            break Json::Array(items);
+                        }
+                        Some(',') => {
+                            chars.next();
+                        }
+                        _ => items.push(parse_value(chars)),
+                    }
+                }
+            }
+            '{' => {
+                let mut fields = Vec::new();
+                loop {
+                    while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+                    if chars.next_if_eq(&'}').is_some() {
+                        break Json::Object(fields);
+                    }
+
+                    let key = match parse_value(chars) {
+                        Json::String(key) => key,
+                        value => panic!("invalid key {:?}", value),
+                    };
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    assert_eq!(chars.next(), Some(':'));
+                    fields.push((key, parse_value(chars)));
+                }
+            }
+            c => {
+                let mut number = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+                {
+                    number.push(c);
+                }
+                Json::Number(number.parse().expect("invalid number"))
+            }
+        }
+    }
+
+    fn sample_history() -> History {
+        let mut history = History::new();
+        history.add_at(
+            "echo \"quoted\" \\ path",
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        );
+        history.add_at(
+            "if true\nthen\ttrue\nfi",
+            UNIX_EPOCH + Duration::from_secs(1_700_000_060),
+        );
+        history
+    }
+
+    #[test]
+    fn test_history_delete() {
+        let mut history = History::new();
+        for command in ["a", "b", "c", "d", "e"] {
+            history.add(command);
+        }
+
+        // Later entries are renumbered after a delete
+        assert!(history.delete(2, 2));
+        assert_eq!(history.get(2), Some("c"));
+        assert!(history.delete(2, 3));
+        assert_eq!(history.iter().collect::<Vec<_>>(), [(1, "a"), (2, "e")]);
+
+        for (first, last) in [(0, 1), (2, 1), (2, 3), (3, 3)] {
+            assert!(!history.delete(first, last), "{}:{}", first, last);
+        }
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_entry_range() {
+        assert_eq!(parse_entry_range("5"), Some((5, 5)));
+        assert_eq!(parse_entry_range("3:7"), Some((3, 7)));
+        assert_eq!(parse_entry_range("3:"), None);
+        assert_eq!(parse_entry_range("-1"), None);
+    }
+
+    #[test]
+    fn test_export_plain() {
+        assert_eq!(
+            sample_history().export(ExportFormat::Plain),
+            "echo \"quoted\" \\ path\nif true\nthen\ttrue\nfi\n"
+        );
+        assert_eq!(History::new().export(ExportFormat::Plain), "");
+    }
+
+    #[test]
+    fn test_export_json() {
+        assert_eq!(
+            sample_history().export(ExportFormat::Json),
+            concat!(
+                "[\n",
+                "  {\"command\": \"echo \\\"quoted\\\" \\\\ path\", \"time\": 1700000000},\n",
+                "  {\"command\": \"if true\\nthen\\ttrue\\nfi\", \"time\": 1700000060}\n",
+                "]\n"
+            )
+        );
+        assert_eq!(History::new().export(ExportFormat::Json), "[]\n");
+    }
+
+    #[test]
+    fn test_export_json_parses_back() {
+        let history = sample_history();
+        let exported = parse_json(&history.export(ExportFormat::Json));
+
+        let expected = Json::Array(
+            history
+                .iter()
+                .map(|(number, command)| {
+                    let (_, time) = history.entry(number).unwrap();
+                    let seconds = time.duration_since(UNIX_EPOCH).unwrap();
+                    Json::Object(vec![
+                        (String::from("command"), Json::String(command.to_string())),
+                        (String::from("time"), Json::Number(seconds.as_secs() as f64)),
+                    ])
+                })
+                .collect(),
+        );
+        assert_eq!(exported, expected);
+        assert_eq!(
+            parse_json(&History::new().export(ExportFormat::Json)),
+            Json::Array(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_history_times() {
+        let mut history = History::new();
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        history.add_at("true", time);
+
+        assert_eq!(history.entry(1).map(|(_, time)| *time), Some(time));
+        assert_eq!(history.entry(2), None);
+        assert_eq!(
+            history.format_entry(1, None),
+            Some(String::from("    1  true"))
+        );
+
+        let date = DateTime::from_system_time(time).format("%Y-%m-%d %H:%M:%S ");
+        assert_eq!(
+            history.format_entry(1, Some("%Y-%m-%d %H:%M:%S ")),
+            Some(format!("    1  {}true", date))
+        );
+        assert_eq!(history.format_entry(2, None), None);
+    }
+
+    #[test]
+    fn test_erase_duplicates() {
+        let commands = ["a", "b", "a", "c", "b"].map(String::from).to_vec();
+        assert_eq!(erase_duplicates(commands), ["a", "c", "b"]);
+        assert!(erase_duplicates(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_erases_duplicates() {
+        assert!(erases_duplicates("erasedups"));
+        assert!(erases_duplicates("ignorespace:erasedups"));
+        assert!(!erases_duplicates("ignoredups"));
+        assert!(!erases_duplicates(""));
+    }
+
+    #[test]
+    fn test_load_file() {
+        let dir = crate::test_utils::temp_dir("history");
+        let path = dir.join("history");
+        fs::write(&path, "ls\ncd /tmp\n\nls\necho hi\ncd /tmp\n").unwrap();
+
+        assert_eq!(
+            load_file(&path, false).unwrap(),
+            ["ls", "cd /tmp", "ls", "echo hi", "cd /tmp"]
+        );
+        assert_eq!(
+            load_file(&path, true).unwrap(),
+            ["ls", "echo hi", "cd /tmp"]
+        );
+
+        append_to_file(&path, "  pwd\n", DEFAULT_HISTORY_SIZE).unwrap();
+        append_to_file(&path, "   ", DEFAULT_HISTORY_SIZE).unwrap();
+        assert_eq!(
+            load_file(&path, true).unwrap(),
+            ["ls", "echo hi", "cd /tmp", "pwd"]
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert!(load_file(&path, true).unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_history_file_multi_line_commands() {
+        let dir = crate::test_utils::temp_dir("history-lines");
+        let path = dir.join("history");
+        let compound = "if true\nthen\n  echo yes\nfi";
+
+        append_to_file(&path, "ls", 2).unwrap();
+        append_to_file(&path, compound, 2).unwrap();
+        assert_eq!(load_file(&path, false).unwrap(), ["ls", compound]);
+
+        // A multi-line command counts as a single command towards $HISTFILESIZE
+        append_to_file(&path, "for x in a b\ndo\n  echo $x\ndone", 2).unwrap();
+        assert_eq!(
+            load_file(&path, false).unwrap(),
+            [compound, "for x in a b\ndo\n  echo $x\ndone"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_is_fc_command() {
+        assert!(is_fc_command("fc"));
+        assert!(is_fc_command("  fc -l 1 5"));
+        assert!(is_fc_command("fc; ls"));
+        assert!(!is_fc_command("echo fc"));
+        assert!(!is_fc_command("fcat"));
+        assert!(!is_fc_command("'fc x"));
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dir = crate::test_utils::temp_dir("history-write");
+        let path = dir.join("history");
+        fs::write(&path, "a\nb\nc\n").unwrap();
+
+        let mut history = History::new();
+        for command in ["ls", "if true\nthen\n  echo yes\nfi", "pwd"] {
+            history.add(command);
+        }
+        history.delete(3, 3);
+        write_file(&path, &history).unwrap();
+        assert_eq!(
+            load_file(&path, false).unwrap(),
+            ["ls", "if true\nthen\n  echo yes\nfi"]
+        );
+
+        history.clear();
+        write_file(&path, &history).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_history_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::test_utils::temp_dir("history-permissions");
+        let path = dir.join("history");
+
+        append_to_file(&path, "export TOKEN=secret", 2).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_history_file_size() {
+        let dir = crate::test_utils::temp_dir("history-size");
+        let path = dir.join("history");
+        fs::write(&path, "a\nb\nc\n").unwrap();
+
+        append_to_file(&path, "d", 3).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\nc\nd\n");
+        append_to_file(&path, "e", 0).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut history = History::new();
+        history.set_limit(2);
+        for command in ["a", "b", "c"] {
+            history.add(command);
+        }
+        assert_eq!(history.iter().collect::<Vec<_>>(), [(1, "b"), (2, "c")]);
+
+        history.set_limit(1);
+        assert_eq!(history.iter().collect::<Vec<_>>(), [(1, "c")]);
+    }
+
+    #[test]
+    fn test_parse_history_size() {
+        assert_eq!(parse_history_size(Some("50")), 50);
+        assert_eq!(parse_history_size(Some("0")), 0);
+        assert_eq!(parse_history_size(Some("-1")), DEFAULT_HISTORY_SIZE);
+        assert_eq!(parse_history_size(Some("lots")), DEFAULT_HISTORY_SIZE);
+        assert_eq!(parse_history_size(None), DEFAULT_HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test_reverse_search_cycles() {
+        let mut history = History::new();
+        history.add("make build");
+        history.add("list-directory");
+        history.add("make test");
+        history.add("read-file Makefile");
+        history.add("make install");
+
+        let first = reverse_search("make", history.len(), &history);
+        assert_eq!(first, Some((5, String::from("make install"))));
+        let second = reverse_search("make", 4, &history);
+        assert_eq!(second, Some((3, String::from("make test"))));
+        let third = reverse_search("make", 2, &history);
+        assert_eq!(third, Some((1, String::from("make build"))));
+        // There's nothing older than the first entry
+        assert_eq!(reverse_search("make", 0, &history), None);
+
+        // The search is case-sensitive and looks anywhere in the command
+        assert_eq!(
+            reverse_search("Make", 10, &history),
+            Some((4, String::from("read-file Makefile")))
+        );
+        assert_eq!(reverse_search("", 5, &history), None);
+        assert_eq!(reverse_search("missing", 5, &history), None);
+    }
+
+    #[test]
+    fn test_suggest_from_history() {
+        let mut history = History::new();
+        history.add("change-directory ~/projects");
+        history.add("list-directory");
+        history.add("change-directory /tmp");
+
+        // The most recent match wins
+        assert_eq!(
+            suggest_from_history("change", &history),
+            Some(String::from("change-directory /tmp"))
+        );
+        assert_eq!(
+            suggest_from_history("change-directory ~", &history),
+            Some(String::from("change-directory ~/projects"))
+        );
+        assert_eq!(suggest_from_history("list-directory", &history), None);
+        assert_eq!(suggest_from_history("read", &history), None);
+        assert_eq!(suggest_from_history("", &history), None);
+    }
+}
@@ -0,0 +1,385 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, ExitStatus, Stdio};
+
+// Represents an external command running in the background
+pub struct Job {
+    pub id: usize,
+    pub child: Child,
+    pub command: String,
+    pub state: JobState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+}
+
+// Keeps track of the shell's background jobs
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    // Adds a job to the table and returns its ID
+    // Like other shells, IDs start at 1 and are reused once the highest job finishes
+    pub fn add(&mut self, child: Child, command: &str) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            child,
+            command: command.to_string(),
+            state: JobState::Running,
+        });
+
+        id
+    }
+
+    // Counts the jobs whose processes haven't finished yet, without reaping the ones that have
+    pub fn running_count(&mut self) -> usize {
+        self.jobs
+            .iter_mut()
+            .filter_map(|job| job.child.try_wait().ok())
+            .filter(Option::is_none)
+            .count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    // Finds the job for a process ID, if the process was started as a job
+    pub fn find_by_pid(&mut self, pid: u32) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.child.id() == pid)
+    }
+
+    // Removes every job that has finished, returning them along with their exit statuses
+    pub fn reap(&mut self) -> Vec<(Job, ExitStatus)> {
+        let mut finished = Vec::new();
+        let mut index = 0;
+
+        while index < self.jobs.len() {
+            match self.jobs[index].child.try_wait() {
+                Ok(Some(status)) => finished.push((self.jobs.remove(index), status)),
+                _ => index += 1,
+            }
+        }
+
+        finished
+    }
+
+    // Blocks until a job finishes, then removes it from the table
+    // Returns None if there's no job with the ID
+    pub fn wait(&mut self, id: usize) -> Option<(Job, io::Result<ExitStatus>)> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        let mut job = self.jobs.remove(index);
+        let status = job.child.wait();
+        Some((job, status))
+    }
+
+    // Blocks until every job finishes, returning them in order of their IDs
+    pub fn wait_all(&mut self) -> Vec<(Job, io::Result<ExitStatus>)> {
+        let mut jobs: Vec<Job> = self.jobs.drain(..).collect();
+        jobs.sort_by_key(|job| job.id);
+
+        jobs.into_iter()
+            .map(|mut job| {
+                let status = job.child.wait();
+                (job, status)
+            })
+            .collect()
+    }
+}
+
+// Starts an external binary without waiting for it to finish
+// Background jobs can't read from the terminal, so their stdin is closed
+pub fn spawn_background(program: &Path, arguments: &[&str]) -> io::Result<Child> {
+    process::Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::null())
+        .spawn()
+}
+
+// Picks the file that nohup's output goes to, like coreutils' nohup
+// Output only needs redirecting when it would go to the terminal, since the terminal may close
+// 'nohup.out' in the working directory is preferred, falling back to the home directory if it can't be written
+// Fails if neither can be written, rather than letting the output go to a terminal that may close
+pub fn nohup_output_path(
+    stdout_is_terminal: bool,
+    working_directory: &Path,
+    home_directory: &Path,
+    is_writable: &dyn Fn(&Path) -> bool,
+) -> Result<Option<PathBuf>, String> {
+    if !stdout_is_terminal {
+        return Ok(None);
+    }
+
+    let paths = [working_directory, home_directory].map(|directory| directory.join("nohup.out"));
+    match paths.iter().find(|path| is_writable(path)) {
+        Some(path) => Ok(Some(path.clone())),
+        None => Err(format!(
+            "Failed to open '{}' or '{}' for writing",
+            paths[0].display(),
+            paths[1].display()
+        )),
+    }
+}
+
+// Opens a file for appending output, creating it if needed
+pub fn open_for_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Starts an external binary in the background, ignoring the hangup signal sent when the terminal closes
+// If an output file is given, both stdout and stderr are appended to it
+#[cfg(unix)]
+pub fn spawn_nohup(program: &Path, arguments: &[&str], output: Option<&Path>) -> io::Result<Child> {
+    use std::os::unix::process::CommandExt;
+
+    let mut command = process::Command::new(program);
+    command.args(arguments).stdin(Stdio::null());
+
+    if let Some(output) = output {
+        let file = open_for_append(output)?;
+        command.stdout(file.try_clone()?).stderr(file);
+    }
+
+    // SAFETY: signal() is async-signal-safe, so it can be called between fork() and exec()
+    unsafe {
+        command.pre_exec(|| {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+            Ok(())
+        });
+    }
+
+    command.spawn()
+}
+
+#[cfg(not(unix))]
+pub fn spawn_nohup(
+    _program: &Path,
+    _arguments: &[&str],
+    _output: Option<&Path>,
+) -> io::Result<Child> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+// Parses a job specification such as '%2' into its job ID
+pub fn parse_job_spec(spec: &str) -> Option<usize> {
+    spec.strip_prefix('%')?.parse().ok().filter(|id| *id > 0)
+}
+
+// Gets the signal number for a name (with or without the 'SIG' prefix) or a number
+#[cfg(unix)]
+pub fn parse_signal(signal: &str) -> Option<i32> {
+    if let Ok(number) = signal.parse::<i32>() {
+        return Some(number).filter(|number| *number >= 0);
+    }
+
+    let name = signal.to_ascii_uppercase();
+    let signal = match name.strip_prefix("SIG").unwrap_or(&name) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        "TSTP" => libc::SIGTSTP,
+        _ => return None,
+    };
+
+    Some(signal)
+}
+
+#[cfg(not(unix))]
+pub fn parse_signal(_signal: &str) -> Option<i32> {
+    None
+}
+
+// Gets the state a job will be in after receiving a signal, if the signal changes it
+#[cfg(unix)]
+pub fn state_after_signal(signal: i32) -> Option<JobState> {
+    match signal {
+        libc::SIGSTOP | libc::SIGTSTP => Some(JobState::Stopped),
+        libc::SIGCONT => Some(JobState::Running),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn state_after_signal(_signal: i32) -> Option<JobState> {
+    None
+}
+
+// Sends a signal to a process
+#[cfg(unix)]
+pub fn send_signal(pid: u32, signal: i32) -> io::Result<()> {
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: kill() has no memory safety requirements, it only takes plain integers
+    match unsafe { libc::kill(pid, signal) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send_signal(_pid: u32, _signal: i32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_job_table_add_and_reap() {
+        let mut jobs = JobTable::new();
+        let first = spawn_background(Path::new("true"), &[]).unwrap();
+        let second = spawn_background(Path::new("sleep"), &["5"]).unwrap();
+
+        assert_eq!(jobs.add(first, "true"), 1);
+        assert_eq!(jobs.add(second, "sleep 5"), 2);
+        assert_eq!(jobs.iter().count(), 2);
+
+        // Wait for the quick job to finish so that it can be reaped
+        jobs.jobs[0].child.wait().unwrap();
+        let finished = jobs.reap();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0.command, "true");
+        assert_eq!(jobs.iter().count(), 1);
+
+        jobs.jobs[0].child.kill().unwrap();
+        jobs.jobs[0].child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_parse_job_spec() {
+        assert_eq!(parse_job_spec("%1"), Some(1));
+        assert_eq!(parse_job_spec("%12"), Some(12));
+        assert_eq!(parse_job_spec("%0"), None);
+        assert_eq!(parse_job_spec("%"), None);
+        assert_eq!(parse_job_spec("12"), None);
+        assert_eq!(parse_job_spec("%a"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("9"), Some(9));
+        assert_eq!(parse_signal("KILL"), Some(libc::SIGKILL));
+        assert_eq!(parse_signal("sigterm"), Some(libc::SIGTERM));
+        assert_eq!(parse_signal("STOP"), Some(libc::SIGSTOP));
+        assert_eq!(parse_signal("BOGUS"), None);
+        assert_eq!(parse_signal("-1"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_updates_job_state() {
+        let mut jobs = JobTable::new();
+        let child = spawn_background(Path::new("sleep"), &["5"]).unwrap();
+        let pid = child.id();
+        let id = jobs.add(child, "sleep 5");
+
+        for (signal, state) in [
+            (libc::SIGSTOP, JobState::Stopped),
+            (libc::SIGCONT, JobState::Running),
+        ] {
+            send_signal(pid, signal).unwrap();
+            let job = jobs.find_by_pid(pid).unwrap();
+            job.state = state_after_signal(signal).unwrap();
+            assert_eq!(job.state, state);
+        }
+
+        send_signal(pid, libc::SIGTERM).unwrap();
+        let job = jobs.get_mut(id).unwrap();
+        assert!(!job.child.wait().unwrap().success());
+        assert_eq!(jobs.reap().len(), 1);
+        assert_eq!(jobs.iter().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_job_table_wait() {
+        let mut jobs = JobTable::new();
+        let quick = spawn_background(Path::new("true"), &[]).unwrap();
+        let failing = spawn_background(Path::new("false"), &[]).unwrap();
+        let other = spawn_background(Path::new("true"), &[]).unwrap();
+        jobs.add(quick, "true");
+        jobs.add(failing, "false");
+        jobs.add(other, "true");
+
+        let (job, status) = jobs.wait(2).unwrap();
+        assert_eq!(job.command, "false");
+        assert!(!status.unwrap().success());
+        assert_eq!(jobs.iter().count(), 2);
+        assert!(jobs.wait(2).is_none());
+
+        let finished = jobs.wait_all();
+        let ids: Vec<usize> = finished.iter().map(|(job, _)| job.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert!(finished
+            .into_iter()
+            .all(|(_, status)| status.unwrap().success()));
+        assert_eq!(jobs.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_nohup_output_path() {
+        let cwd = Path::new("/work");
+        let home = Path::new("/home/user");
+
+        // Output that's already redirected is left alone
+        assert_eq!(nohup_output_path(false, cwd, home, &|_| true), Ok(None));
+        assert_eq!(nohup_output_path(false, cwd, home, &|_| false), Ok(None));
+
+        assert_eq!(
+            nohup_output_path(true, cwd, home, &|_| true),
+            Ok(Some(PathBuf::from("/work/nohup.out")))
+        );
+        assert_eq!(
+            nohup_output_path(true, cwd, home, &|path| path.starts_with(home)),
+            Ok(Some(PathBuf::from("/home/user/nohup.out")))
+        );
+        assert_eq!(
+            nohup_output_path(true, cwd, home, &|_| false),
+            Err(String::from(
+                "Failed to open '/work/nohup.out' or '/home/user/nohup.out' for writing"
+            ))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_nohup_writes_output() {
+        let dir = crate::test_utils::temp_dir("nohup");
+        let output = dir.join("nohup.out");
+
+        let mut child = spawn_nohup(
+            Path::new("sh"),
+            &["-c", "echo out; echo err >&2"],
+            Some(&output),
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "out\nerr\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
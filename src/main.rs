@@ -1,9 +1,29 @@
+mod archive;
+mod args;
+mod arithmetic;
 mod builtins;
 mod commands;
+mod completion;
+mod config;
+mod datetime;
+mod disk;
+mod editor;
 mod environment;
 mod errors;
+mod expansion;
+mod frecency;
+mod git;
+mod history;
+mod jobs;
+mod manual;
+mod parser;
 mod path;
+mod prompt;
+mod script;
 mod shell;
+#[cfg(test)]
+mod test_utils;
+mod trash;
 
 use anyhow::Result;
 
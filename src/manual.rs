@@ -0,0 +1,230 @@
+// An extended help page for a builtin command, shown by 'man'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpPage {
+    // What the command is for, in a single line
+    pub purpose: &'static str,
+    pub synopsis: &'static str,
+    // Each flag along with what it does
+    pub flags: &'static [(&'static str, &'static str)],
+    pub examples: &'static [&'static str],
+}
+
+impl HelpPage {
+    // Formats the help page in the familiar man page layout
+    pub fn render(&self, name: &str, aliases: &[String]) -> String {
+        let mut page = format!("NAME\n    {} - {}\n", name, self.purpose);
+
+        if !aliases.is_empty() {
+            page.push_str(&format!("\nALIASES\n    {}\n", aliases.join(", ")));
+        }
+
+        page.push_str(&format!("\nSYNOPSIS\n    {}\n", self.synopsis));
+
+        if !self.flags.is_empty() {
+            let width = self
+                .flags
+                .iter()
+                .map(|(flag, _)| flag.len())
+                .max()
+                .unwrap_or(0);
+            page.push_str("\nOPTIONS\n");
+            for (flag, description) in self.flags {
+                page.push_str(&format!(
+                    "    {:<width$}  {}\n",
+                    flag,
+                    description,
+                    width = width
+                ));
+            }
+        }
+
+        if !self.examples.is_empty() {
+            page.push_str("\nEXAMPLES\n");
+            for example in self.examples {
+                page.push_str(&format!("    {}\n", example));
+            }
+        }
+
+        page
+    }
+}
+
+// Gets the help pages for the builtins that have them, keyed by each command's true name
+pub fn pages() -> Vec<(&'static str, HelpPage)> {
+    vec![
+        (
+            "change-directory",
+            HelpPage {
+                purpose: "change the working directory",
+                synopsis: "change-directory [-L | -P] [-p] <path>",
+                flags: &[
+                    ("-L", "keep symlinks in the new working directory (the default)"),
+                    ("-P", "resolve symlinks to the directories they point to"),
+                    ("-p, --create", "create the directory and any missing parents first"),
+                ],
+                examples: &[
+                    "change-directory ~/projects",
+                    "cd src    # also searches the directories in $CDPATH",
+                    "cd @proj/src    # starts from a directory saved with 'bookmark proj <path>'",
+                    "cd backup.tar    # browse a tar archive read-only, and 'cd ..' at its top to leave",
+                ],
+            },
+        ),
+        (
+            "delete-file",
+            HelpPage {
+                purpose: "delete a file, or move it to the trash",
+                synopsis: "delete-file [--trash] <path>",
+                flags: &[("--trash", "move the file to the trash instead, so restore-trash can bring it back")],
+                examples: &["delete-file notes.txt", "delete-file --trash draft.md"],
+            },
+        ),
+        (
+            "find",
+            HelpPage {
+                purpose: "search for files beneath a directory",
+                synopsis: "find <path (default .)> [-name <pattern>] [-type <f|d|l>] [-exec <command> {} ;]",
+                flags: &[
+                    ("-name <pattern>", "only match file names matching a wildcard pattern"),
+                    ("-type <f|d|l>", "only match files, directories or symbolic links"),
+                    ("-exec <command> {} ;", "run a command for each match, with {} replaced by its path"),
+                ],
+                examples: &["find src -name '*.rs'", "find . -type d -exec list-directory {} ';'"],
+            },
+        ),
+        (
+            "sort",
+            HelpPage {
+                purpose: "sort the lines of a file or stdin",
+                synopsis: "sort [-r] [-n] [-u] <path (default stdin)>",
+                flags: &[
+                    ("-r", "reverse the order"),
+                    ("-n", "compare lines by their leading number"),
+                    ("-u", "only print the first of each run of equal lines"),
+                ],
+                examples: &["sort names.txt", "sort -rn sizes.txt"],
+            },
+        ),
+        (
+            "uniq",
+            HelpPage {
+                purpose: "collapse repeated adjacent lines",
+                synopsis: "uniq [-c] [-d] <path (default stdin)>",
+                flags: &[
+                    ("-c", "prefix each line with the number of times it occurred"),
+                    ("-d", "only print lines that were repeated"),
+                ],
+                examples: &["uniq log.txt", "uniq -c words.txt"],
+            },
+        ),
+        (
+            "cut",
+            HelpPage {
+                purpose: "print selected fields or characters of each line",
+                synopsis: "cut [-d <delimiter>] -f <fields> | -c <characters> <path (default stdin)>",
+                flags: &[
+                    ("-d <delimiter>", "the field delimiter (default tab)"),
+                    ("-f <fields>", "the fields to print, such as 1,3 or 2-"),
+                    ("-c <characters>", "the character positions to print"),
+                ],
+                examples: &["cut -d : -f 1 /etc/passwd", "cut -c 1-8 notes.txt"],
+            },
+        ),
+        (
+            "xargs",
+            HelpPage {
+                purpose: "run a command with arguments read from stdin",
+                synopsis: "xargs [-0] [-n <count>] <command> [arguments...]",
+                flags: &[
+                    ("-0", "arguments are separated by null characters instead of whitespace"),
+                    ("-n <count>", "pass at most this many arguments to each run of the command"),
+                ],
+                examples: &["xargs delete-file", "xargs -n 1 read-file"],
+            },
+        ),
+        (
+            "fc",
+            HelpPage {
+                purpose: "list or edit and re-run commands from the history",
+                synopsis: "fc [-l] [first] [last]",
+                flags: &[("-l", "list the entries instead of editing them")],
+                examples: &["fc", "fc -l -5", "fc 10 15"],
+            },
+        ),
+        (
+            "history",
+            HelpPage {
+                purpose: "list, edit or export the command history",
+                synopsis: "history [-c | -d <n[:m]> | --export <path> [--format plain|json]]",
+                flags: &[
+                    ("-c", "clear the history"),
+                    ("-d <n[:m]>", "delete an entry, or a range of entries, renumbering the rest"),
+                    ("--export <path>", "write the history to a file"),
+                    ("--format plain|json", "one command per line, or a JSON array with times"),
+                ],
+                examples: &[
+                    "history",
+                    "history -d 10:15",
+                    "history --export backup.json --format json",
+                ],
+            },
+        ),
+        (
+            "kill",
+            HelpPage {
+                purpose: "send a signal to a background job or process",
+                synopsis: "kill [-<signal>] <%job or pid...>",
+                flags: &[("-<signal>", "the signal to send, by number or name (default TERM)")],
+                examples: &["kill %1", "kill -9 4242", "kill -STOP %2"],
+            },
+        ),
+        (
+            "expand",
+            HelpPage {
+                purpose: "show what a command line expands to without running it",
+                synopsis: "expand [-v] <command line>",
+                flags: &[("-v", "show the words after each expansion stage")],
+                examples: &["expand 'll $HOME/*.txt'", "expand -v 'cd ~/src'"],
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_help_page() {
+        let page = HelpPage {
+            purpose: "sort the lines of a file or stdin",
+            synopsis: "sort [-r] [-n] <path>",
+            flags: &[("-r", "reverse the order"), ("-n", "compare numbers")],
+            examples: &["sort -r names.txt"],
+        };
+
+        assert_eq!(
+            page.render("sort", &[String::from("s")]),
+            "NAME\n    sort - sort the lines of a file or stdin\n\n\
+             ALIASES\n    s\n\n\
+             SYNOPSIS\n    sort [-r] [-n] <path>\n\n\
+             OPTIONS\n    -r  reverse the order\n    -n  compare numbers\n\n\
+             EXAMPLES\n    sort -r names.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_render_help_page_without_flags() {
+        let page = HelpPage {
+            purpose: "do something",
+            synopsis: "thing",
+            flags: &[],
+            examples: &[],
+        };
+
+        assert_eq!(
+            page.render("thing", &[]),
+            "NAME\n    thing - do something\n\nSYNOPSIS\n    thing\n"
+        );
+    }
+}
@@ -0,0 +1,757 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::arithmetic;
+use crate::errors::ParseError;
+use crate::path;
+
+// Represents a single piece of a command line
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Token {
+    Word(Word),
+    // An unquoted ';' or newline, ending a command
+    Separator,
+    // An unquoted '&', ending a command and running it in the background
+    Background,
+    // An unquoted ';;', ending a branch of a 'case' statement
+    CaseBreak,
+}
+
+// Represents a word whose variables haven't been expanded yet
+// Quoting is remembered so that later expansions only apply to the unquoted parts
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum WordPart {
+    // Unquoted text
+    Literal(String),
+    // Text from inside quotes (or escaped with a backslash)
+    Quoted(String),
+    // A variable reference, such as $NAME or ${NAME}
+    // The text inside the braces is kept as it is, including forms like ${NAME:-default} (see Parameter)
+    Variable(String),
+    // An arithmetic expansion, such as $((NAME + 1)), holding the expression inside the parentheses
+    Arithmetic(String),
+}
+
+impl WordPart {
+    // Gets the text of the part, with variables replaced by their values and arithmetic by its result
+    fn expand(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        match self {
+            Self::Literal(text) | Self::Quoted(text) => text.clone(),
+            Self::Variable(text) => Parameter::parse(text).value(lookup).unwrap_or_default(),
+            Self::Arithmetic(expression) => arithmetic::evaluate(expression, lookup)
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// A variable reference, along with what to do when the variable is unset or empty
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Parameter<'a> {
+    // $NAME or ${NAME}
+    Plain(&'a str),
+    // ${NAME:-word} uses the word instead
+    Default(&'a str, &'a str),
+    // ${NAME:=word} sets the variable to the word, then uses it
+    Assign(&'a str, &'a str),
+    // ${NAME:?message} stops the command with an error
+    Error(&'a str, &'a str),
+}
+
+impl<'a> Parameter<'a> {
+    // Splits the text of a variable reference into the name and the form it uses
+    pub fn parse(text: &'a str) -> Self {
+        let (name, operator, word) = match text.split_once(':') {
+            Some((name, rest)) => match rest.chars().next() {
+                Some(operator) => (name, operator, &rest[operator.len_utf8()..]),
+                None => return Self::Plain(text),
+            },
+            None => return Self::Plain(text),
+        };
+
+        match operator {
+            '-' => Self::Default(name, word),
+            '=' => Self::Assign(name, word),
+            '?' => Self::Error(name, word),
+            _ => Self::Plain(text),
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        match self {
+            Self::Plain(name)
+            | Self::Default(name, _)
+            | Self::Assign(name, _)
+            | Self::Error(name, _) => name,
+        }
+    }
+
+    // Gets the value of the reference, using the word of ${NAME:-word} and ${NAME:=word} if the variable is unset or empty
+    // The word can refer to other variables ('${EDITOR:-$VISUAL}')
+    pub fn value(&self, lookup: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+        let value = lookup(self.name()).filter(|value| !value.is_empty());
+        match self {
+            Self::Default(_, word) | Self::Assign(_, word) if value.is_none() => {
+                Some(expand_text(word, lookup))
+            }
+            _ => value,
+        }
+    }
+}
+
+impl Word {
+    // Creates a word from unquoted text
+    pub fn literal(text: &str) -> Self {
+        Self {
+            parts: vec![WordPart::Literal(text.to_string())],
+        }
+    }
+
+    // Gets the text of a word made up of a single unquoted literal, which is how keywords are written
+    pub fn as_plain(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    // Checks whether the unquoted parts of the word contain wildcards
+    pub fn has_glob(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            WordPart::Literal(text) => path::is_glob(text),
+            _ => false,
+        })
+    }
+
+    // Joins the parts of the word, replacing variables with their values (or nothing if they're unset)
+    // and arithmetic expansions with their results (or nothing if they can't be evaluated)
+    pub fn expand(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        self.parts.iter().map(|part| part.expand(lookup)).collect()
+    }
+
+    // Expands the word like expand(), except that $@ becomes a separate word for each argument, like "$@" in sh
+    // Text before and after $@ is joined to the first and last arguments, and a word that is
+    // only $@ expands to no words at all when there are no arguments
+    pub fn expand_fields(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<String>,
+        arguments: &[String],
+    ) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        // Whether the word has $@, and nothing else except the empty quotes that "$@" starts with
+        let mut has_arguments = false;
+        let mut only_arguments = true;
+
+        for part in &self.parts {
+            match part {
+                WordPart::Quoted(text) if text.is_empty() => (),
+                WordPart::Variable(text) if Parameter::parse(text) == Parameter::Plain("@") => {
+                    has_arguments = true;
+                    for (index, argument) in arguments.iter().enumerate() {
+                        if index > 0 {
+                            fields.push(String::new());
+                        }
+                        fields.last_mut().unwrap().push_str(argument);
+                    }
+                }
+                part => {
+                    only_arguments = false;
+                    fields.last_mut().unwrap().push_str(&part.expand(lookup));
+                }
+            }
+        }
+
+        match has_arguments && only_arguments && arguments.is_empty() {
+            true => Vec::new(),
+            false => fields,
+        }
+    }
+
+    // Removes a character from the end of the word if it's unquoted there, like the ')' after a case pattern
+    pub fn strip_unquoted_suffix(&self, suffix: char) -> Option<Word> {
+        let mut word = self.clone();
+        match word.parts.last_mut() {
+            Some(WordPart::Literal(text)) if text.ends_with(suffix) => {
+                text.pop();
+                if text.is_empty() {
+                    word.parts.pop();
+                }
+                Some(word)
+            }
+            _ => None,
+        }
+    }
+
+    // Splits the word at each unquoted occurrence of a character, like the '|' between case patterns
+    pub fn split_unquoted(&self, separator: char) -> Vec<Word> {
+        let mut words = vec![Word::default()];
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => {
+                    for (index, piece) in text.split(separator).enumerate() {
+                        if index > 0 {
+                            words.push(Word::default());
+                        }
+                        if !piece.is_empty() {
+                            words
+                                .last_mut()
+                                .unwrap()
+                                .parts
+                                .push(WordPart::Literal(piece.to_string()));
+                        }
+                    }
+                }
+                part => words.last_mut().unwrap().parts.push(part.clone()),
+            }
+        }
+        words
+    }
+
+    fn push(&mut self, c: char, quoted: bool) {
+        match (self.parts.last_mut(), quoted) {
+            (Some(WordPart::Literal(text)), false) | (Some(WordPart::Quoted(text)), true) => {
+                text.push(c)
+            }
+            (_, false) => self.parts.push(WordPart::Literal(c.to_string())),
+            (_, true) => self.parts.push(WordPart::Quoted(c.to_string())),
+        }
+    }
+
+    // Makes sure a word exists even if nothing was added to it, such as for ''
+    fn push_empty_quoted(&mut self) {
+        if self.parts.is_empty() {
+            self.parts.push(WordPart::Quoted(String::new()));
+        }
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => write!(f, "{}", text)?,
+                WordPart::Quoted(text) => write!(f, "{}", quote(text))?,
+                WordPart::Variable(name) => write!(f, "${{{}}}", name)?,
+                WordPart::Arithmetic(expression) => write!(f, "$(({}))", expression)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Splits a command line into tokens, following the usual shell quoting rules:
+// single quotes preserve everything literally, double quotes preserve everything except
+// variables and backslash escapes of '"', '\' and '$', and a backslash outside of quotes escapes the next character
+// An unquoted '#' at the start of a word begins a comment that runs to the end of the line
+pub fn tokenize(line: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut word = Word::default();
+    // Tracks whether a word has started, so that quoted empty strings ('') still count as words
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    // Ends the current word (if there is one) so that a new token can begin
+    let finish_word = |tokens: &mut Vec<Token>, word: &mut Word, in_word: &mut bool| {
+        if *in_word {
+            tokens.push(Token::Word(std::mem::take(word)));
+            *in_word = false;
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' if chars.next_if_eq(&';').is_some() => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::CaseBreak);
+            }
+            ';' | '\n' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Separator);
+            }
+            '&' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Background);
+            }
+            c if c.is_whitespace() => finish_word(&mut tokens, &mut word, &mut in_word),
+            '#' if !in_word => {
+                // Skip to the end of the line, but keep the newline as a separator
+                if chars.by_ref().any(|c| c == '\n') {
+                    tokens.push(Token::Separator);
+                }
+            }
+            '\'' => {
+                in_word = true;
+                word.push_empty_quoted();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c, true),
+                        None => return Err(ParseError::UnterminatedQuote),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                word.push_empty_quoted();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$')) => word.push(c, true),
+                            Some(c) => {
+                                word.push('\\', true);
+                                word.push(c, true);
+                            }
+                            None => return Err(ParseError::UnterminatedQuote),
+                        },
+                        Some('$') => read_variable(&mut chars, &mut word, true)?,
+                        Some(c) => word.push(c, true),
+                        None => return Err(ParseError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // An escaped newline continues the command on the next line
+                Some('\n') => (),
+                Some(c) => {
+                    in_word = true;
+                    word.push(c, true);
+                }
+                None => {
+                    in_word = true;
+                    word.push('\\', false);
+                }
+            },
+            '$' => {
+                in_word = true;
+                read_variable(&mut chars, &mut word, false)?;
+            }
+            c => {
+                in_word = true;
+                word.push(c, false);
+            }
+        }
+    }
+
+    finish_word(&mut tokens, &mut word, &mut in_word);
+    Ok(tokens)
+}
+
+// Reads a variable reference after a '$', in either the $NAME or ${NAME} form, or an arithmetic expansion ($((...)))
+// Special parameters ($?, $@, $#) and positional parameters ($1 to $9, or ${10} and above) are single characters
+// A '$' that isn't followed by a name is kept as it is
+fn read_variable(
+    chars: &mut Peekable<Chars>,
+    word: &mut Word,
+    quoted: bool,
+) -> Result<(), ParseError> {
+    if chars.peek() == Some(&'(') && chars.clone().nth(1) == Some('(') {
+        chars.nth(1);
+        let expression = read_arithmetic(chars)?;
+        word.parts.push(WordPart::Arithmetic(expression));
+        return Ok(());
+    }
+
+    let is_name_start = |c: &char| c.is_ascii_alphabetic() || *c == '_';
+    let is_name_char = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+
+    let mut name = String::new();
+    if chars.next_if_eq(&'{').is_some() {
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => name.push(c),
+                None => return Err(ParseError::UnterminatedQuote),
+            }
+        }
+    } else if let Some(c) = chars.next_if(|c| "?@#".contains(*c) || c.is_ascii_digit()) {
+        name.push(c);
+    } else if chars.peek().is_some_and(is_name_start) {
+        while let Some(c) = chars.next_if(is_name_char) {
+            name.push(c);
+        }
+    } else {
+        word.push('$', quoted);
+        return Ok(());
+    }
+
+    word.parts.push(WordPart::Variable(name));
+    Ok(())
+}
+
+// Reads the expression of an arithmetic expansion, up to the '))' that closes it
+// Parentheses inside the expression have to be balanced
+fn read_arithmetic(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut expression = String::new();
+    let mut depth = 0;
+
+    loop {
+        match chars.next() {
+            Some('(') => {
+                depth += 1;
+                expression.push('(');
+            }
+            Some(')') if depth > 0 => {
+                depth -= 1;
+                expression.push(')');
+            }
+            Some(')') => match chars.next_if_eq(&')') {
+                Some(_) => return Ok(expression),
+                None => return Err(ParseError::UnexpectedToken(String::from(")"))),
+            },
+            Some(c) => expression.push(c),
+            None => return Err(ParseError::UnexpectedEnd),
+        }
+    }
+}
+
+// Parses text as if it were inside double quotes, so only variables are special
+// A backslash before a '$' keeps it literal
+pub fn variable_word(text: &str) -> Result<Word, ParseError> {
+    let mut word = Word::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.next_if_eq(&'$').is_some() => word.push('$', true),
+            '$' => read_variable(&mut chars, &mut word, true)?,
+            c => word.push(c, true),
+        }
+    }
+
+    Ok(word)
+}
+
+// Expands the variables in text as if it were inside double quotes, keeping text that can't be parsed as it is
+pub fn expand_text(text: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    match variable_word(text) {
+        Ok(word) => word.expand(lookup),
+        Err(_) => text.to_string(),
+    }
+}
+
+// Checks whether a string is a valid variable name (letters, digits and underscores, not starting with a digit)
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// Quotes a word so that tokenize() turns it back into the same single word
+// Words made up entirely of safe characters are left as they are
+pub fn quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        return word.to_string();
+    }
+
+    // Single quotes can't be escaped inside single quotes, so close the quotes, add an escaped quote, and reopen them
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expands the words of a command line without any variables set
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(word) => word.expand(&|_| None),
+                token => panic!("unexpected token {:?}", token),
+            })
+            .collect()
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(Word::literal(text))
+    }
+
+    #[test]
+    fn test_tokenize_whitespace() {
+        assert_eq!(words("  cd   /tmp \t"), ["cd", "/tmp"]);
+        assert!(tokenize("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_quotes() {
+        assert_eq!(
+            words("echo 'a  b' \"c \\\"d\\\"\" e\\ f"),
+            ["echo", "a  b", "c \"d\"", "e f"]
+        );
+        assert_eq!(words("x='' y\"z\""), ["x=", "yz"]);
+        assert_eq!(words("''"), [""]);
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_quote() {
+        assert_eq!(tokenize("echo 'oops"), Err(ParseError::UnterminatedQuote));
+        assert_eq!(tokenize("echo \"oops"), Err(ParseError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn test_case_pattern_words() {
+        let pattern = match &tokenize("a*|'b|c'|d)").unwrap()[0] {
+            Token::Word(word) => word.clone(),
+            token => panic!("unexpected token {:?}", token),
+        };
+
+        let alternatives = pattern
+            .strip_unquoted_suffix(')')
+            .unwrap()
+            .split_unquoted('|');
+        let texts: Vec<String> = alternatives
+            .iter()
+            .map(|word| word.expand(&|_| None))
+            .collect();
+        assert_eq!(texts, ["a*", "b|c", "d"]);
+        assert!(alternatives[0].has_glob());
+        assert_eq!(Word::literal("a").strip_unquoted_suffix(')'), None);
+        assert_eq!(
+            tokenize("a;; b").unwrap(),
+            vec![word("a"), Token::CaseBreak, word("b")]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        assert_eq!(
+            tokenize("a; b\nc & d ';' \\&").unwrap(),
+            vec![
+                word("a"),
+                Token::Separator,
+                word("b"),
+                Token::Separator,
+                word("c"),
+                Token::Background,
+                word("d"),
+                Token::Word(Word {
+                    parts: vec![WordPart::Quoted(String::from(";"))]
+                }),
+                Token::Word(Word {
+                    parts: vec![WordPart::Quoted(String::from("&"))]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_variables() {
+        let tokens = tokenize(r#"$A x${B}y "$C-\$D" '$E' $ a$"#).unwrap();
+        let expanded: Vec<String> = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.expand(&|name| Some(name.to_lowercase())),
+                token => panic!("unexpected token {:?}", token),
+            })
+            .collect();
+
+        assert_eq!(expanded, ["a", "xby", "c-$D", "$E", "$", "a$"]);
+        assert_eq!(tokenize("${A"), Err(ParseError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn test_tokenize_positional_parameters() {
+        let tokens = tokenize("$1 $12 ${12} $@ $# $?").unwrap();
+        let expanded: Vec<String> = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.expand(&|name| Some(format!("<{}>", name))),
+                token => panic!("unexpected token {:?}", token),
+            })
+            .collect();
+
+        // Only a single digit is read without braces
+        assert_eq!(expanded, ["<1>", "<1>2", "<12>", "<@>", "<#>", "<?>"]);
+    }
+
+    #[test]
+    fn test_expand_fields() {
+        let lookup = |name: &str| Some(format!("<{}>", name));
+        let arguments = ["a b", "c"].map(String::from);
+        let expand = |line: &str, arguments: &[String]| match &tokenize(line).unwrap()[0] {
+            Token::Word(word) => word.expand_fields(&lookup, arguments),
+            token => panic!("unexpected token {:?}", token),
+        };
+
+        assert_eq!(expand("$@", &arguments), ["a b", "c"]);
+        assert_eq!(expand("\"$@\"", &arguments), ["a b", "c"]);
+        assert_eq!(expand("[${@}]", &arguments), ["[a b", "c]"]);
+        assert_eq!(expand("$1$@", &arguments), ["<1>a b", "c"]);
+        // Other variables stay as a single word
+        assert_eq!(expand("$#", &arguments), ["<#>"]);
+        assert_eq!(expand("$@", &[]), Vec::<String>::new());
+        assert_eq!(expand("\"$@\"", &[]), Vec::<String>::new());
+        assert_eq!(expand("x$@", &[]), ["x"]);
+        assert_eq!(expand("''", &[]), [""]);
+    }
+
+    #[test]
+    fn test_variable_word() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some(String::from("/home/user")),
+            "?" => Some(String::from("3")),
+            _ => None,
+        };
+        let expand = |text: &str| variable_word(text).unwrap().expand(&lookup);
+
+        assert_eq!(
+            expand("$HOME/'docs' \"quoted\""),
+            "/home/user/'docs' \"quoted\""
+        );
+        assert_eq!(
+            expand("${HOME}x $UNSET. status $?"),
+            "/home/userx . status 3"
+        );
+        assert_eq!(expand("cost: \\$5 $"), "cost: $5 $");
+        assert_eq!(variable_word("${HOME"), Err(ParseError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn test_tokenize_arithmetic() {
+        assert_eq!(
+            tokenize("echo $((1 + (2 * 3)))x").unwrap(),
+            [
+                word("echo"),
+                Token::Word(Word {
+                    parts: vec![
+                        WordPart::Arithmetic(String::from("1 + (2 * 3)")),
+                        WordPart::Literal(String::from("x")),
+                    ]
+                })
+            ]
+        );
+        assert_eq!(
+            words("echo \"$(( 7 / 2 ))\" $((-(2+3)))"),
+            ["echo", "3", "-5"]
+        );
+        // A single '$(' isn't an arithmetic expansion
+        assert_eq!(words("echo $(x"), ["echo", "$(x"]);
+        assert_eq!(tokenize("echo $((1 + 2"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            tokenize("echo $((1 + 2) )"),
+            Err(ParseError::UnexpectedToken(String::from(")")))
+        );
+        assert_eq!(
+            Word {
+                parts: vec![WordPart::Arithmetic(String::from("x * 2"))]
+            }
+            .to_string(),
+            "$((x * 2))"
+        );
+
+        let lookup = |name: &str| (name == "COUNT").then(|| String::from("4"));
+        let expand = |line: &str| match &tokenize(line).unwrap()[0] {
+            Token::Word(word) => word.expand(&lookup),
+            token => panic!("unexpected token {:?}", token),
+        };
+        assert_eq!(expand("$((COUNT * 2 + $COUNT))"), "12");
+        // Errors (reported before the command runs) leave nothing behind
+        assert_eq!(expand("[$((COUNT / 0))]"), "[]");
+    }
+
+    #[test]
+    fn test_parse_parameter() {
+        assert_eq!(Parameter::parse("HOME"), Parameter::Plain("HOME"));
+        assert_eq!(
+            Parameter::parse("EDITOR:-vi -n"),
+            Parameter::Default("EDITOR", "vi -n")
+        );
+        assert_eq!(Parameter::parse("N:=0"), Parameter::Assign("N", "0"));
+        assert_eq!(Parameter::parse("FILE:?"), Parameter::Error("FILE", ""));
+        // Forms that aren't supported are looked up as they are
+        assert_eq!(Parameter::parse("A:+b"), Parameter::Plain("A:+b"));
+        assert_eq!(Parameter::parse("A:"), Parameter::Plain("A:"));
+        assert_eq!(Parameter::parse("A:\u{e9}"), Parameter::Plain("A:\u{e9}"));
+        assert_eq!(
+            Parameter::parse("A:-\u{e9}t\u{e9}"),
+            Parameter::Default("A", "\u{e9}t\u{e9}")
+        );
+    }
+
+    #[test]
+    fn test_expand_default_values() {
+        let lookup = |name: &str| match name {
+            "SET" => Some(String::from("value")),
+            "EMPTY" => Some(String::new()),
+            "VISUAL" => Some(String::from("vim")),
+            _ => None,
+        };
+        let expand = |line: &str| match &tokenize(line).unwrap()[0] {
+            Token::Word(word) => word.expand(&lookup),
+            token => panic!("unexpected token {:?}", token),
+        };
+
+        assert_eq!(expand("${SET:-default}"), "value");
+        assert_eq!(expand("${UNSET:-default}"), "default");
+        assert_eq!(expand("${EMPTY:-default}"), "default");
+        assert_eq!(expand("${UNSET:=assigned}"), "assigned");
+        assert_eq!(expand("${SET:?missing}"), "value");
+        assert_eq!(expand("\"${UNSET:-a b}\""), "a b");
+        assert_eq!(expand("${EDITOR:-$VISUAL}.x"), "vim.x");
+    }
+
+    #[test]
+    fn test_word_as_plain() {
+        let plain = |line: &str| match &tokenize(line).unwrap()[0] {
+            Token::Word(word) => word.as_plain().map(String::from),
+            token => panic!("unexpected token {:?}", token),
+        };
+
+        assert_eq!(plain("fi"), Some(String::from("fi")));
+        assert_eq!(plain("'fi'"), None);
+        assert_eq!(plain("$fi"), None);
+    }
+
+    #[test]
+    fn test_word_has_glob() {
+        let has_glob = |line: &str| match &tokenize(line).unwrap()[0] {
+            Token::Word(word) => word.has_glob(),
+            token => panic!("unexpected token {:?}", token),
+        };
+
+        assert!(has_glob("*.txt"));
+        assert!(has_glob("'dir name'/file?"));
+        assert!(!has_glob("'*.txt'"));
+        assert!(!has_glob("\\*"));
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        assert_eq!(
+            tokenize("a # comment ; b\nc d#e").unwrap(),
+            vec![word("a"), Token::Separator, word("c"), word("d#e"),]
+        );
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        for word in [
+            "plain",
+            "two words",
+            "it's",
+            "$HOME",
+            "",
+            "a\"b\\c",
+            "a;b&c",
+        ] {
+            assert_eq!(words(&quote(word)), [word]);
+        }
+
+        assert_eq!(quote("plain/path.txt"), "plain/path.txt");
+        assert_eq!(quote("two words"), "'two words'");
+    }
+}
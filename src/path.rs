@@ -1,14 +1,17 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+use std::env;
 use std::fmt::{Display, Formatter};
-use std::fs::canonicalize;
-use std::path::PathBuf;
+use std::fs::{self, canonicalize};
+use std::path::{Component, Path as StdPath, PathBuf};
 
 use anyhow::Result;
 
 use crate::errors::ShellError;
 
 // Wrapper class for a directory path string
+#[derive(Clone)]
 pub struct Path {
     absolute_path: PathBuf,
     home_directory: PathBuf,
@@ -25,8 +28,8 @@ impl Display for Path {
 impl Path {
     // Safely constructs a new Path from a given directory, taking into account
     // the user's home directory so it can be collapsed into a shorthand '~'
-    pub fn new(absolute_path: PathBuf, home_directory: &PathBuf) -> Result<Self> {
-        let home_directory = home_directory.clone();
+    pub fn new(absolute_path: PathBuf, home_directory: &StdPath) -> Result<Self> {
+        let home_directory = home_directory.to_path_buf();
         let mut path = Self {
             absolute_path,
             home_directory,
@@ -39,7 +42,7 @@ impl Path {
     }
 
     // Attempts to construct a new Path from a given path string by resolving it to an absolute path
-    fn from_str_path(path: &str, home_directory: &PathBuf) -> Result<Self> {
+    fn from_str_path(path: &str, home_directory: &StdPath) -> Result<Self> {
         match resolve(path, home_directory) {
             Some(absolute_path) => Ok(Self::new(absolute_path, home_directory)?),
             None => Err(ShellError::UnknownDirectory.into()),
@@ -105,18 +108,40 @@ impl Path {
             truncated_directories = directories;
         }
 
-        let truncated_directories = truncated_directories.join("/");
-        Ok(self.shortened_path = truncated_directories)
+        self.shortened_path = truncated_directories.join("/");
+        Ok(())
     }
 
-    // Updates the Path using a new absolute path
+    // Updates the Path using a new path, keeping any symlinks in it (like 'cd -L')
+    // '..' goes back up through a symlink rather than to the parent of its target
+    // Falls back to the physical path if the logical one doesn't exist
     pub fn set_path(&mut self, new_path: &str) -> Result<()> {
+        let new_absolute_path =
+            resolve_logical(new_path, &self.absolute_path, &self.home_directory)
+                .or_else(|| resolve(new_path, &self.home_directory));
+
+        match new_absolute_path {
+            Some(path) => self.change_to(path),
+            None => Err(ShellError::UnknownDirectory.into()),
+        }
+    }
+
+    // Updates the Path using a new path, resolving every symlink in it (like 'cd -P')
+    pub fn set_physical_path(&mut self, new_path: &str) -> Result<()> {
         let new_absolute_path = match resolve(new_path, &self.home_directory) {
             Some(path) => path,
             // ? Should this be a FailedToCanonicalizePath error?
             None => return Err(ShellError::UnknownDirectory.into()),
         };
 
+        self.change_to(new_absolute_path)
+    }
+
+    fn change_to(&mut self, new_absolute_path: PathBuf) -> Result<()> {
+        if !new_absolute_path.is_dir() {
+            return Err(ShellError::UnknownDirectory.into());
+        }
+
         self.absolute_path = new_absolute_path;
         self.update_shortened_path()?;
 
@@ -126,7 +151,7 @@ impl Path {
 
 // Attempts to convert a path string into a canonicalized absolute path
 // ? Should this be a Result instead of an Option?
-pub fn resolve(path: &str, home_directory: &PathBuf) -> Option<PathBuf> {
+pub fn resolve(path: &str, home_directory: &StdPath) -> Option<PathBuf> {
     // The home directory shorthand must be expanded before resolving the path,
     // because PathBuf is not user-aware and only uses absolute and relative paths
     let expanded_path = match expand_home(path, home_directory) {
@@ -149,7 +174,45 @@ pub fn resolve(path: &str, home_directory: &PathBuf) -> Option<PathBuf> {
     }
 }
 
-fn expand_home(path: &str, home_directory: &PathBuf) -> Result<String> {
+// Resolves a directory path relative to a base directory without following symlinks,
+// by removing '.' and '..' components lexically instead
+pub fn resolve_logical(
+    path: &str,
+    base_directory: &StdPath,
+    home_directory: &StdPath,
+) -> Option<PathBuf> {
+    let expanded_path = expand_home(path, home_directory).ok()?;
+    let mut logical_path = PathBuf::new();
+
+    // Joining an absolute path replaces the base directory entirely
+    for component in base_directory.join(expanded_path).components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                logical_path.pop();
+            }
+            component => logical_path.push(component),
+        }
+    }
+
+    logical_path.is_dir().then_some(logical_path)
+}
+
+// Resolves a path that may not exist yet (such as a file about to be created)
+// The parent directory must exist, and is canonicalized like resolve() does
+pub fn resolve_new(path: &str, home_directory: &StdPath) -> Option<PathBuf> {
+    let expanded_path = PathBuf::from(expand_home(path, home_directory).ok()?);
+    let file_name = expanded_path.file_name()?;
+    let parent = match expanded_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    Some(canonicalize(parent).ok()?.join(file_name))
+}
+
+// Replaces the '~' shorthand with the home directory
+pub fn expand_home(path: &str, home_directory: &StdPath) -> Result<String> {
     if path.starts_with("~") {
         Ok(path.replace(
             "~",
@@ -162,3 +225,299 @@ fn expand_home(path: &str, home_directory: &PathBuf) -> Result<String> {
         Ok(path.to_string())
     }
 }
+
+// Searches a colon-separated list of directories (like $CDPATH) for a subdirectory with a given name
+// Empty entries in the list refer to the current directory
+pub fn search_cdpath(name: &str, cdpath: &str, home_directory: &StdPath) -> Option<PathBuf> {
+    cdpath.split(':').find_map(|parent| {
+        let parent = match parent {
+            "" => ".",
+            _ => parent,
+        };
+
+        resolve(&format!("{}/{}", parent, name), home_directory).filter(|path| path.is_dir())
+    })
+}
+
+// Finds an executable by searching a colon-separated list of directories (like $PATH)
+// Names containing a '/' are treated as paths and are not searched for
+pub fn find_executable(name: &str, search_path: &str) -> Option<PathBuf> {
+    executable_candidates(name, search_path).find(|path| is_executable(path))
+}
+
+// Finds every match for an executable in the search path, in order, including ones shadowed by earlier matches
+pub fn find_all_executables(name: &str, search_path: &str) -> Vec<PathBuf> {
+    executable_candidates(name, search_path)
+        .filter(|path| is_executable(path))
+        .collect()
+}
+
+// Lists the paths an executable name could refer to, in the order they should be tried
+fn executable_candidates<'a>(
+    name: &'a str,
+    search_path: &'a str,
+) -> Box<dyn Iterator<Item = PathBuf> + 'a> {
+    if name.contains('/') {
+        return Box::new(std::iter::once(PathBuf::from(name)));
+    }
+
+    Box::new(
+        search_path
+            .split(':')
+            .filter(|directory| !directory.is_empty())
+            .map(move |directory| StdPath::new(directory).join(name)),
+    )
+}
+
+// Checks whether a path is a file that the user is allowed to execute
+#[cfg(unix)]
+pub fn is_executable(path: &StdPath) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &StdPath) -> bool {
+    path.is_file()
+}
+
+// Checks whether two paths lead to the same file (following symlinks), including hard links to it
+#[cfg(unix)]
+pub fn is_same_file(a: &StdPath, b: &StdPath) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_same_file(a: &StdPath, b: &StdPath) -> bool {
+    matches!((canonicalize(a), canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+// Checks whether a string contains any wildcard characters
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('?')
+}
+
+// Checks whether a name matches a wildcard pattern, where '*' matches any run of characters
+// and '?' matches exactly one character
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last '*' seen in the pattern, and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // On a mismatch, let the last '*' swallow one more character and try again
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star + 1;
+                    n = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Any trailing '*'s can match the empty string
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Expands a wildcard pattern into the sorted list of matching paths
+// Only the final path component may contain wildcards, and hidden files are only
+// matched if the pattern itself starts with a '.'
+pub fn expand_glob(pattern: &str, home_directory: &StdPath) -> Vec<String> {
+    let (prefix, file_pattern) = match pattern.rfind('/') {
+        Some(index) => pattern.split_at(index + 1),
+        None => ("", pattern),
+    };
+
+    let directory = match prefix {
+        "" => match env::current_dir() {
+            Ok(dir) => dir,
+            Err(_) => return Vec::new(),
+        },
+        _ => match resolve(prefix, home_directory) {
+            Some(dir) => dir,
+            None => return Vec::new(),
+        },
+    };
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut matches: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') || file_pattern.starts_with('.'))
+        .filter(|name| glob_match(file_pattern, name))
+        .map(|name| format!("{}{}", prefix, name))
+        .collect();
+
+    matches.sort();
+    matches
+}
+
+// Recursively collects every path beneath (and including) a root, in sorted pre-order
+// Symbolic links to directories are only descended into if follow_symlinks is set, and
+// each directory is visited at most once to guard against symlink loops
+pub fn walk(root: &StdPath, follow_symlinks: bool) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    walk_each(root, follow_symlinks, &mut |path| {
+        paths.push(path.to_path_buf());
+        true
+    });
+    paths
+}
+
+// Calls a function on every path that walk() would collect, as each one is reached
+// Nothing beneath a directory is visited if the function returns false for it
+pub fn walk_each(root: &StdPath, follow_symlinks: bool, visit: &mut dyn FnMut(&StdPath) -> bool) {
+    let mut visited = HashSet::new();
+    walk_into(root, follow_symlinks, &mut visited, visit);
+}
+
+fn walk_into(
+    path: &StdPath,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+    visit: &mut dyn FnMut(&StdPath) -> bool,
+) {
+    if !visit(path) {
+        return;
+    }
+
+    let is_symlink = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    if !path.is_dir() || (is_symlink && !follow_symlinks) {
+        return;
+    }
+
+    // Directories are tracked by their canonical path, so a link back to an ancestor is skipped
+    match canonicalize(path) {
+        Ok(canonical) if !visited.contains(&canonical) => visited.insert(canonical),
+        _ => return,
+    };
+
+    let mut children: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+
+    children.sort();
+    for child in children {
+        walk_into(&child, follow_symlinks, visited, visit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match_star() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(glob_match("*.txt", ".txt"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("*.txt", "notes.md"));
+    }
+
+    #[test]
+    fn test_glob_match_question_mark() {
+        assert!(glob_match("file?.rs", "file1.rs"));
+        assert!(!glob_match("file?.rs", "file.rs"));
+        assert!(!glob_match("file?.rs", "file10.rs"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::test_utils::temp_dir("find-executable");
+
+        let executable = dir.join("runnable");
+        fs::write(&executable, "").unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("not-runnable"), "").unwrap();
+
+        let search_path = format!("/invalid/path::{}", dir.display());
+        assert_eq!(find_executable("runnable", &search_path), Some(executable));
+        assert_eq!(find_executable("not-runnable", &search_path), None);
+        assert_eq!(find_executable("missing", &search_path), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_all_executables() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::test_utils::temp_dir("find-all-executables");
+        let mut expected = Vec::new();
+        for name in ["first", "second"] {
+            let executable = dir.join(name).join("runnable");
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(&executable, "").unwrap();
+            fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+            expected.push(executable);
+        }
+
+        let search_path = format!("{0}/first:/invalid/path:{0}/second", dir.display());
+        assert_eq!(find_all_executables("runnable", &search_path), expected);
+        assert!(find_all_executables("missing", &search_path).is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_walk_each_skips_directories() {
+        let dir = crate::test_utils::temp_dir("walk-each");
+        fs::create_dir_all(dir.join("kept").join("inner")).unwrap();
+        fs::create_dir_all(dir.join("skipped").join("inner")).unwrap();
+        fs::write(dir.join("skipped").join("file"), "").unwrap();
+
+        let mut visited = Vec::new();
+        walk_each(&dir, false, &mut |path| {
+            visited.push(path.strip_prefix(&dir).unwrap().to_path_buf());
+            !path.ends_with("skipped")
+        });
+        assert_eq!(
+            visited,
+            ["", "kept", "kept/inner", "skipped"].map(PathBuf::from)
+        );
+        assert_eq!(walk(&dir, false).len(), 6);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_resolve_new() {
+        let home = env::temp_dir();
+        let resolved = resolve_new("~/rush-not-created-yet", &home).unwrap();
+        assert_eq!(
+            resolved,
+            canonicalize(&home).unwrap().join("rush-not-created-yet")
+        );
+        assert_eq!(resolve_new("/rush-no-such-dir/file", &home), None);
+    }
+}
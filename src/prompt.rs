@@ -0,0 +1,120 @@
+use colored::Colorize;
+
+use crate::editor;
+
+// The prompt used when $PROMPT isn't set
+pub const DEFAULT_PROMPT: &str = "\\u on \\w\\n\\$ ";
+
+// Everything the prompt can display, gathered from the shell before rendering
+pub struct PromptInfo<'a> {
+    pub user: &'a str,
+    pub working_directory: &'a str,
+    pub job_count: usize,
+    pub success: bool,
+}
+
+// Renders a prompt format string, replacing its placeholders:
+// \u (user), \w (working directory), \j (background job count, if any),
+// \$ (status indicator), \n (newline) and \\ (backslash)
+// Unknown placeholders are left as they are
+pub fn render(format: &str, info: &PromptInfo) -> String {
+    let mut rendered = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            rendered.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('u') => rendered.push_str(&info.user.blue().to_string()),
+            Some('w') => rendered.push_str(&info.working_directory.green().to_string()),
+            // Nothing is shown when there are no jobs, so the prompt stays uncluttered
+            Some('j') if info.job_count > 0 => rendered.push_str(&format!("[{}]", info.job_count)),
+            Some('j') => (),
+            Some('$') => rendered.push_str(&match info.success {
+                true => "❯".bright_green().bold().to_string(),
+                false => "❯".bright_red().bold().to_string(),
+            }),
+            Some('n') => rendered.push('\n'),
+            Some('\\') => rendered.push('\\'),
+            Some(other) => {
+                rendered.push('\\');
+                rendered.push(other);
+            }
+            None => rendered.push('\\'),
+        }
+    }
+
+    rendered
+}
+
+// Builds the terminal sequence that draws a right prompt at the end of the left prompt's last line,
+// putting the cursor back where the left prompt ended
+// Returns None when the width isn't known or both don't fit on the line with a space between them
+pub fn right_align(left: &str, right: &str, width: Option<usize>) -> Option<String> {
+    let width = width?;
+    let left_width = editor::visible_width(left.rsplit('\n').next().unwrap_or_default());
+    let right_width = editor::visible_width(right);
+    if right_width == 0 || left_width + right_width + 1 > width {
+        return None;
+    }
+
+    // Save the cursor, move to the column where the right prompt starts, then restore the cursor
+    Some(format!(
+        "\x1b7\x1b[{}G{}\x1b8",
+        width - right_width + 1,
+        right
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(job_count: usize) -> PromptInfo<'static> {
+        PromptInfo {
+            user: "user",
+            working_directory: "~/projects",
+            job_count,
+            success: true,
+        }
+    }
+
+    #[test]
+    fn test_render_job_count() {
+        assert_eq!(render("jobs: \\j", &info(2)), "jobs: [2]");
+        assert_eq!(render("jobs: \\j", &info(0)), "jobs: ");
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let rendered = render("\\u in \\w\\n> ", &info(0));
+        assert!(rendered.contains("user"));
+        assert!(rendered.contains("~/projects"));
+        assert!(rendered.ends_with("\n> "));
+    }
+
+    #[test]
+    fn test_render_unknown_placeholder() {
+        assert_eq!(render("\\q \\\\ \\", &info(0)), "\\q \\ \\");
+    }
+
+    #[test]
+    fn test_right_align() {
+        assert_eq!(
+            right_align("> ", "main", Some(20)),
+            Some(String::from("\x1b7\x1b[17Gmain\x1b8"))
+        );
+        // Only the last line of the left prompt shares the line, and colors take up no space
+        assert_eq!(
+            right_align("a long first line\n\x1b[32m>\x1b[0m ", "12:00", Some(10)),
+            Some(String::from("\x1b7\x1b[6G12:00\x1b8"))
+        );
+        assert_eq!(right_align("prompt> ", "12:00", Some(13)), None);
+        assert!(right_align("prompt> ", "12:00", Some(14)).is_some());
+        assert_eq!(right_align("> ", "", Some(80)), None);
+        assert_eq!(right_align("> ", "main", None), None);
+    }
+}
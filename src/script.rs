@@ -0,0 +1,569 @@
+use crate::errors::ParseError;
+use crate::parser::{self, Token, Word};
+
+// Represents a single (possibly compound) command in a script or command line
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Statement {
+    Command {
+        words: Vec<Word>,
+        background: bool,
+    },
+    If {
+        condition: Vec<Statement>,
+        then_branch: Vec<Statement>,
+        else_branch: Vec<Statement>,
+    },
+    While {
+        condition: Vec<Statement>,
+        body: Vec<Statement>,
+    },
+    For {
+        variable: String,
+        words: Vec<Word>,
+        body: Vec<Statement>,
+    },
+    // Runs the commands of the first branch with a pattern matching the word
+    Case {
+        word: Word,
+        branches: Vec<CaseBranch>,
+    },
+    // Defines a function, which runs its body when called like a command
+    Function {
+        name: String,
+        body: Vec<Statement>,
+    },
+}
+
+// One 'PATTERN|PATTERN) COMMANDS ;;' branch of a case statement
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CaseBranch {
+    pub patterns: Vec<Word>,
+    pub body: Vec<Statement>,
+}
+
+// Keywords that can only appear inside a compound statement
+const RESERVED_KEYWORDS: [&str; 7] = ["then", "else", "fi", "do", "done", "}", "esac"];
+
+// Collects the words of every simple command in the statements, including those inside compound statements
+pub fn simple_commands(statements: &[Statement]) -> Vec<&[Word]> {
+    let mut commands = Vec::new();
+
+    for statement in statements {
+        match statement {
+            Statement::Command { words, .. } => commands.push(words.as_slice()),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                commands.extend(simple_commands(condition));
+                commands.extend(simple_commands(then_branch));
+                commands.extend(simple_commands(else_branch));
+            }
+            Statement::While { condition, body } => {
+                commands.extend(simple_commands(condition));
+                commands.extend(simple_commands(body));
+            }
+            Statement::For { body, .. } | Statement::Function { body, .. } => {
+                commands.extend(simple_commands(body))
+            }
+            Statement::Case { branches, .. } => {
+                for branch in branches {
+                    commands.extend(simple_commands(&branch.body));
+                }
+            }
+        }
+    }
+
+    commands
+}
+
+// Parses a command line or script into a list of statements
+// Incomplete input (such as an 'if' without a 'fi') produces an error for which
+// ParseError::is_incomplete() is true, so the caller can ask for more input
+pub fn parse(input: &str) -> Result<Vec<Statement>, ParseError> {
+    let mut parser = Parser {
+        tokens: parser::tokenize(input)?,
+        position: 0,
+    };
+
+    parser.parse_list(&[])
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // Checks whether the next token is a specific (unquoted) keyword
+    fn at_keyword(&self, keywords: &[&str]) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.as_plain().is_some_and(|word| keywords.contains(&word)))
+    }
+
+    // Consumes the next token, failing unless it is the expected keyword
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.next() {
+            Some(Token::Word(word)) if word.as_plain() == Some(keyword) => Ok(()),
+            token => Err(unexpected(token)),
+        }
+    }
+
+    // Consumes the next token, failing unless it is a word
+    fn expect_word(&mut self) -> Result<Word, ParseError> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            token => Err(unexpected(token)),
+        }
+    }
+
+    // Parses statements until one of the terminating keywords (which is left unconsumed)
+    // With no terminators, parses until the end of the input
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = Vec::new();
+
+        loop {
+            while self.peek() == Some(&Token::Separator) {
+                self.next();
+            }
+
+            match self.peek() {
+                None if terminators.is_empty() => return Ok(statements),
+                None => return Err(ParseError::UnexpectedEnd),
+                // A case branch ends at ';;'
+                Some(Token::CaseBreak) if terminators.contains(&"esac") => return Ok(statements),
+                Some(Token::Background | Token::CaseBreak) => return Err(unexpected(self.next())),
+                _ if self.at_keyword(terminators) => return Ok(statements),
+                _ => statements.push(self.parse_statement()?),
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        if self.at_keyword(&["if"]) {
+            return self.parse_if();
+        }
+
+        if self.at_keyword(&["while"]) {
+            return self.parse_while();
+        }
+
+        if self.at_keyword(&["for"]) {
+            return self.parse_for();
+        }
+
+        if self.at_keyword(&["function"]) {
+            return self.parse_function();
+        }
+
+        if self.at_keyword(&["case"]) {
+            return self.parse_case();
+        }
+
+        if self.at_keyword(&RESERVED_KEYWORDS) {
+            if let Some(Token::Word(word)) = self.next() {
+                return Err(ParseError::UnexpectedToken(word.to_string()));
+            }
+        }
+
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            words.push(word.clone());
+            self.next();
+        }
+
+        let background = self.peek() == Some(&Token::Background);
+        if background {
+            self.next();
+        }
+
+        Ok(Statement::Command { words, background })
+    }
+
+    // Parses 'if CONDITION; then COMMANDS; [else COMMANDS;] fi'
+    fn parse_if(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("if")?;
+        let condition = self.parse_list(&["then"])?;
+        // Without a condition there's no status to pick a branch with
+        if condition.is_empty() {
+            return Err(unexpected(self.next()));
+        }
+        self.expect_keyword("then")?;
+        let then_branch = self.parse_list(&["else", "fi"])?;
+
+        let else_branch = match self.at_keyword(&["else"]) {
+            true => {
+                self.expect_keyword("else")?;
+                self.parse_list(&["fi"])?
+            }
+            false => Vec::new(),
+        };
+
+        self.expect_keyword("fi")?;
+        self.end_of_compound()?;
+
+        Ok(Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    // Parses 'while CONDITION; do COMMANDS; done'
+    fn parse_while(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("while")?;
+        let condition = self.parse_list(&["do"])?;
+        self.expect_keyword("do")?;
+        let body = self.parse_list(&["done"])?;
+        self.expect_keyword("done")?;
+        self.end_of_compound()?;
+
+        Ok(Statement::While { condition, body })
+    }
+
+    // Parses 'for NAME in WORDS; do COMMANDS; done'
+    fn parse_for(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("for")?;
+        let word = self.expect_word()?;
+        let variable = match word.as_plain() {
+            Some(name) if parser::is_variable_name(name) => name.to_string(),
+            _ => return Err(ParseError::UnexpectedToken(word.to_string())),
+        };
+        self.expect_keyword("in")?;
+
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            words.push(word.clone());
+            self.next();
+        }
+
+        match self.peek() {
+            Some(Token::Background | Token::CaseBreak) | None => {
+                return Err(unexpected(self.next()))
+            }
+            _ => (),
+        }
+        while self.peek() == Some(&Token::Separator) {
+            self.next();
+        }
+
+        self.expect_keyword("do")?;
+        let body = self.parse_list(&["done"])?;
+        self.expect_keyword("done")?;
+        self.end_of_compound()?;
+
+        Ok(Statement::For {
+            variable,
+            words,
+            body,
+        })
+    }
+
+    // Parses 'function NAME { COMMANDS; }'
+    fn parse_function(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("function")?;
+        let word = self.expect_word()?;
+        let name = match word.as_plain() {
+            Some(name) if !name.contains('/') && !RESERVED_KEYWORDS.contains(&name) => {
+                name.to_string()
+            }
+            _ => return Err(ParseError::UnexpectedToken(word.to_string())),
+        };
+
+        // The opening brace may be on the next line
+        while self.peek() == Some(&Token::Separator) {
+            self.next();
+        }
+
+        self.expect_keyword("{")?;
+        let body = self.parse_list(&["}"])?;
+        self.expect_keyword("}")?;
+        self.end_of_compound()?;
+
+        Ok(Statement::Function { name, body })
+    }
+
+    // Parses 'case WORD in PATTERN) COMMANDS;; ... esac', where a branch may have several patterns ('a|b)')
+    // The ';;' after the last branch can be left out
+    fn parse_case(&mut self) -> Result<Statement, ParseError> {
+        self.expect_keyword("case")?;
+        let word = self.expect_word()?;
+        self.expect_keyword("in")?;
+
+        let mut branches = Vec::new();
+        loop {
+            while self.peek() == Some(&Token::Separator) {
+                self.next();
+            }
+
+            if self.at_keyword(&["esac"]) {
+                break;
+            }
+
+            let pattern = self.expect_word()?;
+            let patterns = match pattern.strip_unquoted_suffix(')') {
+                Some(pattern) => pattern.split_unquoted('|'),
+                None => return Err(ParseError::UnexpectedToken(pattern.to_string())),
+            };
+
+            let body = self.parse_list(&["esac"])?;
+            branches.push(CaseBranch { patterns, body });
+
+            if self.peek() == Some(&Token::CaseBreak) {
+                self.next();
+            }
+        }
+
+        self.expect_keyword("esac")?;
+        self.end_of_compound()?;
+
+        Ok(Statement::Case { word, branches })
+    }
+
+    // A compound statement must be followed by a separator (or the end of the enclosing block)
+    fn end_of_compound(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Word(word)) if !self.at_keyword(&RESERVED_KEYWORDS) => {
+                Err(ParseError::UnexpectedToken(word.to_string()))
+            }
+            Some(Token::Background) => Err(unexpected(self.next())),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Describes a token that wasn't expected where it was found
+fn unexpected(token: Option<Token>) -> ParseError {
+    match token {
+        Some(Token::Word(word)) => ParseError::UnexpectedToken(word.to_string()),
+        Some(Token::Separator) => ParseError::UnexpectedToken(String::from(";")),
+        Some(Token::Background) => ParseError::UnexpectedToken(String::from("&")),
+        Some(Token::CaseBreak) => ParseError::UnexpectedToken(String::from(";;")),
+        None => ParseError::UnexpectedEnd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> Statement {
+        Statement::Command {
+            words: line.split_whitespace().map(Word::literal).collect(),
+            background: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse("a b; c\n\nd").unwrap(),
+            vec![command("a b"), command("c"), command("d")]
+        );
+        assert_eq!(
+            parse("a & b").unwrap(),
+            vec![
+                Statement::Command {
+                    words: vec![Word::literal("a")],
+                    background: true
+                },
+                command("b")
+            ]
+        );
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_if() {
+        let expected = Statement::If {
+            condition: vec![command("test -f x")],
+            then_branch: vec![command("a"), command("b")],
+            else_branch: vec![command("c")],
+        };
+
+        assert_eq!(
+            parse("if test -f x; then a; b; else c; fi").unwrap(),
+            vec![expected.clone()]
+        );
+        assert_eq!(
+            parse("if test -f x\nthen\n  a\n  b\nelse\n  c\nfi\n").unwrap(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_parse_if_without_else() {
+        let expected = Statement::If {
+            condition: vec![command("true")],
+            then_branch: vec![command("a")],
+            else_branch: Vec::new(),
+        };
+
+        assert_eq!(
+            parse("if true; then a; fi; b").unwrap(),
+            vec![expected, command("b")]
+        );
+    }
+
+    #[test]
+    fn test_parse_if_nested_keywords_as_arguments() {
+        // Keywords are only special in the position of a command name
+        let expected = Statement::If {
+            condition: vec![command("test fi = then")],
+            then_branch: vec![command("a else")],
+            else_branch: Vec::new(),
+        };
+
+        assert_eq!(
+            parse("if test fi = then; then a else; fi").unwrap(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_parse_while() {
+        let expected = Statement::While {
+            condition: vec![command("test -f x")],
+            body: vec![Statement::If {
+                condition: vec![command("a")],
+                then_branch: vec![command("break")],
+                else_branch: Vec::new(),
+            }],
+        };
+
+        assert_eq!(
+            parse("while test -f x; do if a; then break; fi; done").unwrap(),
+            vec![expected.clone()]
+        );
+        assert_eq!(
+            parse("while test -f x\ndo\n  if a\n  then break\n  fi\ndone").unwrap(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_parse_for() {
+        let expected = Statement::For {
+            variable: String::from("f"),
+            words: vec![Word::literal("a"), Word::literal("*.txt")],
+            body: vec![command("read-file f")],
+        };
+
+        assert_eq!(
+            parse("for f in a *.txt; do read-file f; done").unwrap(),
+            vec![expected.clone()]
+        );
+        assert_eq!(
+            parse("for f in a *.txt\ndo\n  read-file f\ndone").unwrap(),
+            vec![expected]
+        );
+        assert_eq!(
+            parse("for f in; do a; done").unwrap(),
+            vec![Statement::For {
+                variable: String::from("f"),
+                words: Vec::new(),
+                body: vec![command("a")],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let expected = Statement::Function {
+            name: String::from("greet"),
+            body: vec![command("echo hello"), command("echo bye")],
+        };
+
+        assert_eq!(
+            parse("function greet { echo hello; echo bye; }").unwrap(),
+            vec![expected.clone()]
+        );
+        assert_eq!(
+            parse("function greet\n{\n  echo hello\n  echo bye\n}\n").unwrap(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_parse_case() {
+        let expected = Statement::Case {
+            word: Word::literal("x"),
+            branches: vec![
+                CaseBranch {
+                    patterns: vec![Word::literal("a"), Word::literal("b*")],
+                    body: vec![command("one"), command("two")],
+                },
+                CaseBranch {
+                    patterns: vec![Word::literal("*")],
+                    body: vec![command("three")],
+                },
+            ],
+        };
+
+        assert_eq!(
+            parse("case x in a|b*) one; two;; *) three;; esac").unwrap(),
+            vec![expected.clone()]
+        );
+        assert_eq!(
+            parse("case x in\n  a|b*)\n    one\n    two\n    ;;\n  *) three\nesac").unwrap(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        for input in [
+            "if true",
+            "if true; then a",
+            "if true; then a; else b",
+            "echo 'a",
+            "while a; do b",
+            "for f in a b",
+            "for f in a; do b",
+            "function f { a",
+            "function f",
+            "case x in a) b;;",
+            "case x in",
+        ] {
+            assert!(parse(input).unwrap_err().is_incomplete(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_unexpected() {
+        for input in [
+            "fi",
+            "then a",
+            "if true; then a; fi b",
+            "if; then a; fi &",
+            "if; then a; fi",
+            "if then a; fi",
+            "& a",
+            "done",
+            "while a; do b; done c",
+            "for 1 in a; do b; done",
+            "for f a; do b; done",
+            "}",
+            "function { a; }",
+            "function f a; }",
+            "function f { a; } b",
+            "a;; b",
+            "case x in a b;; esac",
+            "case x a) b;; esac",
+            "if a;; then b; fi",
+        ] {
+            let error = parse(input).unwrap_err();
+            assert!(!error.is_incomplete(), "{}", input);
+        }
+    }
+}
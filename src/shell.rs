@@ -1,97 +1,1173 @@
 #![allow(dead_code, unused_variables)]
 
-use std::io::{stdin, stdout, Write};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, stdin, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use anyhow::Result;
 use colored::Colorize;
 
 use crate::commands::{CommandManager, Context};
+use crate::completion;
+use crate::config;
+use crate::editor;
 use crate::environment::Environment;
 use crate::errors::ShellError;
+use crate::expansion;
+use crate::frecency::{self, DirectoryStore};
+use crate::history::{self, History};
+use crate::jobs::{self, JobTable};
+use crate::parser::{self, Word};
+use crate::path;
+use crate::prompt::{self, PromptInfo};
+use crate::script::{self, CaseBranch, Statement};
+
+// Shown while reading the rest of an unfinished block (such as an 'if' without its 'fi')
+const CONTINUATION_PROMPT: &str = "> ";
+// The number of times a loop may run before it's assumed to be stuck, unless overridden through $LOOP_LIMIT
+const DEFAULT_LOOP_LIMIT: usize = 10_000;
+// How deeply functions and sourced scripts may nest before the shell gives up, to stop runaway recursion
+const FRAME_DEPTH_LIMIT: usize = 100;
+
+// A request from 'break' or 'continue' to the innermost running loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopControl {
+    Break,
+    Continue,
+}
 
 pub struct Shell {
     pub environment: Environment,
-    success: bool,
+    // Shared so that builtins can dispatch other commands while the shell is borrowed
+    pub commands: Rc<CommandManager>,
+    // User-defined aliases, mapping a name to the words it expands to
+    pub aliases: BTreeMap<String, String>,
+    // Named directories that 'change-directory @name' jumps to
+    pub bookmarks: BTreeMap<String, PathBuf>,
+    // User-defined functions, which are looked up before builtins and external commands
+    pub functions: BTreeMap<String, Rc<Vec<Statement>>>,
+    // The arguments of the function that is running, for $1, $2, $@ and $#
+    pub positional_parameters: Vec<String>,
+    // The number of functions and sourced scripts currently running, so 'return' knows if it's allowed
+    pub frame_depth: usize,
+    // Set by 'return' to stop the running function or sourced script
+    pub returning: bool,
+    // The canonical paths of the scripts currently being sourced, outermost first
+    pub sourcing: Vec<PathBuf>,
+    // Command lines registered with 'trap ... EXIT', run in reverse order when the shell exits
+    pub exit_traps: Vec<String>,
+    // Set when 'exit' refused to exit because of running jobs, so that trying again straight away exits
+    pub exit_pending: bool,
+    pub jobs: JobTable,
+    pub history: History,
+    // Where history is saved, once it's been loaded (tests leave this unset)
+    history_path: Option<PathBuf>,
+    // How often and how recently directories have been visited, for 'jump'
+    pub directories: DirectoryStore,
+    // Where visited directories are saved, once they've been loaded (tests leave this unset)
+    directories_path: Option<PathBuf>,
+    // The number of loops currently running, so 'break' and 'continue' know if they're allowed
+    pub loop_depth: usize,
+    pub loop_control: Option<LoopControl>,
+    // Whether commands are printed before they run ('set -x')
+    pub trace: bool,
+    // Whether a directory name on its own changes into it ('set -o autocd')
+    pub autocd: bool,
+    // Whether commands are read from a terminal, rather than piped in
+    pub interactive: bool,
+    // The exit status of the last command, for $? (0 means it succeeded)
+    status: i32,
 }
 
 impl Shell {
     pub fn new() -> Result<Self> {
         Ok(Self {
             environment: Environment::new()?,
-            success: true,
+            commands: Rc::new(CommandManager::default()),
+            aliases: BTreeMap::new(),
+            bookmarks: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            positional_parameters: Vec::new(),
+            frame_depth: 0,
+            returning: false,
+            sourcing: Vec::new(),
+            exit_traps: Vec::new(),
+            exit_pending: false,
+            jobs: JobTable::new(),
+            history: History::new(),
+            history_path: None,
+            directories: DirectoryStore::new(),
+            directories_path: None,
+            loop_depth: 0,
+            loop_control: None,
+            trace: false,
+            autocd: false,
+            interactive: stdin().is_terminal(),
+            status: 0,
         })
     }
 
     // Repeatedly prompts the user for commands and executes them
     pub fn run(&mut self) -> Result<()> {
-        // ? What should this name be?
-        let dispatcher = CommandManager::default();
+        let config_path = config::config_path(self.environment.home());
+        if config_path.exists() && self.source_file(&config_path).is_err() {
+            eprintln!("Failed to read config file: '{}'", config_path.display());
+        }
 
+        // Loaded after the config file, so that it can set $HISTCONTROL, $HISTSIZE and $HISTFILESIZE
+        let history_path = history::history_path(self.environment.home());
+        let history_size = self.environment.get_variable("HISTSIZE");
+        self.history
+            .set_limit(history::parse_history_size(history_size.as_deref()));
+        let history_file_size = self.environment.get_variable("HISTFILESIZE");
+        let history_file_size = history::parse_history_size(history_file_size.as_deref());
+        let erase_duplicates = self
+            .environment
+            .get_variable("HISTCONTROL")
+            .is_some_and(|control| history::erases_duplicates(&control));
+        match history::load_file(&history_path, erase_duplicates) {
+            Ok(commands) => {
+                commands
+                    .iter()
+                    .for_each(|command| self.history.add(command));
+                self.history_path = Some(history_path.clone());
+            }
+            Err(_) => eprintln!("Failed to read history file: '{}'", history_path.display()),
+        }
+
+        let directories_path = frecency::directories_path(self.environment.home());
+        match DirectoryStore::load(&directories_path) {
+            Ok(directories) => {
+                self.directories = directories;
+                self.directories_path = Some(directories_path);
+            }
+            Err(_) => eprintln!(
+                "Failed to read directories file: '{}'",
+                directories_path.display()
+            ),
+        }
+
+        let result = self.read_commands(&history_path, history_file_size);
+        self.shutdown();
+        result
+    }
+
+    // Runs the commands the user enters until stdin is closed
+    fn read_commands(&mut self, history_path: &Path, history_file_size: usize) -> Result<()> {
         loop {
-            self.interpret(&dispatcher, self.prompt()?);
+            self.report_finished_jobs();
+            // Stop once stdin is closed (such as when the user presses Ctrl-D)
+            let input = match self.prompt()? {
+                Some(line) => line,
+                None => return Ok(()),
+            };
+            let mut input = match self.expand_history(input) {
+                Some(input) => input,
+                None => continue,
+            };
+
+            // Keep reading lines while a block (or a quote) is left open
+            let statements = loop {
+                match script::parse(&input) {
+                    Err(error) if error.is_incomplete() => {
+                        match self.read_line(CONTINUATION_PROMPT)? {
+                            // Ctrl-C abandons the whole unfinished block
+                            Some(line) if line.is_empty() => break Ok(Vec::new()),
+                            Some(line) => match self.expand_history(line) {
+                                Some(line) => input.push_str(&line),
+                                None => break Ok(Vec::new()),
+                            },
+                            None => return Ok(()),
+                        }
+                    }
+                    result => break result,
+                }
+            };
+
+            match statements {
+                Ok(statements) => self.execute(&statements),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    self.status = 1;
+                }
+            }
+            // 'fc' itself is never added, so it doesn't end up editing itself the next time it runs
+            if !history::is_fc_command(&input) {
+                self.history.add(&input);
+                // Failing to save history shouldn't interrupt the session
+                let _ = history::append_to_file(history_path, &input, history_file_size);
+            }
             // Print an extra line break to prevent malformed output
             println!();
         }
     }
 
+    // Replaces '!$' and '!^' in a line with arguments of the previous command, showing the result
+    // Returns None (after reporting why) if the previous command doesn't have them
+    fn expand_history(&mut self, line: String) -> Option<String> {
+        match history::expand_last_arguments(&line, self.history.last()) {
+            Ok(expanded) if expanded != line => {
+                println!("{}", expanded.trim_end());
+                Some(expanded)
+            }
+            Ok(_) => Some(line),
+            Err(error) => {
+                eprintln!("{}", error);
+                self.status = 1;
+                None
+            }
+        }
+    }
+
+    // Runs the EXIT traps, most recently registered first, before the shell exits
+    // Every way of exiting goes through here, so the traps run exactly once
+    pub fn shutdown(&mut self) {
+        // Taken first, so that a trap running 'exit' doesn't run the traps again
+        let traps = std::mem::take(&mut self.exit_traps);
+        for trap in traps.into_iter().rev() {
+            self.interpret(trap);
+        }
+    }
+
     // Displays the prompt and returns the user input
-    fn prompt(&self) -> Result<String> {
-        print!(
-            "{} on {}\n{} ",
-            self.environment.user().blue(),
-            self.environment.working_directory.short().green(),
-            match self.success {
-                true => "❯".bright_green().bold(),
-                false => "❯".bright_red().bold(),
+    // The prompt's format can be customized through $PROMPT
+    fn prompt(&mut self) -> Result<Option<String>> {
+        let format = self
+            .environment
+            .get_variable("PROMPT")
+            .unwrap_or_else(|| String::from(prompt::DEFAULT_PROMPT));
+
+        let mut rendered = self.render_prompt(&format);
+        // $RPROMPT uses the same placeholders, and is shown on the right of the line being edited
+        if let Some(right) = self.environment.get_variable("RPROMPT") {
+            let right = self.render_prompt(&right);
+            if let Some(sequence) = prompt::right_align(&rendered, &right, editor::terminal_width())
+            {
+                rendered.push_str(&sequence);
             }
+        }
+
+        self.read_line(&rendered)
+    }
+
+    // Renders a prompt format using the shell's current state
+    pub fn render_prompt(&mut self, format: &str) -> String {
+        let job_count = self.job_count();
+        let info = PromptInfo {
+            user: self.environment.user(),
+            working_directory: self.environment.working_directory.short(),
+            job_count,
+            success: self.status == 0,
+        };
+
+        prompt::render(format, &info)
+    }
+
+    // Reads a line of input with the line editor, returning None if the end of input has been reached
+    fn read_line(&self, prompt: &str) -> Result<Option<String>> {
+        editor::read_line(prompt, self).map_err(|_| ShellError::FailedToReadStdin.into())
+    }
+
+    // Checks whether the last command succeeded
+    pub fn succeeded(&self) -> bool {
+        self.status == 0
+    }
+
+    // Gets the exit status of the last command
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    // Checks whether the user is typing the commands being run, rather than them coming from a script
+    pub fn is_interactive(&self) -> bool {
+        self.interactive && self.sourcing.is_empty()
+    }
+
+    // Gets the value of a variable, including special ones like $? (the last command's exit status)
+    // and the positional parameters of the running function ($1, $@ and $#)
+    // Here $@ is a single word with the arguments separated by spaces, but commands and 'for' loops
+    // get a separate word for each argument (see Word::expand_fields)
+    pub fn lookup_variable(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "@" | "*" => Some(self.positional_parameters.join(" ")),
+            "#" => Some(self.positional_parameters.len().to_string()),
+            "0" => Some(String::from("rush")),
+            _ => match name.parse::<usize>() {
+                Ok(number) => number
+                    .checked_sub(1)
+                    .and_then(|index| self.positional_parameters.get(index).cloned()),
+                Err(_) => self.environment.get_variable(name),
+            },
+        }
+    }
+
+    // Changes the working directory (see Environment::change_to), recording the visit for 'jump'
+    // Builtins change directory through this, so that every way of getting somewhere counts as a visit
+    pub fn change_directory(&mut self, path: &str, physical: bool) -> Result<()> {
+        self.environment.change_to(path, physical)?;
+        self.record_directory();
+        Ok(())
+    }
+
+    // Records a visit to the working directory for 'jump', saving it if the directories file has been loaded
+    // The file is read again first, so that visits saved by other shells since aren't lost
+    fn record_directory(&mut self) {
+        if let Some(directories) = self
+            .directories_path
+            .as_ref()
+            .and_then(|path| DirectoryStore::load(path).ok())
+        {
+            self.directories = directories;
+        }
+
+        let directory = self.environment.working_directory.absolute().clone();
+        self.directories.visit(&directory, frecency::now());
+        if let Some(path) = &self.directories_path {
+            let _ = self.directories.save(path);
+        }
+    }
+
+    // Saves the history in memory over the history file, if it's been loaded, after entries are deleted
+    pub fn rewrite_history_file(&self) -> io::Result<()> {
+        match &self.history_path {
+            Some(path) => history::write_file(path, &self.history),
+            None => Ok(()),
+        }
+    }
+
+    // Gets the number of background jobs that are still running, leaving finished ones to be reported later
+    pub fn job_count(&mut self) -> usize {
+        self.jobs.running_count()
+    }
+
+    // Reaps background jobs that have finished and lets the user know about them
+    fn report_finished_jobs(&mut self) {
+        for (job, status) in self.jobs.reap() {
+            let state = match status.success() {
+                true => String::from("Done"),
+                false => format!("Exit {}", status.code().unwrap_or(1)),
+            };
+
+            println!("[{}] {}  {}", job.id, state, job.command);
+        }
+    }
+
+    // Starts an external command as a background job
+    fn run_in_background(&mut self, command_name: &str, command_args: Vec<&str>) {
+        if self.commands.is_builtin(command_name) {
+            eprintln!("Builtin commands cannot be run in the background");
+            self.status = 1;
+            return;
+        }
+
+        let search_path = self.environment.get_variable("PATH").unwrap_or_default();
+        let binary = match path::find_executable(command_name, &search_path) {
+            Some(binary) => binary,
+            None => {
+                eprintln!("Unknown command: {}", command_name.red());
+                self.status = 127;
+                return;
+            }
+        };
+
+        match jobs::spawn_background(&binary, &command_args) {
+            Ok(child) => {
+                let pid = child.id();
+                let command = [vec![command_name], command_args].concat().join(" ");
+                let id = self.jobs.add(child, &command);
+                println!("[{}] {}", id, pid);
+                self.status = 0;
+            }
+            Err(_) => {
+                eprintln!("Failed to run '{}'", command_name);
+                self.status = 1;
+            }
+        }
+    }
+
+    // Runs every command in a file (such as the config file), skipping blank lines and '#' comments
+    // Returns whether the last command succeeded
+    pub fn source_file(&mut self, path: &Path) -> Result<bool> {
+        self.guard_source(path, |shell| {
+            let script = fs::read_to_string(path).map_err(|_| ShellError::FailedToReadFile)?;
+            shell.interpret(script);
+            Ok(shell.status == 0)
+        })
+    }
+
+    // Interprets one or more commands from a string
+    pub fn interpret(&mut self, line: String) {
+        match script::parse(&line) {
+            Ok(statements) => self.execute(&statements),
+            Err(error) => {
+                eprintln!("{}", error);
+                self.status = 1;
+            }
+        }
+    }
+
+    // Executes parsed statements in order
+    pub fn execute(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            // Skip the rest of the loop body after 'break' or 'continue', or the rest of a function after 'return'
+            if self.loop_control.is_some() || self.returning {
+                return;
+            }
+
+            match statement {
+                Statement::Command { words, background } => {
+                    if let Err(error) = self.assign_parameters(words) {
+                        eprintln!("{}", error);
+                        self.status = 1;
+                        continue;
+                    }
+
+                    let stages = match expansion::expand_stages(
+                        words.clone(),
+                        &self.aliases,
+                        &|name| self.lookup_variable(name),
+                        &self.positional_parameters,
+                        self.environment.home(),
+                    ) {
+                        Ok(stages) => stages,
+                        Err(error) => {
+                            eprintln!("{}", error);
+                            self.status = 1;
+                            continue;
+                        }
+                    };
+
+                    if let Some(note) =
+                        self.verbose_alias_note(words, &stages.aliases, &stages.applied_aliases)
+                    {
+                        eprintln!("{}", note.dimmed());
+                    }
+
+                    if let Some(line) = self.trace_line(&stages.globs) {
+                        eprintln!("{}", line);
+                    }
+
+                    // A refused 'exit' only lasts until the next command, which would have exited if it were 'exit'
+                    let exit_pending = self.exit_pending;
+                    self.run_command(stages.globs, *background);
+                    if exit_pending {
+                        self.exit_pending = false;
+                    }
+                }
+                Statement::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.execute(condition);
+                    if self.returning {
+                        return;
+                    }
+
+                    match self.status == 0 {
+                        true => self.execute(then_branch),
+                        // An 'if' whose condition fails without an 'else' still succeeds
+                        false if else_branch.is_empty() => self.status = 0,
+                        false => self.execute(else_branch),
+                    }
+                }
+                Statement::While { condition, body } => self.execute_while(condition, body),
+                Statement::For {
+                    variable,
+                    words,
+                    body,
+                } => self.execute_for(variable, words, body),
+                Statement::Case { word, branches } => self.execute_case(word, branches),
+                Statement::Function { name, body } => {
+                    self.functions.insert(name.clone(), Rc::new(body.clone()));
+                    self.status = 0;
+                }
+            }
+        }
+    }
+
+    // Sets the variables of ${NAME:=word} references in a command's words, before they're expanded
+    // Arithmetic expansions are checked afterwards, so they can use the variables that were set
+    fn assign_parameters(&mut self, words: &[Word]) -> Result<()> {
+        let assignments = expansion::check_parameters(words, &|name| self.lookup_variable(name))?;
+        for (name, value) in assignments {
+            self.environment.set_variable(&name, &value);
+        }
+
+        expansion::check_arithmetic(words, &|name| self.lookup_variable(name))?;
+        Ok(())
+    }
+
+    // Gets the line printed before a command runs when tracing is turned on with 'set -x'
+    // The words are quoted where needed, so the line could be run again as it is
+    fn trace_line(&self, words: &[String]) -> Option<String> {
+        if !self.trace || words.is_empty() {
+            return None;
+        }
+
+        let words: Vec<String> = words.iter().map(|word| parser::quote(word)).collect();
+        Some(format!("+ {}", words.join(" ")))
+    }
+
+    // Gets a note about which alias a command used, if $VERBOSE_ALIAS is turned on (it's off by default)
+    fn verbose_alias_note(
+        &self,
+        original: &[Word],
+        expanded: &[Word],
+        applied: &[String],
+    ) -> Option<String> {
+        match self.environment.get_variable("VERBOSE_ALIAS").as_deref() {
+            Some("1" | "on" | "true") => expansion::alias_note(original, expanded, applied),
+            _ => None,
+        }
+    }
+
+    // Runs the body of a loop for as long as its condition succeeds
+    fn execute_while(&mut self, condition: &[Statement], body: &[Statement]) {
+        let limit = self
+            .environment
+            .get_variable("LOOP_LIMIT")
+            .and_then(|limit| limit.parse().ok())
+            .unwrap_or(DEFAULT_LOOP_LIMIT);
+
+        self.loop_depth += 1;
+        let mut iterations = 0;
+
+        loop {
+            self.execute(condition);
+            if self.loop_control.take() == Some(LoopControl::Break) || self.returning {
+                break;
+            }
+
+            // The loop finishing normally isn't a failure
+            if self.status != 0 {
+                self.status = 0;
+                break;
+            }
+
+            // A limit of 0 lets loops run forever
+            if limit != 0 && iterations == limit {
+                eprintln!(
+                    "Loop stopped after {} iterations (set LOOP_LIMIT=0 to disable the limit)",
+                    limit
+                );
+                self.status = 1;
+                break;
+            }
+
+            iterations += 1;
+            self.execute(body);
+            if self.loop_control.take() == Some(LoopControl::Break) || self.returning {
+                break;
+            }
+        }
+
+        self.loop_depth -= 1;
+    }
+
+    // Runs the body of a loop once for each word, with the loop variable set to that word
+    // Unquoted wildcards in the words are expanded to the matching paths
+    fn execute_for(&mut self, variable: &str, words: &[Word], body: &[Statement]) {
+        let values = expansion::expand_variables(
+            words,
+            &|name| self.lookup_variable(name),
+            &self.positional_parameters,
         );
+        let values = expansion::expand_globs(words, values, self.environment.home());
+
+        let previous = self.environment.unset_variable(variable);
+        self.loop_depth += 1;
+        self.status = 0;
+
+        for value in values {
+            self.environment.set_variable(variable, &value);
+            self.execute(body);
+            if self.loop_control.take() == Some(LoopControl::Break) || self.returning {
+                break;
+            }
+        }
+
+        self.loop_depth -= 1;
+        self.environment.unset_variable(variable);
+        if let Some(previous) = previous {
+            self.environment.set_variable(variable, &previous);
+        }
+    }
+
+    // Runs the commands of the first branch with a pattern that matches the word
+    // Patterns are wildcard patterns after their variables are expanded, unless their wildcards are quoted
+    fn execute_case(&mut self, word: &Word, branches: &[CaseBranch]) {
+        let lookup = |name: &str| self.lookup_variable(name);
+        let value = word.expand(&lookup);
+
+        let branch = branches.iter().find(|branch| {
+            branch.patterns.iter().any(|pattern| {
+                let pattern_value = pattern.expand(&lookup);
+                match pattern.has_glob() {
+                    true => path::glob_match(&pattern_value, &value),
+                    false => pattern_value == value,
+                }
+            })
+        });
+
+        // Like an 'if' without an 'else', nothing matching isn't a failure
+        self.status = 0;
+        if let Some(branch) = branch {
+            self.execute(&branch.body);
+        }
+    }
+
+    // Runs the body of a function with its arguments as the positional parameters
+    fn call_function(&mut self, body: &[Statement], arguments: &[String]) {
+        let caller_parameters =
+            std::mem::replace(&mut self.positional_parameters, arguments.to_vec());
+        self.status = 0;
+        self.run_frame(|shell| shell.execute(body));
+        self.positional_parameters = caller_parameters;
+    }
+
+    // Runs a script in the current shell like 'source', as a frame that 'return' can stop
+    // Returns the exit status of the script
+    pub fn source_script(&mut self, path: &Path) -> Result<i32> {
+        self.guard_source(path, |shell| {
+            let script = fs::read_to_string(path).map_err(|_| ShellError::FailedToReadFile)?;
+            shell.run_frame(|shell| shell.interpret(script));
+            Ok(shell.status)
+        })
+    }
+
+    // Runs a script while it's recorded as being sourced, so that a script that sources itself
+    // (directly or through other scripts) fails instead of recursing forever
+    fn guard_source<T>(
+        &mut self,
+        path: &Path,
+        run: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let path = path
+            .canonicalize()
+            .map_err(|_| ShellError::FailedToReadFile)?;
+        if self.sourcing.contains(&path) {
+            return Err(ShellError::RecursiveSource(path.display().to_string()).into());
+        }
+
+        self.sourcing.push(path);
+        let result = run(self);
+        self.sourcing.pop();
+        result
+    }
 
-        flush()?;
-        read_line()
+    // Runs a function body or sourced script, which 'return' stops early
+    // Variables set with 'local' inside it only last until it finishes, and loops outside it can't be controlled from inside it
+    fn run_frame(&mut self, run: impl FnOnce(&mut Self)) {
+        if self.frame_depth == FRAME_DEPTH_LIMIT {
+            eprintln!(
+                "Functions and sourced scripts nested too deeply (the limit is {})",
+                FRAME_DEPTH_LIMIT
+            );
+            self.status = 1;
+            return;
+        }
+
+        self.frame_depth += 1;
+        self.environment.push_scope();
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+
+        run(self);
+
+        self.loop_depth = loop_depth;
+        self.returning = false;
+        self.environment.pop_scope();
+        self.frame_depth -= 1;
     }
 
-    // Interprets a command from a string
-    fn interpret(&mut self, dispatcher: &CommandManager, line: String) {
-        let mut words = line.split_whitespace();
-        // Get the first word (the command name)
-        let command_name = words.next().unwrap();
+    // Runs a single simple command
+    fn run_command(&mut self, words: Vec<String>, background: bool) {
+        // Get the first word (the command name), ignoring empty commands
+        let command_name = match words.first() {
+            Some(name) => name.as_str(),
+            None => return,
+        };
         // Get the rest of the words (the command arguments)
-        let command_args: Vec<&str> = words.collect();
+        let command_args: Vec<&str> = words[1..].iter().map(|w| w.as_str()).collect();
+
+        // Functions shadow builtins and external commands with the same name
+        if let Some(body) = self.functions.get(command_name).cloned() {
+            if background {
+                eprintln!("Functions cannot be run in the background");
+                self.status = 1;
+                return;
+            }
+
+            self.call_function(&body, &words[1..]);
+            return;
+        }
+
+        if background {
+            self.run_in_background(command_name, command_args);
+            return;
+        }
 
         // Bundle all the information that needs to be modifiable by the commands into a Context
         let mut context = Context::new(self);
 
         // Dispatch the command to the CommandManager
-        let exit_code = dispatcher.dispatch(command_name, command_args, &mut context);
+        let exit_code = context.dispatch(command_name, command_args);
 
         // If the command was not found, print an error message
         match exit_code {
-            Some(code) => self.success = code.is_success(),
+            Some(code) => self.status = code.code(),
             None => {
                 eprintln!("Unknown command: {}", command_name.red());
-                self.success = false;
+                self.status = 127;
             }
         }
     }
 }
 
-// Flushes stdout
-fn flush() -> Result<()> {
-    let mut stdout = stdout();
-    match stdout.flush() {
-        Ok(_) => Ok(()),
-        Err(_) => Err(ShellError::FailedToFlushStdout.into()),
+impl editor::Helper for Shell {
+    // Command names include the aliases defined so far, and an alias completes arguments
+    // the same way as the command it expands to
+    fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let mut names = self.commands.names();
+        names.extend(self.aliases.keys().cloned());
+
+        let resolve = |name: &str| {
+            let (words, _) =
+                expansion::expand_aliases(vec![Word::literal(name)], &self.aliases).ok()?;
+            let command_name = words.first()?.as_plain()?;
+            self.commands.true_name_of(command_name).cloned()
+        };
+
+        completion::complete(
+            line,
+            &names,
+            &resolve,
+            self.environment.working_directory.absolute(),
+            self.environment.home(),
+        )
+    }
+
+    fn hint(&self, line: &str) -> Option<String> {
+        history::suggest_from_history(line, &self.history)
+    }
+
+    fn search_history(&self, query: &str, start: Option<usize>) -> Option<(usize, String)> {
+        history::reverse_search(query, start.unwrap_or(self.history.len()), &self.history)
     }
 }
 
-// Reads a line of input from stdin
-fn read_line() -> Result<String> {
-    let mut line = String::new();
-    let stdin = stdin();
-    match stdin.read_line(&mut line) {
-        Ok(_) => (),
-        Err(_) => return Err(ShellError::FailedToReadStdin.into()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_render_prompt() {
+        let mut shell = Shell::new().unwrap();
+        let working_directory = shell.environment.working_directory.short().to_string();
+        assert!(shell.render_prompt("in \\w").contains(&working_directory));
+
+        // The stored format is left alone
+        assert_eq!(shell.environment.get_variable("PROMPT"), None);
+        shell.environment.set_variable("PROMPT", "\\u");
+        shell.render_prompt("\\w");
+        assert_eq!(
+            shell.environment.get_variable("PROMPT"),
+            Some(String::from("\\u"))
+        );
+    }
+
+    #[test]
+    fn test_complete_aliases() {
+        use editor::Helper;
+
+        let mut shell = Shell::new().unwrap();
+        assert!(shell.complete("rush-te").1.is_empty());
+
+        let mut context = Context::new(&mut shell);
+        builtins::alias(&mut context, vec!["rush-test-alias=change-directory"]);
+        assert_eq!(
+            shell.complete("rush-te"),
+            (0, vec![String::from("rush-test-alias")])
+        );
+
+        // Arguments to the alias are completed like arguments to change-directory (directories only)
+        let dir = temp_dir("complete-aliases");
+        fs::create_dir(dir.join("subdirectory")).unwrap();
+        fs::write(dir.join("subfile"), "").unwrap();
+        let line = format!("rush-test-alias {}/sub", dir.display());
+        let (_, candidates) = shell.complete(&line);
+        assert_eq!(candidates, [format!("{}/subdirectory/", dir.display())]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_if_branches() {
+        let dir = temp_dir("if-branches");
+        let mut shell = Shell::new().unwrap();
+        let d = dir.display();
+
+        shell.interpret(format!(
+            "if test -d {d}; then create-file {d}/then1; else create-file {d}/else1; fi"
+        ));
+        shell.interpret(format!(
+            "if test -f {d}/missing\nthen\n  create-file {d}/then2\nelse\n  create-file {d}/else2\nfi"
+        ));
+        assert!(dir.join("then1").exists() && !dir.join("else1").exists());
+        assert!(!dir.join("then2").exists() && dir.join("else2").exists());
+
+        // A false condition without an 'else' does nothing, but still succeeds
+        shell.interpret(format!("if false; then create-file {d}/then3; fi"));
+        assert!(!dir.join("then3").exists());
+        assert!(shell.succeeded());
+
+        // An empty condition is a syntax error, rather than reusing the previous command's status
+        shell.interpret(format!("if ; then create-file {d}/then4; fi"));
+        assert!(!dir.join("then4").exists());
+        assert!(!shell.succeeded());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_while_counting_loop() {
+        let dir = temp_dir("while-count");
+        let mut shell = Shell::new().unwrap();
+        let d = dir.display();
+
+        // Count to three by growing a string one character at a time
+        shell.interpret(format!(
+            "set n x\nwhile test $n != xxxx\ndo\n  create-file {d}/$n\n  set n ${{n}}x\ndone"
+        ));
+        assert!(shell.succeeded());
+        for name in ["x", "xx", "xxx"] {
+            assert!(dir.join(name).exists(), "{}", name);
+        }
+        assert!(!dir.join("xxxx").exists());
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    Ok(line)
+    #[test]
+    fn test_while_break_and_continue() {
+        let dir = temp_dir("while-break");
+        let mut shell = Shell::new().unwrap();
+        let d = dir.display();
+
+        shell.interpret(format!(
+            "set n x; while true; do set n ${{n}}x; if test $n = xxx; then continue; fi; \
+             if test $n = xxxxx; then break; fi; create-file {d}/$n; done"
+        ));
+        let mut created: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        created.sort();
+        assert_eq!(created, ["xx", "xxxx"]);
+        assert_eq!(shell.loop_depth, 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_while_loop_limit() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from("set LOOP_LIMIT 5; while true; do true; done"));
+        assert!(!shell.succeeded());
+        assert_eq!(shell.loop_depth, 0);
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let dir = temp_dir("for-loop");
+        let mut shell = Shell::new().unwrap();
+        let d = dir.display();
+
+        shell.interpret(format!(
+            "set f original; for f in a 'b c' d; do if test $f = d; then break; fi; create-file \"{d}/$f\"; done"
+        ));
+        assert!(shell.succeeded());
+        assert!(dir.join("a").exists() && dir.join("b c").exists());
+        assert!(!dir.join("d").exists());
+        // The loop variable is restored afterwards
+        assert_eq!(
+            shell.environment.get_variable("f"),
+            Some(String::from("original"))
+        );
+
+        // Globs are expanded to the matching files
+        shell.interpret(format!("for f in {d}/*; do create-file $f.copy; done"));
+        assert!(dir.join("a.copy").exists() && dir.join("b c.copy").exists());
+        assert_eq!(shell.loop_depth, 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let mut shell = Shell::new().unwrap();
+        let words = [String::from("printf"), String::from("a b")];
+        assert_eq!(shell.trace_line(&words), None);
+
+        let mut context = Context::new(&mut shell);
+        builtins::set(&mut context, vec!["-x"]);
+        assert_eq!(
+            shell.trace_line(&words),
+            Some(String::from("+ printf 'a b'"))
+        );
+        assert_eq!(shell.trace_line(&[]), None);
+
+        let mut context = Context::new(&mut shell);
+        builtins::set(&mut context, vec!["+x"]);
+        assert_eq!(shell.trace_line(&words), None);
+    }
+
+    #[test]
+    fn test_function_definition_and_call() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "function rush_test_greet { local RUSH_GREETING=\"$1 $2\"; export RUSH_GREETED=\"$RUSH_GREETING ($#)\"; }",
+        ));
+        assert!(shell.functions.contains_key("rush_test_greet"));
+
+        shell.interpret(String::from("rush_test_greet hello world"));
+        assert!(shell.succeeded());
+        assert_eq!(
+            shell.lookup_variable("RUSH_GREETED").as_deref(),
+            Some("hello world (2)")
+        );
+        // Local variables and arguments don't outlive the call
+        assert_eq!(shell.lookup_variable("RUSH_GREETING"), None);
+        assert_eq!(shell.lookup_variable("1"), None);
+        assert_eq!(shell.lookup_variable("#").as_deref(), Some("0"));
+
+        // A function's status is that of its last command
+        shell.interpret(String::from(
+            "function rush_test_fail { true; false; }; rush_test_fail",
+        ));
+        assert_eq!(shell.lookup_variable("?").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_function_arguments_as_words() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "function rush_test_count { export RUSH_ARGUMENT_COUNT=$#; export RUSH_SECOND=$2; }",
+        ));
+        shell.interpret(String::from(
+            "function rush_test_forward { rush_test_count \"$@\"; }",
+        ));
+        shell.interpret(String::from(
+            "function rush_test_loop { export RUSH_LOOPED=; for a in $@; do export RUSH_LOOPED=\"$RUSH_LOOPED[$a]\"; done; }",
+        ));
+
+        // $@ passes each argument on as a word of its own, even one with a space in it
+        shell.interpret(String::from("rush_test_forward x 'y z'"));
+        assert_eq!(
+            shell.lookup_variable("RUSH_ARGUMENT_COUNT").as_deref(),
+            Some("2")
+        );
+        assert_eq!(shell.lookup_variable("RUSH_SECOND").as_deref(), Some("y z"));
+
+        shell.interpret(String::from("rush_test_loop x 'y z' w"));
+        assert_eq!(
+            shell.lookup_variable("RUSH_LOOPED").as_deref(),
+            Some("[x][y z][w]")
+        );
+
+        // With no arguments, $@ is no words at all
+        shell.interpret(String::from("rush_test_forward"));
+        assert_eq!(
+            shell.lookup_variable("RUSH_ARGUMENT_COUNT").as_deref(),
+            Some("0")
+        );
+    }
+
+    #[test]
+    fn test_case_statement() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "function rush_test_kind {\n  case $1 in\n    *.rs|*.toml) export RUSH_KIND=rust;;\n    '*') export RUSH_KIND=star;;\n    *) export RUSH_KIND=other\n  esac\n}",
+        ));
+
+        for (argument, kind) in [
+            ("main.rs", "rust"),
+            ("Cargo.toml", "rust"),
+            ("'*'", "star"),
+            ("notes.txt", "other"),
+        ] {
+            shell.interpret(format!("rush_test_kind {}", argument));
+            assert_eq!(
+                shell.lookup_variable("RUSH_KIND").as_deref(),
+                Some(kind),
+                "{}",
+                argument
+            );
+        }
+
+        // Only the first matching branch runs
+        shell.interpret(String::from(
+            "case a in a) export RUSH_FIRST=1;; a) export RUSH_FIRST=2;; esac",
+        ));
+        assert_eq!(shell.lookup_variable("RUSH_FIRST").as_deref(), Some("1"));
+
+        // No match runs nothing, and still succeeds
+        shell.interpret(String::from("false; case b in a) false;; esac"));
+        assert!(shell.succeeded());
+    }
+
+    #[test]
+    fn test_function_return() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "function rush_test_early { for x in a b; do return 3; done; export RUSH_NOT_RETURNED=1; }",
+        ));
+        shell.interpret(String::from("rush_test_early; export RUSH_AFTER_RETURN=$?"));
+        assert_eq!(shell.lookup_variable("RUSH_NOT_RETURNED"), None);
+        assert_eq!(
+            shell.lookup_variable("RUSH_AFTER_RETURN").as_deref(),
+            Some("3")
+        );
+        assert!(!shell.returning);
+
+        // Without a status, the last command's status is returned
+        shell.interpret(String::from(
+            "function rush_test_last { false; return; true; }; rush_test_last",
+        ));
+        assert_eq!(shell.status(), 1);
+    }
+
+    #[test]
+    fn test_function_recursion_limit() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "function rush_test_forever { rush_test_forever; }; rush_test_forever",
+        ));
+        assert!(!shell.succeeded());
+        assert_eq!(shell.frame_depth, 0);
+    }
+
+    #[test]
+    fn test_source_recursion_guard() {
+        let dir = temp_dir("source-recursion");
+        let itself = dir.join("itself.rush");
+        fs::write(&itself, format!("source {}", itself.display())).unwrap();
+
+        let mut shell = Shell::new().unwrap();
+        // The nested 'source' fails, rather than sourcing the script again
+        assert_eq!(shell.source_script(&itself).unwrap(), 3);
+        assert!(shell.sourcing.is_empty());
+
+        // Chains of scripts are caught too
+        let first = dir.join("first.rush");
+        let second = dir.join("second.rush");
+        fs::write(&first, format!("source {}", second.display())).unwrap();
+        fs::write(&second, format!("source {}", first.display())).unwrap();
+        assert_eq!(shell.source_script(&first).unwrap(), 3);
+        assert_eq!(shell.frame_depth, 0);
+
+        shell.sourcing.push(first.canonicalize().unwrap());
+        let error = shell.source_script(&first).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ShellError>(),
+            Some(ShellError::RecursiveSource(_))
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_last_status_variable() {
+        let mut shell = Shell::new().unwrap();
+        assert_eq!(shell.lookup_variable("?"), Some(String::from("0")));
+        shell.interpret(String::from("false"));
+        assert_eq!(shell.lookup_variable("?"), Some(String::from("1")));
+        shell.interpret(String::from("rush-no-such-command"));
+        assert_eq!(shell.lookup_variable("?"), Some(String::from("127")));
+        shell.interpret(String::from("true"));
+        assert_eq!(shell.lookup_variable("?"), Some(String::from("0")));
+    }
+
+    #[test]
+    fn test_parameter_expansion() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from(
+            "export RUSH_DEFAULTED=${RUSH_UNSET_A:-fallback}",
+        ));
+        assert_eq!(
+            shell.lookup_variable("RUSH_DEFAULTED").as_deref(),
+            Some("fallback")
+        );
+        assert_eq!(shell.lookup_variable("RUSH_UNSET_A"), None);
+
+        shell.interpret(String::from("true ${RUSH_ASSIGNED:=first}"));
+        shell.interpret(String::from("true ${RUSH_ASSIGNED:=second}"));
+        assert_eq!(
+            shell.lookup_variable("RUSH_ASSIGNED").as_deref(),
+            Some("first")
+        );
+
+        // An unset variable with ':?' stops the command before it runs
+        shell.interpret(String::from(
+            "export RUSH_NOT_EXPORTED=${RUSH_UNSET_B:?missing}",
+        ));
+        assert_eq!(shell.status(), 1);
+        assert_eq!(shell.lookup_variable("RUSH_NOT_EXPORTED"), None);
+        shell.interpret(String::from("true ${RUSH_ASSIGNED:?missing}"));
+        assert!(shell.succeeded());
+    }
+
+    #[test]
+    fn test_arithmetic_expansion() {
+        let mut shell = Shell::new().unwrap();
+        shell.interpret(String::from("export RUSH_ARITHMETIC_BASE=7"));
+        shell.interpret(String::from(
+            "export RUSH_ARITHMETIC_RESULT=$(((RUSH_ARITHMETIC_BASE + 3) * 2 - $RUSH_ARITHMETIC_BASE % 4))",
+        ));
+        assert_eq!(
+            shell.lookup_variable("RUSH_ARITHMETIC_RESULT").as_deref(),
+            Some("17")
+        );
+
+        // Dividing by zero stops the command before it runs
+        shell.interpret(String::from(
+            "export RUSH_ARITHMETIC_FAILED=$((RUSH_ARITHMETIC_BASE / 0))",
+        ));
+        assert_eq!(shell.status(), 1);
+        assert_eq!(shell.lookup_variable("RUSH_ARITHMETIC_FAILED"), None);
+    }
+
+    #[test]
+    fn test_verbose_alias_note() {
+        let mut shell = Shell::new().unwrap();
+        shell
+            .aliases
+            .insert(String::from("ll"), String::from("list-directory -la"));
+        let original = vec![Word::literal("ll")];
+        let (expanded, applied) =
+            expansion::expand_aliases(original.clone(), &shell.aliases).unwrap();
+
+        // Off by default
+        assert_eq!(
+            shell.verbose_alias_note(&original, &expanded, &applied),
+            None
+        );
+
+        shell.environment.set_variable("VERBOSE_ALIAS", "on");
+        assert_eq!(
+            shell.verbose_alias_note(&original, &expanded, &applied),
+            Some(String::from("(ll -> list-directory -la)"))
+        );
+
+        shell.environment.set_variable("VERBOSE_ALIAS", "off");
+        assert_eq!(
+            shell.verbose_alias_note(&original, &expanded, &applied),
+            None
+        );
+    }
 }
@@ -0,0 +1,14 @@
+// Helpers shared by the tests of several modules
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+// Creates a fresh, empty directory in the system temp directory for a single test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rush-test-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
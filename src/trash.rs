@@ -0,0 +1,320 @@
+// Implements a subset of the freedesktop.org trash specification, so that files deleted
+// from the shell show up in (and can be restored from) the desktop's trash as well
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::datetime::DateTime;
+
+// Gets the trash directory, respecting $XDG_DATA_HOME if it is set
+pub fn trash_directory(home: &Path, xdg_data_home: Option<String>) -> PathBuf {
+    match xdg_data_home {
+        Some(data_home) if !data_home.is_empty() => PathBuf::from(data_home).join("Trash"),
+        _ => home.join(".local/share/Trash"),
+    }
+}
+
+// Picks a name for a file in the trash, appending a numeric suffix if the name is already taken
+pub fn unique_trash_name(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    if !is_taken(name) {
+        return name.to_string();
+    }
+
+    (1..)
+        .map(|n| format!("{}.{}", name, n))
+        .find(|candidate| !is_taken(candidate))
+        .unwrap()
+}
+
+// Moves a file or directory into the trash and records where it came from
+// Returns the path of the item inside the trash
+pub fn move_to_trash(path: &Path, trash: &Path) -> io::Result<PathBuf> {
+    let original = match (path.parent(), path.file_name()) {
+        // Only the directory it's in is resolved, so a symlink is trashed rather than what it points to
+        (Some(parent), Some(name)) => {
+            let parent = match parent.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => parent,
+            };
+            fs::canonicalize(parent)?.join(name)
+        }
+        // Paths like '.' and '..' have no name of their own
+        _ => fs::canonicalize(path)?,
+    };
+    fs::symlink_metadata(&original)?;
+
+    let name = match original.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Err(io::Error::new(ErrorKind::InvalidInput, "Cannot trash root")),
+    };
+
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let name = unique_trash_name(&name, |candidate| {
+        files.join(candidate).exists() || info.join(format!("{}.trashinfo", candidate)).exists()
+    });
+
+    let info_contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&original.to_string_lossy()),
+        DateTime::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    let info_path = info.join(format!("{}.trashinfo", name));
+    fs::write(&info_path, info_contents)?;
+
+    let destination = files.join(&name);
+    if let Err(error) = move_path(&original, &destination) {
+        let _ = fs::remove_file(&info_path);
+        return Err(error);
+    }
+
+    Ok(destination)
+}
+
+// Moves the most recently trashed item back to where it came from
+// Returns the restored path
+pub fn restore_latest(trash: &Path) -> io::Result<PathBuf> {
+    let mut latest: Option<(String, PathBuf, PathBuf)> = None;
+
+    for entry in fs::read_dir(trash.join("info"))? {
+        let info_path = entry?.path();
+        let contents = match fs::read_to_string(&info_path) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+
+        let field = |key: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key).map(String::from))
+        };
+
+        let (original, deleted_at) = match (field("Path="), field("DeletionDate=")) {
+            (Some(original), Some(deleted_at)) => (percent_decode(&original), deleted_at),
+            _ => continue,
+        };
+
+        // ISO 8601 timestamps sort chronologically as plain strings
+        if latest
+            .as_ref()
+            .is_none_or(|(latest_at, _, _)| deleted_at > *latest_at)
+        {
+            latest = Some((deleted_at, PathBuf::from(original), info_path));
+        }
+    }
+
+    let (_, original, info_path) = match latest {
+        Some(latest) => latest,
+        None => return Err(io::Error::new(ErrorKind::NotFound, "Trash is empty")),
+    };
+
+    if original.exists() || original.is_symlink() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            "Original path is taken",
+        ));
+    }
+
+    let name = info_path.file_stem().unwrap_or_default();
+    move_path(&trash.join("files").join(name), &original)?;
+    fs::remove_file(&info_path)?;
+
+    Ok(original)
+}
+
+// Moves a file or directory, copying it and then removing the original when it's on another filesystem
+fn move_path(source: &Path, destination: &Path) -> io::Result<()> {
+    match fs::rename(source, destination) {
+        Err(error) if error.kind() == ErrorKind::CrossesDevices => {
+            copy_then_remove(source, destination)
+        }
+        result => result,
+    }
+}
+
+// Copies a file or directory tree (keeping symlinks as links), removing each part of the source once it's copied
+fn copy_then_remove(source: &Path, destination: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+
+    if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_then_remove(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        fs::set_permissions(destination, metadata.permissions())?;
+        fs::remove_dir(source)
+    } else if metadata.file_type().is_symlink() {
+        copy_symlink(source, destination)?;
+        fs::remove_file(source)
+    } else {
+        fs::copy(source, destination)?;
+        fs::remove_file(source)
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::from(ErrorKind::Unsupported))
+}
+
+// Escapes a path for a .trashinfo file, leaving unreserved URL characters and '/' as they are
+fn percent_encode(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// Reverses percent_encode(), leaving any malformed escapes as they are
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = match bytes[index] {
+            b'%' => path
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn test_trash_directory() {
+        let home = Path::new("/home/user");
+        assert_eq!(
+            trash_directory(home, None),
+            PathBuf::from("/home/user/.local/share/Trash")
+        );
+        assert_eq!(
+            trash_directory(home, Some(String::from("/data"))),
+            PathBuf::from("/data/Trash")
+        );
+    }
+
+    #[test]
+    fn test_unique_trash_name() {
+        let taken = ["notes.txt", "notes.txt.1"];
+        assert_eq!(
+            unique_trash_name("other.txt", |n| taken.contains(&n)),
+            "other.txt"
+        );
+        assert_eq!(
+            unique_trash_name("notes.txt", |n| taken.contains(&n)),
+            "notes.txt.2"
+        );
+    }
+
+    #[test]
+    fn test_percent_encoding_round_trip() {
+        let path = "/tmp/with space/100%/ünïcode";
+        assert_eq!(percent_encode("/tmp/a b"), "/tmp/a%20b");
+        assert_eq!(percent_decode(&percent_encode(path)), path);
+    }
+
+    #[test]
+    fn test_move_to_trash_and_restore() {
+        let dir = temp_dir("move-to-trash");
+        let trash = dir.join("Trash");
+        let file = dir.join("doomed.txt");
+
+        fs::write(&file, "first").unwrap();
+        let trashed = move_to_trash(&file, &trash).unwrap();
+        assert_eq!(trashed, trash.join("files").join("doomed.txt"));
+        assert!(!file.exists());
+        assert!(trash.join("info").join("doomed.txt.trashinfo").exists());
+
+        // A second file with the same name gets a suffix instead of replacing the first
+        fs::write(&file, "second").unwrap();
+        let trashed = move_to_trash(&file, &trash).unwrap();
+        assert_eq!(trashed, trash.join("files").join("doomed.txt.1"));
+        assert_eq!(fs::read_to_string(&trashed).unwrap(), "second");
+
+        let restored = restore_latest(&trash).unwrap();
+        assert_eq!(restored, fs::canonicalize(&dir).unwrap().join("doomed.txt"));
+        assert!(file.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_symlink_to_trash() {
+        let dir = temp_dir("trash-symlink");
+        let trash = dir.join("Trash");
+        let target = dir.join("target.txt");
+        let link = dir.join("link");
+        fs::write(&target, "kept").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        // The link goes to the trash, and what it points to stays where it is
+        let trashed = move_to_trash(&link, &trash).unwrap();
+        assert_eq!(trashed, trash.join("files").join("link"));
+        assert!(trashed.is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "kept");
+        assert!(!link.is_symlink());
+
+        let restored = restore_latest(&trash).unwrap();
+        assert_eq!(restored, fs::canonicalize(&dir).unwrap().join("link"));
+        assert!(link.is_symlink());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_then_remove() {
+        let dir = temp_dir("trash-copy-then-remove");
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("sub").join("file.txt"), "contents").unwrap();
+        std::os::unix::fs::symlink("sub", source.join("link")).unwrap();
+
+        let destination = dir.join("destination");
+        copy_then_remove(&source, &destination).unwrap();
+        assert!(!source.exists());
+        assert_eq!(
+            fs::read_to_string(destination.join("sub").join("file.txt")).unwrap(),
+            "contents"
+        );
+        assert_eq!(
+            fs::read_link(destination.join("link")).unwrap(),
+            Path::new("sub")
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}